@@ -9,7 +9,8 @@ use std::sync::Arc;
 
 use criterion::{self, criterion_group, criterion_main, Criterion};
 use rpmrepo_metadata::{
-    utils, FilelistsXml, OtherXml, PackageIterator, PrimaryXml, RepomdXml, Repository,
+    read_package_refs, utils, Field, FilelistsXml, OtherXml, PackageIterator, PrimaryXml,
+    RepomdXml, Repository,
 };
 use std::io::{BufReader, Cursor, Read};
 
@@ -51,7 +52,11 @@ fn metadata_parse_benchmark(c: &mut Criterion) {
     // Load metadata files to memory, then parse from memory, to avoid IO interactions.
 
     let primary_path = path.join(&repo.repomd().get_record("primary").unwrap().location_href);
-    let mut primary = Vec::new();
+    let mut primary = Vec::with_capacity(
+        utils::estimated_decompressed_size(&primary_path)
+            .unwrap()
+            .unwrap_or(0) as usize,
+    );
     utils::reader_from_file(&primary_path)
         .unwrap()
         .read_to_end(&mut primary)
@@ -64,8 +69,48 @@ fn metadata_parse_benchmark(c: &mut Criterion) {
         })
     });
 
+    // the hot path of consumers which only need primary.xml, without building a Repository
+    group.bench_function("primary_xml_iterative", |b| {
+        b.iter(|| {
+            let primary_xml = PrimaryXml::new_reader(utils::create_xml_reader(BufReader::new(
+                Box::new(Cursor::new(primary.clone())) as Box<dyn Read + Send>,
+            )));
+            let mut parser = PackageIterator::from_primary_reader(primary_xml).unwrap();
+            while let Some(pkg) = parser.parse_package().unwrap() {
+                criterion::black_box(pkg);
+            }
+        })
+    });
+
+    group.bench_function("primary_xml_projected", |b| {
+        b.iter(|| {
+            let primary_xml = PrimaryXml::new_reader(utils::create_xml_reader(BufReader::new(
+                Box::new(Cursor::new(primary.clone())) as Box<dyn Read + Send>,
+            )));
+            let mut parser = PackageIterator::from_primary_reader(primary_xml)
+                .unwrap()
+                .project(&[Field::Name, Field::Evr, Field::Arch, Field::Location]);
+            while let Some(pkg) = parser.parse_package().unwrap() {
+                criterion::black_box(pkg);
+            }
+        })
+    });
+
+    // packages borrowing from the metadata, for consumers which don't need to keep most of them
+    group.bench_function("primary_xml_borrowed", |b| {
+        b.iter(|| {
+            for pkg in read_package_refs(&primary).unwrap() {
+                criterion::black_box(pkg.unwrap());
+            }
+        })
+    });
+
     let filelists_path = path.join(&repo.repomd().get_record("filelists").unwrap().location_href);
-    let mut filelists = Vec::new();
+    let mut filelists = Vec::with_capacity(
+        utils::estimated_decompressed_size(&filelists_path)
+            .unwrap()
+            .unwrap_or(0) as usize,
+    );
     utils::reader_from_file(&filelists_path)
         .unwrap()
         .read_to_end(&mut filelists)
@@ -80,7 +125,11 @@ fn metadata_parse_benchmark(c: &mut Criterion) {
     });
 
     let other_path = path.join(&repo.repomd().get_record("other").unwrap().location_href);
-    let mut other = Vec::new();
+    let mut other = Vec::with_capacity(
+        utils::estimated_decompressed_size(&other_path)
+            .unwrap()
+            .unwrap_or(0) as usize,
+    );
     utils::reader_from_file(&other_path)
         .unwrap()
         .read_to_end(&mut other)
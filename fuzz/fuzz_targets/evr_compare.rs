@@ -0,0 +1,25 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use std::cmp::Ordering;
+
+use libfuzzer_sys::fuzz_target;
+use rpmrepo_metadata::version::rpmvercmp;
+
+// The input is split into two versions at the first NUL byte.
+fuzz_target!(|data: &[u8]| {
+    let input = match std::str::from_utf8(data) {
+        Ok(input) => input,
+        Err(_) => return,
+    };
+    let (version1, version2) = input.split_once('\0').unwrap_or((input, ""));
+
+    let ordering = rpmvercmp(version1, version2);
+    assert_eq!(ordering, rpmvercmp(version2, version1).reverse());
+    assert_eq!(rpmvercmp(version1, version1), Ordering::Equal);
+});
@@ -0,0 +1,24 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpmrepo_metadata::{utils, FilelistsXml};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = FilelistsXml::new_reader(utils::create_xml_reader(data));
+    if reader.read_header().is_err() {
+        return;
+    }
+    loop {
+        let mut package = None;
+        match reader.read_package(&mut package) {
+            Ok(true) => (),
+            _ => break,
+        }
+    }
+});
@@ -0,0 +1,19 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpmrepo_metadata::{utils, UpdateinfoXml};
+
+fuzz_target!(|data: &[u8]| {
+    let reader = UpdateinfoXml::new_reader(utils::create_xml_reader(data));
+    for record in reader {
+        if record.is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,76 @@
+/*
+ * Copyright (c) 2022 Daniel Alley
+ *
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+/*
+ * C API for rpmrepo_metadata, available when the library is built with the `capi` feature.
+ *
+ * All objects are opaque handles which must be released with the matching *_free() function.
+ * Strings returned by the library are newly allocated and must be released with rpmrepo_string_free().
+ * Functions which can fail return NULL or -1, and rpmrepo_last_error() describes the error.
+ */
+
+#ifndef RPMREPO_METADATA_H
+#define RPMREPO_METADATA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RpmrepoReader RpmrepoReader;
+typedef struct RpmrepoPackageIterator RpmrepoPackageIterator;
+typedef struct RpmrepoPackage RpmrepoPackage;
+typedef struct RpmrepoWriter RpmrepoWriter;
+
+/* The last error which occurred on the calling thread, or NULL. Owned by the library. */
+const char *rpmrepo_last_error(void);
+void rpmrepo_string_free(char *value);
+
+/* Reading */
+RpmrepoReader *rpmrepo_reader_open(const char *path);
+void rpmrepo_reader_free(RpmrepoReader *reader);
+RpmrepoPackageIterator *rpmrepo_reader_iter_packages(RpmrepoReader *reader);
+size_t rpmrepo_package_iterator_total(RpmrepoPackageIterator *iterator);
+/* Returns NULL at the end of the iteration, or on error if rpmrepo_last_error() is not NULL. */
+RpmrepoPackage *rpmrepo_package_iterator_next(RpmrepoPackageIterator *iterator);
+void rpmrepo_package_iterator_free(RpmrepoPackageIterator *iterator);
+
+/* Packages */
+RpmrepoPackage *rpmrepo_package_new(void);
+void rpmrepo_package_free(RpmrepoPackage *package);
+char *rpmrepo_package_name(const RpmrepoPackage *package);
+int rpmrepo_package_set_name(RpmrepoPackage *package, const char *name);
+uint32_t rpmrepo_package_epoch(const RpmrepoPackage *package);
+int rpmrepo_package_set_epoch(RpmrepoPackage *package, uint32_t epoch);
+char *rpmrepo_package_version(const RpmrepoPackage *package);
+int rpmrepo_package_set_version(RpmrepoPackage *package, const char *version);
+char *rpmrepo_package_release(const RpmrepoPackage *package);
+int rpmrepo_package_set_release(RpmrepoPackage *package, const char *release);
+char *rpmrepo_package_arch(const RpmrepoPackage *package);
+int rpmrepo_package_set_arch(RpmrepoPackage *package, const char *arch);
+char *rpmrepo_package_summary(const RpmrepoPackage *package);
+int rpmrepo_package_set_summary(RpmrepoPackage *package, const char *summary);
+char *rpmrepo_package_location_href(const RpmrepoPackage *package);
+int rpmrepo_package_set_location_href(RpmrepoPackage *package, const char *location_href);
+char *rpmrepo_package_pkgid(const RpmrepoPackage *package);
+int rpmrepo_package_set_checksum(RpmrepoPackage *package, const char *checksum_type, const char *checksum);
+
+/* Writing */
+RpmrepoWriter *rpmrepo_writer_new(const char *path, size_t num_pkgs);
+int rpmrepo_writer_add_package(RpmrepoWriter *writer, const RpmrepoPackage *package);
+/* Releases the writer, whether or not it succeeds. */
+int rpmrepo_writer_finish(RpmrepoWriter *writer);
+void rpmrepo_writer_free(RpmrepoWriter *writer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RPMREPO_METADATA_H */
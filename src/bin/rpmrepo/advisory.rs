@@ -0,0 +1,308 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+
+use clap::Args;
+use rpmrepo_metadata::{HttpFetcher, MetadataError, UpdateRecord};
+use serde_json::json;
+
+use crate::format_checksum;
+use crate::location::OpenedRepository;
+
+#[derive(Args)]
+pub struct AdvisoriesArgs {
+    /// Path or URL of the repository
+    location: String,
+    /// Only list advisories of the given type, e.g. "security" (may be repeated)
+    #[arg(long = "type")]
+    update_type: Vec<String>,
+    /// Only list advisories of the given severity, e.g. "Important" (may be repeated)
+    #[arg(long)]
+    severity: Vec<String>,
+    /// Only list advisories issued on or after this date, e.g. "2022-01-31"
+    #[arg(long)]
+    since: Option<String>,
+    /// Only list advisories issued on or before this date, e.g. "2022-12-31"
+    #[arg(long)]
+    until: Option<String>,
+    /// Only list the CVE IDs addressed by each advisory, one line per advisory ID and CVE ID
+    #[arg(long = "cves-only")]
+    cves_only: bool,
+    /// Print each advisory as a line of JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+pub struct AdvisoryArgs {
+    /// ID of the advisory, e.g. "RHSA-2022:1234"
+    id: String,
+    /// Path or URL of the repository
+    location: String,
+    /// Print the advisory as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run_list(args: AdvisoriesArgs, fetcher: &HttpFetcher) -> Result<(), MetadataError> {
+    let repo = OpenedRepository::open(&args.location, &["updateinfo"], fetcher)?;
+    for advisory in repo.iter_advisories()? {
+        let advisory = advisory?;
+        if !args.matches(&advisory) {
+            continue;
+        }
+        if args.cves_only {
+            let cve_ids = advisory.cve_ids();
+            if args.json {
+                println!("{}", json!({"id": advisory.id, "cves": cve_ids}));
+            } else {
+                for cve_id in cve_ids {
+                    println!("{}\t{}", advisory.id, cve_id);
+                }
+            }
+        } else if args.json {
+            println!("{}", advisory_json(&advisory));
+        } else {
+            println!(
+                "{}\t{}\t{}\t{}\t{}",
+                advisory.id,
+                advisory.update_type,
+                if advisory.severity.is_empty() {
+                    "-"
+                } else {
+                    advisory.severity.as_str()
+                },
+                issued_date(&advisory).as_deref().unwrap_or("-"),
+                advisory.title
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn run_show(args: AdvisoryArgs, fetcher: &HttpFetcher) -> Result<(), MetadataError> {
+    let repo = OpenedRepository::open(&args.location, &["updateinfo"], fetcher)?;
+    let mut advisories = repo.iter_advisories()?;
+    let advisory = loop {
+        match advisories.next().transpose()? {
+            Some(advisory) if advisory.id == args.id => break advisory,
+            Some(_) => continue,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("advisory {} not found", args.id),
+                )
+                .into())
+            }
+        }
+    };
+
+    if args.json {
+        println!("{}", advisory_json(&advisory));
+        return Ok(());
+    }
+
+    println!("{}: {}", advisory.id, advisory.title);
+    println!("  type:      {}", advisory.update_type);
+    println!("  severity:  {}", advisory.severity);
+    println!("  status:    {}", advisory.status);
+    println!("  from:      {}", advisory.from);
+    println!("  release:   {}", advisory.release);
+    println!(
+        "  issued:    {}",
+        issued_date(&advisory).as_deref().unwrap_or("-")
+    );
+    if let Some(updated) = advisory.updated_date.as_deref() {
+        println!("  updated:   {}", normalize_date(updated));
+    }
+    if advisory.reboot_suggested {
+        println!("  reboot suggested");
+    }
+    if !advisory.summary.is_empty() {
+        println!("\n{}", advisory.summary);
+    }
+    if !advisory.description.is_empty() {
+        println!("\n{}", advisory.description);
+    }
+    if !advisory.solution.is_empty() {
+        println!("\nsolution: {}", advisory.solution);
+    }
+    if !advisory.references.is_empty() {
+        println!("\nreferences:");
+        for reference in &advisory.references {
+            println!(
+                "  [{}] {} {}",
+                reference.reftype, reference.id, reference.href
+            );
+        }
+    }
+    for collection in &advisory.pkglist {
+        println!("\npackages ({}):", collection.name);
+        if let Some(module) = &collection.module {
+            println!(
+                "  module {}:{}:{}:{}:{}",
+                module.name, module.stream, module.version, module.context, module.arch
+            );
+        }
+        for package in &collection.packages {
+            println!("  {}", package.filename);
+        }
+    }
+    Ok(())
+}
+
+impl AdvisoriesArgs {
+    fn matches(&self, advisory: &UpdateRecord) -> bool {
+        if !self.update_type.is_empty()
+            && !self
+                .update_type
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(&advisory.update_type))
+        {
+            return false;
+        }
+        if !self.severity.is_empty()
+            && !self
+                .severity
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&advisory.severity))
+        {
+            return false;
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+
+        // dates are compared as strings, "2022-01-31" is a prefix of "2022-01-31 12:00:00"
+        let issued = match issued_date(advisory) {
+            Some(issued) => issued,
+            None => return false,
+        };
+        if let Some(since) = &self.since {
+            if issued.as_str() < since.as_str() {
+                return false;
+            }
+        }
+        if let Some(until) = &self.until {
+            if issued.get(..until.len()).unwrap_or(&issued) > until.as_str() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn issued_date(advisory: &UpdateRecord) -> Option<String> {
+    advisory
+        .issued_date
+        .as_deref()
+        .map(str::trim)
+        .filter(|date| !date.is_empty())
+        .map(normalize_date)
+}
+
+/// Dates are usually formatted as `2020-05-27 04:10:31`, but some producers use UNIX timestamps instead,
+/// which are converted to the same format.
+fn normalize_date(date: &str) -> String {
+    let timestamp = match date.trim().parse::<i64>() {
+        Ok(timestamp) => timestamp,
+        Err(_) => return date.trim().to_owned(),
+    };
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn advisory_json(advisory: &UpdateRecord) -> serde_json::Value {
+    let references: Vec<_> = advisory
+        .references
+        .iter()
+        .map(|r| json!({"type": r.reftype, "id": r.id, "title": r.title, "href": r.href}))
+        .collect();
+    let pkglist: Vec<_> = advisory
+        .pkglist
+        .iter()
+        .map(|collection| {
+            let packages: Vec<_> = collection
+                .packages
+                .iter()
+                .map(|p| {
+                    json!({
+                        "name": p.name,
+                        "epoch": p.epoch,
+                        "version": p.version,
+                        "release": p.release,
+                        "arch": p.arch,
+                        "src": p.src,
+                        "filename": p.filename,
+                        "checksum": p.checksum.as_ref().map(format_checksum),
+                        "reboot_suggested": p.reboot_suggested,
+                        "restart_suggested": p.restart_suggested,
+                        "relogin_suggested": p.relogin_suggested,
+                    })
+                })
+                .collect();
+            let module = collection.module.as_ref().map(|m| {
+                json!({
+                    "name": m.name,
+                    "stream": m.stream,
+                    "version": m.version,
+                    "context": m.context,
+                    "arch": m.arch,
+                })
+            });
+            json!({
+                "name": collection.name,
+                "shortname": collection.shortname,
+                "module": module,
+                "packages": packages,
+            })
+        })
+        .collect();
+
+    json!({
+        "id": advisory.id,
+        "type": advisory.update_type,
+        "status": advisory.status,
+        "from": advisory.from,
+        "version": advisory.version,
+        "title": advisory.title,
+        "severity": advisory.severity,
+        "issued_date": advisory.issued_date,
+        "updated_date": advisory.updated_date,
+        "rights": advisory.rights,
+        "release": advisory.release,
+        "pushcount": advisory.pushcount,
+        "summary": advisory.summary,
+        "description": advisory.description,
+        "solution": advisory.solution,
+        "reboot_suggested": advisory.reboot_suggested,
+        "restart_suggested": advisory.restart_suggested,
+        "relogin_suggested": advisory.relogin_suggested,
+        "references": references,
+        "pkglist": pkglist,
+    })
+}
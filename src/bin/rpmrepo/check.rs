@@ -0,0 +1,36 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::path::PathBuf;
+
+use clap::Args;
+use rpmrepo_metadata::{MetadataError, RepositoryReader};
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Path of the repository
+    path: PathBuf,
+    /// Also check the metadata files against the createrepo XML schemas
+    #[arg(long)]
+    schema: bool,
+}
+
+pub fn run(args: CheckArgs) -> Result<(), MetadataError> {
+    let reader = RepositoryReader::new_from_directory(&args.path)?;
+    let mut problems = reader.validate()?.problems;
+    if args.schema {
+        problems.extend(reader.validate_schema()?.problems);
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    Err(io::Error::other(format!("{} problems found", problems.len())).into())
+}
@@ -0,0 +1,363 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use clap::Args;
+use rpmrepo_metadata::{
+    package_layout_href, utils, ChecksumType, MetadataError, Package, Repository,
+    RepositoryOptions, RepositoryReader, RepositoryWriter, ScanOptions, TransferMode,
+};
+
+use crate::parse_compression_type;
+use crate::pkglist::{read_rpm_paths, relative_href};
+
+/// The subset of the `createrepo_c` command line which is commonly used by build scripts.
+#[derive(Args)]
+pub struct CreaterepoArgs {
+    /// Directory containing the RPMs, several with --split
+    #[arg(required = true)]
+    directories: Vec<PathBuf>,
+    /// Directory in which the "repodata" directory is written, by default the RPM directory. If the RPM
+    /// directory is inside of it, locations are relative to the output directory
+    #[arg(short = 'o', long)]
+    outputdir: Option<PathBuf>,
+    /// Split media mode: each directory is a separate medium, and the locations of its packages are
+    /// relative to "media:#N#"
+    #[arg(long, requires = "outputdir", conflicts_with_all = ["pkglist", "baseurl"])]
+    split: bool,
+    /// Base URL of the packages, if they are not stored in the repository itself
+    #[arg(short = 'u', long)]
+    baseurl: Option<String>,
+    /// Prefix the locations of the packages with a subdirectory, e.g. "Packages/"
+    #[arg(long = "location-prefix", default_value = "")]
+    location_prefix: String,
+    /// Place the RPMs in the "Packages/<first letter>/" layout of the output directory: move, hardlink,
+    /// copy or reflink
+    #[arg(long, conflicts_with_all = ["split", "location_prefix", "baseurl"])]
+    organize: Option<String>,
+    /// Reuse the existing metadata of packages whose size and modification time are unchanged
+    #[arg(long)]
+    update: bool,
+    /// Checksum type of the packages and metadata files
+    #[arg(short = 's', long, default_value = "sha256")]
+    checksum: String,
+    /// Compression of the metadata files: gz, bz2, xz, zstd or none
+    #[arg(long = "compress-type", default_value = "zstd")]
+    compress_type: String,
+    /// Compression level of the metadata files, 1-9 for gz, bz2 and xz and 1-22 for zstd. Defaults to 9
+    #[arg(long = "compress-level")]
+    compress_level: Option<u32>,
+    /// Enable zstd long distance matching, which compresses large metadata files better
+    #[arg(long = "zstd-long")]
+    zstd_long: bool,
+    /// Number of threads used to compress each metadata file with zstd or xz
+    #[arg(long = "compress-threads", default_value_t = 1)]
+    compress_threads: u32,
+    /// Number of changelog entries to keep for each package, newest first. 0 or "none" keeps all of them
+    // spelled out so that clap parses the value itself as an Option, rather than making the flag optional
+    #[arg(long = "changelog-limit", value_name = "N", default_value = "10", value_parser = parse_changelog_limit)]
+    changelog_limit: std::option::Option<usize>,
+    /// Accepted for compatibility, sqlite databases are never generated
+    #[arg(long = "no-database")]
+    no_database: bool,
+    /// Skip RPMs with a path or filename matching a shell-style glob (may be repeated)
+    #[arg(short = 'x', long)]
+    excludes: Vec<String>,
+    /// Only include RPMs with a path or filename matching a shell-style glob (may be repeated)
+    #[arg(long)]
+    includes: Vec<String>,
+    /// Skip symlinks to RPMs and directories
+    #[arg(short = 'S', long = "skip-symlinks")]
+    skip_symlinks: bool,
+    /// Only include the RPMs listed in this file, one path relative to the RPM directory per line. Listed
+    /// RPMs which don't exist are an error
+    #[arg(short = 'i', long)]
+    pkglist: Option<PathBuf>,
+    /// Instead of scanning the RPM directory, use the RPM files listed in this file, one path per line
+    /// relative to the current directory, or "-" to read the list from stdin. The RPMs must be inside of
+    /// the RPM directory
+    #[arg(long = "add-package-list", conflicts_with_all = ["split", "pkglist"])]
+    add_package_list: Option<PathBuf>,
+    /// Revision of the repository, by default the current time
+    #[arg(long)]
+    revision: Option<String>,
+    /// Distro tag, optionally preceded by a CPE ID, e.g. "cpe:/o:fedoraproject:fedora:36,Fedora 36" (may be
+    /// repeated)
+    #[arg(long)]
+    distro: Vec<String>,
+    /// Content tags, comma-separated, e.g. "binary-x86_64" (may be repeated)
+    #[arg(long = "content-tags", value_delimiter = ',')]
+    content_tags: Vec<String>,
+    /// Repo tags, comma-separated (may be repeated)
+    #[arg(long = "repo-tags", value_delimiter = ',')]
+    repo_tags: Vec<String>,
+    /// Only include packages of these architectures, comma-separated, e.g. "x86_64,noarch" (may be repeated)
+    #[arg(long, value_delimiter = ',')]
+    arches: Vec<String>,
+    /// Only include the newest N versions of each package name and architecture. The RPM files of older
+    /// versions are left in place
+    #[arg(long = "retain-versions", value_name = "N")]
+    retain_versions: Option<usize>,
+}
+
+fn parse_changelog_limit(value: &str) -> Result<Option<usize>, String> {
+    match value {
+        "none" | "0" => Ok(None),
+        _ => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("expected a number or \"none\", got \"{}\"", value)),
+    }
+}
+
+pub fn run(args: CreaterepoArgs) -> Result<(), MetadataError> {
+    let checksum_type: ChecksumType = args.checksum.as_str().try_into()?;
+    let compression_type = parse_compression_type(&args.compress_type)?;
+    if args.directories.len() > 1 && !args.split {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "several RPM directories can only be used with --split",
+        )
+        .into());
+    }
+    let organize = match args.organize.as_deref() {
+        None => None,
+        Some("move") => Some(TransferMode::Move),
+        Some("hardlink") => Some(TransferMode::Hardlink),
+        Some("copy") => Some(TransferMode::Copy),
+        Some("reflink") => Some(TransferMode::Reflink),
+        Some(other) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unknown --organize mode {}, expected move, hardlink, copy or reflink",
+                    other
+                ),
+            )
+            .into())
+        }
+    };
+    let output_dir = args.outputdir.as_deref().unwrap_or(&args.directories[0]);
+
+    let scan_options = ScanOptions {
+        includes: args.includes.clone(),
+        excludes: args.excludes.clone(),
+        ..ScanOptions::default()
+    }
+    .follow_symlinks(!args.skip_symlinks);
+    let mut packages = Vec::new();
+    for (idx, directory) in args.directories.iter().enumerate() {
+        let hrefs = match (&args.pkglist, &args.add_package_list) {
+            (Some(pkglist), _) => RepositoryWriter::read_package_list(directory, pkglist)?
+                .into_iter()
+                .filter(|href| scan_options.matches(href))
+                .collect(),
+            (None, Some(list)) => read_rpm_paths(list)?
+                .iter()
+                .map(|path| relative_href(directory, path))
+                .filter(|href| {
+                    href.as_ref()
+                        .map_or(true, |href| scan_options.matches(href))
+                })
+                .collect::<Result<_, _>>()?,
+            (None, None) => RepositoryWriter::scan_directory(directory, &scan_options)?,
+        };
+        let (prefix, location_base) = if args.split {
+            (String::new(), Some(format!("media:#{}#", idx + 1)))
+        } else {
+            (relative_prefix(output_dir, directory)?, None)
+        };
+        for href in hrefs {
+            let path = directory.join(&href);
+            let href = match organize {
+                Some(_) => package_layout_href(href.rsplit('/').next().unwrap_or(&href)),
+                None => format!("{}{}", prefix, href),
+            };
+            packages.push(PackageLocation {
+                path,
+                href,
+                location_base: location_base.clone(),
+            });
+        }
+    }
+
+    if organize.is_some() {
+        // RPMs with the same filename in different subdirectories would overwrite each other
+        let mut seen = HashMap::new();
+        for location in &packages {
+            if let Some(other) = seen.insert(&location.href, &location.path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} and {} would both be placed at {}",
+                        other.display(),
+                        location.path.display(),
+                        location.href
+                    ),
+                )
+                .into());
+            }
+        }
+    }
+
+    let existing = if args.update {
+        existing_packages(output_dir)?
+    } else {
+        HashMap::new()
+    };
+
+    let options = RepositoryOptions::default()
+        .package_checksum_type(checksum_type)
+        .metadata_checksum_type(checksum_type)
+        .metadata_compression_type(compression_type)
+        .metadata_compression_level(args.compress_level)
+        .zstd_long_distance_matching(args.zstd_long)
+        .compression_threads(args.compress_threads)
+        .changelog_limit(args.changelog_limit);
+
+    // the locations of existing packages already include the prefix and base applied by the writer
+    let prefix = match args.location_prefix.trim_matches('/') {
+        "" => String::new(),
+        prefix => format!("{}/", prefix),
+    };
+    // the packages are filtered before writing, as the writer needs to know how many there are
+    let arches = Some(args.arches.as_slice()).filter(|arches| !arches.is_empty());
+    let mut included = Vec::new();
+    for location in packages {
+        let key = (
+            format!("{}{}", prefix, location.href),
+            location
+                .location_base
+                .clone()
+                .or_else(|| args.baseurl.clone()),
+        );
+        let mut pkg = match existing.get(&key) {
+            Some(pkg) if is_unchanged(pkg, &location.path, checksum_type)? => {
+                let mut pkg = pkg.clone();
+                pkg.set_location_href(location.href);
+                pkg
+            }
+            _ => options.read_rpm_file(&location.path, &location.href)?,
+        };
+        if location.location_base.is_some() {
+            pkg.set_location_base(location.location_base);
+        }
+        if utils::arch_matches(arches, pkg.arch()) {
+            included.push((location.path, pkg));
+        }
+    }
+
+    if let Some(n) = args.retain_versions {
+        included = retain_newest_versions(included, n);
+    }
+
+    let mut writer = RepositoryWriter::new_with_options(output_dir, included.len(), options)?;
+    writer.set_location_prefix(&args.location_prefix);
+    writer.set_location_base(args.baseurl.as_deref());
+    let repomd = writer.repomd_mut();
+    if let Some(revision) = &args.revision {
+        repomd.set_revision(revision);
+    }
+    for distro in &args.distro {
+        match distro.split_once(',') {
+            Some((cpeid, name)) => repomd.add_distro_tag(name.to_owned(), Some(cpeid.to_owned())),
+            None => repomd.add_distro_tag(distro.to_owned(), None),
+        }
+    }
+    for tag in &args.content_tags {
+        repomd.add_content_tag(tag.to_owned());
+    }
+    for tag in &args.repo_tags {
+        repomd.add_repo_tag(tag.to_owned());
+    }
+    for (path, pkg) in &included {
+        // only placed once all packages have been read successfully
+        if let Some(mode) = organize {
+            writer.organize_rpm_file(path, mode)?;
+        }
+        writer.add_package(pkg)?;
+    }
+    writer.finish()
+}
+
+/// Keep the newest `n` versions of each package, see [`Repository::retain_newest_versions`]. Copies of the
+/// same RPM file, which share a pkgid, are only kept once.
+fn retain_newest_versions(packages: Vec<(PathBuf, Package)>, n: usize) -> Vec<(PathBuf, Package)> {
+    let mut paths = HashMap::new();
+    let mut repo = Repository::new();
+    for (path, pkg) in packages {
+        paths.insert(pkg.pkgid().to_owned(), path);
+        repo.packages_mut().insert(pkg.pkgid().to_owned(), pkg);
+    }
+    repo.retain_newest_versions(n);
+    repo.packages_mut()
+        .drain(..)
+        .map(|(pkgid, pkg)| (paths.remove(&pkgid).unwrap(), pkg))
+        .collect()
+}
+
+struct PackageLocation {
+    path: PathBuf,
+    href: String,
+    location_base: Option<String>,
+}
+
+/// The location of `directory` relative to `output_dir`, as a prefix for the locations of its packages,
+/// e.g. "Packages/". Empty if it is the same directory or not inside of it, in which case the locations
+/// are relative to `directory` like with createrepo_c.
+fn relative_prefix(output_dir: &Path, directory: &Path) -> Result<String, MetadataError> {
+    std::fs::create_dir_all(output_dir)?;
+    let (output_dir, directory) = (output_dir.canonicalize()?, directory.canonicalize()?);
+    let prefix = match directory.strip_prefix(&output_dir) {
+        Ok(relative) => relative
+            .components()
+            .map(|c| format!("{}/", c.as_os_str().to_string_lossy()))
+            .collect(),
+        Err(_) => String::new(),
+    };
+    Ok(prefix)
+}
+
+/// The packages of the existing repository in `path`, if there is one, keyed by location.
+fn existing_packages(
+    path: &Path,
+) -> Result<HashMap<(String, Option<String>), Package>, MetadataError> {
+    if !path.join("repodata/repomd.xml").is_file() {
+        return Ok(HashMap::new());
+    }
+    let reader = RepositoryReader::new_from_directory(path)?;
+    let mut packages = HashMap::new();
+    for pkg in reader.iter_packages()? {
+        let pkg = pkg?;
+        let key = (
+            pkg.location_href().to_owned(),
+            pkg.location_base().map(str::to_owned),
+        );
+        packages.insert(key, pkg);
+    }
+    Ok(packages)
+}
+
+/// Whether the existing metadata of a package can be reused, like `createrepo_c --update` the size
+/// and modification time of the file are compared.
+fn is_unchanged(
+    pkg: &Package,
+    path: &Path,
+    checksum_type: ChecksumType,
+) -> Result<bool, MetadataError> {
+    let metadata = path.metadata()?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok(metadata.len() == pkg.size_package()
+        && mtime == pkg.time_file()
+        && pkg.checksum().map(|c| c.checksum_type()) == Some(checksum_type))
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use rpmrepo_metadata::{
+    HttpFetcher, MetadataError, RemoteRepository, RepositoryReader, RepositoryTransformer,
+    TransferMode,
+};
+
+use crate::location::is_url;
+
+#[derive(Args)]
+pub struct ExtractUpdatesArgs {
+    /// ID of an advisory to extract, e.g. "FEDORA-2022-1234" (may be repeated)
+    #[arg(long = "advisory", required = true)]
+    advisories: Vec<String>,
+    /// Path or URL of the source repository
+    #[arg(long)]
+    from: String,
+    /// Directory to write the new repository to
+    #[arg(long)]
+    to: PathBuf,
+    /// Only write the metadata, without copying or downloading the package files
+    #[arg(long = "metadata-only")]
+    metadata_only: bool,
+}
+
+pub fn run(args: ExtractUpdatesArgs, fetcher: &HttpFetcher) -> Result<(), MetadataError> {
+    let ids: Vec<&str> = args.advisories.iter().map(String::as_str).collect();
+
+    if !is_url(&args.from) {
+        let reader = RepositoryReader::new_from_directory(Path::new(&args.from))?;
+        let mut transformer = RepositoryTransformer::new(reader);
+        if !args.metadata_only {
+            transformer = transformer.package_files(TransferMode::Copy);
+        }
+        transformer.extract_advisories(&args.to, &ids)?;
+        return Ok(());
+    }
+
+    // a fresh directory with a random name, which other users can't anticipate
+    let download_dir = tempfile::Builder::new().prefix("rpmrepo-").tempdir()?;
+    let remote = RemoteRepository::new(&args.from, fetcher.clone())?;
+    let reader = remote.fetch_metadata(
+        &["primary", "filelists", "other", "updateinfo"],
+        download_dir.path(),
+    )?;
+    RepositoryTransformer::new(reader).extract_advisories(&args.to, &ids)?;
+    if args.metadata_only {
+        return Ok(());
+    }
+
+    // like when extracting from a local repository, packages stored elsewhere are left where they are
+    for package in RepositoryReader::new_from_directory(&args.to)?.iter_packages()? {
+        let package = package?;
+        if package.location_base().is_none() {
+            remote.download_package(&package, &args.to)?;
+        }
+    }
+    Ok(())
+}
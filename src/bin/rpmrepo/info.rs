@@ -0,0 +1,104 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use clap::Args;
+use rpmrepo_metadata::{HttpFetcher, MetadataError, MetadataSelection, RepomdRecord};
+use serde_json::json;
+
+use crate::format_checksum;
+use crate::location::OpenedRepository;
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Path or URL of the repository
+    location: String,
+    /// Print the information as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run(args: InfoArgs, fetcher: &HttpFetcher) -> Result<(), MetadataError> {
+    let repo = OpenedRepository::open(&args.location, &["primary", "updateinfo"], fetcher)?;
+    let repomd = repo.repomd();
+
+    // only the header of primary.xml is read to get the package count
+    let packages = repo
+        .iter_packages_with(MetadataSelection {
+            filelists: false,
+            other: false,
+        })?
+        .total_packages();
+    let advisories = repo
+        .iter_advisories()?
+        .try_fold(0usize, |count, advisory| advisory.map(|_| count + 1))?;
+
+    if args.json {
+        let records: Vec<_> = repomd.records().iter().map(record_json).collect();
+        let distro_tags: Vec<_> = repomd
+            .distro_tags()
+            .iter()
+            .map(|tag| json!({"name": tag.name, "cpeid": tag.cpeid}))
+            .collect();
+        let info = json!({
+            "revision": repomd.revision(),
+            "repo_tags": repomd.repo_tags(),
+            "content_tags": repomd.content_tags(),
+            "distro_tags": distro_tags,
+            "records": records,
+            "packages": packages,
+            "advisories": advisories,
+        });
+        println!("{}", info);
+        return Ok(());
+    }
+
+    println!("revision:     {}", repomd.revision().unwrap_or("-"));
+    if !repomd.repo_tags().is_empty() {
+        println!("repo tags:    {}", repomd.repo_tags().join(", "));
+    }
+    if !repomd.content_tags().is_empty() {
+        println!("content tags: {}", repomd.content_tags().join(", "));
+    }
+    for tag in repomd.distro_tags() {
+        match &tag.cpeid {
+            Some(cpeid) => println!("distro tag:   {} ({})", tag.name, cpeid),
+            None => println!("distro tag:   {}", tag.name),
+        }
+    }
+    println!("packages:     {}", packages);
+    println!("advisories:   {}", advisories);
+    println!();
+    println!("records:");
+    for record in repomd.records() {
+        println!("  {}", record.metadata_name);
+        println!("    location:  {}", record.location_href.display());
+        println!("    checksum:  {}", format_checksum(&record.checksum));
+        if let Some(size) = record.size {
+            println!("    size:      {}", size);
+        }
+        if let Some(open_checksum) = &record.open_checksum {
+            println!("    open checksum: {}", format_checksum(open_checksum));
+        }
+        if let Some(open_size) = record.open_size {
+            println!("    open size: {}", open_size);
+        }
+        println!("    timestamp: {}", record.timestamp);
+    }
+    Ok(())
+}
+
+fn record_json(record: &RepomdRecord) -> serde_json::Value {
+    json!({
+        "type": record.metadata_name,
+        "location_href": record.location_href.to_string_lossy(),
+        "location_base": record.location_base,
+        "checksum": format_checksum(&record.checksum),
+        "size": record.size,
+        "open_checksum": record.open_checksum.as_ref().map(format_checksum),
+        "open_size": record.open_size,
+        "timestamp": record.timestamp,
+    })
+}
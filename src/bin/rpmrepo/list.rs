@@ -0,0 +1,100 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use clap::Args;
+use indexmap::IndexMap;
+use rpmrepo_metadata::{utils, HttpFetcher, MetadataError, MetadataSelection, Package};
+use serde_json::json;
+
+use crate::format_checksum;
+use crate::location::OpenedRepository;
+
+#[derive(Args)]
+pub struct ListArgs {
+    /// Path or URL of the repository
+    location: String,
+    /// Only list packages with a name matching a shell-style glob, e.g. "kernel*"
+    #[arg(long)]
+    name: Option<String>,
+    /// Only list packages of the given architecture (may be repeated)
+    #[arg(long)]
+    arch: Vec<String>,
+    /// Only list the newest version of each package name and architecture
+    #[arg(long)]
+    newest: bool,
+    /// Print each package as a line of JSON
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run(args: ListArgs, fetcher: &HttpFetcher) -> Result<(), MetadataError> {
+    let mut repo = OpenedRepository::open(&args.location, &["primary"], fetcher)?;
+    if !args.arch.is_empty() {
+        let arches: Vec<&str> = args.arch.iter().map(String::as_str).collect();
+        repo.set_arches(&arches);
+    }
+    let packages = repo.iter_packages_with(MetadataSelection {
+        filelists: false,
+        other: false,
+    })?;
+    let matches = |pkg: &Package| {
+        args.name
+            .as_deref()
+            .map_or(true, |pattern| utils::glob_match(pattern, pkg.name()))
+    };
+
+    if !args.newest {
+        // packages are printed as they are parsed, so memory usage doesn't grow with the repository
+        for pkg in packages {
+            let pkg = pkg?;
+            if matches(&pkg) {
+                print_package(&pkg, args.json);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut newest: IndexMap<(String, String), Package> = IndexMap::new();
+    for pkg in packages {
+        let pkg = pkg?;
+        if !matches(&pkg) {
+            continue;
+        }
+        let key = (pkg.name().to_owned(), pkg.arch().to_owned());
+        match newest.get(&key) {
+            Some(existing) if existing.evr() >= pkg.evr() => (),
+            _ => {
+                newest.insert(key, pkg);
+            }
+        }
+    }
+    for pkg in newest.values() {
+        print_package(pkg, args.json);
+    }
+    Ok(())
+}
+
+fn print_package(pkg: &Package, as_json: bool) {
+    let checksum = pkg.checksum().map(format_checksum);
+    if as_json {
+        let line = json!({
+            "nevra": pkg.nevra(),
+            "location_href": pkg.location_href(),
+            "location_base": pkg.location_base(),
+            "size": pkg.size_package(),
+            "checksum": checksum,
+        });
+        println!("{}", line);
+    } else {
+        println!(
+            "{}\t{}\t{}\t{}",
+            pkg.nevra(),
+            pkg.location_href(),
+            pkg.size_package(),
+            checksum.as_deref().unwrap_or("-")
+        );
+    }
+}
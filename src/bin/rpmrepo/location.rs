@@ -0,0 +1,66 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+use rpmrepo_metadata::{HttpFetcher, MetadataError, RemoteRepository, RepositoryReader};
+use tempfile::TempDir;
+
+/// A repository opened from a local directory or a URL.
+///
+/// The metadata of remote repositories is downloaded to a temporary directory, which is removed when
+/// this is dropped.
+pub struct OpenedRepository {
+    reader: RepositoryReader,
+    _download_dir: Option<TempDir>,
+}
+
+impl OpenedRepository {
+    /// Open the repository at `location`, which is either a path or an `http(s)://` URL.
+    ///
+    /// For remote repositories, only the metadata files of the given types (e.g. "primary") are
+    /// downloaded.
+    pub fn open(
+        location: &str,
+        records: &[&str],
+        fetcher: &HttpFetcher,
+    ) -> Result<Self, MetadataError> {
+        if !is_url(location) {
+            return Ok(Self {
+                reader: RepositoryReader::new_from_directory(Path::new(location))?,
+                _download_dir: None,
+            });
+        }
+
+        // a fresh directory with a random name, which other users can't anticipate
+        let download_dir = tempfile::Builder::new().prefix("rpmrepo-").tempdir()?;
+        let remote = RemoteRepository::new(location, fetcher.clone())?;
+        let reader = remote.fetch_metadata(records, download_dir.path())?;
+        Ok(Self {
+            reader,
+            _download_dir: Some(download_dir),
+        })
+    }
+}
+
+impl Deref for OpenedRepository {
+    type Target = RepositoryReader;
+
+    fn deref(&self) -> &RepositoryReader {
+        &self.reader
+    }
+}
+
+impl DerefMut for OpenedRepository {
+    fn deref_mut(&mut self) -> &mut RepositoryReader {
+        &mut self.reader
+    }
+}
+
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
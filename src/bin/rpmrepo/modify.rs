@@ -0,0 +1,96 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+
+use clap::Args;
+use rpmrepo_metadata::{
+    ChecksumType, MetadataError, Repository, RepositoryOptions, RepositoryWriter,
+};
+
+use crate::parse_compression_type;
+use crate::pkglist::{read_rpm_paths, relative_href};
+
+#[derive(Args)]
+pub struct ModifyArgs {
+    /// Path of the repository
+    path: PathBuf,
+    /// Add an RPM file, which must be inside of the repository (may be repeated)
+    #[arg(long = "add")]
+    add: Vec<PathBuf>,
+    /// Add the RPM files listed in this file, one path per line, or "-" to read the list from stdin
+    #[arg(long = "add-package-list")]
+    add_package_list: Option<PathBuf>,
+    /// Remove the package with this NEVRA, e.g. "bash-0:5.1.16-3.fc36.x86_64" (may be repeated). The
+    /// package file is left in place
+    #[arg(long = "remove")]
+    remove: Vec<String>,
+    /// Replace the advisories of the repository with this updateinfo.xml file. Without packages to add or
+    /// remove, only the updateinfo record is rewritten
+    #[arg(long)]
+    updateinfo: Option<PathBuf>,
+    /// Checksum type of the added packages and the rewritten metadata files
+    #[arg(short = 's', long, default_value = "sha256")]
+    checksum: String,
+    /// Compression of the rewritten metadata files: gz, bz2, xz, zstd or none
+    #[arg(long = "compress-type", default_value = "zstd")]
+    compress_type: String,
+}
+
+pub fn run(args: ModifyArgs) -> Result<(), MetadataError> {
+    let checksum_type: ChecksumType = args.checksum.as_str().try_into()?;
+    let compression_type = parse_compression_type(&args.compress_type)?;
+    let options = RepositoryOptions::default()
+        .package_checksum_type(checksum_type)
+        .metadata_checksum_type(checksum_type)
+        .metadata_compression_type(compression_type);
+
+    if let Some(updateinfo) = &args.updateinfo {
+        if args.add.is_empty() && args.add_package_list.is_none() && args.remove.is_empty() {
+            let mut writer = RepositoryWriter::modify_existing(&args.path, options)?;
+            writer.add_metadata_file("updateinfo", updateinfo, compression_type)?;
+            return writer.finish();
+        }
+    }
+
+    let mut repo = Repository::load_from_directory(&args.path)?;
+    for nevra in &args.remove {
+        if repo.remove_package_by_nevra(nevra)?.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no package {} in the repository", nevra),
+            )
+            .into());
+        }
+    }
+
+    let mut add = Vec::new();
+    for path in &args.add {
+        add.push(path.canonicalize()?);
+    }
+    if let Some(list) = &args.add_package_list {
+        add.extend(read_rpm_paths(list)?);
+    }
+    let mut seen = HashSet::new();
+    add.retain(|path| seen.insert(path.clone()));
+
+    let num_pkgs = repo.packages().len() + add.len();
+    let mut writer = RepositoryWriter::modify_existing_packages(&args.path, num_pkgs, options)?;
+    for path in &add {
+        let href = relative_href(&args.path, path)?;
+        // fails if a package with the same NEVRA is already present
+        repo.add_package(writer.read_rpm_file(path, &href)?)?;
+    }
+    for pkg in repo.packages().values() {
+        writer.add_package(pkg)?;
+    }
+    if let Some(updateinfo) = &args.updateinfo {
+        writer.add_metadata_file("updateinfo", updateinfo, compression_type)?;
+    }
+    writer.finish()
+}
@@ -0,0 +1,70 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use rpmrepo_metadata::{MetadataError, RepoLayoutError};
+
+/// Read a list of RPM files, one path per line, from a file or from stdin if `list` is `-`. Empty lines and
+/// lines starting with `#` are ignored.
+///
+/// Relative paths are relative to the current directory. The paths are returned canonicalized and without
+/// duplicates, in the order they were listed. If any listed file does not exist, fails with
+/// [`RepoLayoutError::MissingPackages`] naming all of them.
+pub fn read_rpm_paths(list: &Path) -> Result<Vec<PathBuf>, MetadataError> {
+    let reader: Box<dyn BufRead> = if list == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(list)?))
+    };
+
+    let mut paths = Vec::new();
+    let mut seen = HashSet::new();
+    let mut missing = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match Path::new(line).canonicalize() {
+            Ok(path) if path.is_file() => {
+                if seen.insert(path.clone()) {
+                    paths.push(path);
+                }
+            }
+            _ => missing.push(line.to_owned()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(RepoLayoutError::MissingPackages(missing).into());
+    }
+    Ok(paths)
+}
+
+/// The location of an RPM file relative to the repository at `repo_path`, separated by `/`.
+pub fn relative_href(repo_path: &Path, path: &Path) -> Result<String, MetadataError> {
+    let (repo_path, path) = (repo_path.canonicalize()?, path.canonicalize()?);
+    match path.strip_prefix(&repo_path) {
+        Ok(relative) => Ok(relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is not inside of {}",
+                path.display(),
+                repo_path.display()
+            ),
+        )
+        .into()),
+    }
+}
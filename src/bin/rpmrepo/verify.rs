@@ -0,0 +1,43 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::path::PathBuf;
+
+use clap::Args;
+use rpmrepo_metadata::{MetadataError, RepositoryReader, ValidationProblem};
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Path of the repository
+    path: PathBuf,
+    /// Number of threads used to compute checksums, by default based on the available parallelism
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+}
+
+pub fn run(args: VerifyArgs) -> Result<(), MetadataError> {
+    let reader = RepositoryReader::new_from_directory(&args.path)?;
+    let report = reader.verify_package_files(args.threads)?;
+    if report.is_valid() {
+        return Ok(());
+    }
+
+    let (mut missing, mut corrupt, mut orphaned) = (0, 0, 0);
+    for problem in &report.problems {
+        match problem {
+            ValidationProblem::MissingPackageFile { .. } => missing += 1,
+            ValidationProblem::OrphanedPackageFile { .. } => orphaned += 1,
+            _ => corrupt += 1,
+        }
+        println!("{}", problem);
+    }
+    Err(io::Error::other(format!(
+        "{} missing, {} corrupt and {} orphaned package files",
+        missing, corrupt, orphaned
+    ))
+    .into())
+}
@@ -0,0 +1,72 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+
+use clap::Args;
+use rpmrepo_metadata::{utils, HttpFetcher, MetadataError, MetadataSelection, Requirement};
+
+use crate::location::OpenedRepository;
+
+#[derive(Args)]
+pub struct WhatprovidesArgs {
+    /// A capability, e.g. "libfoo.so.1()(64bit)" or "foo >= 1.2", or a file path. Shell-style globs
+    /// such as "*/bin/foo" are accepted for unversioned capabilities and paths
+    capability: String,
+    /// Path or URL of the repository
+    location: String,
+}
+
+pub fn run(args: WhatprovidesArgs, fetcher: &HttpFetcher) -> Result<(), MetadataError> {
+    let capability = args.capability.trim();
+    let is_glob = capability.contains(['*', '?', '[']);
+    // file paths are also provided implicitly by the packages containing them
+    let is_path = capability.starts_with('/') || capability.starts_with('*');
+
+    let records: &[&str] = if is_path {
+        &["primary", "filelists"]
+    } else {
+        &["primary"]
+    };
+    let repo = OpenedRepository::open(&args.location, records, fetcher)?;
+
+    let requirement = if is_glob {
+        None
+    } else {
+        Some(Requirement::parse(capability)?)
+    };
+    let mut found = HashSet::new();
+
+    // primary.xml is streamed, only the packages which match are kept
+    let packages = repo.iter_packages_with(MetadataSelection {
+        filelists: false,
+        other: false,
+    })?;
+    for pkg in packages {
+        let pkg = pkg?;
+        let provide = pkg.provides().iter().find(|provide| match &requirement {
+            Some(requirement) => requirement.satisfied_by_provide(provide),
+            None => utils::glob_match(capability, &provide.name),
+        });
+        if let Some(provide) = provide {
+            println!("{}\tprovides {}", pkg.nevra(), provide);
+            found.insert(pkg.pkgid().to_owned());
+        }
+    }
+
+    if is_path {
+        for owner in repo.find_file_owners(capability)? {
+            let owner = owner?;
+            if found.contains(&owner.pkgid) {
+                continue;
+            }
+            for path in &owner.paths {
+                println!("{}\tcontains {}", owner.nevra, path);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,465 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+
+use quick_xml::events::attributes::Attributes;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::metadata::{HeaderRange, METADATA_PRIMARY};
+use crate::{
+    utils, Checksum, MetadataError, Package, ParseError, Requirement, RequirementType, EVR,
+};
+
+const TAG_METADATA: &[u8] = b"metadata";
+const TAG_PACKAGE: &[u8] = b"package";
+const TAG_NAME: &[u8] = b"name";
+const TAG_ARCH: &[u8] = b"arch";
+const TAG_VERSION: &[u8] = b"version";
+const TAG_CHECKSUM: &[u8] = b"checksum";
+const TAG_SUMMARY: &[u8] = b"summary";
+const TAG_DESCRIPTION: &[u8] = b"description";
+const TAG_PACKAGER: &[u8] = b"packager";
+const TAG_URL: &[u8] = b"url";
+const TAG_TIME: &[u8] = b"time";
+const TAG_SIZE: &[u8] = b"size";
+const TAG_LOCATION: &[u8] = b"location";
+const TAG_RPM_LICENSE: &[u8] = b"rpm:license";
+const TAG_RPM_VENDOR: &[u8] = b"rpm:vendor";
+const TAG_RPM_GROUP: &[u8] = b"rpm:group";
+const TAG_RPM_BUILDHOST: &[u8] = b"rpm:buildhost";
+const TAG_RPM_SOURCERPM: &[u8] = b"rpm:sourcerpm";
+const TAG_RPM_HEADER_RANGE: &[u8] = b"rpm:header-range";
+const TAG_RPM_ENTRY: &[u8] = b"rpm:entry";
+const TAG_RPM_PROVIDES: &[u8] = b"rpm:provides";
+const TAG_RPM_REQUIRES: &[u8] = b"rpm:requires";
+const TAG_RPM_CONFLICTS: &[u8] = b"rpm:conflicts";
+const TAG_RPM_OBSOLETES: &[u8] = b"rpm:obsoletes";
+const TAG_RPM_SUGGESTS: &[u8] = b"rpm:suggests";
+const TAG_RPM_ENHANCES: &[u8] = b"rpm:enhances";
+const TAG_RPM_RECOMMENDS: &[u8] = b"rpm:recommends";
+const TAG_RPM_SUPPLEMENTS: &[u8] = b"rpm:supplements";
+
+/// A package from primary.xml whose fields borrow from the metadata instead of being copied out of it.
+///
+/// Only values containing escape sequences (e.g. `&amp;`) need to be allocated, so reading `PackageRef`s is
+/// much faster than reading [`Package`]s, for consumers which look at packages one at a time, e.g. to filter
+/// or index them. [`PackageRef::to_package`] converts the packages which are to be kept.
+///
+/// See [`read_package_refs`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PackageRef<'a> {
+    pub name: Cow<'a, str>,
+    pub arch: Cow<'a, str>,
+    pub epoch: Cow<'a, str>,
+    pub version: Cow<'a, str>,
+    pub release: Cow<'a, str>,
+    pub checksum_type: Cow<'a, str>,
+    pub pkgid: Cow<'a, str>,
+    pub summary: Cow<'a, str>,
+    pub description: Cow<'a, str>,
+    pub packager: Cow<'a, str>,
+    pub url: Cow<'a, str>,
+    pub time_file: u64,
+    pub time_build: u64,
+    pub size_package: u64,
+    pub size_installed: u64,
+    pub size_archive: u64,
+    pub location_href: Cow<'a, str>,
+    pub location_base: Option<Cow<'a, str>>,
+
+    pub rpm_license: Cow<'a, str>,
+    pub rpm_vendor: Cow<'a, str>,
+    pub rpm_group: Cow<'a, str>,
+    pub rpm_buildhost: Cow<'a, str>,
+    pub rpm_sourcerpm: Cow<'a, str>,
+    pub rpm_header_range: HeaderRange,
+
+    pub requires: Vec<RequirementRef<'a>>,
+    pub provides: Vec<RequirementRef<'a>>,
+    pub conflicts: Vec<RequirementRef<'a>>,
+    pub obsoletes: Vec<RequirementRef<'a>>,
+    pub suggests: Vec<RequirementRef<'a>>,
+    pub enhances: Vec<RequirementRef<'a>>,
+    pub recommends: Vec<RequirementRef<'a>>,
+    pub supplements: Vec<RequirementRef<'a>>,
+}
+
+impl PackageRef<'_> {
+    pub fn nevra(&self) -> String {
+        format!(
+            "{}-{}:{}-{}.{}",
+            self.name, self.epoch, self.version, self.release, self.arch
+        )
+    }
+
+    /// Copy the package into a [`Package`], as if it had been read with [`crate::PackageIterator`] from
+    /// primary.xml alone.
+    pub fn to_package(&self) -> Result<Package, MetadataError> {
+        let to_requirements = |list: &[RequirementRef]| -> Vec<Requirement> {
+            list.iter().map(RequirementRef::to_requirement).collect()
+        };
+        let mut package = Package::default();
+        package
+            .set_name(&*self.name)
+            .set_arch(&*self.arch)
+            .set_evr(EVR::new(&*self.epoch, &*self.version, &*self.release))
+            .set_checksum(Checksum::parse(&self.checksum_type, &self.pkgid)?)
+            .set_summary(&*self.summary)
+            .set_description(&*self.description)
+            .set_packager(&*self.packager)
+            .set_url(&*self.url)
+            .set_time_file(self.time_file)
+            .set_time_build(self.time_build)
+            .set_size_package(self.size_package)
+            .set_size_installed(self.size_installed)
+            .set_size_archive(self.size_archive)
+            .set_location_href(&*self.location_href)
+            .set_location_base(self.location_base.as_deref())
+            .set_rpm_license(&*self.rpm_license)
+            .set_rpm_vendor(&*self.rpm_vendor)
+            .set_rpm_group(&*self.rpm_group)
+            .set_rpm_buildhost(&*self.rpm_buildhost)
+            .set_rpm_sourcerpm(&*self.rpm_sourcerpm)
+            .set_rpm_header_range(self.rpm_header_range.start, self.rpm_header_range.end)
+            .set_requires(to_requirements(&self.requires))
+            .set_provides(to_requirements(&self.provides))
+            .set_conflicts(to_requirements(&self.conflicts))
+            .set_obsoletes(to_requirements(&self.obsoletes))
+            .set_suggests(to_requirements(&self.suggests))
+            .set_enhances(to_requirements(&self.enhances))
+            .set_recommends(to_requirements(&self.recommends))
+            .set_supplements(to_requirements(&self.supplements));
+        Ok(package)
+    }
+}
+
+/// A dependency of a [`PackageRef`], borrowing from the metadata like the package.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequirementRef<'a> {
+    pub name: Cow<'a, str>,
+    pub flags: Option<RequirementType>,
+    pub epoch: Option<Cow<'a, str>>,
+    pub version: Option<Cow<'a, str>>,
+    pub release: Option<Cow<'a, str>>,
+    pub preinstall: bool,
+}
+
+impl RequirementRef<'_> {
+    pub fn to_requirement(&self) -> Requirement {
+        Requirement {
+            name: self.name.as_ref().into(),
+            flags: self.flags,
+            epoch: self.epoch.as_deref().map(str::to_owned),
+            version: self.version.as_deref().map(str::to_owned),
+            release: self.release.as_deref().map(str::to_owned),
+            preinstall: self.preinstall,
+        }
+    }
+}
+
+/// Iterator over the [`PackageRef`]s of primary.xml. See [`read_package_refs`].
+pub struct PackageRefIterator<'a> {
+    input: &'a [u8],
+    reader: Reader<&'a [u8]>,
+    num_packages: usize,
+    exhausted: bool,
+}
+
+/// Read the packages of the (decompressed) contents of primary.xml as [`PackageRef`]s, which borrow from
+/// `primary_xml`.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{read_package_refs, MetadataError, RepositoryReader};
+/// # fn main() -> Result<(), MetadataError> {
+/// let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// let primary_xml = reader.read_record("primary")?.expect("no primary.xml");
+///
+/// let mut noarch = Vec::new();
+/// for package in read_package_refs(&primary_xml)? {
+///     let package = package?;
+///     if package.arch == "noarch" {
+///         noarch.push(package.to_package()?);
+///     }
+/// }
+/// assert_eq!(noarch.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_package_refs(primary_xml: &[u8]) -> Result<PackageRefIterator<'_>, MetadataError> {
+    let mut reader = Reader::from_bytes(primary_xml);
+    // empty elements are handled as such, expanding them costs an allocation each
+    reader.trim_text(true);
+    let mut iterator = PackageRefIterator {
+        input: primary_xml,
+        reader,
+        num_packages: 0,
+        exhausted: false,
+    };
+    iterator.num_packages = iterator
+        .read_header()
+        .map_err(|e| e.at_position(METADATA_PRIMARY, iterator.reader.buffer_position(), None))?;
+    Ok(iterator)
+}
+
+impl<'a> PackageRefIterator<'a> {
+    /// The number of packages stated in the header of primary.xml.
+    pub fn total_packages(&self) -> usize {
+        self.num_packages
+    }
+
+    fn read_header(&mut self) -> Result<usize, MetadataError> {
+        loop {
+            match self.reader.read_event_unbuffered()? {
+                Event::Decl(_) => (),
+                Event::Start(e) if e.name() == TAG_METADATA => {
+                    let count = self
+                        .required_attribute(&e, "packages")?
+                        .parse()
+                        .map_err(ParseError::from)?;
+                    return Ok(count);
+                }
+                _ => return Err(ParseError::MissingHeader.into()),
+            }
+        }
+    }
+
+    fn read_package(&mut self, package: &mut Option<PackageRef<'a>>) -> Result<(), MetadataError> {
+        loop {
+            let (tag, is_empty) = match self.reader.read_event_unbuffered()? {
+                Event::Start(e) => (e, false),
+                Event::Empty(e) => (e, true),
+                Event::End(e) if e.name() == TAG_PACKAGE => return Ok(()),
+                Event::Eof if package.is_some() => return Err(utils::unexpected_eof(TAG_PACKAGE)),
+                Event::Eof => return Ok(()),
+                _ => continue,
+            };
+
+            if tag.name() == TAG_PACKAGE {
+                let ptype = self.required_attribute(&tag, "type")?;
+                if ptype != "rpm" {
+                    return Err(
+                        ParseError::UnknownAttribute(format!("package type {}", ptype)).into(),
+                    );
+                }
+                // nested <package> elements
+                if package.is_some() {
+                    return Err(ParseError::UnexpectedElement("package".to_owned()).into());
+                }
+                *package = Some(PackageRef::default());
+                continue;
+            }
+            let pkg = utils::enclosing(package, tag.name())?;
+
+            match tag.name() {
+                TAG_NAME => pkg.name = self.read_text(&tag, is_empty)?,
+                TAG_ARCH => pkg.arch = self.read_text(&tag, is_empty)?,
+                TAG_VERSION => {
+                    pkg.epoch = self.required_attribute(&tag, "epoch")?;
+                    pkg.version = self.required_attribute(&tag, "ver")?;
+                    pkg.release = self.required_attribute(&tag, "rel")?;
+                }
+                TAG_CHECKSUM => {
+                    pkg.checksum_type = self.required_attribute(&tag, "type")?;
+                    pkg.pkgid = self.read_text(&tag, is_empty)?;
+                }
+                TAG_SUMMARY => pkg.summary = self.read_text(&tag, is_empty)?,
+                TAG_DESCRIPTION => pkg.description = self.read_text(&tag, is_empty)?,
+                TAG_PACKAGER => pkg.packager = self.read_text(&tag, is_empty)?,
+                TAG_URL => pkg.url = self.read_text(&tag, is_empty)?,
+                TAG_TIME => {
+                    pkg.time_file = self.required_attribute(&tag, "file")?.parse()?;
+                    pkg.time_build = self.required_attribute(&tag, "build")?.parse()?;
+                }
+                TAG_SIZE => {
+                    pkg.size_package = self.required_attribute(&tag, "package")?.parse()?;
+                    pkg.size_installed = self.required_attribute(&tag, "installed")?.parse()?;
+                    pkg.size_archive = self.required_attribute(&tag, "archive")?.parse()?;
+                }
+                TAG_LOCATION => {
+                    pkg.location_href = self.required_attribute(&tag, "href")?;
+                    // createrepo_c writes "xml:base", older tools wrote "base"
+                    pkg.location_base = match self.attribute_value(&tag, "xml:base")? {
+                        Some(base) => Some(base),
+                        None => self.attribute_value(&tag, "base")?,
+                    };
+                }
+                TAG_RPM_LICENSE => pkg.rpm_license = self.read_text(&tag, is_empty)?,
+                TAG_RPM_VENDOR => pkg.rpm_vendor = self.read_text(&tag, is_empty)?,
+                TAG_RPM_GROUP => pkg.rpm_group = self.read_text(&tag, is_empty)?,
+                TAG_RPM_BUILDHOST => pkg.rpm_buildhost = self.read_text(&tag, is_empty)?,
+                TAG_RPM_SOURCERPM => pkg.rpm_sourcerpm = self.read_text(&tag, is_empty)?,
+                TAG_RPM_HEADER_RANGE => {
+                    pkg.rpm_header_range = HeaderRange {
+                        start: self.required_attribute(&tag, "start")?.parse()?,
+                        end: self.required_attribute(&tag, "end")?.parse()?,
+                    };
+                }
+                TAG_RPM_PROVIDES => pkg.provides = self.read_requirement_list(&tag, is_empty)?,
+                TAG_RPM_REQUIRES => pkg.requires = self.read_requirement_list(&tag, is_empty)?,
+                TAG_RPM_CONFLICTS => pkg.conflicts = self.read_requirement_list(&tag, is_empty)?,
+                TAG_RPM_OBSOLETES => pkg.obsoletes = self.read_requirement_list(&tag, is_empty)?,
+                TAG_RPM_SUGGESTS => pkg.suggests = self.read_requirement_list(&tag, is_empty)?,
+                TAG_RPM_ENHANCES => pkg.enhances = self.read_requirement_list(&tag, is_empty)?,
+                TAG_RPM_RECOMMENDS => {
+                    pkg.recommends = self.read_requirement_list(&tag, is_empty)?
+                }
+                TAG_RPM_SUPPLEMENTS => {
+                    pkg.supplements = self.read_requirement_list(&tag, is_empty)?
+                }
+                // e.g. <format> and the files listed in primary.xml, which are read from filelists.xml instead
+                _ => (),
+            }
+        }
+    }
+
+    /// Read the text content of the element opened by `tag`, up to its end tag.
+    fn read_text(
+        &mut self,
+        tag: &BytesStart,
+        is_empty: bool,
+    ) -> Result<Cow<'a, str>, MetadataError> {
+        if is_empty {
+            return Ok(Cow::Borrowed(""));
+        }
+        let text = match self.reader.read_event_unbuffered()? {
+            Event::Text(text) => decode(text.into_inner())?,
+            Event::End(end) if end.name() == tag.name() => return Ok(Cow::Borrowed("")),
+            Event::Eof => return Err(utils::unexpected_eof(tag.name())),
+            _ => return Err(quick_xml::Error::TextNotFound.into()),
+        };
+        self.reader.read_to_end_unbuffered(tag.name())?;
+        Ok(text)
+    }
+
+    fn read_requirement_list(
+        &mut self,
+        tag: &BytesStart,
+        is_empty: bool,
+    ) -> Result<Vec<RequirementRef<'a>>, MetadataError> {
+        let mut list = Vec::new();
+        if is_empty {
+            return Ok(list);
+        }
+        loop {
+            match self.reader.read_event_unbuffered()? {
+                Event::Start(entry) | Event::Empty(entry) if entry.name() == TAG_RPM_ENTRY => {
+                    let mut requirement = RequirementRef::default();
+                    for attr in self.attributes(&entry) {
+                        let attr = attr.map_err(quick_xml::Error::from)?;
+                        match attr.key {
+                            b"name" => requirement.name = utils::decode_attribute(&attr)?,
+                            b"flags" => {
+                                requirement.flags =
+                                    Some((&*utils::decode_attribute(&attr)?).try_into()?)
+                            }
+                            b"epoch" => requirement.epoch = Some(utils::decode_attribute(&attr)?),
+                            b"ver" => requirement.version = Some(utils::decode_attribute(&attr)?),
+                            b"rel" => requirement.release = Some(utils::decode_attribute(&attr)?),
+                            b"pre" => {
+                                requirement.preinstall = utils::decode_attribute(&attr)
+                                    .ok()
+                                    .filter(|val| *val != "0" && !val.eq_ignore_ascii_case("false"))
+                                    .is_some()
+                            }
+                            _ => (),
+                        }
+                    }
+                    if requirement.name.is_empty() {
+                        return Err(ParseError::MissingAttribute("name").into());
+                    }
+                    list.push(requirement);
+                }
+                Event::End(end) if end.name() == tag.name() => return Ok(list),
+                Event::Eof => return Err(utils::unexpected_eof(tag.name())),
+                _ => (),
+            }
+        }
+    }
+
+    /// The attributes of `tag`, borrowing from the input rather than from the event.
+    fn attributes(&self, tag: &BytesStart) -> Attributes<'a> {
+        // the events of a reader of a slice always borrow from it, but can't hand out that lifetime
+        let content: &[u8] = tag;
+        let start = content.as_ptr() as usize - self.input.as_ptr() as usize;
+        let mut attributes =
+            Attributes::new(&self.input[start..start + content.len()], tag.name().len());
+        attributes.with_checks(false);
+        attributes
+    }
+
+    fn attribute_value(
+        &self,
+        tag: &BytesStart,
+        name: &'static str,
+    ) -> Result<Option<Cow<'a, str>>, MetadataError> {
+        for attr in self.attributes(tag) {
+            let attr = attr.map_err(quick_xml::Error::from)?;
+            if attr.key == name.as_bytes() {
+                return Ok(Some(utils::decode_attribute(&attr)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn required_attribute(
+        &self,
+        tag: &BytesStart,
+        name: &'static str,
+    ) -> Result<Cow<'a, str>, MetadataError> {
+        self.attribute_value(tag, name)?
+            .ok_or_else(|| ParseError::MissingAttribute(name).into())
+    }
+}
+
+impl<'a> Iterator for PackageRefIterator<'a> {
+    type Item = Result<PackageRef<'a>, MetadataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.exhausted {
+            return None;
+        }
+        let mut package = None;
+        let result = self.read_package(&mut package);
+        match result {
+            Ok(()) if package.is_some() => package.map(Ok),
+            Ok(()) => {
+                self.exhausted = true;
+                None
+            }
+            Err(e) => {
+                self.exhausted = true;
+                let context = package
+                    .filter(|pkg| !pkg.name.is_empty())
+                    .map(|pkg| pkg.nevra());
+                Some(Err(e.at_position(
+                    METADATA_PRIMARY,
+                    self.reader.buffer_position(),
+                    context,
+                )))
+            }
+        }
+    }
+}
+
+/// Decode text or an attribute value, which is only copied if it contains escape sequences.
+fn decode(bytes: Cow<[u8]>) -> Result<Cow<str>, MetadataError> {
+    match bytes {
+        Cow::Borrowed(bytes) if !bytes.contains(&b'&') => {
+            Ok(Cow::Borrowed(std::str::from_utf8(bytes)?))
+        }
+        bytes => {
+            let unescaped = quick_xml::escape::unescape(&bytes)
+                .map_err(quick_xml::Error::from)?
+                .into_owned();
+            Ok(Cow::Owned(
+                String::from_utf8(unescaped).map_err(|e| e.utf8_error())?,
+            ))
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use crate::{utils, Checksum, MetadataError};
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of decompressed metadata files, keyed by the checksum of the compressed file.
+///
+/// Decompressing large xz or zstd compressed metadata can take a significant amount of time. Tools
+/// which read the same metadata repeatedly can use a cache so that only the first read pays that cost.
+///
+/// Entries are written to a temporary file and atomically renamed into place, so multiple processes
+/// or threads can share a cache directory without locking. If a maximum size is set, the least
+/// recently used entries are evicted whenever a new entry is added.
+///
+/// The cache trusts that the checksum provided (usually from `repomd.xml`) matches the file - it does not
+/// verify the compressed file.
+#[derive(Clone, Debug)]
+pub struct MetadataCache {
+    path: PathBuf,
+    max_size: Option<u64>,
+}
+
+impl MetadataCache {
+    /// Create a cache using the directory at `path`, creating it if necessary.
+    pub fn new(path: &Path) -> Result<Self, MetadataError> {
+        fs::create_dir_all(path)?;
+        Ok(Self {
+            path: path.to_owned(),
+            max_size: None,
+        })
+    }
+
+    /// Limit the total size (in bytes) of the cached files.
+    pub fn with_max_size(self, max_size: u64) -> Self {
+        Self {
+            max_size: Some(max_size),
+            ..self
+        }
+    }
+
+    /// The directory containing the cached files.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Open the decompressed contents of the metadata file at `path`, which has the checksum `checksum`.
+    ///
+    /// If the decompressed file is not already in the cache, it is decompressed and added.
+    pub fn open(
+        &self,
+        path: &Path,
+        checksum: &Checksum,
+    ) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+        self.open_with(path, checksum, |file| Ok(Box::new(file)))
+    }
+
+    /// Like [`MetadataCache::open`], but the cached file is read using memory-mapped IO.
+    ///
+    /// Entries are never modified in place, so mapping them is safe as long as nothing else writes to
+    /// the cache directory.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(
+        &self,
+        path: &Path,
+        checksum: &Checksum,
+    ) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+        self.open_with(path, checksum, utils::mmap_reader)
+    }
+
+    fn open_with(
+        &self,
+        path: &Path,
+        checksum: &Checksum,
+        open: impl Fn(File) -> Result<Box<dyn io::Read + Send>, MetadataError>,
+    ) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+        let entry_path = self.entry_path(checksum);
+
+        match File::open(&entry_path) {
+            Ok(file) => {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(path = %path.display(), "metadata cache hit");
+                // update the modification time so that eviction is least-recently-used
+                let _ = file.set_modified(SystemTime::now());
+                return open(file);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(path = %path.display(), "metadata cache miss, decompressing");
+        self.insert(path, &entry_path)?;
+        self.evict()?;
+
+        // the entry could (in theory) have been evicted by a concurrent user in the meantime
+        match File::open(&entry_path) {
+            Ok(file) => open(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => utils::reader_from_file(path),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the least recently used entries until the cache is within its maximum size.
+    pub fn evict(&self) -> Result<(), MetadataError> {
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return Ok(()),
+        };
+
+        let mut entries = self.entries()?;
+        let mut total_size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(_, _, modified)| *modified);
+
+        for (path, size, _) in entries {
+            if total_size <= max_size {
+                break;
+            }
+            remove_if_exists(&path)?;
+            total_size -= size;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all entries from the cache.
+    pub fn clear(&self) -> Result<(), MetadataError> {
+        for (path, _, _) in self.entries()? {
+            remove_if_exists(&path)?;
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, checksum: &Checksum) -> PathBuf {
+        let checksum_type: &str = checksum.checksum_type().into();
+        self.path
+            .join(format!("{}-{}.xml", checksum_type, checksum.digest()))
+    }
+
+    fn insert(&self, path: &Path, entry_path: &Path) -> Result<(), MetadataError> {
+        let temp_path = self.path.join(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = (|| -> Result<(), MetadataError> {
+            let mut reader = utils::reader_from_file(path)?;
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            io::copy(&mut reader, &mut writer)?;
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            fs::rename(&temp_path, entry_path)?;
+            Ok(())
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// List the (path, size, modification time) of each cached file, excluding in-progress temporary files.
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>, MetadataError> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if metadata.is_file() {
+                entries.push((entry.path(), metadata.len(), metadata.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), MetadataError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! C-compatible API, enabled with the `capi` feature. See `include/rpmrepo_metadata.h`.
+//!
+//! All objects are opaque handles which must be released with the matching `*_free()` function.
+//! Strings returned by the library are newly allocated and must be released with `rpmrepo_string_free()`.
+//! Functions which can fail return a null pointer or a non-zero status, and the error message can be
+//! retrieved with `rpmrepo_last_error()`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::ptr;
+
+use crate::{
+    Checksum, ChecksumType, MetadataError, Package, PackageIterator, RepositoryReader,
+    RepositoryWriter,
+};
+
+pub struct RpmrepoReader(RepositoryReader);
+pub struct RpmrepoPackageIterator(PackageIterator);
+pub struct RpmrepoPackage(Package);
+pub struct RpmrepoWriter(RepositoryWriter);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn null_argument_error() -> MetadataError {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "null pointer argument").into()
+}
+
+/// Convert a result into a status code, storing the error message
+fn status(result: Result<(), MetadataError>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            set_last_error(err.to_string());
+            -1
+        }
+    }
+}
+
+/// Convert a result into a heap-allocated handle, storing the error message
+fn into_handle<T>(result: Result<T, MetadataError>) -> *mut T {
+    match result {
+        Ok(value) => Box::into_raw(Box::new(value)),
+        Err(err) => {
+            set_last_error(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(value: *const c_char) -> Result<&'a str, MetadataError> {
+    if value.is_null() {
+        return Err(null_argument_error());
+    }
+    Ok(CStr::from_ptr(value).to_str()?)
+}
+
+unsafe fn handle_arg<'a, T>(handle: *mut T) -> Result<&'a mut T, MetadataError> {
+    handle.as_mut().ok_or_else(null_argument_error)
+}
+
+fn string_result(value: &str) -> *mut c_char {
+    match CString::new(value) {
+        Ok(value) => value.into_raw(),
+        Err(_) => {
+            set_last_error("string contains a nul byte".to_owned());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The message of the last error which occurred on this thread, or null. Owned by the library.
+#[no_mangle]
+pub extern "C" fn rpmrepo_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Release a string returned by the library.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// Open the repository in the directory `path`.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_reader_open(path: *const c_char) -> *mut RpmrepoReader {
+    into_handle(
+        str_arg(path)
+            .and_then(|path| RepositoryReader::new_from_directory(Path::new(path)))
+            .map(RpmrepoReader),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_reader_free(reader: *mut RpmrepoReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Start iterating over the packages of a repository.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_reader_iter_packages(
+    reader: *mut RpmrepoReader,
+) -> *mut RpmrepoPackageIterator {
+    into_handle(
+        handle_arg(reader)
+            .and_then(|reader| reader.0.iter_packages())
+            .map(RpmrepoPackageIterator),
+    )
+}
+
+/// The number of packages declared by the repository metadata.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_iterator_total(
+    iterator: *mut RpmrepoPackageIterator,
+) -> usize {
+    iterator
+        .as_ref()
+        .map_or(0, |iterator| iterator.0.total_packages())
+}
+
+/// The next package, or null once all packages have been read or if an error occurred. Errors can
+/// be told apart from the end of the iteration by `rpmrepo_last_error()`, which is cleared by this call.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_iterator_next(
+    iterator: *mut RpmrepoPackageIterator,
+) -> *mut RpmrepoPackage {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+    match handle_arg(iterator).and_then(|iterator| iterator.0.parse_package()) {
+        Ok(Some(package)) => Box::into_raw(Box::new(RpmrepoPackage(package))),
+        Ok(None) => ptr::null_mut(),
+        Err(err) => {
+            set_last_error(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_iterator_free(iterator: *mut RpmrepoPackageIterator) {
+    if !iterator.is_null() {
+        drop(Box::from_raw(iterator));
+    }
+}
+
+/// Create an empty package.
+#[no_mangle]
+pub extern "C" fn rpmrepo_package_new() -> *mut RpmrepoPackage {
+    Box::into_raw(Box::new(RpmrepoPackage(Package::default())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_free(package: *mut RpmrepoPackage) {
+    if !package.is_null() {
+        drop(Box::from_raw(package));
+    }
+}
+
+unsafe fn package_string(
+    package: *const RpmrepoPackage,
+    field: fn(&Package) -> &str,
+) -> *mut c_char {
+    match package.as_ref() {
+        Some(package) => string_result(field(&package.0)),
+        None => {
+            set_last_error(null_argument_error().to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn set_package_string(
+    package: *mut RpmrepoPackage,
+    value: *const c_char,
+    set: fn(&mut Package, &str),
+) -> c_int {
+    status(handle_arg(package).and_then(|package| {
+        set(&mut package.0, str_arg(value)?);
+        Ok(())
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_name(package: *const RpmrepoPackage) -> *mut c_char {
+    package_string(package, Package::name)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_name(
+    package: *mut RpmrepoPackage,
+    name: *const c_char,
+) -> c_int {
+    set_package_string(package, name, |package, name| {
+        package.set_name(name);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_epoch(package: *const RpmrepoPackage) -> u32 {
+    package
+        .as_ref()
+        .and_then(|package| package.0.epoch().ok())
+        .unwrap_or(0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_epoch(
+    package: *mut RpmrepoPackage,
+    epoch: u32,
+) -> c_int {
+    status(handle_arg(package).map(|package| {
+        package.0.set_epoch(epoch);
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_version(package: *const RpmrepoPackage) -> *mut c_char {
+    package_string(package, Package::version)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_version(
+    package: *mut RpmrepoPackage,
+    version: *const c_char,
+) -> c_int {
+    set_package_string(package, version, |package, version| {
+        package.set_version(version);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_release(package: *const RpmrepoPackage) -> *mut c_char {
+    package_string(package, Package::release)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_release(
+    package: *mut RpmrepoPackage,
+    release: *const c_char,
+) -> c_int {
+    set_package_string(package, release, |package, release| {
+        package.set_release(release);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_arch(package: *const RpmrepoPackage) -> *mut c_char {
+    package_string(package, Package::arch)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_arch(
+    package: *mut RpmrepoPackage,
+    arch: *const c_char,
+) -> c_int {
+    set_package_string(package, arch, |package, arch| {
+        package.set_arch(arch);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_summary(package: *const RpmrepoPackage) -> *mut c_char {
+    package_string(package, Package::summary)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_summary(
+    package: *mut RpmrepoPackage,
+    summary: *const c_char,
+) -> c_int {
+    set_package_string(package, summary, |package, summary| {
+        package.set_summary(summary);
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_location_href(
+    package: *const RpmrepoPackage,
+) -> *mut c_char {
+    package_string(package, Package::location_href)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_location_href(
+    package: *mut RpmrepoPackage,
+    location_href: *const c_char,
+) -> c_int {
+    set_package_string(package, location_href, |package, location_href| {
+        package.set_location_href(location_href);
+    })
+}
+
+/// The package checksum (pkgid), as a hex string.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_pkgid(package: *const RpmrepoPackage) -> *mut c_char {
+    match package.as_ref() {
+        Some(package) if !package.0.pkgid().is_empty() => string_result(package.0.pkgid()),
+        Some(_) => {
+            set_last_error("the package has no checksum".to_owned());
+            ptr::null_mut()
+        }
+        None => {
+            set_last_error(null_argument_error().to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Set the package checksum, e.g. `("sha256", "6a915b6e...")`.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_package_set_checksum(
+    package: *mut RpmrepoPackage,
+    checksum_type: *const c_char,
+    checksum: *const c_char,
+) -> c_int {
+    status(handle_arg(package).and_then(|package| {
+        let checksum_type: ChecksumType = str_arg(checksum_type)?.try_into()?;
+        let checksum_type: &str = checksum_type.into();
+        let checksum = Checksum::try_create(checksum_type, str_arg(checksum)?)?;
+        package.0.set_checksum(checksum);
+        Ok(())
+    }))
+}
+
+/// Create a repository in the directory `path`, which will contain `num_pkgs` packages.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_writer_new(
+    path: *const c_char,
+    num_pkgs: usize,
+) -> *mut RpmrepoWriter {
+    into_handle(
+        str_arg(path)
+            .and_then(|path| RepositoryWriter::new(Path::new(path), num_pkgs))
+            .map(RpmrepoWriter),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_writer_add_package(
+    writer: *mut RpmrepoWriter,
+    package: *const RpmrepoPackage,
+) -> c_int {
+    status(handle_arg(writer).and_then(|writer| {
+        let package = package.as_ref().ok_or_else(null_argument_error)?;
+        writer.0.add_package(&package.0)
+    }))
+}
+
+/// Finish writing the repository metadata. This releases the writer, whether or not it succeeds.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_writer_finish(writer: *mut RpmrepoWriter) -> c_int {
+    if writer.is_null() {
+        return status(Err(null_argument_error()));
+    }
+    status(Box::from_raw(writer).0.finish())
+}
+
+/// Release a writer without finishing the repository.
+#[no_mangle]
+pub unsafe extern "C" fn rpmrepo_writer_free(writer: *mut RpmrepoWriter) {
+    if !writer.is_null() {
+        drop(Box::from_raw(writer));
+    }
+}
@@ -1,5 +1,7 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 /// A full RPM "version" specifier has 3 different components - Epoch, Version, and Release.
 ///
@@ -21,19 +23,22 @@ use std::fmt;
 /// without a caret, e.g. 0.5.0 vs 0.5.0~rc1. Including ^ in a version is used for denoting snapshots
 /// not directly associated with an upstream release and will force it to sort higher, e.g.
 /// 0.5.0 vs 0.5.0^deadbeef
-#[derive(Clone, Debug, Default, Eq, Hash)]
+///
+/// The overwhelmingly common epochs "0" and "" (and empty releases) are stored without a heap
+/// allocation, which adds up for repositories with hundreds of thousands of packages.
+#[derive(Clone, Debug, Default, Eq)]
 pub struct EVR {
-    pub epoch: String,
-    pub version: String,
-    pub release: String,
+    pub epoch: Cow<'static, str>,
+    pub version: Cow<'static, str>,
+    pub release: Cow<'static, str>,
 }
 
 impl EVR {
     pub fn new<T: Into<String>>(epoch: T, version: T, release: T) -> EVR {
         EVR {
-            epoch: epoch.into(),
-            version: version.into(),
-            release: release.into(),
+            epoch: evr_component(epoch),
+            version: evr_component(version),
+            release: evr_component(release),
         }
     }
 
@@ -50,7 +55,7 @@ impl EVR {
     }
 
     pub fn values(&self) -> (&str, &str, &str) {
-        (&self.epoch, &self.version, &self.release)
+        (self.epoch(), self.version(), self.release())
     }
 
     pub fn parse_values(evr: &str) -> (&str, &str, &str) {
@@ -65,6 +70,22 @@ impl EVR {
     }
 }
 
+/// Convert a string into the representation used for the fields of [`EVR`].
+///
+/// Strings built up by the parsers often have excess capacity, which would otherwise be kept alive for
+/// as long as the package is (https://github.com/rust-lang/rust/issues/107115), so it is released here.
+pub(crate) fn evr_component(value: impl Into<String>) -> Cow<'static, str> {
+    let mut value = value.into();
+    match value.as_str() {
+        "" => Cow::Borrowed(""),
+        "0" => Cow::Borrowed("0"),
+        _ => {
+            value.shrink_to_fit();
+            Cow::Owned(value)
+        }
+    }
+}
+
 impl From<(&str, &str, &str)> for EVR {
     fn from(val: (&str, &str, &str)) -> Self {
         EVR::new(val.0, val.1, val.2)
@@ -81,6 +102,20 @@ impl PartialEq for EVR {
     }
 }
 
+impl Hash for EVR {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // consistent with `eq`, which treats an empty epoch as "0"
+        let epoch: &str = if self.epoch.is_empty() {
+            "0"
+        } else {
+            &self.epoch
+        };
+        epoch.hash(state);
+        self.version.hash(state);
+        self.release.hash(state);
+    }
+}
+
 impl fmt::Display for EVR {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if !self.epoch.is_empty() {
@@ -102,12 +137,12 @@ impl Ord for EVR {
         let epoch_1 = if self.epoch.is_empty() {
             "0"
         } else {
-            &self.epoch
+            self.epoch()
         };
         let epoch_2 = if other.epoch.is_empty() {
             "0"
         } else {
-            &other.epoch
+            other.epoch()
         };
 
         let epoch_cmp = compare_version_string(epoch_1, epoch_2);
@@ -164,7 +199,7 @@ fn compare_version_string(version1: &str, version2: &str) -> Ordering {
                 true => return Ordering::Greater,
                 false => return Ordering::Less,
             },
-            (None, Some(_)) => match version1.is_empty() {
+            (None, Some(_)) => match version1_part.is_empty() {
                 true => return Ordering::Less,
                 false => return Ordering::Greater,
             },
@@ -204,10 +239,10 @@ fn compare_version_string(version1: &str, version2: &str) -> Ordering {
                     let (prefix2, version2) = b;
                     version1_part = version1;
                     version2_part = version2;
-                    let ordering = prefix1
-                        .trim_start_matches('0')
-                        .len()
-                        .cmp(&prefix2.trim_start_matches('0').len());
+                    // leading zeroes are insignificant, "0001" == "1"
+                    let prefix1 = prefix1.trim_start_matches('0');
+                    let prefix2 = prefix2.trim_start_matches('0');
+                    let ordering = prefix1.len().cmp(&prefix2.len());
                     if ordering != Ordering::Equal {
                         return ordering;
                     }
@@ -254,6 +289,18 @@ pub fn rpmvercmp(evr1: &str, evr2: &str) -> Ordering {
     evr1.cmp(&evr2)
 }
 
+/// Split a NEVRA string like `bash-0:5.1.16-3.fc36.x86_64` (or NVRA, without the epoch) into its name, EVR and arch
+pub(crate) fn split_nevra(nevra: &str) -> Option<(&str, EVR, &str)> {
+    let (nevr, arch) = nevra.rsplit_once('.')?;
+    let (nev, release) = nevr.rsplit_once('-')?;
+    let (name, ev) = nev.rsplit_once('-')?;
+    let (epoch, version) = ev.split_once(':').unwrap_or(("", ev));
+    if [name, version, release, arch].iter().any(|s| s.is_empty()) {
+        return None;
+    }
+    Some((name, EVR::new(epoch, version, release), arch))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -267,6 +314,19 @@ mod test {
         assert_eq!("0:1.2.3-45", evr.to_string());
     }
 
+    #[test]
+    fn test_evr_components() {
+        let evr = EVR::new("0", "1.2.3", "");
+        assert!(matches!(evr.epoch, Cow::Borrowed("0")));
+        assert!(matches!(evr.release, Cow::Borrowed("")));
+
+        let mut version = String::with_capacity(64);
+        version.push_str("1.2.3");
+        let evr = EVR::new(String::from("1"), version, String::from("45"));
+        assert_eq!(evr.values(), ("1", "1.2.3", "45"));
+        assert!(matches!(&evr.version, Cow::Owned(v) if v.capacity() == v.len()));
+    }
+
     #[test]
     fn test_evr_parse() {
         let evr = EVR::new("", "1.2.3", "45");
@@ -276,6 +336,17 @@ mod test {
         assert_eq!(EVR::parse("0:1.2.3-45"), evr);
     }
 
+    #[test]
+    fn test_evr_hash() {
+        use std::collections::HashSet;
+
+        // an empty epoch is equal to epoch 0, so they must hash the same
+        let evrs: HashSet<EVR> = [EVR::new("", "1.2.3", "45"), EVR::new("0", "1.2.3", "45")]
+            .into_iter()
+            .collect();
+        assert_eq!(evrs.len(), 1);
+    }
+
     #[test]
     fn test_rpmvercmp() {
         assert_eq!(Ordering::Equal, rpmvercmp("0:1.2.3-45", "1.2.3-45"));
@@ -458,4 +529,18 @@ mod test {
         assert_eq!(EVR::parse_values("asdf:"), ("asdf", "", ""));
         assert_eq!(EVR::parse_values("~:"), ("~", "", ""));
     }
+
+    #[test]
+    fn test_split_nevra() {
+        assert_eq!(
+            split_nevra("bash-completion-1:2.11-6.fc36.noarch"),
+            Some(("bash-completion", EVR::new("1", "2.11", "6.fc36"), "noarch"))
+        );
+        assert_eq!(
+            split_nevra("hello-2.10-1.fc36.x86_64"),
+            Some(("hello", EVR::new("", "2.10", "1.fc36"), "x86_64"))
+        );
+        assert_eq!(split_nevra("hello-2.10.x86_64"), None);
+        assert_eq!(split_nevra("hello-2.10-1"), None);
+    }
 }
@@ -0,0 +1,292 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Check the metadata generated by this library against the output of `createrepo_c`.
+//!
+//! Both tools generate metadata for the same directory of RPMs, and the packages are compared field by
+//! field. This is meant to be run in CI against real package sets.
+//!
+//! ```no_run
+//! # use std::path::Path;
+//! # use rpmrepo_metadata::conformance::ConformanceCheck;
+//! # fn main() -> Result<(), rpmrepo_metadata::MetadataError> {
+//! let report = ConformanceCheck::new(Path::new("./packages")).run(Path::new("./work"))?;
+//! match report {
+//!     Some(report) if report.is_conformant() => (),
+//!     Some(report) => panic!("{} divergences", report.divergences.len()),
+//!     None => eprintln!("createrepo_c is not installed, skipping"),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{
+    ChecksumType, Field, MetadataError, Package, RepositoryOptions, RepositoryReader,
+    RepositoryWriter,
+};
+
+/// Every field compared between the two repositories, in the order they are reported.
+const FIELDS: &[Field] = &[
+    Field::Name,
+    Field::Arch,
+    Field::Evr,
+    Field::Location,
+    Field::Summary,
+    Field::Description,
+    Field::Packager,
+    Field::Url,
+    Field::Time,
+    Field::Size,
+    Field::License,
+    Field::Vendor,
+    Field::Group,
+    Field::Buildhost,
+    Field::SourceRpm,
+    Field::HeaderRange,
+    Field::Provides,
+    Field::Requires,
+    Field::Conflicts,
+    Field::Obsoletes,
+    Field::Suggests,
+    Field::Enhances,
+    Field::Recommends,
+    Field::Supplements,
+    Field::Files,
+    Field::Changelogs,
+];
+
+/// Generates metadata for a directory of RPMs with both this library and `createrepo_c`, and compares them.
+#[derive(Clone, Debug)]
+pub struct ConformanceCheck {
+    packages_dir: PathBuf,
+    createrepo_c: PathBuf,
+    checksum_type: ChecksumType,
+}
+
+impl ConformanceCheck {
+    /// Check the metadata for the RPMs found (recursively) in `packages_dir`.
+    pub fn new(packages_dir: &Path) -> Self {
+        Self {
+            packages_dir: packages_dir.to_owned(),
+            createrepo_c: PathBuf::from("createrepo_c"),
+            checksum_type: ChecksumType::Sha256,
+        }
+    }
+
+    /// The `createrepo_c` executable to run. By default, it is looked up in `PATH`.
+    pub fn createrepo_c(self, path: &Path) -> Self {
+        Self {
+            createrepo_c: path.to_owned(),
+            ..self
+        }
+    }
+
+    /// The checksum type used for the packages by both tools. Default is [`ChecksumType::Sha256`].
+    pub fn checksum_type(self, checksum_type: ChecksumType) -> Self {
+        Self {
+            checksum_type,
+            ..self
+        }
+    }
+
+    /// Generate both repositories in subdirectories of `work_dir` and compare them.
+    ///
+    /// Returns `None` if `createrepo_c` is not installed.
+    pub fn run(&self, work_dir: &Path) -> Result<Option<ConformanceReport>, MetadataError> {
+        let ours = work_dir.join("rpmrepo_metadata");
+        let theirs = work_dir.join("createrepo_c");
+        for dir in [&ours, &theirs] {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir)?;
+            }
+            std::fs::create_dir_all(dir)?;
+        }
+
+        if !self.run_createrepo_c(&theirs)? {
+            return Ok(None);
+        }
+        self.run_writer(&ours)?;
+
+        let ours = packages_by_location(&ours)?;
+        let theirs = packages_by_location(&theirs)?;
+        Ok(Some(ConformanceReport::compare(&ours, &theirs)))
+    }
+
+    /// Returns `false` if the executable could not be found.
+    fn run_createrepo_c(&self, output_dir: &Path) -> Result<bool, MetadataError> {
+        let checksum_type: &str = self.checksum_type.into();
+        let result = Command::new(&self.createrepo_c)
+            .arg("--no-database")
+            .arg("--checksum")
+            .arg(checksum_type)
+            .arg("--outputdir")
+            .arg(output_dir)
+            .arg(&self.packages_dir)
+            .output();
+        let output = match result {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "createrepo_c failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+        Ok(true)
+    }
+
+    fn run_writer(&self, output_dir: &Path) -> Result<(), MetadataError> {
+        let mut rpms = Vec::new();
+        find_rpms(&self.packages_dir, &mut rpms)?;
+        rpms.sort();
+
+        let options = RepositoryOptions::default().package_checksum_type(self.checksum_type);
+        let mut writer = RepositoryWriter::new_with_options(output_dir, rpms.len(), options)?;
+        for path in &rpms {
+            let href = path
+                .strip_prefix(&self.packages_dir)
+                .unwrap_or(path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            writer.add_rpm_file(path, &href)?;
+        }
+        writer.finish()
+    }
+}
+
+/// A field which has a different value in the metadata generated by the two tools.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The location of the package, relative to the packages directory
+    pub location_href: String,
+    pub field: Field,
+    /// The value generated by this library, formatted for display
+    pub ours: String,
+    /// The value generated by `createrepo_c`, formatted for display
+    pub createrepo_c: String,
+}
+
+/// The differences between the metadata generated by this library and by `createrepo_c`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConformanceReport {
+    /// The number of packages present in both repositories
+    pub packages_compared: usize,
+    /// Locations of packages which only this library generated metadata for
+    pub only_ours: Vec<String>,
+    /// Locations of packages which only `createrepo_c` generated metadata for
+    pub only_createrepo_c: Vec<String>,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ConformanceReport {
+    fn compare(ours: &BTreeMap<String, Package>, theirs: &BTreeMap<String, Package>) -> Self {
+        let mut report = ConformanceReport::default();
+        for (href, our_pkg) in ours {
+            let their_pkg = match theirs.get(href) {
+                Some(pkg) => pkg,
+                None => {
+                    report.only_ours.push(href.clone());
+                    continue;
+                }
+            };
+            report.packages_compared += 1;
+            for field in FIELDS {
+                let (ours, createrepo_c) =
+                    (field_value(our_pkg, *field), field_value(their_pkg, *field));
+                if ours != createrepo_c {
+                    report.divergences.push(Divergence {
+                        location_href: href.clone(),
+                        field: *field,
+                        ours,
+                        createrepo_c,
+                    });
+                }
+            }
+        }
+        report.only_createrepo_c = theirs
+            .keys()
+            .filter(|href| !ours.contains_key(*href))
+            .cloned()
+            .collect();
+        report
+    }
+
+    /// Whether the metadata generated by both tools is identical.
+    pub fn is_conformant(&self) -> bool {
+        self.only_ours.is_empty()
+            && self.only_createrepo_c.is_empty()
+            && self.divergences.is_empty()
+    }
+}
+
+fn packages_by_location(path: &Path) -> Result<BTreeMap<String, Package>, MetadataError> {
+    let repo = RepositoryReader::new_from_directory(path)?.into_repo()?;
+    Ok(repo
+        .packages()
+        .values()
+        .map(|pkg| (pkg.location_href().to_owned(), pkg.clone()))
+        .collect())
+}
+
+fn find_rpms(dir: &Path, rpms: &mut Vec<PathBuf>) -> Result<(), MetadataError> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_rpms(&path, rpms)?;
+        } else if path.extension().is_some_and(|ext| ext == "rpm") {
+            rpms.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The value of `field`, formatted so that it can be compared and displayed.
+fn field_value(pkg: &Package, field: Field) -> String {
+    match field {
+        Field::Name => pkg.name().to_owned(),
+        Field::Arch => pkg.arch().to_owned(),
+        Field::Evr => pkg.evr().to_string(),
+        Field::Location => format!("{:?} {}", pkg.location_base(), pkg.location_href()),
+        Field::Summary => pkg.summary().to_owned(),
+        Field::Description => pkg.description().to_owned(),
+        Field::Packager => pkg.packager().to_owned(),
+        Field::Url => pkg.url().to_owned(),
+        Field::Time => format!("file {} build {}", pkg.time_file(), pkg.time_build()),
+        Field::Size => format!(
+            "package {} installed {} archive {}",
+            pkg.size_package(),
+            pkg.size_installed(),
+            pkg.size_archive()
+        ),
+        Field::License => pkg.rpm_license().to_owned(),
+        Field::Vendor => pkg.rpm_vendor().to_owned(),
+        Field::Group => pkg.rpm_group().to_owned(),
+        Field::Buildhost => pkg.rpm_buildhost().to_owned(),
+        Field::SourceRpm => pkg.rpm_sourcerpm().to_owned(),
+        Field::HeaderRange => format!("{:?}", pkg.rpm_header_range()),
+        Field::Provides => format!("{:?}", pkg.provides()),
+        Field::Requires => format!("{:?}", pkg.requires()),
+        Field::Conflicts => format!("{:?}", pkg.conflicts()),
+        Field::Obsoletes => format!("{:?}", pkg.obsoletes()),
+        Field::Suggests => format!("{:?}", pkg.suggests()),
+        Field::Enhances => format!("{:?}", pkg.enhances()),
+        Field::Recommends => format!("{:?}", pkg.recommends()),
+        Field::Supplements => format!("{:?}", pkg.supplements()),
+        Field::Files => format!("{:?}", pkg.files()),
+        Field::Changelogs => format!("{:?}", pkg.changelogs()),
+    }
+}
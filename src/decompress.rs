@@ -0,0 +1,208 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::MetadataError;
+
+/// A decompressor for a compression format which isn't supported out of the box.
+///
+/// Registered decompressors are consulted by everything that reads (possibly compressed) metadata -
+/// [`crate::RepositoryReader`], [`crate::MetadataCache`], downloaded repositories, tarballs and the
+/// `read_*` functions - before the built-in formats.
+///
+/// ```
+/// # use std::path::Path;
+/// use rpmrepo_metadata::{register_decompressor, LzmaDecompressor, RepositoryReader};
+///
+/// # fn main() -> Result<(), rpmrepo_metadata::MetadataError> {
+/// register_decompressor(LzmaDecompressor);
+/// let repo = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait Decompressor: Send + Sync {
+    /// A short name for the format, e.g. `"lzma"`.
+    fn name(&self) -> &str;
+
+    /// File extensions (including the leading `.`) used by the format, e.g. `".lzma"`.
+    ///
+    /// Extensions are only used to identify files whose content isn't recognized as any format, as they
+    /// are not available when decompressing a stream.
+    fn extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Magic numbers at the start of data compressed with the format.
+    fn magic(&self) -> &[&[u8]] {
+        &[]
+    }
+
+    /// Wrap a reader of compressed data into a reader of the decompressed data.
+    fn decompress(&self, reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>>;
+}
+
+static DECOMPRESSORS: RwLock<Vec<Arc<dyn Decompressor>>> = RwLock::new(Vec::new());
+
+/// Register a decompressor for use by all readers in the process.
+///
+/// Decompressors registered later take precedence over those registered earlier, and all of them take
+/// precedence over the built-in formats.
+pub fn register_decompressor<D: Decompressor + 'static>(decompressor: D) {
+    DECOMPRESSORS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Arc::new(decompressor));
+}
+
+/// Remove all registered decompressors with the given name. Returns whether any were removed.
+pub fn unregister_decompressor(name: &str) -> bool {
+    let mut decompressors = DECOMPRESSORS.write().unwrap_or_else(|e| e.into_inner());
+    let count = decompressors.len();
+    decompressors.retain(|d| d.name() != name);
+    decompressors.len() != count
+}
+
+fn registered() -> Vec<Arc<dyn Decompressor>> {
+    DECOMPRESSORS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// The number of header bytes needed to compare against all magic numbers.
+fn magic_len(decompressors: &[Arc<dyn Decompressor>]) -> usize {
+    decompressors
+        .iter()
+        .flat_map(|d| d.magic().iter().map(|magic| magic.len()))
+        .max()
+        .unwrap_or(0)
+}
+
+fn find_by_magic(
+    decompressors: &[Arc<dyn Decompressor>],
+    header: &[u8],
+) -> Option<Arc<dyn Decompressor>> {
+    decompressors
+        .iter()
+        .rev()
+        .find(|d| {
+            d.magic()
+                .iter()
+                .any(|magic| !magic.is_empty() && header.starts_with(magic))
+        })
+        .cloned()
+}
+
+fn find_for_file(
+    decompressors: &[Arc<dyn Decompressor>],
+    path: &Path,
+    header: &[u8],
+) -> Option<Arc<dyn Decompressor>> {
+    if let Some(decompressor) = find_by_magic(decompressors, header) {
+        return Some(decompressor);
+    }
+    // don't let an extension override what the content says it is
+    if is_builtin_format(header) {
+        return None;
+    }
+    let filename = path.file_name()?.to_string_lossy();
+    decompressors
+        .iter()
+        .rev()
+        .find(|d| d.extensions().iter().any(|ext| filename.ends_with(ext)))
+        .cloned()
+}
+
+/// Whether the header of a file matches the magic number of one of the built-in compression formats.
+pub(crate) fn is_builtin_format(header: &[u8]) -> bool {
+    header.starts_with(&[0x1f, 0x8b])
+        || header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd])
+        || header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00])
+        || header.starts_with(b"BZh")
+}
+
+/// Whether a file with the given name and header would be decompressed by a registered decompressor.
+pub(crate) fn is_registered_format(path: &Path, header: &[u8]) -> bool {
+    find_for_file(&registered(), path, header).is_some()
+}
+
+/// Open a file with a registered decompressor, if one handles it.
+pub(crate) fn open_registered(path: &Path) -> Result<Option<Box<dyn Read + Send>>, MetadataError> {
+    let decompressors = registered();
+    if decompressors.is_empty() {
+        return Ok(None);
+    }
+
+    let mut file = File::open(path)?;
+    let mut header = Vec::new();
+    (&mut file)
+        .take(magic_len(&decompressors).max(6) as u64)
+        .read_to_end(&mut header)?;
+
+    match find_for_file(&decompressors, path, &header) {
+        Some(decompressor) => {
+            file.seek(SeekFrom::Start(0))?;
+            Ok(Some(
+                decompressor.decompress(Box::new(BufReader::new(file)))?,
+            ))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Decompress a stream with a registered decompressor, if one recognizes its magic number.
+///
+/// Returns the reader and whether it was decompressed. Either way, the bytes inspected to identify the
+/// format are not lost.
+pub(crate) fn decompress_registered(
+    inner: Box<dyn Read + Send>,
+) -> Result<(Box<dyn Read + Send>, bool), MetadataError> {
+    let decompressors = registered();
+    if decompressors.is_empty() {
+        return Ok((inner, false));
+    }
+
+    let mut header = Vec::new();
+    let mut inner = inner;
+    (&mut inner)
+        .take(magic_len(&decompressors) as u64)
+        .read_to_end(&mut header)?;
+    let find = find_by_magic(&decompressors, &header);
+    let reader: Box<dyn Read + Send> = Box::new(io::Cursor::new(header).chain(inner));
+
+    match find {
+        Some(decompressor) => Ok((decompressor.decompress(reader)?, true)),
+        None => Ok((reader, false)),
+    }
+}
+
+/// A decompressor for the legacy `.lzma` format (LZMA_Alone), as used by some old repositories.
+///
+/// The format has no reliable magic number, so files are identified by their `.lzma` extension.
+/// It is not registered by default.
+#[cfg(feature = "compression")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LzmaDecompressor;
+
+#[cfg(feature = "compression")]
+impl Decompressor for LzmaDecompressor {
+    fn name(&self) -> &str {
+        "lzma"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[".lzma"]
+    }
+
+    fn decompress(&self, reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+        let stream = xz2::stream::Stream::new_lzma_decoder(u64::MAX)?;
+        Ok(Box::new(xz2::read::XzDecoder::new_stream(reader, stream)))
+    }
+}
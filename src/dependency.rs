@@ -0,0 +1,501 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::metadata::RequirementType;
+use crate::{MetadataError, Package, ParseError, Repository, Requirement, EVR};
+
+/// An index mapping capabilities to the packages which provide them.
+///
+/// Packages provide the capabilities listed in their "provides" as well as every file path they
+/// contain. Lookups follow the same rules as RPM for comparing versioned capabilities, and support
+/// rich (boolean) dependencies such as `(foo >= 1.0 with foo < 2.0)`.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{DependencyIndex, MetadataError, Repository};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// let index = DependencyIndex::new(&repo);
+/// let providers = index.whatprovides("(hello >= 2.0 with /usr/bin/hello)")?;
+/// assert_eq!(providers[0].name(), "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub struct DependencyIndex<'a> {
+    packages: Vec<&'a Package>,
+    provides: HashMap<&'a str, Vec<(usize, &'a Requirement)>>,
+    files: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> DependencyIndex<'a> {
+    /// Build an index of all packages in a [`Repository`].
+    pub fn new(repository: &'a Repository) -> Self {
+        Self::from_packages(repository.packages().values())
+    }
+
+    /// Build an index from any collection of packages, e.g. those collected from a [`crate::PackageIterator`].
+    pub fn from_packages<I: IntoIterator<Item = &'a Package>>(packages: I) -> Self {
+        let mut index = DependencyIndex {
+            packages: Vec::new(),
+            provides: HashMap::new(),
+            files: HashMap::new(),
+        };
+
+        for (idx, package) in packages.into_iter().enumerate() {
+            index.packages.push(package);
+            for provide in package.provides() {
+                index
+                    .provides
+                    .entry(&*provide.name)
+                    .or_default()
+                    .push((idx, provide));
+            }
+            for file in package.files() {
+                index.files.entry(file.path.as_str()).or_default().push(idx);
+            }
+        }
+
+        index
+    }
+
+    /// The packages which are part of this index.
+    pub fn packages(&self) -> &[&'a Package] {
+        &self.packages
+    }
+
+    /// Find the packages which provide a capability.
+    ///
+    /// The capability may be a bare name (`libfoo.so.1()(64bit)`), a file path (`/usr/bin/foo`),
+    /// a versioned capability (`foo >= 1:2.0-1`) or a rich dependency (`(foo if bar)`).
+    ///
+    /// For rich dependencies the result is the set of packages which could satisfy the dependency
+    /// by themselves: `and` and `with` produce the packages which provide both operands, `or` produces
+    /// the packages which provide either, `without` those which provide the first operand but not the
+    /// second, and `if` / `unless` those which provide the first operand (or the `else` operand).
+    pub fn whatprovides(&self, capability: &str) -> Result<Vec<&'a Package>, MetadataError> {
+        let dependency = RichDependency::parse(capability)?;
+        Ok(self.to_packages(self.resolve(&dependency)))
+    }
+
+    /// Find the packages which provide a [`Requirement`], as found in the metadata of a package.
+    pub fn whatprovides_requirement(
+        &self,
+        requirement: &Requirement,
+    ) -> Result<Vec<&'a Package>, MetadataError> {
+        let dependency = match requirement.rich_dependency()? {
+            Some(dependency) => dependency,
+            None => RichDependency::Simple(requirement.clone()),
+        };
+
+        Ok(self.to_packages(self.resolve(&dependency)))
+    }
+
+    fn to_packages(&self, indexes: BTreeSet<usize>) -> Vec<&'a Package> {
+        indexes.into_iter().map(|idx| self.packages[idx]).collect()
+    }
+
+    fn resolve(&self, dependency: &RichDependency) -> BTreeSet<usize> {
+        match dependency {
+            RichDependency::Simple(requirement) => {
+                self.resolve_simple(&requirement.name, requirement.constraint().as_ref())
+            }
+            RichDependency::And(a, b) | RichDependency::With(a, b) => {
+                &self.resolve(a) & &self.resolve(b)
+            }
+            RichDependency::Or(a, b) => &self.resolve(a) | &self.resolve(b),
+            RichDependency::Without(a, b) => &self.resolve(a) - &self.resolve(b),
+            RichDependency::If(a, _, otherwise) | RichDependency::Unless(a, _, otherwise) => {
+                let mut result = self.resolve(a);
+                if let Some(otherwise) = otherwise {
+                    result.extend(self.resolve(otherwise));
+                }
+                result
+            }
+        }
+    }
+
+    fn resolve_simple(
+        &self,
+        name: &str,
+        constraint: Option<&(RequirementType, EVR)>,
+    ) -> BTreeSet<usize> {
+        let mut result = BTreeSet::new();
+
+        if let Some(providers) = self.provides.get(name) {
+            for (idx, provide) in providers {
+                let matches = match (constraint, provide.constraint()) {
+                    (Some((flags, evr)), Some((provide_flags, provide_evr))) => {
+                        ranges_overlap(provide_flags, &provide_evr, *flags, evr)
+                    }
+                    // unversioned provides satisfy any version of the requirement
+                    _ => true,
+                };
+                if matches {
+                    result.insert(*idx);
+                }
+            }
+        }
+
+        if name.starts_with('/') && constraint.is_none() {
+            if let Some(owners) = self.files.get(name) {
+                result.extend(owners);
+            }
+        }
+
+        result
+    }
+}
+
+/// Returns (less, equal, greater) for a comparison operator
+fn sense(flags: RequirementType) -> (bool, bool, bool) {
+    match flags {
+        RequirementType::LT => (true, false, false),
+        RequirementType::LE => (true, true, false),
+        RequirementType::EQ => (false, true, false),
+        RequirementType::GE => (false, true, true),
+        RequirementType::GT => (false, false, true),
+    }
+}
+
+/// Determine whether a versioned provide satisfies a versioned requirement, i.e. whether the version
+/// ranges overlap, following the rules of `rpmdsCompare()`.
+pub(crate) fn ranges_overlap(
+    provide_flags: RequirementType,
+    provide_evr: &EVR,
+    flags: RequirementType,
+    evr: &EVR,
+) -> bool {
+    let mut provide_evr = provide_evr.clone();
+    let mut require_evr = evr.clone();
+    // the release is only compared if both sides specify one
+    if provide_evr.release.is_empty() || require_evr.release.is_empty() {
+        provide_evr.release = "".into();
+        require_evr.release = "".into();
+    }
+
+    let (p_less, p_equal, p_greater) = sense(provide_flags);
+    let (r_less, r_equal, r_greater) = sense(flags);
+
+    match provide_evr.cmp(&require_evr) {
+        Ordering::Less => p_greater || r_less,
+        Ordering::Greater => p_less || r_greater,
+        Ordering::Equal => (p_equal && r_equal) || (p_less && r_less) || (p_greater && r_greater),
+    }
+}
+
+/// A parsed rich (boolean) dependency, such as `(foo >= 1.0 if bar else baz)`.
+///
+/// Rich dependencies are stored in the metadata as the name of a [`Requirement`]. They can be parsed
+/// with [`RichDependency::parse`] or [`Requirement::rich_dependency`], and serialized back into a
+/// capability string with [`std::fmt::Display`] or into a [`Requirement`] with [`RichDependency::to_requirement`].
+///
+/// Chains of the same operator, e.g. `(a and b and c)`, are represented as left-nested operations.
+///
+/// ```
+/// # use rpmrepo_metadata::{MetadataError, RichDependency};
+/// # fn main() -> Result<(), MetadataError> {
+/// let dependency = RichDependency::parse("(foo if bar)")?;
+/// assert!(matches!(dependency, RichDependency::If(_, _, None)));
+/// assert_eq!(dependency.to_string(), "(foo if bar)");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum RichDependency {
+    /// A plain, possibly versioned, capability such as `foo >= 1.0`
+    Simple(Requirement),
+    /// `(a and b)`: both operands must be satisfied
+    And(Box<RichDependency>, Box<RichDependency>),
+    /// `(a or b)`: at least one of the operands must be satisfied
+    Or(Box<RichDependency>, Box<RichDependency>),
+    /// `(a with b)`: both operands must be satisfied by the same package
+    With(Box<RichDependency>, Box<RichDependency>),
+    /// `(a without b)`: the first operand must be satisfied by a package not satisfying the second
+    Without(Box<RichDependency>, Box<RichDependency>),
+    /// `(a if b else c)`: `a` is required if `b` is installed, otherwise `c` (if present)
+    If(
+        Box<RichDependency>,
+        Box<RichDependency>,
+        Option<Box<RichDependency>>,
+    ),
+    /// `(a unless b else c)`: `a` is required unless `b` is installed, otherwise `c` (if present)
+    Unless(
+        Box<RichDependency>,
+        Box<RichDependency>,
+        Option<Box<RichDependency>>,
+    ),
+}
+
+impl RichDependency {
+    /// Parse a dependency expression. Both rich dependencies and plain capabilities are accepted,
+    /// the latter producing [`RichDependency::Simple`]. Expressions nested more than 64 levels deep
+    /// are rejected.
+    pub fn parse(dependency: &str) -> Result<Self, MetadataError> {
+        let tokens = tokenize(dependency);
+        let error = |reason: &str| {
+            MetadataError::from(ParseError::InvalidDependency(
+                dependency.to_owned(),
+                reason.to_owned(),
+            ))
+        };
+
+        let mut pos = 0;
+        let parsed = parse_term(&tokens, &mut pos, 0).map_err(error)?;
+        if pos != tokens.len() {
+            return Err(error("unexpected trailing input"));
+        }
+        Ok(parsed)
+    }
+
+    /// Whether this is a boolean expression rather than a plain capability.
+    pub fn is_rich(&self) -> bool {
+        !matches!(self, RichDependency::Simple(_))
+    }
+
+    /// Convert the dependency into a [`Requirement`] as it would be stored in the metadata.
+    ///
+    /// Plain capabilities produce the requirement itself, rich dependencies a requirement whose
+    /// name is the serialized expression.
+    pub fn to_requirement(&self) -> Requirement {
+        match self {
+            RichDependency::Simple(requirement) => requirement.clone(),
+            _ => Requirement {
+                name: self.to_string().into(),
+                ..Requirement::default()
+            },
+        }
+    }
+
+    /// All of the plain capabilities referenced by the dependency, in order of appearance.
+    pub fn requirements(&self) -> Vec<&Requirement> {
+        let mut requirements = Vec::new();
+        self.collect_requirements(&mut requirements);
+        requirements
+    }
+
+    fn collect_requirements<'a>(&'a self, requirements: &mut Vec<&'a Requirement>) {
+        match self {
+            RichDependency::Simple(requirement) => requirements.push(requirement),
+            RichDependency::And(a, b)
+            | RichDependency::Or(a, b)
+            | RichDependency::With(a, b)
+            | RichDependency::Without(a, b) => {
+                a.collect_requirements(requirements);
+                b.collect_requirements(requirements);
+            }
+            RichDependency::If(a, b, otherwise) | RichDependency::Unless(a, b, otherwise) => {
+                a.collect_requirements(requirements);
+                b.collect_requirements(requirements);
+                if let Some(otherwise) = otherwise {
+                    otherwise.collect_requirements(requirements);
+                }
+            }
+        }
+    }
+
+    fn operator(&self) -> Option<&'static str> {
+        match self {
+            RichDependency::Simple(_) => None,
+            RichDependency::And(_, _) => Some("and"),
+            RichDependency::Or(_, _) => Some("or"),
+            RichDependency::With(_, _) => Some("with"),
+            RichDependency::Without(_, _) => Some("without"),
+            RichDependency::If(_, _, _) => Some("if"),
+            RichDependency::Unless(_, _, _) => Some("unless"),
+        }
+    }
+
+    /// Write the expression without the enclosing parentheses
+    fn fmt_inner(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = self.operator().unwrap_or_default();
+        match self {
+            RichDependency::Simple(requirement) => write!(f, "{}", requirement),
+            RichDependency::And(a, b)
+            | RichDependency::Or(a, b)
+            | RichDependency::With(a, b)
+            | RichDependency::Without(a, b) => {
+                // chains such as "(a and b and c)" don't need additional parentheses
+                if operator != "without" && a.operator() == Some(operator) {
+                    a.fmt_inner(f)?;
+                } else {
+                    write!(f, "{}", a)?;
+                }
+                write!(f, " {} {}", operator, b)
+            }
+            RichDependency::If(a, b, otherwise) | RichDependency::Unless(a, b, otherwise) => {
+                write!(f, "{} {} {}", a, operator, b)?;
+                if let Some(otherwise) = otherwise {
+                    write!(f, " else {}", otherwise)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for RichDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RichDependency::Simple(requirement) => write!(f, "{}", requirement),
+            _ => {
+                write!(f, "(")?;
+                self.fmt_inner(f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+const RICH_OPERATORS: &[&str] = &["and", "or", "if", "else", "with", "without", "unless"];
+
+/// How deeply dependencies may be nested, so that untrusted metadata can't exhaust the stack. Each
+/// operator of a chain such as `(a and b and c)` nests the expression one level deeper as well.
+const MAX_DEPTH: usize = 64;
+
+/// Split a dependency into tokens. Parentheses are separate tokens, except when they are part of a
+/// capability name such as `libfoo.so.1()(64bit)`.
+fn tokenize(dependency: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut depth = 0;
+
+    for (idx, c) in dependency.char_indices() {
+        match (c, start) {
+            (c, Some(s)) if c.is_whitespace() => {
+                tokens.push(&dependency[s..idx]);
+                start = None;
+                depth = 0;
+            }
+            (c, None) if c.is_whitespace() => (),
+            ('(', None) | (')', None) => tokens.push(&dependency[idx..idx + 1]),
+            ('(', Some(_)) => depth += 1,
+            (')', Some(s)) if depth == 0 => {
+                tokens.push(&dependency[s..idx]);
+                tokens.push(&dependency[idx..idx + 1]);
+                start = None;
+            }
+            (')', Some(_)) => depth -= 1,
+            (_, None) => start = Some(idx),
+            (_, Some(_)) => (),
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&dependency[s..]);
+    }
+
+    tokens
+}
+
+fn parse_term(
+    tokens: &[&str],
+    pos: &mut usize,
+    depth: usize,
+) -> Result<RichDependency, &'static str> {
+    match tokens.get(*pos) {
+        Some(&"(") => parse_rich(tokens, pos, depth + 1),
+        Some(_) => parse_simple(tokens, pos),
+        None => Err("unexpected end of input"),
+    }
+}
+
+fn parse_simple(tokens: &[&str], pos: &mut usize) -> Result<RichDependency, &'static str> {
+    let name = match tokens.get(*pos) {
+        Some(&name) if name != ")" && !RICH_OPERATORS.contains(&name) => name,
+        _ => return Err("expected a capability name"),
+    };
+    *pos += 1;
+
+    let flags = match tokens
+        .get(*pos)
+        .and_then(|op| RequirementType::from_operator(op))
+    {
+        Some(flags) => flags,
+        None => {
+            return Ok(RichDependency::Simple(Requirement::from_parts(
+                name, None, "",
+            )))
+        }
+    };
+    *pos += 1;
+
+    let evr = match tokens.get(*pos) {
+        Some(&evr) if evr != "(" && evr != ")" => evr,
+        _ => return Err("expected a version after comparison operator"),
+    };
+    *pos += 1;
+
+    Ok(RichDependency::Simple(Requirement::from_parts(
+        name,
+        Some(flags),
+        evr,
+    )))
+}
+
+fn parse_rich(
+    tokens: &[&str],
+    pos: &mut usize,
+    mut depth: usize,
+) -> Result<RichDependency, &'static str> {
+    if depth > MAX_DEPTH {
+        return Err("dependency is nested too deeply");
+    }
+    // opening parenthesis
+    *pos += 1;
+    let mut dependency = parse_term(tokens, pos, depth)?;
+    let mut operator: Option<&str> = None;
+
+    loop {
+        let token = match tokens.get(*pos) {
+            Some(&")") => {
+                *pos += 1;
+                return Ok(dependency);
+            }
+            Some(&token) if RICH_OPERATORS.contains(&token) && token != "else" => token,
+            Some(_) => return Err("expected a boolean operator"),
+            None => return Err("missing closing parenthesis"),
+        };
+        *pos += 1;
+
+        // only "and", "or" and "with" may be chained, other operators must be wrapped in parentheses
+        if let Some(previous) = operator {
+            if previous != token || !matches!(token, "and" | "or" | "with") {
+                return Err("different boolean operators must be separated by parentheses");
+            }
+        }
+        operator = Some(token);
+
+        depth += 1;
+        if depth > MAX_DEPTH {
+            return Err("dependency is nested too deeply");
+        }
+        let lhs = Box::new(dependency);
+        let rhs = Box::new(parse_term(tokens, pos, depth)?);
+        dependency = match token {
+            "and" => RichDependency::And(lhs, rhs),
+            "or" => RichDependency::Or(lhs, rhs),
+            "with" => RichDependency::With(lhs, rhs),
+            "without" => RichDependency::Without(lhs, rhs),
+            "if" | "unless" => {
+                let otherwise = if tokens.get(*pos) == Some(&"else") {
+                    *pos += 1;
+                    Some(Box::new(parse_term(tokens, pos, depth)?))
+                } else {
+                    None
+                };
+                if token == "if" {
+                    RichDependency::If(lhs, rhs, otherwise)
+                } else {
+                    RichDependency::Unless(lhs, rhs, otherwise)
+                }
+            }
+            _ => unreachable!(),
+        };
+    }
+}
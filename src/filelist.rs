@@ -9,12 +9,10 @@ use std::io::{BufRead, Write};
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Reader, Writer};
 
-use crate::Checksum;
-
 use super::metadata::{
-    FileType, FilelistsXml, Package, PackageFile, RpmMetadata, XML_NS_FILELISTS,
+    FileType, FilelistsXml, Package, PackageFile, RpmMetadata, METADATA_FILELISTS, XML_NS_FILELISTS,
 };
-use super::{MetadataError, Repository, EVR};
+use super::{utils, MetadataError, ParseError, Repository, EVR};
 
 const TAG_FILELISTS: &[u8] = b"filelists";
 const TAG_PACKAGE: &[u8] = b"package";
@@ -79,6 +77,20 @@ pub struct FilelistsXmlWriter<W: Write> {
 
 impl<W: Write> FilelistsXmlWriter<W> {
     pub fn write_header(&mut self, num_pkgs: usize) -> Result<(), MetadataError> {
+        self.write_header_with_padding(num_pkgs, 0)
+    }
+
+    /// Write the header, reserving enough space for the package count to be corrected in place later
+    /// with [`utils::rewrite_package_count`], if the output is seekable and uncompressed.
+    pub fn write_resizable_header(&mut self, num_pkgs: usize) -> Result<(), MetadataError> {
+        self.write_header_with_padding(num_pkgs, utils::PACKAGE_COUNT_PADDING)
+    }
+
+    fn write_header_with_padding(
+        &mut self,
+        num_pkgs: usize,
+        padding: usize,
+    ) -> Result<(), MetadataError> {
         // <?xml version="1.0" encoding="UTF-8"?>
         self.writer
             .write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
@@ -87,8 +99,8 @@ impl<W: Write> FilelistsXmlWriter<W> {
         let mut filelists_tag = BytesStart::borrowed_name(TAG_FILELISTS);
         filelists_tag.push_attribute(("xmlns", XML_NS_FILELISTS));
         filelists_tag.push_attribute(("packages", num_pkgs.to_string().as_str()));
-        self.writer
-            .write_event(Event::Start(filelists_tag.to_borrowed()))?;
+        let filelists_tag = utils::pad_start_tag(filelists_tag, padding);
+        self.writer.write_event(Event::Start(filelists_tag))?;
 
         Ok(())
     }
@@ -97,6 +109,9 @@ impl<W: Write> FilelistsXmlWriter<W> {
         // <package pkgid="a2d3bce512f79b0bc840ca7912a86bbc0016cf06d5c363ffbb6fd5e1ef03de1b" name="fontconfig" arch="x86_64">
         let mut package_tag = BytesStart::borrowed_name(TAG_PACKAGE);
         let pkgid = package.pkgid();
+        if pkgid.is_empty() {
+            return Err(ParseError::MissingField("pkgid").into());
+        }
         package_tag.push_attribute(("pkgid", pkgid));
         package_tag.push_attribute(("name", package.name()));
         package_tag.push_attribute(("arch", package.arch()));
@@ -124,6 +139,13 @@ impl<W: Write> FilelistsXmlWriter<W> {
         Ok(())
     }
 
+    /// Write a pre-serialized `<package>` element of filelists.xml, without parsing it.
+    ///
+    /// See [`crate::RepositoryWriter::add_package_raw`].
+    pub fn write_package_raw(&mut self, fragment: &str) -> Result<(), MetadataError> {
+        utils::write_raw_package(&mut self.writer, fragment)
+    }
+
     pub fn finish(&mut self) -> Result<(), MetadataError> {
         // </filelists>
         self.writer
@@ -168,8 +190,35 @@ impl<R: BufRead> FilelistsXmlReader<R> {
         parse_header(&mut self.reader)
     }
 
-    pub fn read_package(&mut self, package: &mut Option<Package>) -> Result<(), MetadataError> {
-        parse_package(package, &mut self.reader)
+    /// Read the next package from the metadata into `package`.
+    ///
+    /// Returns `false` if there were no more packages to read.
+    pub fn read_package(&mut self, package: &mut Option<Package>) -> Result<bool, MetadataError> {
+        parse_package(package, &mut self.reader).map_err(|e| {
+            e.at_package_position(
+                METADATA_FILELISTS,
+                self.reader.buffer_position(),
+                package.as_ref(),
+            )
+        })
+    }
+
+    /// Like [`FilelistsXmlReader::read_package`], but only keep the files for which `keep` returns true.
+    pub(crate) fn read_package_with_files<F>(
+        &mut self,
+        package: &mut Option<Package>,
+        keep: F,
+    ) -> Result<bool, MetadataError>
+    where
+        F: FnMut(&PackageFile) -> bool,
+    {
+        parse_package_with_files(package, &mut self.reader, keep).map_err(|e| {
+            e.at_package_position(
+                METADATA_FILELISTS,
+                self.reader.buffer_position(),
+                package.as_ref(),
+            )
+        })
     }
 }
 
@@ -183,10 +232,13 @@ fn parse_header<R: BufRead>(reader: &mut Reader<R>) -> Result<usize, MetadataErr
         match reader.read_event(&mut buf)? {
             Event::Decl(_) => (),
             Event::Start(e) if e.name().as_ref() == TAG_FILELISTS => {
-                let count = e.try_get_attribute("packages")?.unwrap().value;
+                let count = e
+                    .try_get_attribute("packages")?
+                    .ok_or(ParseError::MissingAttribute("packages"))?
+                    .value;
                 return Ok(std::str::from_utf8(&count)?.parse()?);
             }
-            _ => return Err(MetadataError::MissingHeaderError),
+            _ => return Err(ParseError::MissingHeader.into()),
         }
     }
 }
@@ -200,8 +252,20 @@ fn parse_header<R: BufRead>(reader: &mut Reader<R>) -> Result<usize, MetadataErr
 pub fn parse_package<R: BufRead>(
     package: &mut Option<Package>,
     reader: &mut Reader<R>,
-) -> Result<(), MetadataError> {
+) -> Result<bool, MetadataError> {
+    parse_package_with_files(package, reader, |_| true)
+}
+
+fn parse_package_with_files<R: BufRead, F>(
+    package: &mut Option<Package>,
+    reader: &mut Reader<R>,
+    mut keep: F,
+) -> Result<bool, MetadataError>
+where
+    F: FnMut(&PackageFile) -> bool,
+{
     let mut buf = Vec::with_capacity(128);
+    let mut found_package = false;
 
     loop {
         match reader.read_event(&mut buf)? {
@@ -211,47 +275,56 @@ pub fn parse_package<R: BufRead>(
                 TAG_PACKAGE => {
                     let pkgid = e
                         .try_get_attribute("pkgid")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("pkgid"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("pkgid"))?
                         .unescape_and_decode_value(reader)?;
                     let name = e
                         .try_get_attribute("name")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("name"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("name"))?
                         .unescape_and_decode_value(reader)?;
                     let arch = e
                         .try_get_attribute("arch")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("arch"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("arch"))?
                         .unescape_and_decode_value(reader)?;
 
+                    found_package = true;
+
                     if let Some(pkg) = package {
-                        assert!(pkg.pkgid() == &pkgid); // TODO err instead of assert
+                        if pkg.pkgid() != pkgid {
+                            return Err(ParseError::InconsistentMetadata(format!(
+                                "expected package with pkgid {}, found {}",
+                                pkg.pkgid(),
+                                pkgid
+                            ))
+                            .into());
+                        }
                     } else {
                         let mut pkg = Package::default();
-                        pkg.set_name(&name)
-                            .set_arch(&arch)
-                            .set_checksum(Checksum::Unknown(pkgid));
+                        pkg.set_name(&name).set_arch(arch.as_str()).set_pkgid(pkgid);
                         *package = Some(pkg);
                     };
                 }
                 TAG_VERSION => {
-                    package.as_mut().unwrap().set_evr(parse_evr(reader, &e)?);
+                    utils::enclosing(package, TAG_VERSION)?.set_evr(parse_evr(reader, &e)?);
                 }
                 TAG_FILE => {
                     let file = parse_file(reader, &e)?;
+                    if !keep(&file) {
+                        continue;
+                    }
                     // TODO: temporary PackageFile?
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .add_file(file.filetype, &file.path);
+                    utils::enclosing(package, TAG_FILE)?.add_file(file.filetype, &file.path);
                 }
                 _ => (),
             },
+            // the input ended in the middle of a package
+            Event::Eof if found_package => return Err(utils::unexpected_eof(TAG_PACKAGE)),
             Event::Eof => break,
             _ => (),
         }
     }
 
     // package.parse_state |= ParseState::FILELISTS;
-    Ok(())
+    Ok(found_package)
 }
 
 // <version epoch="0" ver="2.8.0" rel="5.fc33"/>
@@ -261,15 +334,15 @@ pub fn parse_evr<R: BufRead>(
 ) -> Result<EVR, MetadataError> {
     let epoch = open_tag
         .try_get_attribute("epoch")?
-        .ok_or_else(|| MetadataError::MissingAttributeError("epoch"))?
+        .ok_or_else(|| ParseError::MissingAttribute("epoch"))?
         .unescape_and_decode_value(reader)?;
     let version = open_tag
         .try_get_attribute("ver")?
-        .ok_or_else(|| MetadataError::MissingAttributeError("ver"))?
+        .ok_or_else(|| ParseError::MissingAttribute("ver"))?
         .unescape_and_decode_value(reader)?;
     let release = open_tag
         .try_get_attribute("rel")?
-        .ok_or_else(|| MetadataError::MissingAttributeError("rel"))?
+        .ok_or_else(|| ParseError::MissingAttribute("rel"))?
         .unescape_and_decode_value(reader)?;
 
     Ok(EVR::new(epoch, version, release))
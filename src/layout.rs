@@ -0,0 +1,154 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::path::Path;
+
+use crate::{MetadataError, RepositoryWriter};
+
+/// How a package file is placed at its new location.
+///
+/// Hardlinks and reflinks fall back to copying the file if they are not possible, e.g. because the
+/// locations are on different filesystems or the filesystem doesn't support them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TransferMode {
+    /// Move the file. Falls back to copying and removing the original if the new location is on another
+    /// filesystem.
+    #[default]
+    Move,
+    /// Hardlink the file, so that it is stored on disk only once.
+    Hardlink,
+    /// Copy the file.
+    Copy,
+    /// Clone the file copy-on-write on filesystems which support it, such as Btrfs and XFS. It only takes
+    /// up disk space once modified, but unlike a hardlink, modifying it doesn't affect the original.
+    /// Requires the `reflink` feature, otherwise the file is copied.
+    Reflink,
+}
+
+/// The location of a package in the Fedora-style layout, e.g. `Packages/b/bash-5.1.16-3.fc36.x86_64.rpm`.
+///
+/// Packages are grouped by the lowercased first character of their filename.
+pub fn package_layout_href(filename: &str) -> String {
+    let letter = filename
+        .chars()
+        .next()
+        .map_or_else(String::new, |c| c.to_lowercase().collect());
+    format!("Packages/{}/{}", letter, filename)
+}
+
+/// Place the file at `source` at `destination` according to `mode`, replacing any existing file there.
+///
+/// Returns how the file was actually placed, which is [`TransferMode::Copy`] if linking it failed.
+pub(crate) fn transfer_file(
+    source: &Path,
+    destination: &Path,
+    mode: TransferMode,
+) -> Result<TransferMode, MetadataError> {
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if destination.exists() {
+        // the file is already in place, e.g. when re-running on an organized repository
+        if source.canonicalize()? == destination.canonicalize()? {
+            return Ok(mode);
+        }
+        std::fs::remove_file(destination)?;
+    }
+
+    match mode {
+        TransferMode::Move => {
+            if std::fs::rename(source, destination).is_err() {
+                // rename() can't move files across filesystems
+                std::fs::copy(source, destination)?;
+                std::fs::remove_file(source)?;
+            }
+            Ok(TransferMode::Move)
+        }
+        TransferMode::Hardlink => match std::fs::hard_link(source, destination) {
+            Ok(()) => Ok(TransferMode::Hardlink),
+            Err(_) => copy_file(source, destination),
+        },
+        TransferMode::Reflink => reflink_file(source, destination),
+        TransferMode::Copy => copy_file(source, destination),
+    }
+}
+
+fn copy_file(source: &Path, destination: &Path) -> Result<TransferMode, MetadataError> {
+    std::fs::copy(source, destination)?;
+    Ok(TransferMode::Copy)
+}
+
+#[cfg(feature = "reflink")]
+fn reflink_file(source: &Path, destination: &Path) -> Result<TransferMode, MetadataError> {
+    match reflink_copy::reflink(source, destination) {
+        Ok(()) => Ok(TransferMode::Reflink),
+        Err(_) => copy_file(source, destination),
+    }
+}
+
+#[cfg(not(feature = "reflink"))]
+fn reflink_file(source: &Path, destination: &Path) -> Result<TransferMode, MetadataError> {
+    copy_file(source, destination)
+}
+
+impl RepositoryWriter {
+    /// Place an RPM file in the Fedora-style `Packages/<first letter>/` layout of the repository, and
+    /// return its new `location_href`. See [`package_layout_href`].
+    ///
+    /// An existing file at the new location is replaced. Nothing is done if the file is already in place.
+    pub fn organize_rpm_file(
+        &self,
+        path: &Path,
+        mode: TransferMode,
+    ) -> Result<String, MetadataError> {
+        let filename = path
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not a file", path.display()),
+                )
+            })?
+            .to_string_lossy();
+        let href = package_layout_href(&filename);
+        transfer_file(path, &self.destination().join(&href), mode)?;
+        Ok(href)
+    }
+
+    /// Read an RPM file, place it in the Fedora-style `Packages/<first letter>/` layout of the repository
+    /// and add it to the repo metadata with the matching `location_href`, which is returned.
+    ///
+    /// The file is only placed once it has been read successfully. See [`RepositoryWriter::organize_rpm_file`]
+    /// and [`RepositoryWriter::add_rpm_file`].
+    ///
+    /// ```no_run
+    /// # use std::path::{Path, PathBuf};
+    /// # use rpmrepo_metadata::{MetadataError, RepositoryWriter, TransferMode};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let repo_path = Path::new("/srv/repos/hello");
+    /// # let rpms = vec![PathBuf::from("/srv/incoming/hello-2.10-1.fc36.x86_64.rpm")];
+    /// let mut writer = RepositoryWriter::new(repo_path, rpms.len())?;
+    /// for rpm in &rpms {
+    ///     writer.add_rpm_file_organized(rpm, TransferMode::Hardlink)?;
+    /// }
+    /// writer.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "read_rpm")]
+    pub fn add_rpm_file_organized(
+        &mut self,
+        path: &Path,
+        mode: TransferMode,
+    ) -> Result<String, MetadataError> {
+        let mut pkg = self.read_rpm_file(path, "")?;
+        let href = self.organize_rpm_file(path, mode)?;
+        pkg.set_location_href(href.as_str());
+        self.add_package(&pkg)?;
+        Ok(href)
+    }
+}
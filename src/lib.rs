@@ -4,27 +4,201 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+//! Read and write RPM repository metadata (`repomd.xml`, `primary.xml`, `filelists.xml`,
+//! `other.xml` and `updateinfo.xml`).
+//!
+//! The examples below use the small repository bundled at `tests/assets/doctest_repo`.
+//!
+//! # Streaming packages from a repository
+//!
+//! [`RepositoryReader`] parses packages one at a time, so memory usage stays flat no matter how
+//! large the repository is.
+//!
+//! ```
+//! # use std::path::Path;
+//! # use rpmrepo_metadata::{MetadataError, RepositoryReader};
+//! # fn main() -> Result<(), MetadataError> {
+//! let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+//!
+//! let mut names = Vec::new();
+//! for pkg in reader.iter_packages()? {
+//!     let pkg = pkg?;
+//!     names.push(pkg.nevra());
+//! }
+//! assert_eq!(
+//!     names,
+//!     [
+//!         "hello-0:2.10-1.fc36.x86_64",
+//!         "hello-docs-0:2.10-1.fc36.noarch",
+//!         "goodbye-0:1.0-3.fc36.noarch",
+//!     ]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Filtering packages
+//!
+//! The package iterator is an ordinary [`Iterator`], so packages can be filtered as they are read.
+//!
+//! ```
+//! # use std::path::Path;
+//! # use rpmrepo_metadata::{MetadataError, Package, RepositoryReader};
+//! # fn main() -> Result<(), MetadataError> {
+//! let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+//!
+//! let noarch: Vec<Package> = reader
+//!     .iter_packages()?
+//!     .filter(|pkg| pkg.as_ref().map_or(true, |pkg| pkg.arch() == "noarch"))
+//!     .collect::<Result<_, _>>()?;
+//!
+//! assert_eq!(noarch.len(), 2);
+//! assert_eq!(noarch[0].name(), "hello-docs");
+//! assert_eq!(noarch[0].requires()[0].to_string(), "hello = 2.10-1.fc36");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Writing a new repository
+//!
+//! [`RepositoryWriter`] needs to know up front how many packages will be written. The output
+//! is configured with [`RepositoryOptions`]. Advisories may be added alongside the packages.
+//!
+//! ```
+//! # use std::path::Path;
+//! # use rpmrepo_metadata::{
+//! #     CompressionType, MetadataError, RepositoryOptions, RepositoryReader, RepositoryWriter,
+//! # };
+//! # fn main() -> Result<(), MetadataError> {
+//! let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+//! let packages = reader
+//!     .iter_packages()?
+//!     .filter(|pkg| pkg.as_ref().map_or(true, |pkg| pkg.name() != "goodbye"))
+//!     .collect::<Result<Vec<_>, _>>()?;
+//!
+//! let out_dir = tempdir::TempDir::new("doctest_writer")?;
+//! let options = RepositoryOptions::default()
+//!     .metadata_compression_type(CompressionType::Zstd)
+//!     .simple_metadata_filenames(true)
+//!     .changelog_limit(Some(1));
+//!
+//! let mut writer = RepositoryWriter::new_with_options(out_dir.path(), packages.len(), options)?;
+//! for pkg in &packages {
+//!     writer.add_package(pkg)?;
+//! }
+//! for advisory in reader.iter_advisories()? {
+//!     writer.add_advisory(&advisory?)?;
+//! }
+//! writer.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Adding advisories and verifying the output
+//!
+//! [`Repository`] loads the whole repository into memory, which is convenient for making
+//! changes and for checking what was written.
+//!
+//! ```
+//! # use std::path::Path;
+//! # use rpmrepo_metadata::{MetadataError, Repository, UpdateRecord};
+//! # fn main() -> Result<(), MetadataError> {
+//! let mut repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+//! assert_eq!(repo.packages().len(), 3);
+//! assert!(repo.advisories().contains_key("EXAMPLE-2022-0001"));
+//!
+//! let mut advisory = UpdateRecord::default();
+//! advisory.id = "EXAMPLE-2022-0002".to_owned();
+//! advisory.update_type = "bugfix".to_owned();
+//! advisory.title = "goodbye bugfix update".to_owned();
+//! repo.add_advisory(advisory)?;
+//!
+//! let out_dir = tempdir::TempDir::new("doctest_advisories")?;
+//! repo.write_to_directory(out_dir.path())?;
+//!
+//! let written = Repository::load_from_directory(out_dir.path())?;
+//! assert!(written.packages().keys().eq(repo.packages().keys()));
+//! assert_eq!(written.advisories().len(), 2);
+//! assert_eq!(
+//!     written.advisories()["EXAMPLE-2022-0002"].title,
+//!     "goodbye bugfix update"
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+mod borrowed;
+mod cache;
 mod common;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+mod decompress;
+mod dependency;
 mod filelist;
+#[cfg(feature = "http")]
+mod http;
+mod layout;
 mod metadata;
 mod other;
 mod package;
 mod primary;
+mod progress;
+mod query;
+mod raw;
+mod remote;
 mod repomd;
 mod repository;
+mod scan;
+mod schema;
+mod search;
+mod sync;
+mod tarball;
+mod transform;
 mod updateinfo;
 pub mod utils;
+mod validate;
+pub mod version;
+mod warning;
 
 #[cfg(feature = "python_ext")]
 mod python_ext;
 
+#[cfg(feature = "capi")]
+mod capi;
+
+pub use borrowed::{read_package_refs, PackageRef, PackageRefIterator, RequirementRef};
+pub use cache::MetadataCache;
 pub use common::EVR;
+#[cfg(feature = "compression")]
+pub use decompress::LzmaDecompressor;
+pub use decompress::{register_decompressor, unregister_decompressor, Decompressor};
+pub use dependency::{DependencyIndex, RichDependency};
+#[cfg(feature = "http")]
+pub use http::{HttpFetcher, HttpFetcherBuilder};
+pub use layout::{package_layout_href, TransferMode};
 pub use metadata::{
-    Changelog, Checksum, ChecksumType, CompressionType, FileType, FilelistsXml, MetadataError,
-    OtherXml, Package, PackageFile, PrimaryXml, RepomdData, RepomdRecord, RepomdXml, Requirement,
-    UpdateCollection, UpdateCollectionModule, UpdateCollectionPackage, UpdateRecord,
-    UpdateReference, UpdateinfoXml,
+    Changelog, ChangelogTimestampPolicy, Checksum, ChecksumError, ChecksumType, CompressionType,
+    FileType, FilelistsXml, IoError, MetadataError, OtherXml, Package, PackageFile, ParseError,
+    PreinstallPolicy, PrimaryXml, RepoLayoutError, RepomdData, RepomdRecord, RepomdXml,
+    Requirement, RequirementType, TargetClient, UpdateCollection, UpdateCollectionModule,
+    UpdateCollectionPackage, UpdateRecord, UpdateReference, UpdateinfoXml, WriteError,
+};
+pub use package::{Field, PackageCountMismatchPolicy, PackageIterator, PackageMatchPolicy};
+pub use primary::{read_packages, PrimaryXmlReader};
+pub use progress::{ProgressEvent, ProgressHandler};
+pub use query::PackageQuery;
+pub use raw::{RawPackage, RawPackageIterator};
+pub use remote::{CacheValidators, ConditionalFetch, Fetcher, PartialDownload, RemoteRepository};
+pub use repository::{
+    MergePolicy, MergeReport, MetadataSelection, Repository, RepositoryOptions, RepositoryReader,
+    RepositoryWriter,
 };
-pub use package::PackageIterator;
-pub use repository::{Repository, RepositoryOptions, RepositoryReader, RepositoryWriter};
-pub use updateinfo::UpdateinfoXmlReader;
+pub use scan::ScanOptions;
+pub use schema::{validate_schema, MetadataSchema};
+pub use search::{FileOwner, FileOwnerIterator};
+pub use sync::{RepoSyncer, SyncReport};
+pub use transform::{RepositoryTransformer, TransformReport, UnknownRecordPolicy};
+pub use updateinfo::{read_update_records, TextEscaping, UpdateinfoTextField, UpdateinfoXmlReader};
+pub use utils::{DecodingPolicy, HrefPolicy, ParseLimits};
+pub use validate::{ValidationProblem, ValidationReport};
+pub use warning::{MetadataWarning, WarningCollector, WarningHandler};
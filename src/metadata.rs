@@ -4,21 +4,23 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::collections::BTreeMap;
 use std::convert::TryInto;
-use std::io::{BufRead, Write};
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::os::unix::prelude::MetadataExt;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // use bitflags;
-use niffler;
 use quick_xml;
 use quick_xml::{Reader, Writer};
 #[cfg(feature = "read_rpm")]
 use rpm;
 use thiserror::Error;
 
-use crate::{utils, Repository, EVR};
+use crate::common::evr_component;
+use crate::{utils, Repository, RichDependency, EVR};
 
 pub struct RepomdXml;
 pub struct PrimaryXml;
@@ -34,46 +36,253 @@ pub const METADATA_OTHER: &str = "other";
 // pub const METADATA_OTHER_ZCK: &str = "other_zck";
 pub const METADATA_UPDATEINFO: &str = "updateinfo";
 
-// TODO: probably this can / should be broken up better rather than being a kitchen sink
+/// Whether a repomd record is one of the metadata types generated from the packages and advisories, or a
+/// variant of one (e.g. "primary_db" or "filelists_zck"). Other records, like "group", "modules", "susedata"
+/// or "appdata", are opaque to this library.
+pub(crate) fn is_generated_record(name: &str) -> bool {
+    [
+        METADATA_PRIMARY,
+        METADATA_FILELISTS,
+        METADATA_OTHER,
+        METADATA_UPDATEINFO,
+    ]
+    .iter()
+    .any(|core| {
+        name.strip_prefix(core)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('_'))
+    })
+}
+
+pub(crate) const EXTRA_SIGNATURE_TYPE: &str = "signature_type";
+pub(crate) const EXTRA_SIGNATURE_KEY_ID: &str = "signature_keyid";
+
+/// The top-level error type of this crate.
+///
+/// Failures are grouped by category, so that they can be handled without matching on the individual
+/// errors, e.g. retrying [`MetadataError::Io`] errors while rejecting [`MetadataError::Parse`] errors as
+/// corrupt metadata. Errors encountered while parsing a metadata file are wrapped in
+/// [`MetadataError::Located`], use [`MetadataError::root_cause()`] to get at the category.
 #[derive(Error, Debug)]
 pub enum MetadataError {
-    #[cfg(feature = "read_rpm")]
     #[error(transparent)]
-    RpmReadError(#[from] rpm::Error),
+    Parse(#[from] ParseError),
     #[error(transparent)]
-    XmlParseError(#[from] quick_xml::Error),
+    Write(#[from] WriteError),
     #[error(transparent)]
-    Utf8Error(#[from] std::str::Utf8Error),
+    Io(#[from] IoError),
     #[error(transparent)]
-    IoError(#[from] std::io::Error),
+    Checksum(#[from] ChecksumError),
     #[error(transparent)]
-    IntFieldParseError(#[from] std::num::ParseIntError),
+    RepoLayout(#[from] RepoLayoutError),
+    #[cfg(feature = "read_rpm")]
     #[error(transparent)]
-    UnsupportedCompressionTypeError(#[from] niffler::Error),
-    #[error("Checksum type {0} is not supported")]
-    UnsupportedChecksumTypeError(String),
-    #[error("\"{0}\" is not a valid checksum of type \"{1:?}\"")]
-    InvalidChecksumError(String, ChecksumType),
+    RpmRead(#[from] rpm::Error),
+    #[error("{source} (in {file} at byte {position}{})", describe_parse_context(.context))]
+    Located {
+        /// The type of metadata file being parsed, e.g. "primary"
+        file: &'static str,
+        /// Byte offset into the (decompressed) metadata file at which the error was detected
+        position: usize,
+        /// The NEVRA of the package or the ID of the advisory being parsed, if known
+        context: Option<String>,
+        source: Box<MetadataError>,
+    },
+}
+
+/// Metadata which is malformed, invalid or internally inconsistent. Reading it again won't help.
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error(transparent)]
+    Xml(quick_xml::Error),
+    #[error(transparent)]
+    Utf8(#[from] std::str::Utf8Error),
+    #[error(transparent)]
+    IntField(#[from] std::num::ParseIntError),
     #[error("\"{0}\" is not a valid flag value")]
-    InvalidFlagsError(String),
+    InvalidFlags(String),
+    #[error("\"{0}\" is not a valid file type")]
+    InvalidFileType(String),
     #[error("\"{0}\" is not a valid EVR string: {1}")]
-    InvalidEvrError(String, String),
+    InvalidEvr(String, String),
+    #[error("\"{0}\" is not a valid NEVRA")]
+    InvalidNevra(String),
+    #[error("\"{0}\" is not a valid dependency: {1}")]
+    InvalidDependency(String, String),
     #[error("Metadata files are inconsistent: {0}")]
-    InconsistentMetadataError(String),
+    InconsistentMetadata(String),
     #[error("Missing metadata field: {0}")]
-    MissingFieldError(&'static str),
+    MissingField(&'static str),
     #[error("Missing metadata attribute: {0}")]
-    MissingAttributeError(&'static str),
+    MissingAttribute(&'static str),
     #[error("Unknown metadata attribute: {0}")]
-    UnknownAttributeError(String),
+    UnknownAttribute(String),
+    #[error("Unexpected <{0}> element")]
+    UnexpectedElement(String),
     #[error("Missing metadata header")]
-    MissingHeaderError,
+    MissingHeader,
+    #[error("XML elements are nested deeper than the limit of {0}")]
+    DepthLimit(usize),
+    #[error("XML text or tag is longer than the limit of {0} bytes")]
+    TextLengthLimit(usize),
+    #[error("Metadata contains more packages than the limit of {0}")]
+    PackageLimit(usize),
+    #[error("XML document type declarations are not permitted in metadata")]
+    Doctype,
 }
 
-// #[derive(Error, Debug)]
-// pub enum RpmrepoError {
+/// Packages or advisories which can't be added to, or removed from, a repository.
+#[derive(Error, Debug)]
+pub enum WriteError {
+    #[error("A package with NEVRA {0} is already present")]
+    DuplicatePackage(String),
+    #[error("An advisory with ID {0} is already present")]
+    DuplicateAdvisory(String),
+    #[error("Advisory {0} not found")]
+    AdvisoryNotFound(String),
+    #[error("Package is missing a field required to write metadata: {0}")]
+    MissingField(&'static str),
+    #[error("Package {0} does not meet the signature requirements: {1}")]
+    SignatureRequirement(String, String),
+    #[error("{written} packages were written, but the metadata header declares {declared}")]
+    PackageCountMismatch { declared: usize, written: usize },
+    #[error("Written metadata does not conform to the schema: {0}")]
+    SchemaViolation(String),
+}
 
-// }
+/// Failures to read or write files, which may be transient.
+#[derive(Error, Debug)]
+pub enum IoError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "compression")]
+    #[error(transparent)]
+    UnsupportedCompressionType(#[from] niffler::Error),
+}
+
+/// Checksums which can't be computed, or don't match the contents they describe.
+#[derive(Error, Debug)]
+pub enum ChecksumError {
+    #[error("Checksum type {0} is not supported")]
+    UnsupportedType(String),
+    #[error("\"{0}\" is not a valid checksum of type \"{1:?}\"")]
+    Invalid(String, ChecksumType),
+    #[error("Checksum of {0} does not match the repository metadata")]
+    Mismatch(String),
+}
+
+/// Repositories which are missing metadata files, or have files in places they shouldn't be.
+#[derive(Error, Debug)]
+pub enum RepoLayoutError {
+    #[error("Metadata record of unknown type: {0}")]
+    UnknownRecord(String),
+    #[error("Repository has no {0} metadata")]
+    MissingRecord(&'static str),
+    #[error("Metadata record has no base path to read the file from")]
+    MissingBasePath,
+    #[error("Package location \"{0}\" points outside of the repository")]
+    UnsafeHref(String),
+    #[error("Metadata file location \"{0}\" is not in the repodata/ directory")]
+    MetadataLocation(String),
+    #[error("Listed packages do not exist: {}", .0.join(", "))]
+    MissingPackages(Vec<String>),
+}
+
+impl From<quick_xml::Error> for ParseError {
+    fn from(error: quick_xml::Error) -> Self {
+        // violations of the parse limits reach the parser as I/O errors
+        if let quick_xml::Error::Io(io_error) = &error {
+            let limit_error = io_error
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<utils::XmlLimitError>());
+            if let Some(limit_error) = limit_error {
+                return ParseError::from(*limit_error);
+            }
+        }
+        ParseError::Xml(error)
+    }
+}
+
+impl From<quick_xml::Error> for MetadataError {
+    fn from(error: quick_xml::Error) -> Self {
+        ParseError::from(error).into()
+    }
+}
+
+impl From<std::str::Utf8Error> for MetadataError {
+    fn from(error: std::str::Utf8Error) -> Self {
+        ParseError::from(error).into()
+    }
+}
+
+impl From<std::num::ParseIntError> for MetadataError {
+    fn from(error: std::num::ParseIntError) -> Self {
+        ParseError::from(error).into()
+    }
+}
+
+impl From<std::io::Error> for MetadataError {
+    fn from(error: std::io::Error) -> Self {
+        IoError::from(error).into()
+    }
+}
+
+#[cfg(feature = "compression")]
+impl From<niffler::Error> for MetadataError {
+    fn from(error: niffler::Error) -> Self {
+        IoError::from(error).into()
+    }
+}
+
+impl MetadataError {
+    /// Attach the location within a metadata file at which an error occurred.
+    ///
+    /// Errors which already carry a location are returned unchanged.
+    pub(crate) fn at_position(
+        self,
+        file: &'static str,
+        position: usize,
+        context: Option<String>,
+    ) -> MetadataError {
+        match self {
+            MetadataError::Located { .. } => self,
+            source => MetadataError::Located {
+                file,
+                position,
+                context,
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// Attach the location within a metadata file at which an error occurred while parsing `package`.
+    pub(crate) fn at_package_position(
+        self,
+        file: &'static str,
+        position: usize,
+        package: Option<&Package>,
+    ) -> MetadataError {
+        // the NEVRA is meaningless if the error occurred before the name was parsed
+        let context = package
+            .filter(|pkg| !pkg.name().is_empty())
+            .map(|pkg| pkg.nevra());
+        self.at_position(file, position, context)
+    }
+
+    /// The underlying error, without any location information attached.
+    pub fn root_cause(&self) -> &MetadataError {
+        match self {
+            MetadataError::Located { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
+}
+
+fn describe_parse_context(context: &Option<String>) -> String {
+    match context {
+        Some(context) => format!(", while parsing {}", context),
+        None => String::new(),
+    }
+}
 
 /// Default namespace for primary.xml
 pub const XML_NS_COMMON: &str = "http://linux.duke.edu/metadata/common";
@@ -133,11 +342,105 @@ impl TryInto<CompressionType> for &str {
             "xz" => Ok(CompressionType::Xz),
             "zstd" => Ok(CompressionType::Zstd),
             "none" => Ok(CompressionType::None),
-            _ => Err(MetadataError::UnsupportedChecksumTypeError(self.to_owned())),
+            _ => Err(ChecksumError::UnsupportedType(self.to_owned()).into()),
+        }
+    }
+}
+
+impl From<CompressionType> for &str {
+    fn from(comptype: CompressionType) -> &'static str {
+        match comptype {
+            CompressionType::None => "none",
+            CompressionType::Gzip => "gzip",
+            CompressionType::Xz => "xz",
+            CompressionType::Bz2 => "bz2",
+            CompressionType::Zstd => "zstd",
         }
     }
 }
 
+/// The oldest package manager a repository is published for.
+///
+/// Determines which optional elements and records are written to `repomd.xml`, so that older clients
+/// which can't cope with newer additions to the format can still consume the repository.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TargetClient {
+    /// yum 3 (EL5 - EL7). Omits the `<size>`, `<open-size>`, `<header-size>` and `<header-checksum>`
+    /// elements and any zchunk (`*_zck`) records.
+    Yum3,
+    /// dnf 4 (EL8, EL9). Everything is written.
+    #[default]
+    Dnf4,
+    /// dnf 5. Omits the sqlite database (`*_db`) records and `<database_version>` elements, which
+    /// dnf 5 doesn't use.
+    Dnf5,
+}
+
+/// Which dependencies the `pre="1"` attribute is written for in primary.xml.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PreinstallPolicy {
+    /// Only for requires, where the attribute has a meaning. createrepo_c drops it from all other kinds
+    /// of dependencies, so this matches its output.
+    #[default]
+    RequiresOnly,
+    /// For every dependency which has it set, e.g. to reproduce metadata from other tools exactly.
+    Preserve,
+}
+
+/// How changelog entries with timestamps beyond `i32::MAX` seconds (in the year 2038) are written to
+/// other.xml. Such timestamps are usually typos in the spec file, and DNF fails to load them on some
+/// platforms.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChangelogTimestampPolicy {
+    /// Write the timestamp unchanged.
+    #[default]
+    Keep,
+    /// Write `i32::MAX` instead, like createrepo_c.
+    Clamp,
+    /// Leave the changelog entry out.
+    Drop,
+}
+
+impl ChangelogTimestampPolicy {
+    pub const MAX_TIMESTAMP: u64 = i32::MAX as u64;
+
+    /// The timestamp to write for a changelog entry, or `None` if the entry should be left out.
+    pub fn apply(&self, timestamp: u64) -> Option<u64> {
+        match self {
+            _ if timestamp <= Self::MAX_TIMESTAMP => Some(timestamp),
+            ChangelogTimestampPolicy::Keep => Some(timestamp),
+            ChangelogTimestampPolicy::Clamp => Some(Self::MAX_TIMESTAMP),
+            ChangelogTimestampPolicy::Drop => None,
+        }
+    }
+}
+
+impl TargetClient {
+    /// Whether a record of type `name` (e.g. "primary_zck") should be written to `repomd.xml`.
+    pub fn supports_record(&self, name: &str) -> bool {
+        match self {
+            TargetClient::Yum3 => !name.ends_with("_zck"),
+            TargetClient::Dnf4 => true,
+            TargetClient::Dnf5 => !name.ends_with("_db"),
+        }
+    }
+
+    /// Whether the `<size>` and `<open-size>` elements of records should be written.
+    pub fn supports_sizes(&self) -> bool {
+        *self != TargetClient::Yum3
+    }
+
+    /// Whether the `<header-size>` and `<header-checksum>` elements of (zchunk) records should be written.
+    pub fn supports_header_info(&self) -> bool {
+        *self != TargetClient::Yum3
+    }
+
+    /// Whether the `<database_version>` elements of sqlite database records should be written.
+    pub fn supports_database_version(&self) -> bool {
+        *self != TargetClient::Dnf5
+    }
+}
+
 // impl Ord for Package {
 //     #[inline]
 //     fn cmp(&self, other: &Package) -> Ordering {
@@ -160,14 +463,16 @@ impl TryInto<CompressionType> for &str {
 pub struct Package {
     // pub(crate) parse_state: ParseState,
     pub name: String,
-    pub arch: String,
+    // arch, packager, license, vendor, group and buildhost repeat across many packages, so they can be
+    // shared between packages - see PackageIterator::with_string_interning()
+    pub arch: Arc<str>,
     pub evr: EVR,
-    pub checksum: Checksum,
+    pub(crate) checksum: ChecksumState,
     pub location_href: String,
     pub location_base: Option<String>,
     pub summary: String,
     pub description: String,
-    pub packager: String,
+    pub packager: Arc<str>,
     pub url: String,
     pub time_file: u64,
     pub time_build: u64,
@@ -175,24 +480,24 @@ pub struct Package {
     pub size_installed: u64,
     pub size_archive: u64,
 
-    pub rpm_license: String,           // rpm:license
-    pub rpm_vendor: String,            // rpm:vendor
-    pub rpm_group: String,             // rpm:group
-    pub rpm_buildhost: String,         // rpm:buildhost
+    pub rpm_license: Arc<str>,         // rpm:license
+    pub rpm_vendor: Arc<str>,          // rpm:vendor
+    pub rpm_group: Arc<str>,           // rpm:group
+    pub rpm_buildhost: Arc<str>,       // rpm:buildhost
     pub rpm_sourcerpm: String,         // rpm:sourcerpm
     pub rpm_header_range: HeaderRange, // rpm:header-range
 
-    pub rpm_requires: Vec<Requirement>,    // rpm:provides
-    pub rpm_provides: Vec<Requirement>,    // rpm:requires
-    pub rpm_conflicts: Vec<Requirement>,   // rpm:conflicts
-    pub rpm_obsoletes: Vec<Requirement>,   // rpm:obsoletes
-    pub rpm_suggests: Vec<Requirement>,    // rpm:suggests
-    pub rpm_enhances: Vec<Requirement>,    // rpm:enhances
-    pub rpm_recommends: Vec<Requirement>,  // rpm:recommends
-    pub rpm_supplements: Vec<Requirement>, // rpm:supplements
+    // rpm:requires, rpm:provides, rpm:conflicts, rpm:obsoletes, rpm:suggests, rpm:enhances,
+    // rpm:recommends and rpm:supplements
+    pub(crate) dependencies: Dependencies,
 
     pub rpm_changelogs: Vec<Changelog>,
     pub rpm_files: Vec<PackageFile>,
+
+    /// Additional RPM header tags which are not part of the repository metadata, e.g. `excludearch`.
+    ///
+    /// Not written to or read from repository metadata.
+    pub extra: BTreeMap<String, Vec<String>>,
 }
 
 impl Package {
@@ -205,14 +510,9 @@ impl Package {
     ) -> Package {
         Package {
             name: name.to_owned(),
-            arch: arch.to_owned(),
-            // TODO: https://github.com/rust-lang/rust/issues/107115
-            evr: EVR::new(
-                version.epoch().to_owned(),
-                version.version().to_owned(),
-                version.release().to_owned(),
-            ),
-            checksum: checksum.clone(),
+            arch: arch.into(),
+            evr: version.clone(),
+            checksum: ChecksumState::Known(checksum.clone()),
             location_href: location_href.to_owned(),
             ..Package::default()
         }
@@ -228,16 +528,20 @@ impl Package {
     }
 
     pub fn set_epoch(&mut self, epoch: u32) -> &mut Self {
-        self.evr.epoch = epoch.to_string().into();
+        self.evr.epoch = evr_component(epoch.to_string());
         self
     }
 
-    pub fn epoch(&self) -> u32 {
-        self.evr.epoch.parse().expect("TODO: don't do this")
+    /// The epoch as a number, 0 if the package has none. Fails if the epoch isn't numeric.
+    pub fn epoch(&self) -> Result<u32, MetadataError> {
+        match self.evr.epoch() {
+            "" => Ok(0),
+            epoch => Ok(epoch.parse()?),
+        }
     }
 
     pub fn set_version(&mut self, version: impl Into<String>) -> &mut Self {
-        self.evr.version = version.into().into();
+        self.evr.version = evr_component(version);
         self
     }
 
@@ -246,7 +550,7 @@ impl Package {
     }
 
     pub fn set_release(&mut self, release: impl Into<String>) -> &mut Self {
-        self.evr.release = release.into().into();
+        self.evr.release = evr_component(release);
         self
     }
 
@@ -254,7 +558,7 @@ impl Package {
         &self.evr.release
     }
 
-    pub fn set_arch(&mut self, arch: impl Into<String>) -> &mut Self {
+    pub fn set_arch(&mut self, arch: impl Into<Arc<str>>) -> &mut Self {
         self.arch = arch.into();
         self
     }
@@ -264,13 +568,7 @@ impl Package {
     }
 
     // TODO: signature
-    // TODO: https://github.com/rust-lang/rust/issues/107115
     pub fn set_evr(&mut self, evr: EVR) -> &mut Self {
-        let evr = EVR::new(
-            evr.epoch().to_owned(),
-            evr.version().to_owned(),
-            evr.release().to_owned(),
-        );
         self.evr = evr;
         self
     }
@@ -302,17 +600,31 @@ impl Package {
     }
     // TODO: signature
     pub fn set_checksum(&mut self, checksum: Checksum) -> &mut Self {
-        self.checksum = checksum;
+        self.checksum = ChecksumState::Known(checksum);
         self
     }
 
-    pub fn checksum(&self) -> &Checksum {
-        &self.checksum
+    /// The checksum of the package, if known. Packages read only from filelists.xml or other.xml have a
+    /// pkgid but no checksum, since those files don't contain the checksum type.
+    pub fn checksum(&self) -> Option<&Checksum> {
+        match &self.checksum {
+            ChecksumState::Known(checksum) => Some(checksum),
+            ChecksumState::Pkgid(_) | ChecksumState::Empty => None,
+        }
     }
 
+    /// The pkgid (the hex digest of the checksum) of the package, or an empty string if it has none.
     pub fn pkgid(&self) -> &str {
-        // TODO: better way to do this
-        &self.checksum.to_values().unwrap().1
+        match &self.checksum {
+            ChecksumState::Known(checksum) => checksum.digest(),
+            ChecksumState::Pkgid(pkgid) => pkgid,
+            ChecksumState::Empty => "",
+        }
+    }
+
+    pub(crate) fn set_pkgid(&mut self, pkgid: String) -> &mut Self {
+        self.checksum = ChecksumState::Pkgid(pkgid);
+        self
     }
 
     pub fn set_location_href(&mut self, location_href: impl Into<String>) -> &mut Self {
@@ -351,7 +663,7 @@ impl Package {
         &self.description
     }
 
-    pub fn set_packager(&mut self, packager: impl Into<String>) -> &mut Self {
+    pub fn set_packager(&mut self, packager: impl Into<Arc<str>>) -> &mut Self {
         self.packager = packager.into();
         self
     }
@@ -414,7 +726,7 @@ impl Package {
         self.size_archive
     }
 
-    pub fn set_rpm_license(&mut self, license: impl Into<String>) -> &mut Self {
+    pub fn set_rpm_license(&mut self, license: impl Into<Arc<str>>) -> &mut Self {
         self.rpm_license = license.into();
         self
     }
@@ -423,7 +735,7 @@ impl Package {
         &self.rpm_license
     }
 
-    pub fn set_rpm_vendor(&mut self, vendor: impl Into<String>) -> &mut Self {
+    pub fn set_rpm_vendor(&mut self, vendor: impl Into<Arc<str>>) -> &mut Self {
         self.rpm_vendor = vendor.into();
         self
     }
@@ -432,7 +744,7 @@ impl Package {
         &self.rpm_vendor
     }
 
-    pub fn set_rpm_group(&mut self, group: impl Into<String>) -> &mut Self {
+    pub fn set_rpm_group(&mut self, group: impl Into<Arc<str>>) -> &mut Self {
         self.rpm_group = group.into();
         self
     }
@@ -441,7 +753,7 @@ impl Package {
         &self.rpm_group
     }
 
-    pub fn set_rpm_buildhost(&mut self, rpm_buildhost: impl Into<String>) -> &mut Self {
+    pub fn set_rpm_buildhost(&mut self, rpm_buildhost: impl Into<Arc<str>>) -> &mut Self {
         self.rpm_buildhost = rpm_buildhost.into();
         self
     }
@@ -470,75 +782,113 @@ impl Package {
 
     // TODO: probably adjust the signatures on all of these w/ builder pattern or something
     pub fn set_requires(&mut self, requires: Vec<Requirement>) -> &mut Self {
-        self.rpm_requires = requires;
+        self.dependencies.set(DependencyKind::Requires, requires);
         self
     }
 
     pub fn requires(&self) -> &[Requirement] {
-        &self.rpm_requires
+        self.dependencies.get(DependencyKind::Requires)
+    }
+
+    /// Normalize the requires of the package the way createrepo_c does.
+    ///
+    /// - `rpmlib()` requires, which are satisfied by rpm itself, are dropped
+    /// - requires on files contained by the package which are listed in primary.xml are dropped
+    /// - duplicate requires are dropped
+    /// - of the `libc.so.6` requires, only the one for the newest glibc version is kept, and moved to the end
+    pub fn normalize_dependencies(&mut self) -> &mut Self {
+        let original = self.dependencies.take(DependencyKind::Requires);
+        let mut requires: Vec<Requirement> = Vec::with_capacity(original.len());
+        let mut libc_require: Option<Requirement> = None;
+
+        for require in original {
+            if require.name.starts_with("rpmlib(")
+                || requires.contains(&require)
+                || (crate::primary::is_primary_file(&require.name)
+                    && self.rpm_files.iter().any(|f| *f.path == *require.name))
+            {
+                continue;
+            }
+            if require.name.starts_with("libc.so.6") {
+                let newer = libc_require.as_ref().map_or(true, |newest| {
+                    libc_version_cmp(&newest.name, &require.name).is_lt()
+                });
+                if newer {
+                    libc_require = Some(require);
+                }
+                continue;
+            }
+            requires.push(require);
+        }
+        requires.extend(libc_require);
+
+        self.dependencies.set(DependencyKind::Requires, requires);
+        self
     }
 
     pub fn set_provides(&mut self, provides: Vec<Requirement>) -> &mut Self {
-        self.rpm_provides = provides;
+        self.dependencies.set(DependencyKind::Provides, provides);
         self
     }
 
     pub fn provides(&self) -> &[Requirement] {
-        &self.rpm_provides
+        self.dependencies.get(DependencyKind::Provides)
     }
 
     pub fn set_conflicts(&mut self, conflicts: Vec<Requirement>) -> &mut Self {
-        self.rpm_conflicts = conflicts;
+        self.dependencies.set(DependencyKind::Conflicts, conflicts);
         self
     }
 
     pub fn conflicts(&self) -> &[Requirement] {
-        &self.rpm_conflicts
+        self.dependencies.get(DependencyKind::Conflicts)
     }
 
     pub fn set_obsoletes(&mut self, obsoletes: Vec<Requirement>) -> &mut Self {
-        self.rpm_obsoletes = obsoletes;
+        self.dependencies.set(DependencyKind::Obsoletes, obsoletes);
         self
     }
 
     pub fn obsoletes(&self) -> &[Requirement] {
-        &self.rpm_obsoletes
+        self.dependencies.get(DependencyKind::Obsoletes)
     }
 
     pub fn set_suggests(&mut self, suggests: Vec<Requirement>) -> &mut Self {
-        self.rpm_suggests = suggests;
+        self.dependencies.set(DependencyKind::Suggests, suggests);
         self
     }
 
     pub fn suggests(&self) -> &[Requirement] {
-        &self.rpm_suggests
+        self.dependencies.get(DependencyKind::Suggests)
     }
 
     pub fn set_enhances(&mut self, enhances: Vec<Requirement>) -> &mut Self {
-        self.rpm_enhances = enhances;
+        self.dependencies.set(DependencyKind::Enhances, enhances);
         self
     }
 
     pub fn enhances(&self) -> &[Requirement] {
-        &self.rpm_enhances
+        self.dependencies.get(DependencyKind::Enhances)
     }
 
     pub fn set_recommends(&mut self, recommends: Vec<Requirement>) -> &mut Self {
-        self.rpm_recommends = recommends;
+        self.dependencies
+            .set(DependencyKind::Recommends, recommends);
         self
     }
 
     pub fn recommends(&self) -> &[Requirement] {
-        &self.rpm_recommends
+        self.dependencies.get(DependencyKind::Recommends)
     }
 
     pub fn set_supplements(&mut self, supplements: Vec<Requirement>) -> &mut Self {
-        self.rpm_supplements = supplements;
+        self.dependencies
+            .set(DependencyKind::Supplements, supplements);
         self
     }
 
     pub fn supplements(&self) -> &[Requirement] {
-        &self.rpm_supplements
+        self.dependencies.get(DependencyKind::Supplements)
     }
 
     pub fn add_file(&mut self, filetype: FileType, path: &str) -> &mut Self {
@@ -575,6 +925,39 @@ impl Package {
     pub fn changelogs(&self) -> &[Changelog] {
         &self.rpm_changelogs
     }
+
+    /// Keep only the newest `limit` changelog entries, as createrepo_c does with `--changelog-limit`.
+    ///
+    /// Changelogs are expected to be ordered oldest first, as they are in other.xml.
+    pub fn truncate_changelogs(&mut self, limit: usize) -> &mut Self {
+        let excess = self.rpm_changelogs.len().saturating_sub(limit);
+        self.rpm_changelogs.drain(..excess);
+        self
+    }
+
+    /// Whether the package is signed. Only known for packages read from RPM files, otherwise `false`.
+    pub fn is_signed(&self) -> bool {
+        self.extra.contains_key(EXTRA_SIGNATURE_TYPE)
+    }
+
+    /// The ID of the key the package was signed with, as 16 hexadecimal digits.
+    ///
+    /// Only known for packages read from RPM files.
+    pub fn signature_key_id(&self) -> Option<&str> {
+        self.extra
+            .get(EXTRA_SIGNATURE_KEY_ID)
+            .and_then(|values| values.first())
+            .map(|key_id| key_id.as_str())
+    }
+
+    pub fn set_extra(&mut self, tag: impl Into<String>, values: Vec<String>) -> &mut Self {
+        self.extra.insert(tag.into(), values);
+        self
+    }
+
+    pub fn extra(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.extra
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -585,7 +968,6 @@ pub enum ChecksumType {
     Sha256,
     Sha384,
     Sha512,
-    Unknown,
 }
 
 impl Default for ChecksumType {
@@ -594,6 +976,50 @@ impl Default for ChecksumType {
     }
 }
 
+impl ChecksumType {
+    /// The length of a hex digest of this type.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            ChecksumType::Md5 => 32,
+            ChecksumType::Sha1 => 40,
+            ChecksumType::Sha224 => 56,
+            ChecksumType::Sha256 => 64,
+            ChecksumType::Sha384 => 96,
+            ChecksumType::Sha512 => 128,
+        }
+    }
+}
+
+impl TryInto<ChecksumType> for &str {
+    type Error = MetadataError;
+
+    fn try_into(self) -> Result<ChecksumType, Self::Error> {
+        match self {
+            "md5" => Ok(ChecksumType::Md5),
+            "sha" | "sha1" => Ok(ChecksumType::Sha1),
+            "sha224" => Ok(ChecksumType::Sha224),
+            "sha256" => Ok(ChecksumType::Sha256),
+            "sha384" => Ok(ChecksumType::Sha384),
+            "sha512" => Ok(ChecksumType::Sha512),
+            _ => Err(ChecksumError::UnsupportedType(self.to_owned()).into()),
+        }
+    }
+}
+
+impl From<ChecksumType> for &str {
+    fn from(chktype: ChecksumType) -> &'static str {
+        match chktype {
+            ChecksumType::Md5 => "md5",
+            ChecksumType::Sha1 => "sha1",
+            ChecksumType::Sha224 => "sha224",
+            ChecksumType::Sha256 => "sha256",
+            ChecksumType::Sha384 => "sha384",
+            ChecksumType::Sha512 => "sha512",
+        }
+    }
+}
+
+/// A checksum of a known type. Create one from metadata values with [`Checksum::parse`].
 #[derive(Clone, Debug, PartialEq)]
 pub enum Checksum {
     Md5(String),
@@ -602,139 +1028,93 @@ pub enum Checksum {
     Sha256(String),
     Sha384(String),
     Sha512(String),
-    Unknown(String),
-    Empty,
-}
-
-impl Default for Checksum {
-    fn default() -> Self {
-        Checksum::Empty
-    }
 }
 
 impl Hash for Checksum {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        match self {
-            Self::Md5(hash) => format!("md5:{}", hash).hash(state),
-            Self::Sha1(hash) => format!("sha1:{}", hash).hash(state),
-            Self::Sha224(hash) => format!("sha224:{}", hash).hash(state),
-            Self::Sha256(hash) => format!("sha256:{}", hash).hash(state),
-            Self::Sha384(hash) => format!("sha384:{}", hash).hash(state),
-            Self::Sha512(hash) => format!("sha512:{}", hash).hash(state),
-            // TODO: adjust this representation. Currently these exist because of reuse of these enums
-            // to represent intermediate parsing states, but those probably ought to be pulled out somehow
-            Self::Unknown(hash) => unimplemented!(),
-            Self::Empty => unimplemented!(),
-        }
+        let (checksum_type, digest) = self.to_pair();
+        format!("{}:{}", checksum_type, digest).hash(state)
     }
 }
 
 impl Checksum {
+    /// Create a checksum from its type, e.g. `"sha256"`, and its hex digest.
+    ///
+    /// Fails if the type is not supported or the digest is not a hex string of the right length.
+    pub fn parse(checksum_type: &str, digest: &str) -> Result<Self, MetadataError> {
+        let checksum_type: ChecksumType = checksum_type.try_into()?;
+        if digest.len() != checksum_type.digest_len()
+            || !digest.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(ChecksumError::Invalid(digest.to_owned(), checksum_type).into());
+        }
+        Ok(Self::from_digest(checksum_type, digest.to_owned()))
+    }
+
     pub fn try_create<N: AsRef<[u8]> + Sized>(
         checksum_type: N,
         checksum: N,
     ) -> Result<Self, MetadataError> {
-        let bytes_to_str = |value| std::str::from_utf8(value).unwrap().to_owned();
-
-        match checksum_type.as_ref() {
-            b"md5" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 32 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Md5,
-                    ))
-                } else {
-                    Ok(Checksum::Md5(digest))
-                }
-            }
-            b"sha" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 40 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Sha1,
-                    ))
-                } else {
-                    Ok(Checksum::Sha1(digest))
-                }
-            }
-            b"sha1" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 40 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Sha1,
-                    ))
-                } else {
-                    Ok(Checksum::Sha1(digest))
-                }
-            }
-            b"sha224" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 56 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Sha224,
-                    ))
-                } else {
-                    Ok(Checksum::Sha224(digest))
-                }
-            }
-            b"sha256" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 64 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Sha256,
-                    ))
-                } else {
-                    Ok(Checksum::Sha256(digest))
-                }
-            }
-            b"sha384" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 96 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Sha384,
-                    ))
-                } else {
-                    Ok(Checksum::Sha384(digest))
-                }
-            }
-            b"sha512" => {
-                let digest = bytes_to_str(checksum.as_ref());
-                if digest.len() != 128 {
-                    Err(MetadataError::InvalidChecksumError(
-                        digest,
-                        ChecksumType::Sha512,
-                    ))
-                } else {
-                    Ok(Checksum::Sha512(digest))
-                }
-            }
-            _ => {
-                return Err(MetadataError::UnsupportedChecksumTypeError(bytes_to_str(
-                    checksum_type.as_ref(),
-                )))
-            }
+        Self::parse(
+            std::str::from_utf8(checksum_type.as_ref())?,
+            std::str::from_utf8(checksum.as_ref())?,
+        )
+    }
+
+    pub(crate) fn from_digest(checksum_type: ChecksumType, digest: String) -> Self {
+        match checksum_type {
+            ChecksumType::Md5 => Checksum::Md5(digest),
+            ChecksumType::Sha1 => Checksum::Sha1(digest),
+            ChecksumType::Sha224 => Checksum::Sha224(digest),
+            ChecksumType::Sha256 => Checksum::Sha256(digest),
+            ChecksumType::Sha384 => Checksum::Sha384(digest),
+            ChecksumType::Sha512 => Checksum::Sha512(digest),
         }
     }
 
+    fn to_pair(&self) -> (&'static str, &str) {
+        (self.checksum_type().into(), self.digest())
+    }
+
+    /// The type and hex digest of the checksum, e.g. `("sha256", "6a915b6e...")`. This never fails.
     pub fn to_values<'a>(&'a self) -> Result<(&str, &'a str), MetadataError> {
-        let values = match self {
-            Checksum::Md5(c) => ("md5", c.as_str()),
-            Checksum::Sha1(c) => ("sha1", c.as_str()),
-            Checksum::Sha224(c) => ("sha224", c.as_str()),
-            Checksum::Sha256(c) => ("sha256", c.as_str()),
-            Checksum::Sha384(c) => ("sha384", c.as_str()),
-            Checksum::Sha512(c) => ("sha512", c.as_str()),
-            Checksum::Unknown(c) => ("unknown", c.as_str()), // TODO: need to fix this - if filelists is loaded w/o metadata the pkgid is known but the type is not
-            Checksum::Empty => panic!("Cannot take value of empty checksum"),
-        };
-        Ok(values)
+        Ok(self.to_pair())
     }
+
+    /// The hex digest of the checksum.
+    pub fn digest(&self) -> &str {
+        match self {
+            Checksum::Md5(c)
+            | Checksum::Sha1(c)
+            | Checksum::Sha224(c)
+            | Checksum::Sha256(c)
+            | Checksum::Sha384(c)
+            | Checksum::Sha512(c) => c.as_str(),
+        }
+    }
+
+    pub fn checksum_type(&self) -> ChecksumType {
+        match self {
+            Checksum::Md5(_) => ChecksumType::Md5,
+            Checksum::Sha1(_) => ChecksumType::Sha1,
+            Checksum::Sha224(_) => ChecksumType::Sha224,
+            Checksum::Sha256(_) => ChecksumType::Sha256,
+            Checksum::Sha384(_) => ChecksumType::Sha384,
+            Checksum::Sha512(_) => ChecksumType::Sha512,
+        }
+    }
+}
+
+/// How much is known about the checksum of a package while it is being parsed.
+///
+/// filelists.xml and other.xml only contain the pkgid, not the type of the checksum, so packages read from
+/// them alone have a pkgid but no [`Checksum`].
+#[derive(Clone, Debug, Default, PartialEq, Hash)]
+pub(crate) enum ChecksumState {
+    #[default]
+    Empty,
+    Pkgid(String),
+    Known(Checksum),
 }
 
 #[derive(Clone, Debug, Default, Hash, PartialEq)]
@@ -750,18 +1130,236 @@ pub struct HeaderRange {
     pub end: u64,
 }
 
+/// Compare the glibc versions of two `libc.so.6` dependencies, e.g. `libc.so.6(GLIBC_2.14)(64bit)`.
+fn libc_version_cmp(dep1: &str, dep2: &str) -> std::cmp::Ordering {
+    fn glibc_version(dep: &str) -> &str {
+        let version = dep
+            .split_once('(')
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .map(|(version, _)| version)
+            .unwrap_or_default();
+        version.rsplit('_').next().unwrap_or(version)
+    }
+    crate::common::rpmvercmp(glibc_version(dep1), glibc_version(dep2))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DependencyKind {
+    Requires,
+    Provides,
+    Conflicts,
+    Obsoletes,
+    Suggests,
+    Enhances,
+    Recommends,
+    Supplements,
+}
+
+// All of the dependency lists of a package, stored in a single Vec. Most packages have short requires and
+// provides lists and nothing else, so this avoids carrying eight separate (mostly empty) Vecs per package.
+#[derive(Clone, Debug, Default, Hash, PartialEq)]
+pub(crate) struct Dependencies {
+    requirements: Vec<Requirement>,
+    // end offset into `requirements` of each segment, in DependencyKind order
+    ends: [u32; 8],
+}
+
+impl Dependencies {
+    fn range(&self, kind: DependencyKind) -> std::ops::Range<usize> {
+        let idx = kind as usize;
+        let start = if idx == 0 {
+            0
+        } else {
+            self.ends[idx - 1] as usize
+        };
+        start..self.ends[idx] as usize
+    }
+
+    fn resize_segment(&mut self, kind: DependencyKind, old_len: usize, new_len: usize) {
+        for end in self.ends[kind as usize..].iter_mut() {
+            *end = (*end as usize - old_len + new_len) as u32;
+        }
+    }
+
+    pub(crate) fn get(&self, kind: DependencyKind) -> &[Requirement] {
+        &self.requirements[self.range(kind)]
+    }
+
+    pub(crate) fn set(&mut self, kind: DependencyKind, list: Vec<Requirement>) {
+        let range = self.range(kind);
+        let (old_len, new_len) = (range.len(), list.len());
+        if self.requirements.is_empty() {
+            // the common case of the first list being set, reuse the allocation
+            self.requirements = list;
+        } else {
+            self.requirements.splice(range, list);
+        }
+        self.resize_segment(kind, old_len, new_len);
+    }
+
+    pub(crate) fn take(&mut self, kind: DependencyKind) -> Vec<Requirement> {
+        let range = self.range(kind);
+        let old_len = range.len();
+        let list = self.requirements.drain(range).collect();
+        self.resize_segment(kind, old_len, 0);
+        list
+    }
+}
+
 // Requirement (Provides, Conflicts, Obsoletes, Requires).
 #[derive(Clone, Debug, Default, Hash, PartialEq)]
 pub struct Requirement {
-    pub name: String,
-    pub flags: Option<String>,
+    // names such as "libc.so.6()(64bit)" repeat across many packages, see PackageIterator::with_string_interning()
+    pub name: Arc<str>,
+    pub flags: Option<RequirementType>,
     pub epoch: Option<String>,
     pub version: Option<String>,
     pub release: Option<String>,
     pub preinstall: bool,
 }
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+impl Requirement {
+    /// Parse a capability string as used in spec files and dnf output, e.g. `foo >= 1:1.2-3`.
+    ///
+    /// The epoch defaults to 0 for versioned capabilities. Rich dependencies such as `(foo if bar)`
+    /// are validated and kept whole as the name.
+    ///
+    /// ```
+    /// # use rpmrepo_metadata::{MetadataError, Requirement, RequirementType};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// let requirement = Requirement::parse("python3-libs >= 3.9")?;
+    /// assert_eq!(requirement.flags, Some(RequirementType::GE));
+    /// assert_eq!(requirement.to_string(), "python3-libs >= 3.9");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(capability: &str) -> Result<Self, MetadataError> {
+        let capability = capability.trim();
+        let error = |reason: &str| {
+            MetadataError::from(ParseError::InvalidDependency(
+                capability.to_owned(),
+                reason.to_owned(),
+            ))
+        };
+
+        if capability.starts_with('(') {
+            RichDependency::parse(capability)?;
+            return Ok(Requirement {
+                name: capability.into(),
+                ..Requirement::default()
+            });
+        }
+
+        let is_operator = |c: char| matches!(c, '<' | '>' | '=');
+        let name_end = capability
+            .find(|c: char| c.is_whitespace() || is_operator(c))
+            .unwrap_or(capability.len());
+        let (name, rest) = capability.split_at(name_end);
+        if name.is_empty() {
+            return Err(error("expected a capability name"));
+        }
+
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(Requirement {
+                name: name.into(),
+                ..Requirement::default()
+            });
+        }
+
+        let operator_end = rest.find(|c: char| !is_operator(c)).unwrap_or(rest.len());
+        let (operator, evr) = rest.split_at(operator_end);
+        let flags = RequirementType::from_operator(operator)
+            .ok_or_else(|| error("expected a comparison operator"))?;
+
+        let evr = evr.trim_start();
+        if evr.is_empty() || evr.contains(char::is_whitespace) {
+            return Err(error("expected a version after the comparison operator"));
+        }
+
+        Ok(Requirement::from_parts(name, Some(flags), evr))
+    }
+
+    /// Build a requirement from a name and an optional version constraint. The epoch defaults to 0.
+    pub(crate) fn from_parts(name: &str, flags: Option<RequirementType>, evr: &str) -> Self {
+        if flags.is_none() {
+            return Requirement {
+                name: name.into(),
+                ..Requirement::default()
+            };
+        }
+
+        let (epoch, version, release) = EVR::parse_values(evr);
+        Requirement {
+            name: name.into(),
+            flags,
+            epoch: Some(if epoch.is_empty() { "0" } else { epoch }.to_owned()),
+            version: Some(version.to_owned()),
+            release: Some(release.to_owned()).filter(|release| !release.is_empty()),
+            preinstall: false,
+        }
+    }
+
+    /// Whether this is a rich (boolean) dependency such as `(foo if bar)`.
+    pub fn is_rich(&self) -> bool {
+        self.name.starts_with('(')
+    }
+
+    /// Parse the rich (boolean) dependency stored in the name of this requirement.
+    ///
+    /// Returns `None` for plain capabilities.
+    pub fn rich_dependency(&self) -> Result<Option<RichDependency>, MetadataError> {
+        if !self.is_rich() {
+            return Ok(None);
+        }
+        RichDependency::parse(&self.name).map(Some)
+    }
+
+    /// The version constraint of the requirement, e.g. `(GE, 1:2.0-1)` for `foo >= 1:2.0-1`.
+    ///
+    /// Returns `None` if the requirement is unversioned.
+    pub fn constraint(&self) -> Option<(RequirementType, EVR)> {
+        self.flags.map(|flags| {
+            let evr = EVR::new(
+                self.epoch.as_deref().unwrap_or(""),
+                self.version.as_deref().unwrap_or(""),
+                self.release.as_deref().unwrap_or(""),
+            );
+            (flags, evr)
+        })
+    }
+
+    /// Whether a package with the given EVR satisfies the version constraint of this requirement.
+    ///
+    /// Unversioned requirements are satisfied by any version. The release is only compared if both
+    /// the requirement and `evr` specify one.
+    pub fn satisfied_by(&self, evr: &EVR) -> bool {
+        match self.constraint() {
+            Some((flags, required)) => {
+                crate::dependency::ranges_overlap(RequirementType::EQ, evr, flags, &required)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether a provided capability (an entry of the provides of a package) satisfies this requirement.
+    ///
+    /// The names must match, and the version ranges of the provide and the requirement must overlap,
+    /// following the same rules as RPM. Unversioned provides satisfy any version of the requirement.
+    pub fn satisfied_by_provide(&self, provide: &Requirement) -> bool {
+        if provide.name != self.name {
+            return false;
+        }
+        match (provide.constraint(), self.constraint()) {
+            (Some((provide_flags, provide_evr)), Some((flags, evr))) => {
+                crate::dependency::ranges_overlap(provide_flags, &provide_evr, flags, &evr)
+            }
+            _ => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum RequirementType {
     LT,
     GT,
@@ -782,6 +1380,52 @@ impl From<RequirementType> for &str {
     }
 }
 
+impl fmt::Display for Requirement {
+    /// Formats the requirement as a capability string, e.g. `foo >= 1:1.2-3`. An epoch of 0 is omitted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if let Some(flags) = self.flags {
+            write!(f, " {} ", flags.as_operator())?;
+            if let Some(epoch) = self.epoch.as_deref().filter(|e| !e.is_empty() && *e != "0") {
+                write!(f, "{}:", epoch)?;
+            }
+            write!(f, "{}", self.version.as_deref().unwrap_or(""))?;
+            if let Some(release) = self.release.as_deref().filter(|r| !r.is_empty()) {
+                write!(f, "-{}", release)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RequirementType {
+    /// Parse a comparison operator as used in spec files, e.g. `>=`.
+    pub fn from_operator(operator: &str) -> Option<Self> {
+        let reqtype = match operator {
+            "<" => RequirementType::LT,
+            "<=" => RequirementType::LE,
+            "=" | "==" => RequirementType::EQ,
+            ">=" => RequirementType::GE,
+            ">" => RequirementType::GT,
+            _ => return None,
+        };
+        Some(reqtype)
+    }
+
+    /// The comparison operator as used in spec files, e.g. `>=`.
+    pub fn as_operator(&self) -> &'static str {
+        match self {
+            RequirementType::LT => "<",
+            RequirementType::LE => "<=",
+            RequirementType::EQ => "=",
+            RequirementType::GE => ">=",
+            RequirementType::GT => ">",
+        }
+    }
+}
+
 impl TryFrom<&str> for RequirementType {
     type Error = MetadataError;
 
@@ -792,7 +1436,7 @@ impl TryFrom<&str> for RequirementType {
             "EQ" => RequirementType::EQ,
             "LE" => RequirementType::LE,
             "GE" => RequirementType::GE,
-            t @ _ => return Err(MetadataError::InvalidFlagsError(t.to_owned())),
+            t => return Err(ParseError::InvalidFlags(t.to_owned()).into()),
         };
 
         Ok(reqtype)
@@ -813,7 +1457,12 @@ impl FileType {
             b"dir" => FileType::Dir,
             b"ghost" => FileType::Ghost,
             b"file" => FileType::File,
-            _ => panic!(),
+            other => {
+                return Err(ParseError::InvalidFileType(
+                    String::from_utf8_lossy(other).into_owned(),
+                )
+                .into())
+            }
         };
         Ok(ftype)
     }
@@ -912,17 +1561,53 @@ impl RepomdData {
         &self.metadata_files
     }
 
-    // pub fn records(&self) -> &BTreeMap<String, RepomdRecord> {
-    //     &self.metadata_files
-    // }
+    pub fn get_record_mut(&mut self, rectype: &str) -> Option<&mut RepomdRecord> {
+        self.metadata_files
+            .iter_mut()
+            .find(|r| r.metadata_name == rectype)
+    }
 
-    // pub fn records_mut(&self) -> &mut BTreeMap<String, RepomdRecord> {
-    //     &mut self.metadata_files
-    // }
+    /// Remove the record of the given type, returning it if it was present.
+    pub fn remove_record(&mut self, rectype: &str) -> Option<RepomdRecord> {
+        let index = self
+            .metadata_files
+            .iter()
+            .position(|r| r.metadata_name == rectype)?;
+        Some(self.metadata_files.remove(index))
+    }
+
+    /// Replace the record of the same type as `record`, keeping its position, and return the old record.
+    ///
+    /// If there is no record of that type, `record` is added at the end.
+    pub fn replace_record(&mut self, record: RepomdRecord) -> Option<RepomdRecord> {
+        match self.get_record_mut(&record.metadata_name) {
+            Some(existing) => Some(std::mem::replace(existing, record)),
+            None => {
+                self.add_record(record);
+                None
+            }
+        }
+    }
 
-    // pub fn remove_record(&mut self, rectype: &str) {
-    //     self.metadata_files.retain(|r| &r.mdtype != rectype);
-    // }
+    /// Sort the records with a custom comparison function. The sort is stable.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, Repository};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let mut repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// # let repomd = repo.repomd_mut();
+    /// repomd.sort_records_by(|a, b| a.metadata_name.cmp(&b.metadata_name));
+    /// # assert_eq!(repomd.records()[0].metadata_name, "filelists");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sort_records_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&RepomdRecord, &RepomdRecord) -> std::cmp::Ordering,
+    {
+        self.metadata_files.sort_by(compare);
+    }
 
     pub fn add_repo_tag(&mut self, repo: String) {
         self.repo_tags.push(repo)
@@ -976,24 +1661,23 @@ impl RepomdData {
         self.metadata_files.sort_by(|a, b| value(a).cmp(&value(b)));
     }
 
-    // TODO error handling
-    pub fn get_primary_data(&self) -> &RepomdRecord {
+    pub fn get_primary_data(&self) -> Result<&RepomdRecord, MetadataError> {
         self.get_record(METADATA_PRIMARY)
-            .expect("Cannot find primary metadata")
+            .ok_or(RepoLayoutError::MissingRecord(METADATA_PRIMARY).into())
     }
 
-    pub fn get_filelist_data(&self) -> &RepomdRecord {
+    pub fn get_filelist_data(&self) -> Result<&RepomdRecord, MetadataError> {
         self.get_record(METADATA_FILELISTS)
-            .expect("Cannot find filelists metadata")
+            .ok_or(RepoLayoutError::MissingRecord(METADATA_FILELISTS).into())
     }
 
-    pub fn get_other_data(&self) -> &RepomdRecord {
+    pub fn get_other_data(&self) -> Result<&RepomdRecord, MetadataError> {
         self.get_record(METADATA_OTHER)
-            .expect("Cannot find other metadata")
+            .ok_or(RepoLayoutError::MissingRecord(METADATA_OTHER).into())
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RepomdRecord {
     base_path: Option<PathBuf>,
 
@@ -1024,6 +1708,28 @@ pub struct RepomdRecord {
     pub database_version: Option<u32>,
 }
 
+impl Default for RepomdRecord {
+    fn default() -> Self {
+        Self {
+            base_path: None,
+            metadata_name: String::new(),
+            location_href: PathBuf::new(),
+            location_base: None,
+            timestamp: 0,
+            size: None,
+            // the checksum of an empty file
+            checksum: Checksum::Sha256(
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_owned(),
+            ),
+            open_size: None,
+            open_checksum: None,
+            header_size: None,
+            header_checksum: None,
+            database_version: None,
+        }
+    }
+}
+
 impl RepomdRecord {
     pub fn new(
         name: &str,
@@ -1033,14 +1739,12 @@ impl RepomdRecord {
     ) -> Result<Self, MetadataError> {
         let mut record = RepomdRecord::default();
         record.metadata_name = name.to_owned();
-        record.location_href = {
-            // let href = href
-            //     .strip_prefix(href.ancestors().nth(2).unwrap())
-            //     .unwrap()
-            //     .to_owned();
-            assert!(href.starts_with("repodata/"));
-            href.to_owned()
-        };
+        if !href.starts_with("repodata/") {
+            return Err(
+                RepoLayoutError::MetadataLocation(href.to_string_lossy().into_owned()).into(),
+            );
+        }
+        record.location_href = href.to_owned();
         record.base_path = Some(base.to_owned());
         record.fill(checksum_type)?;
         Ok(record)
@@ -1050,14 +1754,18 @@ impl RepomdRecord {
         let file_path = self
             .base_path
             .as_ref()
-            .expect("cannot fill metadata if path not on disk")
+            .ok_or(RepoLayoutError::MissingBasePath)?
             .join(&self.location_href);
         let file_metadata = file_path.metadata()?;
-        self.timestamp = file_metadata.mtime();
-        self.size = Some(file_metadata.size());
-        self.checksum = utils::checksum_file(&file_path, checksum_type)?;
-        self.open_checksum = utils::checksum_inner_file(&file_path, checksum_type)?;
-        self.open_size = utils::size_inner_file(&file_path)?;
+        self.timestamp = file_metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |mtime| mtime.as_secs() as i64);
+        let digest = utils::digest_metadata_file(&file_path, checksum_type)?;
+        self.size = Some(digest.size);
+        self.checksum = digest.checksum;
+        self.open_checksum = digest.open_checksum;
+        self.open_size = digest.open_size;
 
         Ok(())
     }
@@ -1080,8 +1788,10 @@ pub struct UpdateRecord {
     pub summary: String,
     pub description: String,
     pub solution: String,
-    // It's not clear that any metadata actually uses this
-    // pub reboot_suggested: bool,
+    /// Set at the update level by some vendors (e.g. SUSE) rather than for individual packages.
+    pub reboot_suggested: bool,
+    pub restart_suggested: bool,
+    pub relogin_suggested: bool,
     pub references: Vec<UpdateReference>,
     pub pkglist: Vec<UpdateCollection>,
 }
@@ -1117,6 +1827,87 @@ pub struct UpdateCollectionPackage {
     pub version: String,
 }
 
+impl UpdateRecord {
+    /// The filenames of the source RPMs which the packages of this advisory were built from, without duplicates.
+    pub fn source_rpms(&self) -> Vec<&str> {
+        let mut source_rpms: Vec<&str> = Vec::new();
+        for package in self.pkglist.iter().flat_map(|c| &c.packages) {
+            if !package.src.is_empty() && !source_rpms.contains(&package.src.as_str()) {
+                source_rpms.push(&package.src);
+            }
+        }
+        source_rpms
+    }
+
+    /// The CVE identifiers addressed by this advisory, without duplicates.
+    ///
+    /// Identifiers are collected from references of type "cve" first, and then from the description.
+    pub fn cve_ids(&self) -> Vec<String> {
+        let mut cve_ids = Vec::new();
+        for reference in self.references.iter().filter(|r| r.reftype == "cve") {
+            for field in [&reference.id, &reference.title, &reference.href] {
+                find_cve_ids(field, &mut cve_ids);
+            }
+        }
+        find_cve_ids(&self.description, &mut cve_ids);
+        cve_ids
+    }
+}
+
+/// Collect the identifiers like `CVE-2022-12345` within a string which aren't already present in `cve_ids`.
+fn find_cve_ids(text: &str, cve_ids: &mut Vec<String>) {
+    let is_digits = |s: &[u8]| s.iter().all(u8::is_ascii_digit);
+    let bytes = text.as_bytes();
+    let mut pos = 0;
+
+    while let Some(offset) = text[pos..].find("CVE-") {
+        let start = pos + offset;
+        pos = start + 4;
+
+        // must not be part of a longer word, and must be "CVE-", 4 digit year, "-", at least 4 digits
+        if start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+            continue;
+        }
+        let year = &bytes[pos..(pos + 5).min(bytes.len())];
+        if year.len() != 5 || !is_digits(&year[..4]) || year[4] != b'-' {
+            continue;
+        }
+        let number_len = bytes[pos + 5..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if number_len < 4 {
+            continue;
+        }
+
+        let end = pos + 5 + number_len;
+        let cve_id = &text[start..end];
+        if !cve_ids.iter().any(|id| id == cve_id) {
+            cve_ids.push(cve_id.to_owned());
+        }
+        pos = end;
+    }
+}
+
+impl UpdateCollectionPackage {
+    /// The EVR of the package.
+    pub fn evr(&self) -> EVR {
+        EVR::new(self.epoch.as_str(), &self.version, &self.release)
+    }
+
+    /// Whether this entry refers to `package`, i.e. they have the same name, architecture and EVR.
+    pub fn refers_to(&self, package: &Package) -> bool {
+        self.name == package.name() && self.arch == package.arch() && &self.evr() == package.evr()
+    }
+
+    /// Whether this entry refers to a source package rather than a binary package.
+    ///
+    /// For source packages, `src` and `filename` both refer to the `.src.rpm` file.
+    pub fn is_source(&self) -> bool {
+        self.arch == "src" || self.arch == "nosrc"
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct UpdateCollectionModule {
     pub name: String,
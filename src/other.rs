@@ -10,10 +10,11 @@ use quick_xml::escape::partial_escape;
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Reader, Writer};
 
-use crate::Checksum;
-
-use super::metadata::{Changelog, OtherXml, Package, RpmMetadata, XML_NS_OTHER};
-use super::{MetadataError, Repository, EVR};
+use super::metadata::{
+    Changelog, ChangelogTimestampPolicy, OtherXml, Package, RpmMetadata, METADATA_OTHER,
+    XML_NS_OTHER,
+};
+use super::{utils, MetadataError, ParseError, Repository, EVR};
 
 const TAG_OTHERDATA: &[u8] = b"otherdata";
 const TAG_PACKAGE: &[u8] = b"package";
@@ -67,7 +68,10 @@ impl RpmMetadata for OtherXml {
 
 impl OtherXml {
     pub fn new_writer<W: Write>(writer: quick_xml::Writer<W>) -> OtherXmlWriter<W> {
-        OtherXmlWriter { writer }
+        OtherXmlWriter {
+            writer,
+            changelog_timestamp_policy: ChangelogTimestampPolicy::default(),
+        }
     }
 
     pub fn new_reader<R: BufRead>(reader: quick_xml::Reader<R>) -> OtherXmlReader<R> {
@@ -77,10 +81,25 @@ impl OtherXml {
 
 pub struct OtherXmlWriter<W: Write> {
     writer: Writer<W>,
+    changelog_timestamp_policy: ChangelogTimestampPolicy,
 }
 
 impl<W: Write> OtherXmlWriter<W> {
     pub fn write_header(&mut self, num_pkgs: usize) -> Result<(), MetadataError> {
+        self.write_header_with_padding(num_pkgs, 0)
+    }
+
+    /// Write the header, reserving enough space for the package count to be corrected in place later
+    /// with [`utils::rewrite_package_count`], if the output is seekable and uncompressed.
+    pub fn write_resizable_header(&mut self, num_pkgs: usize) -> Result<(), MetadataError> {
+        self.write_header_with_padding(num_pkgs, utils::PACKAGE_COUNT_PADDING)
+    }
+
+    fn write_header_with_padding(
+        &mut self,
+        num_pkgs: usize,
+        padding: usize,
+    ) -> Result<(), MetadataError> {
         // <?xml version="1.0" encoding="UTF-8"?>
         self.writer
             .write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
@@ -89,15 +108,24 @@ impl<W: Write> OtherXmlWriter<W> {
         let mut other_tag = BytesStart::borrowed_name(TAG_OTHERDATA);
         other_tag.push_attribute(("xmlns", XML_NS_OTHER));
         other_tag.push_attribute(("packages", num_pkgs.to_string().as_str()));
+        let other_tag = utils::pad_start_tag(other_tag, padding);
         self.writer.write_event(Event::Start(other_tag))?;
 
         Ok(())
     }
 
+    /// Set how changelog entries with out-of-range timestamps are written. See [`ChangelogTimestampPolicy`].
+    pub fn set_changelog_timestamp_policy(&mut self, policy: ChangelogTimestampPolicy) {
+        self.changelog_timestamp_policy = policy;
+    }
+
     pub fn write_package(&mut self, package: &Package) -> Result<(), MetadataError> {
         // <package pkgid="6a915b6e1ad740994aa9688d70a67ff2b6b72e0ced668794aeb27b2d0f2e237b" name="fontconfig" arch="x86_64">
         let mut package_tag = BytesStart::borrowed_name(TAG_PACKAGE);
-        let (_, pkgid) = package.checksum().to_values()?;
+        let pkgid = package.pkgid();
+        if pkgid.is_empty() {
+            return Err(ParseError::MissingField("pkgid").into());
+        }
         package_tag.push_attribute(("pkgid", pkgid));
         package_tag.push_attribute(("name", package.name()));
         package_tag.push_attribute(("arch", package.arch()));
@@ -114,11 +142,15 @@ impl<W: Write> OtherXmlWriter<W> {
             .write_empty()?;
 
         for changelog in package.changelogs() {
+            let timestamp = match self.changelog_timestamp_policy.apply(changelog.timestamp) {
+                Some(timestamp) => timestamp,
+                None => continue,
+            };
             //  <changelog author="dalley &lt;dalley@redhat.com&gt; - 2.7.2-1" date="1251720000">- Update to 2.7.2</changelog>
             self.writer
                 .create_element(TAG_CHANGELOG)
                 .with_attribute(("author", changelog.author.as_str()))
-                .with_attribute(("date", changelog.timestamp.to_string().as_str()))
+                .with_attribute(("date", timestamp.to_string().as_str()))
                 .write_text_content(BytesText::from_escaped(partial_escape(
                     &changelog.description.as_bytes(),
                 )))?;
@@ -130,6 +162,13 @@ impl<W: Write> OtherXmlWriter<W> {
         Ok(())
     }
 
+    /// Write a pre-serialized `<package>` element of other.xml, without parsing it.
+    ///
+    /// See [`crate::RepositoryWriter::add_package_raw`].
+    pub fn write_package_raw(&mut self, fragment: &str) -> Result<(), MetadataError> {
+        utils::write_raw_package(&mut self.writer, fragment)
+    }
+
     pub fn finish(&mut self) -> Result<(), MetadataError> {
         // </otherdata>
         self.writer
@@ -159,8 +198,17 @@ impl<R: BufRead> OtherXmlReader<R> {
         parse_header(&mut self.reader)
     }
 
-    pub fn read_package(&mut self, package: &mut Option<Package>) -> Result<(), MetadataError> {
-        parse_package(package, &mut self.reader)
+    /// Read the next package from the metadata into `package`.
+    ///
+    /// Returns `false` if there were no more packages to read.
+    pub fn read_package(&mut self, package: &mut Option<Package>) -> Result<bool, MetadataError> {
+        parse_package(package, &mut self.reader).map_err(|e| {
+            e.at_package_position(
+                METADATA_OTHER,
+                self.reader.buffer_position(),
+                package.as_ref(),
+            )
+        })
     }
 }
 
@@ -174,10 +222,13 @@ fn parse_header<R: BufRead>(reader: &mut Reader<R>) -> Result<usize, MetadataErr
         match reader.read_event(&mut buf)? {
             Event::Decl(_) => (),
             Event::Start(e) if e.name().as_ref() == TAG_OTHERDATA => {
-                let count = e.try_get_attribute("packages")?.unwrap().value;
+                let count = e
+                    .try_get_attribute("packages")?
+                    .ok_or(ParseError::MissingAttribute("packages"))?
+                    .value;
                 return Ok(std::str::from_utf8(&count)?.parse()?);
             }
-            _ => return Err(MetadataError::MissingHeaderError),
+            _ => return Err(ParseError::MissingHeader.into()),
         }
     }
 }
@@ -191,10 +242,10 @@ fn parse_header<R: BufRead>(reader: &mut Reader<R>) -> Result<usize, MetadataErr
 pub fn parse_package<R: BufRead>(
     package: &mut Option<Package>,
     reader: &mut Reader<R>,
-) -> Result<(), MetadataError> {
+) -> Result<bool, MetadataError> {
     let mut buf = Vec::with_capacity(128);
+    let mut found_package = false;
 
-    // TODO: get rid of unwraps, various branches could happen in wrong order
     loop {
         match reader.read_event(&mut buf)? {
             Event::End(e) if e.name().as_ref() == TAG_PACKAGE => break,
@@ -202,34 +253,41 @@ pub fn parse_package<R: BufRead>(
                 TAG_PACKAGE => {
                     let pkgid = e
                         .try_get_attribute("pkgid")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("pkgid"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("pkgid"))?
                         .unescape_and_decode_value(reader)?;
                     let name = e
                         .try_get_attribute("name")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("name"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("name"))?
                         .unescape_and_decode_value(reader)?;
                     let arch = e
                         .try_get_attribute("arch")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("arch"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("arch"))?
                         .unescape_and_decode_value(reader)?;
 
+                    found_package = true;
+
                     if let Some(pkg) = package {
-                        assert!(pkg.pkgid() == &pkgid); // TODO err instead of assert
+                        if pkg.pkgid() != pkgid {
+                            return Err(ParseError::InconsistentMetadata(format!(
+                                "expected package with pkgid {}, found {}",
+                                pkg.pkgid(),
+                                pkgid
+                            ))
+                            .into());
+                        }
                     } else {
                         let mut pkg = Package::default();
-                        pkg.set_name(&name)
-                            .set_arch(&arch)
-                            .set_checksum(Checksum::Unknown(pkgid));
+                        pkg.set_name(&name).set_arch(arch.as_str()).set_pkgid(pkgid);
                         *package = Some(pkg);
                     };
                 }
                 TAG_VERSION => {
-                    package.as_mut().unwrap().set_evr(parse_evr(reader, &e)?);
+                    utils::enclosing(package, TAG_VERSION)?.set_evr(parse_evr(reader, &e)?);
                 }
                 TAG_CHANGELOG => {
                     let changelog = parse_changelog(reader, &e)?;
                     // TODO: Temporary changelog?
-                    package.as_mut().unwrap().add_changelog(
+                    utils::enclosing(package, TAG_CHANGELOG)?.add_changelog(
                         &changelog.author,
                         &changelog.description,
                         changelog.timestamp,
@@ -237,13 +295,15 @@ pub fn parse_package<R: BufRead>(
                 }
                 _ => (),
             },
+            // the input ended in the middle of a package
+            Event::Eof if found_package => return Err(utils::unexpected_eof(TAG_PACKAGE)),
             Event::Eof => break,
             _ => (),
         }
     }
 
     // package.parse_state |= ParseState::OTHER;
-    Ok(())
+    Ok(found_package)
 }
 
 // <version epoch="0" ver="2.8.0" rel="5.el6"/>
@@ -253,15 +313,15 @@ pub fn parse_evr<R: BufRead>(
 ) -> Result<EVR, MetadataError> {
     let epoch = open_tag
         .try_get_attribute("epoch")?
-        .unwrap()
+        .ok_or_else(|| ParseError::MissingAttribute("epoch"))?
         .unescape_and_decode_value(reader)?;
     let version = open_tag
         .try_get_attribute("ver")?
-        .unwrap()
+        .ok_or_else(|| ParseError::MissingAttribute("ver"))?
         .unescape_and_decode_value(reader)?;
     let release = open_tag
         .try_get_attribute("rel")?
-        .unwrap()
+        .ok_or_else(|| ParseError::MissingAttribute("rel"))?
         .unescape_and_decode_value(reader)?;
 
     Ok(EVR::new(epoch, version, release))
@@ -276,11 +336,11 @@ pub fn parse_changelog<R: BufRead>(
 
     changelog.author = open_tag
         .try_get_attribute("author")?
-        .unwrap()
+        .ok_or_else(|| ParseError::MissingAttribute("author"))?
         .unescape_and_decode_value(reader)?;
     changelog.timestamp = open_tag
         .try_get_attribute("date")?
-        .unwrap()
+        .ok_or_else(|| ParseError::MissingAttribute("date"))?
         .unescape_and_decode_value(reader)?
         .parse()?;
 
@@ -4,21 +4,28 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::collections::HashMap;
 use std::io::BufReader;
 use std::path::Path;
+use std::sync::Arc;
 
 use crate::filelist::FilelistsXmlReader;
 use crate::metadata::{METADATA_FILELISTS, METADATA_OTHER, METADATA_PRIMARY};
 use crate::other::OtherXmlReader;
 use crate::primary::PrimaryXmlReader;
-use crate::{utils, RepomdData};
-use crate::{FilelistsXml, MetadataError, OtherXml, Package, PrimaryXml};
+use crate::progress::{ProgressEvent, ProgressHandler};
+use crate::warning::{MetadataWarning, WarningHandler};
+use crate::{utils, HrefPolicy, RepomdData};
+use crate::{
+    FilelistsXml, MetadataError, OtherXml, Package, ParseError, PrimaryXml, RepoLayoutError,
+};
 
 #[cfg(feature = "read_rpm")]
 pub mod rpm_parsing {
     use std::fs::File;
     use std::time::SystemTime;
 
+    use crate::metadata::{RequirementType, EXTRA_SIGNATURE_KEY_ID, EXTRA_SIGNATURE_TYPE};
     use crate::{Changelog, ChecksumType, PackageFile, Requirement, EVR};
 
     use super::*;
@@ -29,15 +36,15 @@ pub mod rpm_parsing {
 
         fn try_from(d: rpm::Dependency) -> Result<Self, Self::Error> {
             let flags = if d.flags.contains(rpm::DependencyFlags::GE) {
-                Some("GE".to_owned())
+                Some(RequirementType::GE)
             } else if d.flags.contains(rpm::DependencyFlags::LE) {
-                Some("LE".to_owned())
+                Some(RequirementType::LE)
             } else if d.flags.contains(rpm::DependencyFlags::EQUAL) {
-                Some("EQ".to_owned())
+                Some(RequirementType::EQ)
             } else if d.flags.contains(rpm::DependencyFlags::LESS) {
-                Some("LT".to_owned())
+                Some(RequirementType::LT)
             } else if d.flags.contains(rpm::DependencyFlags::GREATER) {
-                Some("GT".to_owned())
+                Some(RequirementType::GT)
             } else {
                 None
             };
@@ -70,7 +77,7 @@ pub mod rpm_parsing {
             };
 
             Ok(Requirement {
-                name: d.name,
+                name: d.name.into(),
                 flags,
                 epoch,
                 version,
@@ -92,148 +99,350 @@ pub mod rpm_parsing {
 
     impl From<rpm::FileEntry> for PackageFile {
         fn from(value: rpm::FileEntry) -> Self {
-            let ft = if value.flags.contains(rpm::FileFlags::GHOST) {
-                crate::FileType::Ghost
-            } else {
-                match value.mode {
-                    rpm::FileMode::Dir { .. } => crate::FileType::Dir,
-                    rpm::FileMode::Regular { .. } => crate::FileType::File,
-                    _ => unreachable!("Failed to detect file type"),
-                }
+            // same precedence as createrepo_c - symlinks, devices etc. are plain files
+            let ft = match value.mode {
+                rpm::FileMode::Dir { .. } => crate::FileType::Dir,
+                _ if value.flags.contains(rpm::FileFlags::GHOST) => crate::FileType::Ghost,
+                _ => crate::FileType::File,
             };
-            let path = value
-                .path
-                .into_os_string()
-                .into_string()
-                .expect("failed to convert PathBuf to String");
+            let path = value.path.to_string_lossy().into_owned();
             PackageFile { filetype: ft, path }
         }
     }
 
-    // todo: restrict # of changelogs
-    // todo: location_href, location_base
-    // todo: checksum type
+    /// Load the metadata of an RPM package, using a SHA-256 checksum and the provided path as the location.
     pub fn load_rpm_package(path: &str) -> Result<Package, MetadataError> {
-        let file = File::open(&path)?;
-        let file_metadata = file.metadata()?;
+        Package::from_rpm_file(Path::new(path), ChecksumType::Sha256, path)
+    }
+
+    impl Package {
+        /// Read the metadata of an RPM package file, as createrepo_c would.
+        ///
+        /// - `checksum_type` - The type of checksum to use as the package checksum (pkgid).
+        /// - `location_href` - The location of the package relative to the repository root.
+        ///
+        /// Optional header tags which are missing from the package, such as `url` or `vendor`, are left empty.
+        pub fn from_rpm_file(
+            path: &Path,
+            checksum_type: ChecksumType,
+            location_href: &str,
+        ) -> Result<Package, MetadataError> {
+            let file = File::open(path)?;
+            let file_metadata = file.metadata()?;
+
+            let pkg = rpm::PackageMetadata::parse(&mut BufReader::new(&file))?;
+
+            let mut pkg_metadata = Package::default();
+
+            pkg_metadata.set_name(pkg.get_name()?);
+
+            let arch = if pkg.is_source_package() {
+                "src"
+            } else {
+                pkg.get_arch()?
+            };
+
+            pkg_metadata.set_arch(arch);
+            pkg_metadata.set_epoch(pkg.get_epoch().unwrap_or(0));
+            pkg_metadata.set_version(pkg.get_version()?);
+            pkg_metadata.set_release(pkg.get_release()?);
+
+            pkg_metadata.set_summary(pkg.get_summary().unwrap_or_default());
+            pkg_metadata.set_description(pkg.get_description().unwrap_or_default());
+            pkg_metadata.set_packager(pkg.get_packager().unwrap_or_default());
+            pkg_metadata.set_url(pkg.get_url().unwrap_or_default());
+            pkg_metadata.set_time_build(pkg.get_build_time().unwrap_or_default());
+            pkg_metadata.set_rpm_license(pkg.get_license().unwrap_or_default());
+            pkg_metadata.set_rpm_vendor(pkg.get_vendor().unwrap_or_default());
+            pkg_metadata.set_rpm_group(pkg.get_group().unwrap_or_default());
+            pkg_metadata.set_rpm_buildhost(pkg.get_build_host().unwrap_or_default());
+            // source packages don't have a source rpm
+            pkg_metadata.set_rpm_sourcerpm(pkg.get_source_rpm().unwrap_or_default());
+
+            let archive_size = pkg
+                .signature
+                .get_entry_data_as_u64(rpm::IndexSignatureTag::RPMSIGTAG_LONGARCHIVESIZE)
+                .unwrap_or_else(|_| {
+                    pkg.signature
+                        .get_entry_data_as_u32(rpm::IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE)
+                        .unwrap_or(0) as u64
+                });
+            pkg_metadata.set_size_archive(archive_size);
+            pkg_metadata.set_size_installed(pkg.get_installed_size().unwrap_or_default());
+
+            fn convert_deps(
+                dependencies: Vec<rpm::Dependency>,
+                is_requires: bool,
+            ) -> Result<Vec<Requirement>, MetadataError> {
+                let mut out = Vec::new();
+                for d in dependencies.into_iter() {
+                    // rpmlib() requirements are satisfied by rpm itself, createrepo_c leaves them out
+                    if is_requires && d.name.starts_with("rpmlib(") {
+                        continue;
+                    }
+                    let mut requirement: Requirement = d.try_into()?;
+                    // only requirements can be needed before installation
+                    requirement.preinstall &= is_requires;
+                    out.push(requirement)
+                }
+                Ok(out)
+            }
+            // todo: deduplicate requires with provides, remove provided deps from requires
+            pkg_metadata.set_requires(convert_deps(pkg.get_requires()?, true)?);
+            pkg_metadata.set_provides(convert_deps(pkg.get_provides()?, false)?);
+            pkg_metadata.set_conflicts(convert_deps(pkg.get_conflicts()?, false)?);
+            pkg_metadata.set_obsoletes(convert_deps(pkg.get_obsoletes()?, false)?);
+            pkg_metadata.set_suggests(convert_deps(pkg.get_suggests()?, false)?);
+            pkg_metadata.set_enhances(convert_deps(pkg.get_enhances()?, false)?);
+            pkg_metadata.set_recommends(convert_deps(pkg.get_recommends()?, false)?);
+            pkg_metadata.set_supplements(convert_deps(pkg.get_supplements()?, false)?);
+
+            // todo: restrict number
+            let mut changelogs: Vec<Changelog> = Vec::new();
+            for f in pkg.get_changelog_entries()?.into_iter() {
+                changelogs.push(f.into())
+            }
+            changelogs.sort_by_key(|a| a.timestamp);
+            pkg_metadata.set_changelogs(changelogs);
 
-        let pkg = rpm::PackageMetadata::parse(&mut BufReader::new(&file))?;
+            let mut files: Vec<PackageFile> = Vec::new();
+            for f in pkg.get_file_entries()?.into_iter() {
+                files.push(f.into())
+            }
+            pkg_metadata.set_files(files);
 
-        let mut pkg_metadata = Package::default();
+            pkg_metadata.set_checksum(utils::checksum_file(path, checksum_type)?);
+            pkg_metadata.set_location_href(location_href);
 
-        pkg_metadata.set_name(pkg.get_name()?);
+            let file_size = file_metadata.len();
+            let unix_timestamp = file_metadata
+                .modified()?
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
 
-        let arch = if pkg.is_source_package() {
-            "src"
-        } else {
-            pkg.get_arch()?
-        };
+            pkg_metadata.set_size_package(file_size);
+            pkg_metadata.set_time_file(unix_timestamp);
+
+            let offsets = pkg.get_package_segment_offsets();
+            pkg_metadata.set_rpm_header_range(offsets.header, offsets.payload);
+
+            load_extra_tags(&pkg, &mut pkg_metadata);
+
+            Ok(pkg_metadata)
+        }
+    }
 
-        // TODO: handle tags that aren't guaranteed to exist
-        // like url, description, time_build, group, etc.
-        pkg_metadata.set_arch(arch);
-        pkg_metadata.set_epoch(pkg.get_epoch().unwrap_or(0));
-        pkg_metadata.set_version(pkg.get_version()?);
-        pkg_metadata.set_release(pkg.get_release()?);
-
-        pkg_metadata.set_summary(pkg.get_summary()?);
-        pkg_metadata.set_description(pkg.get_description()?);
-        pkg_metadata.set_packager(pkg.get_packager()?);
-        pkg_metadata.set_url(pkg.get_url()?);
-        pkg_metadata.set_description(pkg.get_description()?);
-        pkg_metadata.set_time_build(pkg.get_build_time()?);
-        pkg_metadata.set_rpm_license(pkg.get_license()?);
-        pkg_metadata.set_rpm_vendor(pkg.get_vendor()?);
-        pkg_metadata.set_rpm_group(pkg.get_group()?);
-        pkg_metadata.set_rpm_buildhost(pkg.get_build_host()?);
-        pkg_metadata.set_rpm_sourcerpm(pkg.get_source_rpm()?);
-
-        let archive_size = pkg
-            .signature
-            .get_entry_data_as_u64(rpm::IndexSignatureTag::RPMSIGTAG_LONGARCHIVESIZE)
-            .unwrap_or_else(|_| {
-                pkg.signature
-                    .get_entry_data_as_u32(rpm::IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE)
-                    .unwrap_or(0) as u64
-            });
-        pkg_metadata.set_size_archive(archive_size);
-        pkg_metadata.set_size_installed(pkg.get_installed_size()?);
-
-        fn convert_deps(
-            requirements: Vec<rpm::Dependency>,
-        ) -> Result<Vec<Requirement>, MetadataError> {
-            let mut out = Vec::new();
-            for r in requirements.into_iter() {
-                if r.name.starts_with("rpmlib(") {
-                    continue;
+    /// Capture header tags which aren't part of the repository metadata into [`Package::extra`].
+    ///
+    /// Tags which are not present in the header are skipped.
+    fn load_extra_tags(pkg: &rpm::PackageMetadata, pkg_metadata: &mut Package) {
+        let string_array_tags = [
+            ("excludearch", rpm::IndexTag::RPMTAG_EXCLUDEARCH),
+            ("exclusivearch", rpm::IndexTag::RPMTAG_EXCLUSIVEARCH),
+            ("excludeos", rpm::IndexTag::RPMTAG_EXCLUDEOS),
+            ("exclusiveos", rpm::IndexTag::RPMTAG_EXCLUSIVEOS),
+            ("payloaddigest", rpm::IndexTag::RPMTAG_PAYLOADDIGEST),
+        ];
+        for (name, tag) in string_array_tags {
+            if let Ok(values) = pkg.header.get_entry_data_as_string_array(tag) {
+                let values = values.iter().map(|v| v.to_string()).collect();
+                pkg_metadata.set_extra(name, values);
+            }
+        }
+
+        // only the first signature found is recorded - RSA and DSA sign the header, PGP and GPG the header and payload
+        let signature_tags = [
+            ("rsa", rpm::IndexSignatureTag::RPMSIGTAG_RSA),
+            ("dsa", rpm::IndexSignatureTag::RPMSIGTAG_DSA),
+            ("pgp", rpm::IndexSignatureTag::RPMSIGTAG_PGP),
+            ("gpg", rpm::IndexSignatureTag::RPMSIGTAG_GPG),
+        ];
+        for (name, tag) in signature_tags {
+            if let Ok(signature) = pkg.signature.get_entry_data_as_binary(tag) {
+                pkg_metadata.set_extra(EXTRA_SIGNATURE_TYPE, vec![name.to_owned()]);
+                if let Some(key_id) = utils::pgp_signature_key_id(signature) {
+                    pkg_metadata
+                        .set_extra(EXTRA_SIGNATURE_KEY_ID, vec![format!("{:016x}", key_id)]);
                 }
-                out.push(r.try_into()?)
+                break;
             }
-            Ok(out)
         }
-        // todo: only apply rpmlib filter to requires
-        // todo: deduplicate requires with provides, remove provided deps from requires
-        pkg_metadata.set_requires(convert_deps(pkg.get_requires()?)?);
-        pkg_metadata.set_provides(convert_deps(pkg.get_provides()?)?);
-        pkg_metadata.set_conflicts(convert_deps(pkg.get_conflicts()?)?);
-        pkg_metadata.set_obsoletes(convert_deps(pkg.get_obsoletes()?)?);
-        pkg_metadata.set_suggests(convert_deps(pkg.get_suggests()?)?);
-        pkg_metadata.set_enhances(convert_deps(pkg.get_enhances()?)?);
-        pkg_metadata.set_recommends(convert_deps(pkg.get_recommends()?)?);
-        pkg_metadata.set_supplements(convert_deps(pkg.get_supplements()?)?);
-
-        // todo: restrict number
-        let mut changelogs: Vec<Changelog> = Vec::new();
-        for f in pkg.get_changelog_entries()?.into_iter() {
-            changelogs.push(f.into())
+
+        if let Ok(platform) = pkg
+            .header
+            .get_entry_data_as_string(rpm::IndexTag::RPMTAG_PLATFORM)
+        {
+            pkg_metadata.set_extra("platform", vec![platform.to_owned()]);
         }
-        changelogs.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-        pkg_metadata.set_changelogs(changelogs);
 
-        // todo: filter files
-        let mut files: Vec<PackageFile> = Vec::new();
-        for f in pkg.get_file_entries()?.into_iter() {
-            files.push(f.into())
+        // https://github.com/rpm-software-management/rpm/blob/master/include/rpm/rpmpgp.h (pgpHashAlgo)
+        if let Ok(algo) = pkg
+            .header
+            .get_entry_data_as_u32(rpm::IndexTag::RPMTAG_PAYLOADDIGESTALGO)
+        {
+            let algo = match algo {
+                1 => "md5".to_owned(),
+                2 => "sha1".to_owned(),
+                8 => "sha256".to_owned(),
+                9 => "sha384".to_owned(),
+                10 => "sha512".to_owned(),
+                11 => "sha224".to_owned(),
+                other => other.to_string(),
+            };
+            pkg_metadata.set_extra("payloaddigestalgo", vec![algo]);
         }
-        pkg_metadata.set_files(files);
+    }
+}
 
-        pkg_metadata.set_checksum(utils::checksum_file(Path::new(path), ChecksumType::Sha256)?);
-        pkg_metadata.set_location_href(path);
+/// Determines how a [`PackageIterator`] behaves when the package counts declared in the headers of
+/// primary.xml, filelists.xml and other.xml disagree.
+///
+/// Some repositories in the wild have slightly inconsistent metadata, e.g. after a partial regeneration.
+///
+/// With `Warn` and `TrustPrimary`, a mismatch in the declared counts switches the matching of
+/// filelists.xml and other.xml entries to [`PackageMatchPolicy::ByPkgid`], as an entry missing from the
+/// middle of a file would otherwise misalign every entry after it. Until the missing package's entries
+/// are known to be absent, the entries read ahead are buffered in memory, up to the rest of the file.
+/// Packages missing from files which declare the same count as primary.xml aren't detected upfront, and
+/// are still an error with [`PackageMatchPolicy::InOrder`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PackageCountMismatchPolicy {
+    /// Fail with [`ParseError::InconsistentMetadata`] before any packages are yielded.
+    #[default]
+    Error,
+    /// Continue, yielding only the packages present in all three metadata files, matched up by pkgid. The
+    /// mismatched counts and the skipped packages are reported as [`crate::MetadataWarning`]s.
+    Warn,
+    /// Continue, yielding every package in primary.xml. Packages missing from filelists.xml or
+    /// other.xml are yielded without files or changelogs.
+    TrustPrimary,
+}
 
-        let file_size = file_metadata.len();
-        let unix_timestamp = file_metadata
-            .modified()?
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+/// How the entries of filelists.xml and other.xml are matched up with the packages of primary.xml.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PackageMatchPolicy {
+    /// Expect filelists.xml and other.xml to list the packages in the same order as primary.xml, which
+    /// is the case for metadata produced by createrepo_c. Any other order is an error.
+    #[default]
+    InOrder,
+    /// Match the entries of filelists.xml and other.xml to packages by pkgid, regardless of order.
+    ///
+    /// Entries which appear before the package they belong to in primary.xml are buffered until it is
+    /// reached, so memory usage grows with how far out of order the files are. Packages without an entry
+    /// are handled according to the [`PackageCountMismatchPolicy`].
+    ByPkgid,
+}
 
-        pkg_metadata.set_size_package(file_size);
-        pkg_metadata.set_time_file(unix_timestamp);
+/// A package attribute, used to select which parts of the package metadata are parsed.
+///
+/// See [`PackageIterator::project`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    Name,
+    Arch,
+    Evr,
+    Location,
+    Summary,
+    Description,
+    Packager,
+    Url,
+    Time,
+    Size,
+    License,
+    Vendor,
+    Group,
+    Buildhost,
+    SourceRpm,
+    HeaderRange,
+    Provides,
+    Requires,
+    Conflicts,
+    Obsoletes,
+    Suggests,
+    Enhances,
+    Recommends,
+    Supplements,
+    Files,
+    Changelogs,
+}
+
+/// A set of [`Field`]s
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct FieldSet(u32);
+
+impl FieldSet {
+    pub(crate) fn all() -> Self {
+        FieldSet(u32::MAX)
+    }
 
-        let offsets = pkg.get_package_segment_offsets();
-        pkg_metadata.set_rpm_header_range(offsets.header, offsets.payload);
+    pub(crate) fn from_fields(fields: &[Field]) -> Self {
+        FieldSet(fields.iter().fold(0, |set, f| set | (1 << *f as u32)))
+    }
 
-        Ok(pkg_metadata)
+    pub(crate) fn contains(&self, field: Field) -> bool {
+        self.0 & (1 << field as u32) != 0
+    }
+
+    pub(crate) fn with(self, field: Field, val: bool) -> Self {
+        if val {
+            FieldSet(self.0 | (1 << field as u32))
+        } else {
+            FieldSet(self.0 & !(1 << field as u32))
+        }
+    }
+}
+
+impl Default for FieldSet {
+    fn default() -> Self {
+        FieldSet::all()
     }
 }
 
 pub struct PackageIterator {
     primary_xml: PrimaryXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
-    filelists_xml: FilelistsXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
-    other_xml: OtherXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
+    filelists_xml: Option<FilelistsXmlReader<BufReader<Box<dyn std::io::Read + Send>>>>,
+    other_xml: Option<OtherXmlReader<BufReader<Box<dyn std::io::Read + Send>>>>,
+
+    count_mismatch_policy: PackageCountMismatchPolicy,
+    match_policy: PackageMatchPolicy,
+    pending_filelists: HashMap<String, Package>,
+    pending_other: HashMap<String, Package>,
+    header_counts: (usize, usize, usize),
+    exhausted: bool,
+    fields: FieldSet,
 
     num_packages: usize,
     num_remaining: usize,
     in_progress_package: Option<Package>,
+    progress_handler: Option<Arc<dyn ProgressHandler>>,
+    warning_handler: Option<Arc<dyn WarningHandler>>,
+    arches: Option<Vec<String>>,
+    href_policy: HrefPolicy,
+    max_packages: usize,
+    num_parsed: usize,
+    counts_checked: bool,
 }
 
 impl PackageIterator {
+    /// Create an iterator over the package metadata of the repository at `base`.
+    ///
+    /// filelists.xml and other.xml are skipped if they are not present in `repomd`.
     pub fn from_repodata(base: &Path, repomd: &RepomdData) -> Result<Self, MetadataError> {
-        let primary_path = base.join(&repomd.get_record(METADATA_PRIMARY).unwrap().location_href);
-        let filelists_path =
-            base.join(&repomd.get_record(METADATA_FILELISTS).unwrap().location_href);
-        let other_path = base.join(&repomd.get_record(METADATA_OTHER).unwrap().location_href);
-        Self::from_files(&primary_path, &filelists_path, &other_path)
+        let open = |name: &str| {
+            repomd
+                .get_record(name)
+                .map(|record| utils::xml_reader_from_file(&base.join(&record.location_href)))
+                .transpose()
+        };
+        let primary_xml =
+            open(METADATA_PRIMARY)?.ok_or(RepoLayoutError::MissingRecord(METADATA_PRIMARY))?;
+
+        Self::from_optional_readers(
+            PrimaryXml::new_reader(primary_xml),
+            open(METADATA_FILELISTS)?.map(FilelistsXml::new_reader),
+            open(METADATA_OTHER)?.map(OtherXml::new_reader),
+        )
     }
 
     pub fn from_files(
@@ -252,57 +461,299 @@ impl PackageIterator {
         primary_xml: PrimaryXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
         filelists_xml: FilelistsXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
         other_xml: OtherXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
+    ) -> Result<Self, MetadataError> {
+        Self::from_optional_readers(primary_xml, Some(filelists_xml), Some(other_xml))
+    }
+
+    /// Create an iterator which only reads primary.xml.
+    ///
+    /// Packages are yielded without files (other than those listed in primary.xml) or changelogs,
+    /// which avoids reading the much larger filelists.xml and other.xml.
+    pub fn from_primary_only(primary_path: &Path) -> Result<Self, MetadataError> {
+        let primary_xml = PrimaryXml::new_reader(utils::xml_reader_from_file(primary_path)?);
+        Self::from_primary_reader(primary_xml)
+    }
+
+    /// Create an iterator which only reads primary.xml from the provided reader.
+    ///
+    /// See [`PackageIterator::from_primary_only`].
+    pub fn from_primary_reader(
+        primary_xml: PrimaryXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
+    ) -> Result<Self, MetadataError> {
+        Self::from_optional_readers(primary_xml, None, None)
+    }
+
+    pub(crate) fn from_optional_readers(
+        primary_xml: PrimaryXmlReader<BufReader<Box<dyn std::io::Read + Send>>>,
+        filelists_xml: Option<FilelistsXmlReader<BufReader<Box<dyn std::io::Read + Send>>>>,
+        other_xml: Option<OtherXmlReader<BufReader<Box<dyn std::io::Read + Send>>>>,
     ) -> Result<Self, MetadataError> {
         let mut parser = Self {
             primary_xml,
             filelists_xml,
             other_xml,
+            count_mismatch_policy: PackageCountMismatchPolicy::default(),
+            match_policy: PackageMatchPolicy::default(),
+            pending_filelists: HashMap::new(),
+            pending_other: HashMap::new(),
+            header_counts: (0, 0, 0),
+            exhausted: false,
+            fields: FieldSet::all(),
             num_packages: 0,
             num_remaining: 0,
             in_progress_package: None,
+            progress_handler: None,
+            warning_handler: None,
+            arches: None,
+            href_policy: HrefPolicy::default(),
+            max_packages: usize::MAX,
+            num_parsed: 0,
+            counts_checked: false,
         };
         parser.parse_headers()?;
 
         Ok(parser)
     }
 
+    /// Set the policy for handling metadata files which declare different package counts.
+    ///
+    /// See [`PackageCountMismatchPolicy`]. Must be set before any packages are parsed.
+    pub fn with_count_mismatch_policy(mut self, policy: PackageCountMismatchPolicy) -> Self {
+        self.count_mismatch_policy = policy;
+        self.update_counts();
+        self
+    }
+
+    /// Set how the entries of filelists.xml and other.xml are matched up with the packages of primary.xml.
+    ///
+    /// See [`PackageMatchPolicy`]. Must be set before any packages are parsed.
+    pub fn with_match_policy(mut self, policy: PackageMatchPolicy) -> Self {
+        self.match_policy = policy;
+        self
+    }
+
+    /// Only parse the provided package fields, leaving the rest at their default (empty) values.
+    ///
+    /// The package checksum is always parsed, as it is used to identify the package. If neither
+    /// [`Field::Files`] nor [`Field::Changelogs`] are requested, filelists.xml and other.xml respectively
+    /// are not read at all. Must be set before any packages are parsed.
+    pub fn project(mut self, fields: &[Field]) -> Self {
+        self.fields = FieldSet::from_fields(fields);
+        self.primary_xml.set_fields(self.fields);
+        self
+    }
+
+    /// Don't parse the file lists of packages, leaving them empty. filelists.xml is not read at all, and the
+    /// `<file>` entries of primary.xml are skipped.
+    ///
+    /// Equivalent to leaving [`Field::Files`] out of a projection, but keeps any other projection in place.
+    /// Must be set before any packages are parsed.
+    pub fn skip_files(mut self, val: bool) -> Self {
+        self.fields = self.fields.with(Field::Files, !val);
+        self.primary_xml.set_fields(self.fields);
+        self
+    }
+
+    /// Don't parse the changelogs of packages, leaving them empty. other.xml is not read at all.
+    ///
+    /// Equivalent to leaving [`Field::Changelogs`] out of a projection, but keeps any other projection in
+    /// place. Must be set before any packages are parsed.
+    pub fn skip_changelogs(mut self, val: bool) -> Self {
+        self.fields = self.fields.with(Field::Changelogs, !val);
+        self
+    }
+
+    /// Share the values of package fields which repeat across many packages - the architecture, packager,
+    /// license, vendor, group, buildhost and dependency names - instead of allocating them separately for
+    /// every package.
+    ///
+    /// This significantly reduces the memory used when all packages are kept in memory, at the cost of some
+    /// parsing overhead.
+    pub fn with_string_interning(mut self, val: bool) -> Self {
+        self.primary_xml.set_string_interning(val);
+        self
+    }
+
+    /// Report a [`ProgressEvent::PackageRead`] to `handler` for every package parsed.
+    pub fn with_progress_handler(mut self, handler: Arc<dyn ProgressHandler>) -> Self {
+        self.progress_handler = Some(handler);
+        self
+    }
+
+    /// Report mismatched package counts, skipped packages and unknown attributes to `handler`.
+    pub fn with_warning_handler(mut self, handler: Arc<dyn WarningHandler>) -> Self {
+        self.primary_xml.set_warning_handler(handler.clone());
+        self.warning_handler = Some(handler);
+        self
+    }
+
+    fn warn(&self, warning: MetadataWarning) {
+        if let Some(handler) = &self.warning_handler {
+            handler.on_warning(&warning);
+        }
+    }
+
+    /// Only yield packages with one of the given architectures, e.g. `["x86_64", "noarch"]`.
+    ///
+    /// Packages of other architectures are still parsed, and are included in [`PackageIterator::total_packages`].
+    pub fn with_arches(mut self, arches: &[&str]) -> Self {
+        self.arches = Some(arches.iter().map(|arch| arch.to_string()).collect());
+        self
+    }
+
+    /// Set the policy for package locations which point outside of the repository.
+    ///
+    /// See [`HrefPolicy`]. By default, such packages are an error.
+    pub fn with_href_policy(mut self, policy: HrefPolicy) -> Self {
+        self.href_policy = policy;
+        self
+    }
+
+    /// Fail with [`ParseError::PackageLimit`] if the metadata declares or contains more than
+    /// `count` packages. See [`crate::ParseLimits`].
+    pub fn with_max_packages(mut self, count: usize) -> Self {
+        self.max_packages = count;
+        self
+    }
+
     fn parse_headers(&mut self) -> Result<(), MetadataError> {
         let primary_pkg_count = self.primary_xml.read_header()?;
-        let filelists_pkg_count = self.filelists_xml.read_header()?;
-        let other_pkg_count = self.other_xml.read_header()?;
+        let filelists_pkg_count = match &mut self.filelists_xml {
+            Some(filelists_xml) => filelists_xml.read_header()?,
+            None => primary_pkg_count,
+        };
+        let other_pkg_count = match &mut self.other_xml {
+            Some(other_xml) => other_xml.read_header()?,
+            None => primary_pkg_count,
+        };
 
-        if primary_pkg_count != filelists_pkg_count || primary_pkg_count != other_pkg_count {
-            return Err(MetadataError::InconsistentMetadataError(
-                "Metadata package counts don't match".to_owned(),
-            ));
-        }
+        self.header_counts = (primary_pkg_count, filelists_pkg_count, other_pkg_count);
+        self.update_counts();
+
+        Ok(())
+    }
 
-        assert_eq!(primary_pkg_count, filelists_pkg_count);
-        assert_eq!(primary_pkg_count, other_pkg_count);
-        self.num_packages = primary_pkg_count;
+    fn update_counts(&mut self) {
+        let (primary_pkg_count, filelists_pkg_count, other_pkg_count) = self.header_counts;
+        self.num_packages = match self.count_mismatch_policy {
+            PackageCountMismatchPolicy::Warn => primary_pkg_count
+                .min(filelists_pkg_count)
+                .min(other_pkg_count),
+            _ => primary_pkg_count,
+        };
         self.num_remaining = self.num_packages;
+    }
 
-        Ok(())
+    /// Whether the package counts declared by primary.xml, filelists.xml and other.xml agree.
+    pub fn package_counts_match(&self) -> bool {
+        let (primary_pkg_count, filelists_pkg_count, other_pkg_count) = self.header_counts;
+        primary_pkg_count == filelists_pkg_count && primary_pkg_count == other_pkg_count
     }
 
     pub fn parse_package(&mut self) -> Result<Option<Package>, MetadataError> {
-        self.primary_xml
-            .read_package(&mut self.in_progress_package)?;
-        self.filelists_xml
-            .read_package(&mut self.in_progress_package)?;
-        self.other_xml.read_package(&mut self.in_progress_package)?;
+        loop {
+            match self.parse_next_package()? {
+                Some(package) if !utils::arch_matches(self.arches.as_deref(), package.arch()) => {
+                    continue
+                }
+                Some(mut package) => {
+                    if let Some(href) =
+                        utils::check_href(package.location_href(), self.href_policy)?
+                    {
+                        package.set_location_href(href);
+                    }
+                    return Ok(Some(package));
+                }
+                None => return Ok(None),
+            }
+        }
+    }
 
-        let package = self.in_progress_package.take();
+    fn parse_next_package(&mut self) -> Result<Option<Package>, MetadataError> {
+        if self.exhausted {
+            return Ok(None);
+        }
+
+        if self.count_mismatch_policy == PackageCountMismatchPolicy::Error
+            && !self.package_counts_match()
+        {
+            self.exhausted = true;
+            let (primary_pkg_count, filelists_pkg_count, other_pkg_count) = self.header_counts;
+            return Err(ParseError::InconsistentMetadata(format!(
+                "Metadata package counts don't match: primary {}, filelists {}, other {}",
+                primary_pkg_count, filelists_pkg_count, other_pkg_count
+            ))
+            .into());
+        }
+
+        if !self.counts_checked {
+            self.counts_checked = true;
+            if !self.package_counts_match() {
+                let (primary, filelists, other) = self.header_counts;
+                self.warn(MetadataWarning::PackageCountMismatch {
+                    primary,
+                    filelists,
+                    other,
+                });
+                // the missing packages could be anywhere, after which the entries no longer line up
+                self.match_policy = PackageMatchPolicy::ByPkgid;
+            }
+        }
+
+        let (primary_pkg_count, _, _) = self.header_counts;
+        if primary_pkg_count > self.max_packages {
+            self.exhausted = true;
+            return Err(ParseError::PackageLimit(self.max_packages).into());
+        }
+
+        let package = loop {
+            self.primary_xml
+                .read_package(&mut self.in_progress_package)?;
+            if self.in_progress_package.is_none() {
+                break None;
+            }
+
+            let complete = match self.match_policy {
+                PackageMatchPolicy::InOrder => self.read_secondary_in_order()?,
+                PackageMatchPolicy::ByPkgid => self.read_secondary_by_pkgid()?,
+            };
+            if complete {
+                break self.in_progress_package.take();
+            }
+            // the package isn't part of the intersection of all three files, move on to the next one
+            self.in_progress_package = None;
+        };
+
+        // the header can't be trusted to declare the real number of packages
+        if package.is_some() {
+            self.num_parsed += 1;
+            if self.num_parsed > self.max_packages {
+                self.exhausted = true;
+                return Err(ParseError::PackageLimit(self.max_packages).into());
+            }
+        }
 
         // TODO: re-enable this with actual error handling instead of panics - RHEL6 for example will fail
         // because the header lies about the number of packages
         if let Some(_) = package {
-            self.num_remaining -= 1;
+            self.num_remaining = self.num_remaining.saturating_sub(1);
+            if let Some(handler) = &self.progress_handler {
+                handler.on_progress(&ProgressEvent::PackageRead {
+                    count: self.num_packages - self.num_remaining,
+                    total: self.num_packages,
+                });
+            }
             // self.num_remaining = self
             //     .num_remaining
             //     .checked_sub(1)
             //     .expect("More packages parsed than declared in the metadata header.");
         } else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                parsed = self.num_parsed,
+                declared = self.num_packages,
+                "finished parsing packages"
+            );
             // assert!(
             //     self.num_remaining == 0,
             //     "Less packages parsed than declared in metadata header."
@@ -312,6 +763,97 @@ impl PackageIterator {
         Ok(package)
     }
 
+    /// Read the filelists.xml and other.xml entries of the in-progress package, which are expected to be next.
+    fn read_secondary_in_order(&mut self) -> Result<bool, MetadataError> {
+        let found_filelists = match &mut self.filelists_xml {
+            Some(filelists_xml) if self.fields.contains(Field::Files) => {
+                filelists_xml.read_package(&mut self.in_progress_package)?
+            }
+            _ => true,
+        };
+        let found_other = match &mut self.other_xml {
+            Some(other_xml) if self.fields.contains(Field::Changelogs) => {
+                other_xml.read_package(&mut self.in_progress_package)?
+            }
+            _ => true,
+        };
+
+        // If filelists.xml or other.xml ran out of packages before primary.xml did, the
+        // package isn't part of the intersection of all three files, and neither are any which follow
+        if (!found_filelists || !found_other)
+            && self.count_mismatch_policy != PackageCountMismatchPolicy::TrustPrimary
+        {
+            self.exhausted = true;
+            self.in_progress_package = None;
+            if self.count_mismatch_policy == PackageCountMismatchPolicy::Error {
+                return Err(ParseError::InconsistentMetadata(
+                    "Metadata files contain different numbers of packages".to_owned(),
+                )
+                .into());
+            }
+            let file = if found_filelists {
+                METADATA_OTHER
+            } else {
+                METADATA_FILELISTS
+            };
+            self.warn(MetadataWarning::PackageSkipped { pkgid: None, file });
+        }
+        Ok(true)
+    }
+
+    /// Find the filelists.xml and other.xml entries of the in-progress package by pkgid, buffering any
+    /// other entries read along the way. Returns `false` if the package should be skipped.
+    fn read_secondary_by_pkgid(&mut self) -> Result<bool, MetadataError> {
+        let package = self.in_progress_package.as_mut().unwrap();
+        let mut missing = None;
+
+        if let Some(filelists_xml) = &mut self.filelists_xml {
+            if self.fields.contains(Field::Files) {
+                match find_by_pkgid(&mut self.pending_filelists, package.pkgid(), |entry| {
+                    filelists_xml.read_package(entry)
+                })? {
+                    Some(mut entry) => {
+                        package.set_files(std::mem::take(&mut entry.rpm_files));
+                    }
+                    None => missing = Some(METADATA_FILELISTS),
+                }
+            }
+        }
+        if let Some(other_xml) = &mut self.other_xml {
+            if self.fields.contains(Field::Changelogs) {
+                match find_by_pkgid(&mut self.pending_other, package.pkgid(), |entry| {
+                    other_xml.read_package(entry)
+                })? {
+                    Some(mut entry) => {
+                        package.set_changelogs(std::mem::take(&mut entry.rpm_changelogs));
+                    }
+                    None => missing = Some(METADATA_OTHER),
+                }
+            }
+        }
+
+        match (missing, self.count_mismatch_policy) {
+            (None, _) | (_, PackageCountMismatchPolicy::TrustPrimary) => Ok(true),
+            (Some(file), PackageCountMismatchPolicy::Warn) => {
+                let pkgid = Some(package.pkgid().to_owned());
+                self.warn(MetadataWarning::PackageSkipped { pkgid, file });
+                Ok(false)
+            }
+            (Some(file), PackageCountMismatchPolicy::Error) => {
+                self.exhausted = true;
+                Err(ParseError::InconsistentMetadata(format!(
+                    "package {} with pkgid {} is missing from {}",
+                    package.nevra(),
+                    package.pkgid(),
+                    file
+                ))
+                .into())
+            }
+        }
+    }
+
+    /// The number of packages left according to the metadata headers, which may be wrong, see
+    /// [`PackageCountMismatchPolicy`].
     pub fn remaining_packages(&self) -> usize {
         self.num_remaining
     }
@@ -321,6 +863,29 @@ impl PackageIterator {
     }
 }
 
+/// Read entries with `read` until the one with the given `pkgid` is found, stashing the others in `pending`
+/// for later packages. Returns `None` if there is no such entry.
+fn find_by_pkgid(
+    pending: &mut HashMap<String, Package>,
+    pkgid: &str,
+    mut read: impl FnMut(&mut Option<Package>) -> Result<bool, MetadataError>,
+) -> Result<Option<Package>, MetadataError> {
+    if let Some(entry) = pending.remove(pkgid) {
+        return Ok(Some(entry));
+    }
+    loop {
+        let mut entry = None;
+        read(&mut entry)?;
+        match entry {
+            Some(entry) if entry.pkgid() == pkgid => return Ok(Some(entry)),
+            Some(entry) => {
+                pending.insert(entry.pkgid().to_owned(), entry);
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
 impl Iterator for PackageIterator {
     type Item = Result<Package, MetadataError>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -328,6 +893,7 @@ impl Iterator for PackageIterator {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(self.remaining_packages()))
+        // the count declared by the metadata may be wrong, so it is no bound, see `remaining_packages()`
+        (0, None)
     }
 }
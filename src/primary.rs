@@ -4,17 +4,21 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
 
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Reader, Writer};
 
 use super::filelist;
 use super::metadata::{
-    Checksum, MetadataError, Package, PrimaryXml, Requirement, RpmMetadata, XML_NS_COMMON,
-    XML_NS_RPM,
+    Checksum, MetadataError, Package, ParseError, PreinstallPolicy, PrimaryXml, Requirement,
+    RequirementType, RpmMetadata, WriteError, METADATA_PRIMARY, XML_NS_COMMON, XML_NS_RPM,
 };
-use super::{PackageFile, Repository, EVR};
+use super::package::{Field, FieldSet};
+use super::utils::StringInterner;
+use super::warning::{MetadataWarning, WarningHandler};
+use super::{utils, Repository, EVR};
 
 const TAG_METADATA: &[u8] = b"metadata";
 const TAG_PACKAGE: &[u8] = b"package";
@@ -90,16 +94,27 @@ impl RpmMetadata for PrimaryXml {
 
 impl PrimaryXml {
     pub fn new_writer<W: Write>(writer: quick_xml::Writer<W>) -> PrimaryXmlWriter<W> {
-        PrimaryXmlWriter { writer }
+        PrimaryXmlWriter {
+            writer,
+            preinstall_policy: PreinstallPolicy::default(),
+        }
     }
 
     pub fn new_reader<R: BufRead>(reader: quick_xml::Reader<R>) -> PrimaryXmlReader<R> {
-        PrimaryXmlReader { reader }
+        PrimaryXmlReader {
+            reader,
+            fields: FieldSet::all(),
+            interner: None,
+            warning_handler: None,
+        }
     }
 }
 
 pub struct PrimaryXmlReader<R: BufRead> {
     reader: Reader<R>,
+    fields: FieldSet,
+    interner: Option<StringInterner>,
+    warning_handler: Option<Arc<dyn WarningHandler>>,
 }
 
 impl<R: BufRead> PrimaryXmlReader<R> {
@@ -108,10 +123,72 @@ impl<R: BufRead> PrimaryXmlReader<R> {
     }
 
     pub fn read_package(&mut self, package: &mut Option<Package>) -> Result<(), MetadataError> {
-        parse_package(&mut self.reader, package)
+        parse_package(
+            &mut self.reader,
+            package,
+            self.fields,
+            self.interner.as_mut(),
+            self.warning_handler.as_deref(),
+        )
+        .map_err(|e| {
+            e.at_package_position(
+                METADATA_PRIMARY,
+                self.reader.buffer_position(),
+                package.as_ref(),
+            )
+        })
+    }
+
+    pub(crate) fn set_fields(&mut self, fields: FieldSet) {
+        self.fields = fields;
+    }
+
+    pub(crate) fn set_string_interning(&mut self, val: bool) {
+        self.interner = val.then(StringInterner::default);
+    }
+
+    /// Report unknown attributes of dependency entries to `handler`, they are ignored otherwise.
+    pub fn set_warning_handler(&mut self, handler: Arc<dyn WarningHandler>) {
+        self.warning_handler = Some(handler);
+    }
+}
+
+impl<R: BufRead> Iterator for PrimaryXmlReader<R> {
+    type Item = Result<Package, MetadataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut package = None;
+        match self.read_package(&mut package) {
+            Ok(()) => package.map(Ok),
+            Err(e) => Some(Err(e)),
+        }
     }
 }
 
+/// Read the packages of a single (possibly compressed) primary.xml file, without a [`Repository`].
+///
+/// The packages only contain the metadata present in primary.xml - no changelogs, and only the subset
+/// of files which primary.xml lists.
+///
+/// ```
+/// # use std::fs::File;
+/// # fn main() -> Result<(), rpmrepo_metadata::MetadataError> {
+/// let file = File::open("tests/assets/doctest_repo/repodata/primary.xml")?;
+/// for package in rpmrepo_metadata::read_packages(file)? {
+///     assert!(package?.changelogs().is_empty());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_packages<R: io::Read + Send + 'static>(
+    reader: R,
+) -> Result<PrimaryXmlReader<io::BufReader<Box<dyn io::Read + Send>>>, MetadataError> {
+    let reader = utils::create_xml_reader(io::BufReader::new(utils::reader_from_reader(reader)?));
+    let mut primary_xml = PrimaryXml::new_reader(reader);
+    primary_xml.read_header()?;
+    Ok(primary_xml)
+}
+
 // <?xml version="1.0" encoding="UTF-8"?>
 // <metadata xmlns="http://linux.duke.edu/metadata/common" xmlns:rpm="http://linux.duke.edu/metadata/rpm" packages="35">
 fn parse_header<R: BufRead>(reader: &mut Reader<R>) -> Result<usize, MetadataError> {
@@ -122,284 +199,286 @@ fn parse_header<R: BufRead>(reader: &mut Reader<R>) -> Result<usize, MetadataErr
         match reader.read_event(&mut buf)? {
             Event::Decl(_) => (),
             Event::Start(e) if e.name().as_ref() == TAG_METADATA => {
-                let count = e.try_get_attribute("packages")?.unwrap().value;
+                let count = e
+                    .try_get_attribute("packages")?
+                    .ok_or(ParseError::MissingAttribute("packages"))?
+                    .value;
                 return Ok(std::str::from_utf8(&count)?.parse()?);
             }
-            _ => return Err(MetadataError::MissingHeaderError),
+            _ => return Err(ParseError::MissingHeader.into()),
         }
     }
 }
 
-pub fn parse_package<R: BufRead>(
+/// Whether a file is listed in primary.xml, in addition to filelists.xml.
+pub(crate) fn is_primary_file(path: &str) -> bool {
+    // strange algorithm, but it's what the original uses
+    path.starts_with("/etc/") || path.contains("bin/") || path.starts_with("/usr/lib/sendmail")
+}
+
+pub(crate) fn parse_package<R: BufRead>(
     reader: &mut Reader<R>,
     package: &mut Option<Package>,
+    fields: FieldSet,
+    mut interner: Option<&mut StringInterner>,
+    warnings: Option<&dyn WarningHandler>,
 ) -> Result<(), MetadataError> {
+    let mut intern = |value: String| -> Arc<str> {
+        match interner.as_mut() {
+            Some(interner) => interner.intern(value),
+            None => value.into(),
+        }
+    };
     let mut buf = Vec::with_capacity(512);
     let mut text_buf = Vec::with_capacity(512);
+    let mut list_buf = Vec::with_capacity(128);
 
     loop {
         match reader.read_event(&mut buf)? {
             Event::End(e) if e.name().as_ref() == TAG_PACKAGE => break,
-            Event::Start(e) => match e.name().as_ref() {
-                TAG_PACKAGE => {
-                    let ptype = e
-                        .try_get_attribute(b"type")?
-                        .unwrap()
-                        .unescape_and_decode_value(reader)?;
-
-                    assert_eq!(&ptype, "rpm"); // TODO: better error handling
-
-                    if let Some(_pkg) = package {
-                        // TODO: need a temporary place to store this since we don't know the pkgid yet
-                        unimplemented!("package must be parsed from primary.xml first");
-                        // assert!(pkg.pkgid() == &pkgid); // TODO err instead of assert
-                    } else {
-                        let pkg = Package::default();
-                        *package = Some(pkg);
-                    };
-                }
-                TAG_NAME => {
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_name(reader.read_text(TAG_NAME, &mut text_buf)?.as_str());
-                }
-                TAG_VERSION => {
-                    // TODO: unescape_and_decode_value allocates, that can probably be avoided
-                    let epoch = e
-                        .try_get_attribute("epoch")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("epoch"))?
-                        .unescape_and_decode_value(reader)?;
-
-                    let version = e
-                        .try_get_attribute("ver")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("ver"))?
-                        .unescape_and_decode_value(reader)?;
-
-                    let release = e
-                        .try_get_attribute("rel")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("rel"))?
-                        .unescape_and_decode_value(reader)?;
-
-                    // TODO: temporary conversions
-                    let evr = EVR::new(epoch, version, release);
-                    package.as_mut().unwrap().set_evr(evr);
-                }
-                TAG_CHECKSUM => {
-                    let checksum_type = e
-                        .try_get_attribute("type")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("type"))?
-                        .unescape_and_decode_value(reader)?;
-                    let checksum_value = reader.read_text(TAG_CHECKSUM, &mut text_buf)?;
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_checksum(Checksum::try_create(checksum_type, checksum_value)?);
-                }
-                TAG_ARCH => {
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_arch(reader.read_text(TAG_ARCH, &mut text_buf)?.as_str());
-                }
-                TAG_SUMMARY => {
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_summary(reader.read_text(TAG_SUMMARY, &mut text_buf)?.as_str());
-                }
-                TAG_DESCRIPTION => {
-                    package.as_mut().unwrap().set_description(
-                        reader.read_text(TAG_DESCRIPTION, &mut text_buf)?.as_str(),
-                    );
-                }
-                TAG_PACKAGER => {
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_packager(reader.read_text(TAG_PACKAGER, &mut text_buf)?.as_str());
-                }
-                TAG_URL => {
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_url(reader.read_text(TAG_URL, &mut text_buf)?.as_str());
-                }
-                TAG_TIME => {
-                    let time_file = e
-                        .try_get_attribute("file")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("file"))?
-                        .unescape_and_decode_value(reader)?
-                        .parse()?;
-
-                    let time_build = e
-                        .try_get_attribute("build")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("build"))?
-                        .unescape_and_decode_value(reader)?
-                        .parse()?;
-
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_time_file(time_file)
-                        .set_time_build(time_build);
-                }
-                TAG_SIZE => {
-                    let package_size = e
-                        .try_get_attribute("package")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("package"))?
-                        .unescape_and_decode_value(reader)?
-                        .parse()?;
-
-                    let installed_size = e
-                        .try_get_attribute("installed")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("installed"))?
-                        .unescape_and_decode_value(reader)?
-                        .parse()?;
-
-                    let archive_size = e
-                        .try_get_attribute("archive")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("archive"))?
-                        .unescape_and_decode_value(reader)?
-                        .parse()?;
-
-                    package
-                        .as_mut()
-                        .unwrap()
-                        .set_size_package(package_size)
-                        .set_size_installed(installed_size)
-                        .set_size_archive(archive_size);
-                }
-                TAG_LOCATION => {
-                    let location_href = e
-                        .try_get_attribute("href")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("href"))?
-                        .unescape_and_decode_value(reader)?;
-                    let location_base = e
-                        .try_get_attribute("base")?
-                        .map(|a| a.unescape_and_decode_value(reader).unwrap());
-
-                    if let Some(location_base) = location_base {
-                        package
-                            .as_mut()
-                            .unwrap()
-                            .set_location_base(Some(location_base.as_str()));
+            Event::Start(e) => {
+                match e.name() {
+                    TAG_PACKAGE => {
+                        let ptype = utils::required_attribute(&e, "type")?;
+                        if ptype != "rpm" {
+                            return Err(ParseError::UnknownAttribute(format!(
+                                "package type {}",
+                                ptype
+                            ))
+                            .into());
+                        }
+
+                        // nested <package> elements
+                        if package.is_some() {
+                            return Err(ParseError::UnexpectedElement(
+                                String::from_utf8_lossy(TAG_PACKAGE).into_owned(),
+                            )
+                            .into());
+                        }
+                        *package = Some(Package::default());
                     }
-                    package.as_mut().unwrap().set_location_href(&location_href);
-                }
-                TAG_FORMAT => {
-                    // TODO: allocations
-                    buf.clear();
-                    text_buf.clear();
-                    loop {
-                        match reader.read_event(&mut buf)? {
-                            Event::End(e) if e.name().as_ref() == TAG_FORMAT => break,
-                            Event::Start(e) => match e.name().as_ref() {
-                                TAG_RPM_LICENSE => {
-                                    package.as_mut().unwrap().set_rpm_license(
-                                        reader.read_text(TAG_RPM_LICENSE, &mut text_buf)?.as_str(),
-                                    );
-                                }
-                                TAG_RPM_VENDOR => {
-                                    package.as_mut().unwrap().set_rpm_vendor(
-                                        reader.read_text(TAG_RPM_VENDOR, &mut text_buf)?.as_str(),
-                                    );
-                                }
-                                TAG_RPM_GROUP => {
-                                    package.as_mut().unwrap().set_rpm_group(
-                                        reader.read_text(TAG_RPM_GROUP, &mut text_buf)?.as_str(),
-                                    );
-                                }
-                                TAG_RPM_BUILDHOST => {
-                                    package.as_mut().unwrap().set_rpm_buildhost(
-                                        reader
-                                            .read_text(TAG_RPM_BUILDHOST, &mut text_buf)?
-                                            .as_str(),
-                                    );
-                                }
-                                TAG_RPM_SOURCERPM => {
-                                    package.as_mut().unwrap().set_rpm_sourcerpm(
-                                        reader
-                                            .read_text(TAG_RPM_SOURCERPM, &mut text_buf)?
-                                            .as_str(),
-                                    );
-                                }
-                                TAG_RPM_HEADER_RANGE => {
-                                    let start = e
-                                        .try_get_attribute("start")?
-                                        .ok_or_else(|| {
-                                            MetadataError::MissingAttributeError("start")
-                                        })?
-                                        .unescape_and_decode_value(reader)?
-                                        .parse()?;
-
-                                    let end = e
-                                        .try_get_attribute("end")?
-                                        .ok_or_else(|| MetadataError::MissingAttributeError("end"))?
-                                        .unescape_and_decode_value(reader)?
-                                        .parse()?;
-
-                                    package.as_mut().unwrap().set_rpm_header_range(start, end);
-                                }
-                                TAG_RPM_PROVIDES => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_provides(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_REQUIRES => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_requires(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_CONFLICTS => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_conflicts(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_OBSOLETES => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_obsoletes(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_SUGGESTS => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_suggests(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_ENHANCES => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_enhances(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_RECOMMENDS => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_recommends(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_RPM_SUPPLEMENTS => {
-                                    package
-                                        .as_mut()
-                                        .unwrap()
-                                        .set_supplements(parse_requirement_list(reader, &e)?);
-                                }
-                                TAG_FILE => (),
-                                // TODO: share implementation w/ filelists, but don't parse twice.
-                                // use IndexSet to enforce uniqueness while keeping order
+                    TAG_NAME if fields.contains(Field::Name) => {
+                        utils::enclosing(package, TAG_NAME)?
+                            .set_name(reader.read_text(TAG_NAME, &mut text_buf)?);
+                    }
+                    TAG_VERSION if fields.contains(Field::Evr) => {
+                        let epoch = utils::required_attribute(&e, "epoch")?;
+                        let version = utils::required_attribute(&e, "ver")?;
+                        let release = utils::required_attribute(&e, "rel")?;
+
+                        let evr = EVR::new(epoch, version, release);
+                        utils::enclosing(package, TAG_VERSION)?.set_evr(evr);
+                    }
+                    TAG_CHECKSUM => {
+                        let checksum_type = utils::required_attribute(&e, "type")?;
+                        let checksum_value = reader.read_text(TAG_CHECKSUM, &mut text_buf)?;
+                        utils::enclosing(package, TAG_CHECKSUM)?
+                            .set_checksum(Checksum::parse(&checksum_type, &checksum_value)?);
+                    }
+                    TAG_ARCH if fields.contains(Field::Arch) => {
+                        utils::enclosing(package, TAG_ARCH)?
+                            .set_arch(intern(reader.read_text(TAG_ARCH, &mut text_buf)?));
+                    }
+                    TAG_SUMMARY if fields.contains(Field::Summary) => {
+                        utils::enclosing(package, TAG_SUMMARY)?
+                            .set_summary(reader.read_text(TAG_SUMMARY, &mut text_buf)?);
+                    }
+                    TAG_DESCRIPTION if fields.contains(Field::Description) => {
+                        utils::enclosing(package, TAG_DESCRIPTION)?
+                            .set_description(reader.read_text(TAG_DESCRIPTION, &mut text_buf)?);
+                    }
+                    TAG_PACKAGER if fields.contains(Field::Packager) => {
+                        utils::enclosing(package, TAG_PACKAGER)?
+                            .set_packager(intern(reader.read_text(TAG_PACKAGER, &mut text_buf)?));
+                    }
+                    TAG_URL if fields.contains(Field::Url) => {
+                        utils::enclosing(package, TAG_URL)?
+                            .set_url(reader.read_text(TAG_URL, &mut text_buf)?);
+                    }
+                    TAG_TIME if fields.contains(Field::Time) => {
+                        let time_file = utils::required_attribute(&e, "file")?.parse()?;
+
+                        let time_build = utils::required_attribute(&e, "build")?.parse()?;
+
+                        utils::enclosing(package, TAG_TIME)?
+                            .set_time_file(time_file)
+                            .set_time_build(time_build);
+                    }
+                    TAG_SIZE if fields.contains(Field::Size) => {
+                        let package_size = utils::required_attribute(&e, "package")?.parse()?;
+
+                        let installed_size = utils::required_attribute(&e, "installed")?.parse()?;
+
+                        let archive_size = utils::required_attribute(&e, "archive")?.parse()?;
+
+                        utils::enclosing(package, TAG_SIZE)?
+                            .set_size_package(package_size)
+                            .set_size_installed(installed_size)
+                            .set_size_archive(archive_size);
+                    }
+                    TAG_LOCATION if fields.contains(Field::Location) => {
+                        let location_href = utils::required_attribute(&e, "href")?;
+                        // createrepo_c writes "xml:base", older tools wrote "base"
+                        let location_base = match utils::attribute_value(&e, "xml:base")? {
+                            Some(base) => Some(base),
+                            None => utils::attribute_value(&e, "base")?,
+                        };
+
+                        if let Some(location_base) = location_base {
+                            utils::enclosing(package, TAG_LOCATION)?
+                                .set_location_base(Some(location_base));
+                        }
+                        utils::enclosing(package, TAG_LOCATION)?.set_location_href(location_href);
+                    }
+                    TAG_FORMAT => {
+                        buf.clear();
+                        text_buf.clear();
+                        loop {
+                            match reader.read_event(&mut buf)? {
+                                Event::End(e) if e.name() == TAG_FORMAT => break,
+                                Event::Eof => return Err(utils::unexpected_eof(TAG_FORMAT)),
+                                Event::Start(e) => match e.name() {
+                                    TAG_RPM_LICENSE if fields.contains(Field::License) => {
+                                        utils::enclosing(package, TAG_RPM_LICENSE)?
+                                            .set_rpm_license(intern(
+                                                reader.read_text(TAG_RPM_LICENSE, &mut text_buf)?,
+                                            ));
+                                    }
+                                    TAG_RPM_VENDOR if fields.contains(Field::Vendor) => {
+                                        utils::enclosing(package, TAG_RPM_VENDOR)?.set_rpm_vendor(
+                                            intern(
+                                                reader.read_text(TAG_RPM_VENDOR, &mut text_buf)?,
+                                            ),
+                                        );
+                                    }
+                                    TAG_RPM_GROUP if fields.contains(Field::Group) => {
+                                        utils::enclosing(package, TAG_RPM_GROUP)?.set_rpm_group(
+                                            intern(reader.read_text(TAG_RPM_GROUP, &mut text_buf)?),
+                                        );
+                                    }
+                                    TAG_RPM_BUILDHOST if fields.contains(Field::Buildhost) => {
+                                        utils::enclosing(package, TAG_RPM_BUILDHOST)?
+                                            .set_rpm_buildhost(intern(
+                                                reader
+                                                    .read_text(TAG_RPM_BUILDHOST, &mut text_buf)?,
+                                            ));
+                                    }
+                                    TAG_RPM_SOURCERPM if fields.contains(Field::SourceRpm) => {
+                                        utils::enclosing(package, TAG_RPM_SOURCERPM)?
+                                            .set_rpm_sourcerpm(
+                                                reader
+                                                    .read_text(TAG_RPM_SOURCERPM, &mut text_buf)?,
+                                            );
+                                    }
+                                    TAG_RPM_HEADER_RANGE if fields.contains(Field::HeaderRange) => {
+                                        let start =
+                                            utils::required_attribute(&e, "start")?.parse()?;
+
+                                        let end = utils::required_attribute(&e, "end")?.parse()?;
+
+                                        utils::enclosing(package, TAG_RPM_HEADER_RANGE)?
+                                            .set_rpm_header_range(start, end);
+                                    }
+                                    TAG_RPM_PROVIDES if fields.contains(Field::Provides) => {
+                                        utils::enclosing(package, TAG_RPM_PROVIDES)?.set_provides(
+                                            read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?,
+                                        );
+                                    }
+                                    TAG_RPM_REQUIRES if fields.contains(Field::Requires) => {
+                                        utils::enclosing(package, TAG_RPM_REQUIRES)?.set_requires(
+                                            read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?,
+                                        );
+                                    }
+                                    TAG_RPM_CONFLICTS if fields.contains(Field::Conflicts) => {
+                                        utils::enclosing(package, TAG_RPM_CONFLICTS)?
+                                            .set_conflicts(read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?);
+                                    }
+                                    TAG_RPM_OBSOLETES if fields.contains(Field::Obsoletes) => {
+                                        utils::enclosing(package, TAG_RPM_OBSOLETES)?
+                                            .set_obsoletes(read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?);
+                                    }
+                                    TAG_RPM_SUGGESTS if fields.contains(Field::Suggests) => {
+                                        utils::enclosing(package, TAG_RPM_SUGGESTS)?.set_suggests(
+                                            read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?,
+                                        );
+                                    }
+                                    TAG_RPM_ENHANCES if fields.contains(Field::Enhances) => {
+                                        utils::enclosing(package, TAG_RPM_ENHANCES)?.set_enhances(
+                                            read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?,
+                                        );
+                                    }
+                                    TAG_RPM_RECOMMENDS if fields.contains(Field::Recommends) => {
+                                        utils::enclosing(package, TAG_RPM_RECOMMENDS)?
+                                            .set_recommends(read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?);
+                                    }
+                                    TAG_RPM_SUPPLEMENTS if fields.contains(Field::Supplements) => {
+                                        utils::enclosing(package, TAG_RPM_SUPPLEMENTS)?
+                                            .set_supplements(read_requirement_list(
+                                                reader,
+                                                &e,
+                                                &mut list_buf,
+                                                &mut intern,
+                                                warnings,
+                                            )?);
+                                    }
+                                    TAG_FILE => (),
+                                    // TODO: share implementation w/ filelists, but don't parse twice.
+                                    // use IndexSet to enforce uniqueness while keeping order
+                                    _ => (),
+                                },
                                 _ => (),
-                            },
-                            _ => (),
+                            }
+                            buf.clear();
+                            text_buf.clear();
                         }
                     }
+                    _ => (),
                 }
-                _ => (),
-            },
+            }
+            // the input ended in the middle of a package
+            Event::Eof if package.is_some() => return Err(utils::unexpected_eof(TAG_PACKAGE)),
             Event::Eof => break,
             _ => (),
             // TODO: match arms, make sure nothing falls through
@@ -414,10 +493,25 @@ pub fn parse_package<R: BufRead>(
 
 pub struct PrimaryXmlWriter<W: Write> {
     writer: Writer<W>,
+    preinstall_policy: PreinstallPolicy,
 }
 
 impl<W: Write> PrimaryXmlWriter<W> {
     pub fn write_header(&mut self, num_pkgs: usize) -> Result<(), MetadataError> {
+        self.write_header_with_padding(num_pkgs, 0)
+    }
+
+    /// Write the header, reserving enough space for the package count to be corrected in place later
+    /// with [`utils::rewrite_package_count`], if the output is seekable and uncompressed.
+    pub fn write_resizable_header(&mut self, num_pkgs: usize) -> Result<(), MetadataError> {
+        self.write_header_with_padding(num_pkgs, utils::PACKAGE_COUNT_PADDING)
+    }
+
+    fn write_header_with_padding(
+        &mut self,
+        num_pkgs: usize,
+        padding: usize,
+    ) -> Result<(), MetadataError> {
         // <?xml version="1.0" encoding="UTF-8"?>
         self.writer
             .write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
@@ -427,17 +521,29 @@ impl<W: Write> PrimaryXmlWriter<W> {
         metadata_tag.push_attribute(("xmlns", XML_NS_COMMON));
         metadata_tag.push_attribute(("xmlns:rpm", XML_NS_RPM));
         metadata_tag.push_attribute(("packages", num_pkgs.to_string().as_str()));
-        self.writer
-            .write_event(Event::Start(metadata_tag.to_borrowed()))?;
+        let metadata_tag = utils::pad_start_tag(metadata_tag, padding);
+        self.writer.write_event(Event::Start(metadata_tag))?;
 
         Ok(())
     }
 
+    /// Set which dependencies the `pre="1"` attribute is written for. See [`PreinstallPolicy`].
+    pub fn set_preinstall_policy(&mut self, policy: PreinstallPolicy) {
+        self.preinstall_policy = policy;
+    }
+
     pub fn write_package(&mut self, package: &Package) -> Result<(), MetadataError> {
-        write_package(&mut self.writer, package)?;
+        write_package_with(&mut self.writer, package, self.preinstall_policy)?;
         Ok(())
     }
 
+    /// Write a pre-serialized `<package>` element of primary.xml, without parsing it.
+    ///
+    /// See [`crate::RepositoryWriter::add_package_raw`].
+    pub fn write_package_raw(&mut self, fragment: &str) -> Result<(), MetadataError> {
+        utils::write_raw_package(&mut self.writer, fragment)
+    }
+
     pub fn finish(&mut self) -> Result<(), MetadataError> {
         // </metadata>
         self.writer
@@ -458,9 +564,10 @@ impl<W: Write> PrimaryXmlWriter<W> {
     }
 }
 
-pub fn write_package<W: Write>(
+fn write_package_with<W: Write>(
     writer: &mut Writer<W>,
     package: &Package,
+    preinstall_policy: PreinstallPolicy,
 ) -> Result<(), MetadataError> {
     // <package type="rpm">
     let mut package_tag = BytesStart::borrowed_name(TAG_PACKAGE);
@@ -487,7 +594,10 @@ pub fn write_package<W: Write>(
         .write_empty()?;
 
     // <checksum type="sha256" pkgid="YES">6d0fd7f08cef63677726973d327e0b99f819b1983f90c2b656bb27cd2112cb7f</checksum>
-    let (checksum_type, checksum_value) = package.checksum().to_values()?;
+    let (checksum_type, checksum_value) = package
+        .checksum()
+        .ok_or(WriteError::MissingField("checksum"))?
+        .to_values()?;
     writer
         .create_element(TAG_CHECKSUM)
         .with_attribute(("type", checksum_type))
@@ -529,11 +639,13 @@ pub fn write_package<W: Write>(
         .with_attribute(("archive", package.size_archive().to_string().as_str()))
         .write_empty()?;
 
-    // <location href="horse-4.1-1.noarch.rpm"/>
-    writer
-        .create_element(TAG_LOCATION)
-        .with_attribute(("href", package.location_href()))
-        .write_empty()?;
+    // <location xml:base="https://example.com/" href="horse-4.1-1.noarch.rpm"/>
+    let mut location_tag = BytesStart::borrowed_name(TAG_LOCATION);
+    if let Some(location_base) = package.location_base() {
+        location_tag.push_attribute(("xml:base", location_base));
+    }
+    location_tag.push_attribute(("href", package.location_href()));
+    writer.write_event(Event::Empty(location_tag))?;
 
     // <format>
     let format_tag = BytesStart::borrowed_name(TAG_FORMAT);
@@ -576,27 +688,21 @@ pub fn write_package<W: Write>(
     // <rpm:supplements>
     //   <rpm:entry name="horse" flags="EQ" epoch="0" ver="4.1" rel="1"/>
     // </rpm:supplements>
-    write_requirement_section(writer, TAG_RPM_PROVIDES, package.provides())?;
-    write_requirement_section(writer, TAG_RPM_REQUIRES, package.requires())?;
-    write_requirement_section(writer, TAG_RPM_CONFLICTS, package.conflicts())?;
-    write_requirement_section(writer, TAG_RPM_OBSOLETES, package.obsoletes())?;
-    write_requirement_section(writer, TAG_RPM_SUGGESTS, package.suggests())?;
-    write_requirement_section(writer, TAG_RPM_ENHANCES, package.enhances())?;
-    write_requirement_section(writer, TAG_RPM_RECOMMENDS, package.recommends())?;
-    write_requirement_section(writer, TAG_RPM_SUPPLEMENTS, package.supplements())?;
-
-    fn include_file(f: &PackageFile) -> bool {
-        // strange algorithm, but it's what the original uses
-        f.path.starts_with("/etc/")
-            || f.path.contains("bin/")
-            || f.path.starts_with("/usr/lib/sendmail")
-    }
+    let pre = preinstall_policy == PreinstallPolicy::Preserve;
+    write_requirement_section(writer, TAG_RPM_PROVIDES, package.provides(), pre)?;
+    write_requirement_section(writer, TAG_RPM_REQUIRES, package.requires(), true)?;
+    write_requirement_section(writer, TAG_RPM_CONFLICTS, package.conflicts(), pre)?;
+    write_requirement_section(writer, TAG_RPM_OBSOLETES, package.obsoletes(), pre)?;
+    write_requirement_section(writer, TAG_RPM_SUGGESTS, package.suggests(), pre)?;
+    write_requirement_section(writer, TAG_RPM_ENHANCES, package.enhances(), pre)?;
+    write_requirement_section(writer, TAG_RPM_RECOMMENDS, package.recommends(), pre)?;
+    write_requirement_section(writer, TAG_RPM_SUPPLEMENTS, package.supplements(), pre)?;
 
     // <file>/usr/bin/bash</file>
     package
         .files()
         .iter()
-        .filter(|&f| include_file(f))
+        .filter(|f| is_primary_file(&f.path))
         .try_for_each(|f| filelist::write_file_element(writer, f))?;
 
     // </format>
@@ -611,10 +717,13 @@ pub fn write_package<W: Write>(
 // <rpm:supplements>
 //   <rpm:entry name="horse" flags="EQ" epoch="0" ver="4.1" rel="1"/>
 // </rpm:supplements>
+//
+// The "pre" attribute is only written if `write_pre` is set.
 fn write_requirement_section<W: Write, N: AsRef<[u8]> + Sized>(
     writer: &mut Writer<W>,
     section_name: N,
     entry_list: &[Requirement],
+    write_pre: bool,
 ) -> Result<(), MetadataError> {
     // skip writing empty sections
     if entry_list.is_empty() {
@@ -626,10 +735,10 @@ fn write_requirement_section<W: Write, N: AsRef<[u8]> + Sized>(
 
     for entry in entry_list {
         let mut entry_tag = BytesStart::borrowed_name(b"rpm:entry");
-        entry_tag.push_attribute(("name", entry.name.as_str()));
+        entry_tag.push_attribute(("name", &*entry.name));
 
-        if let Some(flags) = &entry.flags {
-            entry_tag.push_attribute(("flags", flags.as_str()));
+        if let Some(flags) = entry.flags {
+            entry_tag.push_attribute(("flags", <&str>::from(flags)));
         }
 
         if let Some(epoch) = &entry.epoch {
@@ -643,7 +752,7 @@ fn write_requirement_section<W: Write, N: AsRef<[u8]> + Sized>(
         if let Some(release) = &entry.release {
             entry_tag.push_attribute(("rel", release.as_str()));
         }
-        if entry.preinstall {
+        if entry.preinstall && write_pre {
             entry_tag.push_attribute(("pre", "1"));
         }
         writer.write_event(Event::Empty(entry_tag))?;
@@ -654,60 +763,67 @@ fn write_requirement_section<W: Write, N: AsRef<[u8]> + Sized>(
     Ok(())
 }
 
-pub fn parse_requirement_list<R: BufRead>(
+/// Parse a list of requirements, using `buf` as the event buffer so that it can be reused across lists.
+///
+/// Unknown attributes of the entries are reported to `warnings` and otherwise ignored.
+fn read_requirement_list<R: BufRead>(
     reader: &mut Reader<R>,
     open_tag: &BytesStart,
+    buf: &mut Vec<u8>,
+    intern: &mut impl FnMut(String) -> Arc<str>,
+    warnings: Option<&dyn WarningHandler>,
 ) -> Result<Vec<Requirement>, MetadataError> {
     let mut list = Vec::with_capacity(10);
 
-    // TODO: another hot allocation
-    let mut buf = Vec::with_capacity(128);
-
     loop {
-        match reader.read_event(&mut buf)? {
+        buf.clear();
+        match reader.read_event(buf)? {
             Event::Start(e) if e.name().as_ref() == TAG_RPM_ENTRY => {
                 let mut requirement = Requirement::default();
                 for attr in e.attributes() {
                     let attr = attr.map_err(|e| quick_xml::Error::from(e))?;
                     match attr.key.as_ref() {
                         b"name" => {
-                            requirement.name = attr.unescape_and_decode_value(reader)?;
+                            requirement.name = intern(utils::decode_attribute(&attr)?.into_owned());
                         }
                         b"flags" => {
-                            requirement.flags = Some(attr.unescape_and_decode_value(reader)?)
+                            let flags = utils::decode_attribute(&attr)?;
+                            requirement.flags = Some(RequirementType::try_from(&*flags)?)
                         }
                         b"epoch" => {
-                            requirement.epoch = Some(attr.unescape_and_decode_value(reader)?)
+                            requirement.epoch = Some(utils::decode_attribute(&attr)?.into_owned())
                         }
                         b"ver" => {
-                            requirement.version = Some(attr.unescape_and_decode_value(reader)?)
+                            requirement.version = Some(utils::decode_attribute(&attr)?.into_owned())
                         }
                         b"rel" => {
-                            requirement.release = Some(attr.unescape_and_decode_value(reader)?)
+                            requirement.release = Some(utils::decode_attribute(&attr)?.into_owned())
                         }
                         b"pre" => {
-                            requirement.preinstall = attr
-                                .unescape_and_decode_value(reader)
+                            requirement.preinstall = utils::decode_attribute(&attr)
                                 .ok()
-                                .filter(|val| val != "0" && !val.eq_ignore_ascii_case("false"))
+                                .filter(|val| *val != "0" && !val.eq_ignore_ascii_case("false"))
                                 .is_some()
                         }
-                        a @ _ => {
-                            return Err(MetadataError::UnknownAttributeError(format!(
-                                "unrecognized attribute {}",
-                                std::str::from_utf8(a)?
-                            )))
+                        key => {
+                            if let Some(warnings) = warnings {
+                                warnings.on_warning(&MetadataWarning::UnknownAttribute {
+                                    element: "rpm:entry",
+                                    attribute: String::from_utf8_lossy(key).into_owned(),
+                                });
+                            }
                         }
                     }
                 }
 
                 if requirement.name.is_empty() {
-                    return Err(MetadataError::MissingAttributeError("name"));
+                    return Err(ParseError::MissingAttribute("name").into());
                 }
 
                 list.push(requirement);
             }
             Event::End(e) if e.name() == open_tag.name() => break,
+            Event::Eof => return Err(utils::unexpected_eof(open_tag.name())),
             _ => (), // TODO
         }
     }
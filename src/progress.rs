@@ -0,0 +1,111 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+/// An event reported to a [`ProgressHandler`] while reading or writing a repository.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent<'a> {
+    /// Decompressed data was read from a metadata file. `bytes` is the total read from the file so far,
+    /// `total` is the decompressed size listed in `repomd.xml`, if any.
+    BytesRead {
+        file: &'a str,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// A package was parsed. `total` is the number of packages declared by the metadata.
+    PackageRead { count: usize, total: usize },
+    /// A package was written. `total` is the number of packages declared when creating the writer.
+    PackageWritten { count: usize, total: usize },
+    /// An advisory was written.
+    AdvisoryWritten { count: usize },
+    /// A metadata file, including `repomd.xml`, was completed.
+    MetadataFileWritten { name: &'a str, path: &'a Path },
+}
+
+/// Receives [`ProgressEvent`]s from a [`crate::RepositoryReader`] or [`crate::RepositoryWriter`], e.g. to drive
+/// a progress bar.
+///
+/// Handlers are called synchronously and frequently (for every package and every read from a metadata
+/// file), so they should be cheap. Any `Fn(&ProgressEvent)` closure is a handler.
+///
+/// ```
+/// # use std::path::Path;
+/// # use std::sync::atomic::{AtomicUsize, Ordering};
+/// # use std::sync::Arc;
+/// # use rpmrepo_metadata::{MetadataError, ProgressEvent, RepositoryReader};
+/// # fn main() -> Result<(), MetadataError> {
+/// let position = Arc::new(AtomicUsize::new(0));
+/// let bar = position.clone();
+///
+/// let mut reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// reader.set_progress_handler(Arc::new(move |event: &ProgressEvent| {
+///     if let ProgressEvent::PackageRead { count, .. } = event {
+///         bar.store(*count, Ordering::Relaxed);
+///     }
+/// }));
+/// for pkg in reader.iter_packages()? {
+///     pkg?;
+/// }
+/// assert_eq!(position.load(Ordering::Relaxed), 3);
+/// # Ok(())
+/// # }
+/// ```
+pub trait ProgressHandler: Send + Sync {
+    fn on_progress(&self, event: &ProgressEvent);
+}
+
+impl<F> ProgressHandler for F
+where
+    F: Fn(&ProgressEvent) + Send + Sync,
+{
+    fn on_progress(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Reports the number of bytes read through it as [`ProgressEvent::BytesRead`]
+pub(crate) struct ProgressReader<R> {
+    inner: R,
+    file: String,
+    bytes: u64,
+    total: Option<u64>,
+    handler: Arc<dyn ProgressHandler>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub(crate) fn new(
+        inner: R,
+        file: &str,
+        total: Option<u64>,
+        handler: Arc<dyn ProgressHandler>,
+    ) -> Self {
+        Self {
+            inner,
+            file: file.to_owned(),
+            bytes: 0,
+            total,
+            handler,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.bytes += read as u64;
+            self.handler.on_progress(&ProgressEvent::BytesRead {
+                file: &self.file,
+                bytes: self.bytes,
+                total: self.total,
+            });
+        }
+        Ok(read)
+    }
+}
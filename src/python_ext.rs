@@ -21,12 +21,8 @@ pyo3::create_exception!(
 
 impl From<crate::MetadataError> for pyo3::PyErr {
     fn from(err: crate::MetadataError) -> Self {
-        match err {
-            // TODO: IoError doesn't really belong as part of the (Rust) MetadataError type to begin with,
-            // might make sense to split it out
-            crate::MetadataError::IoError(err) => {
-                pyo3::exceptions::PyOSError::new_err(err.to_string())
-            }
+        match err.root_cause() {
+            crate::MetadataError::Io(_) => pyo3::exceptions::PyOSError::new_err(err.to_string()),
             _ => MetadataError::new_err(err.to_string()),
         }
     }
@@ -53,39 +49,167 @@ impl Repository {
         Ok(py_repo)
     }
 
-    fn write_to_directory(&self, path: PathBuf) -> PyResult<()> {
-        let options = crate::RepositoryOptions::default();
+    #[pyo3(signature = (path, options=None))]
+    fn write_to_directory(
+        &self,
+        path: PathBuf,
+        options: Option<RepositoryOptions>,
+    ) -> PyResult<()> {
+        let options = options.map(|o| o.inner).unwrap_or_default();
 
-        self.inner.write_to_directory(&path, options)?;
+        self.inner.write_to_directory_with_options(&path, options)?;
         Ok(())
     }
 }
 
 #[pyclass]
 struct RepositoryWriter {
-    inner: crate::RepositoryWriter,
+    // finish() consumes the writer
+    inner: Option<crate::RepositoryWriter>,
+}
+
+impl RepositoryWriter {
+    fn writer(&mut self) -> PyResult<&mut crate::RepositoryWriter> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("writer already finished"))
+    }
 }
 
 #[pymethods]
 impl RepositoryWriter {
     #[new]
-    fn new(path: PathBuf, num_pkgs: usize) -> PyResult<Self> {
-        let repo_writer = crate::RepositoryWriter::new(&path, num_pkgs)?;
-        let py_repo_writer = RepositoryWriter { inner: repo_writer };
+    #[pyo3(signature = (path, num_pkgs, options=None))]
+    fn new(path: PathBuf, num_pkgs: usize, options: Option<RepositoryOptions>) -> PyResult<Self> {
+        let options = options.map(|o| o.inner).unwrap_or_default();
+        let repo_writer = crate::RepositoryWriter::new_with_options(&path, num_pkgs, options)?;
+        let py_repo_writer = RepositoryWriter {
+            inner: Some(repo_writer),
+        };
         Ok(py_repo_writer)
     }
 
     fn add_package(&mut self, pkg: &Package) -> PyResult<()> {
-        self.inner.add_package(&pkg.inner)?;
+        self.writer()?.add_package(&pkg.inner)?;
+        Ok(())
+    }
+
+    fn add_advisory(&mut self, record: &UpdateRecord) -> PyResult<()> {
+        self.writer()?.add_advisory(&record.inner)?;
         Ok(())
     }
 
+    /// Raises RuntimeError if the writer has already been finished.
     fn finish(&mut self) -> PyResult<()> {
-        self.inner.finish()?;
+        let writer = self
+            .inner
+            .take()
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("writer already finished"))?;
+        writer.finish()?;
         Ok(())
     }
 }
 
+/// Options for writing repositories, see the Rust `RepositoryOptions` for details.
+///
+/// Compression types are given as strings ("zstd", "gzip", "xz", "bz2" or "none"), as are
+/// checksum types ("sha256", "sha512", "sha1", ...).
+#[pyclass]
+#[derive(Clone)]
+struct RepositoryOptions {
+    inner: crate::RepositoryOptions,
+}
+
+#[pymethods]
+impl RepositoryOptions {
+    #[new]
+    #[pyo3(signature = (
+        metadata_compression_type=None,
+        metadata_checksum_type=None,
+        package_checksum_type=None,
+        simple_metadata_filenames=None,
+        changelog_limit=None,
+    ))]
+    fn new(
+        metadata_compression_type: Option<&str>,
+        metadata_checksum_type: Option<&str>,
+        package_checksum_type: Option<&str>,
+        simple_metadata_filenames: Option<bool>,
+        changelog_limit: Option<usize>,
+    ) -> PyResult<Self> {
+        let mut options = Self {
+            inner: crate::RepositoryOptions::default(),
+        };
+        if let Some(comptype) = metadata_compression_type {
+            options.set_metadata_compression_type(comptype)?;
+        }
+        if let Some(chktype) = metadata_checksum_type {
+            options.set_metadata_checksum_type(chktype)?;
+        }
+        if let Some(chktype) = package_checksum_type {
+            options.set_package_checksum_type(chktype)?;
+        }
+        if let Some(val) = simple_metadata_filenames {
+            options.set_simple_metadata_filenames(val);
+        }
+        options.set_changelog_limit(changelog_limit);
+        Ok(options)
+    }
+
+    #[setter(metadata_compression_type)]
+    fn set_metadata_compression_type(&mut self, comptype: &str) -> PyResult<()> {
+        self.inner.metadata_compression_type = comptype.try_into()?;
+        Ok(())
+    }
+
+    #[getter(metadata_compression_type)]
+    fn metadata_compression_type(&self) -> &str {
+        self.inner.metadata_compression_type.into()
+    }
+
+    #[setter(metadata_checksum_type)]
+    fn set_metadata_checksum_type(&mut self, chktype: &str) -> PyResult<()> {
+        self.inner.metadata_checksum_type = chktype.try_into()?;
+        Ok(())
+    }
+
+    #[getter(metadata_checksum_type)]
+    fn metadata_checksum_type(&self) -> &str {
+        self.inner.metadata_checksum_type.into()
+    }
+
+    #[setter(package_checksum_type)]
+    fn set_package_checksum_type(&mut self, chktype: &str) -> PyResult<()> {
+        self.inner.package_checksum_type = chktype.try_into()?;
+        Ok(())
+    }
+
+    #[getter(package_checksum_type)]
+    fn package_checksum_type(&self) -> &str {
+        self.inner.package_checksum_type.into()
+    }
+
+    #[setter(simple_metadata_filenames)]
+    fn set_simple_metadata_filenames(&mut self, val: bool) {
+        self.inner.simple_metadata_filenames = val;
+    }
+
+    #[getter(simple_metadata_filenames)]
+    fn simple_metadata_filenames(&self) -> bool {
+        self.inner.simple_metadata_filenames
+    }
+
+    #[setter(changelog_limit)]
+    fn set_changelog_limit(&mut self, limit: Option<usize>) {
+        self.inner.changelog_limit = limit;
+    }
+
+    #[getter(changelog_limit)]
+    fn changelog_limit(&self) -> Option<usize> {
+        self.inner.changelog_limit
+    }
+}
+
 #[pyclass]
 struct RepositoryReader {
     inner: crate::RepositoryReader,
@@ -107,9 +231,11 @@ impl RepositoryReader {
     }
 
     fn iter_advisories(&self) -> PyResult<UpdateinfoReader> {
-        let pkg_reader = self.inner.iter_packages()?;
-        let py_pkg_reader = UpdateinfoReader { inner: pkg_reader };
-        Ok(py_pkg_reader)
+        let updateinfo_reader = self.inner.iter_advisories()?;
+        let py_updateinfo_reader = UpdateinfoReader {
+            inner: updateinfo_reader,
+        };
+        Ok(py_updateinfo_reader)
     }
 }
 #[pyclass]
@@ -155,13 +281,13 @@ impl Package {
     }
 
     #[setter(epoch)]
-    fn set_epoch(&mut self, epoch: &str) {
+    fn set_epoch(&mut self, epoch: u32) {
         self.inner.set_epoch(epoch);
     }
 
     #[getter(epoch)]
-    fn epoch(&self) -> &str {
-        self.inner.epoch()
+    fn epoch(&self) -> PyResult<u32> {
+        Ok(self.inner.epoch()?)
     }
 
     #[setter(version)]
@@ -202,8 +328,10 @@ impl Package {
     }
 
     #[getter(checksum)]
-    pub fn checksum(&self) -> (&str, &str) {
-        self.inner.checksum().to_values().unwrap() // TODO this unwrap doesn't really need to exist
+    pub fn checksum(&self) -> Option<(&str, &str)> {
+        self.inner
+            .checksum()
+            .map(|checksum| (checksum.checksum_type().into(), checksum.digest()))
     }
 
     #[getter(pkgid)]
@@ -212,8 +340,10 @@ impl Package {
     }
 
     #[getter(checksum_type)]
-    pub fn checksum_type(&self) -> &str {
-        self.inner.checksum.to_values().unwrap().0
+    pub fn checksum_type(&self) -> Option<&str> {
+        self.inner
+            .checksum()
+            .map(|checksum| checksum.checksum_type().into())
     }
 
     #[setter(location_href)]
@@ -388,12 +518,13 @@ impl Package {
     }
 
     #[setter(requires)]
-    pub fn set_requires(&mut self, requires: Vec<RequirementTuple>) {
-        let requires: Vec<_> = requires
+    pub fn set_requires(&mut self, requires: Vec<RequirementTuple>) -> PyResult<()> {
+        let requires = requires
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_requires(requires);
+        Ok(())
     }
 
     #[getter(requires)]
@@ -406,12 +537,13 @@ impl Package {
     }
 
     #[setter(provides)]
-    pub fn set_provides(&mut self, provides: Vec<RequirementTuple>) {
-        let provides: Vec<_> = provides
+    pub fn set_provides(&mut self, provides: Vec<RequirementTuple>) -> PyResult<()> {
+        let provides = provides
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_provides(provides);
+        Ok(())
     }
 
     #[getter(provides)]
@@ -424,12 +556,13 @@ impl Package {
     }
 
     #[setter(conflicts)]
-    pub fn set_conflicts(&mut self, conflicts: Vec<RequirementTuple>) {
-        let conflicts: Vec<_> = conflicts
+    pub fn set_conflicts(&mut self, conflicts: Vec<RequirementTuple>) -> PyResult<()> {
+        let conflicts = conflicts
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_conflicts(conflicts);
+        Ok(())
     }
 
     #[getter(conflicts)]
@@ -442,12 +575,13 @@ impl Package {
     }
 
     #[setter(obsoletes)]
-    pub fn set_obsoletes(&mut self, obsoletes: Vec<RequirementTuple>) {
-        let obsoletes: Vec<_> = obsoletes
+    pub fn set_obsoletes(&mut self, obsoletes: Vec<RequirementTuple>) -> PyResult<()> {
+        let obsoletes = obsoletes
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_obsoletes(obsoletes);
+        Ok(())
     }
 
     #[getter(obsoletes)]
@@ -460,12 +594,13 @@ impl Package {
     }
 
     #[setter(suggests)]
-    pub fn set_suggests(&mut self, suggests: Vec<RequirementTuple>) {
-        let suggests: Vec<_> = suggests
+    pub fn set_suggests(&mut self, suggests: Vec<RequirementTuple>) -> PyResult<()> {
+        let suggests = suggests
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_suggests(suggests);
+        Ok(())
     }
 
     #[getter(suggests)]
@@ -478,12 +613,13 @@ impl Package {
     }
 
     #[setter(enhances)]
-    pub fn set_enhances(&mut self, enhances: Vec<RequirementTuple>) {
-        let enhances: Vec<_> = enhances
+    pub fn set_enhances(&mut self, enhances: Vec<RequirementTuple>) -> PyResult<()> {
+        let enhances = enhances
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_enhances(enhances);
+        Ok(())
     }
 
     #[getter(enhances)]
@@ -496,12 +632,13 @@ impl Package {
     }
 
     #[setter(recommends)]
-    pub fn set_recommends(&mut self, recommends: Vec<RequirementTuple>) {
-        let recommends: Vec<_> = recommends
+    pub fn set_recommends(&mut self, recommends: Vec<RequirementTuple>) -> PyResult<()> {
+        let recommends = recommends
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_recommends(recommends);
+        Ok(())
     }
 
     #[getter(recommends)]
@@ -514,12 +651,13 @@ impl Package {
     }
 
     #[setter(supplements)]
-    pub fn set_supplements(&mut self, supplements: Vec<RequirementTuple>) {
-        let supplements: Vec<_> = supplements
+    pub fn set_supplements(&mut self, supplements: Vec<RequirementTuple>) -> PyResult<()> {
+        let supplements = supplements
             .iter()
-            .map(|r| crate::metadata::Requirement::from(r))
-            .collect();
+            .map(crate::metadata::Requirement::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
         self.inner.set_supplements(supplements);
+        Ok(())
     }
 
     #[getter(supplements)]
@@ -613,24 +751,30 @@ type RequirementTuple = (
 );
 
 // TODO: figure out how to do this without cloning?
-impl From<&RequirementTuple> for crate::metadata::Requirement {
-    fn from(tuple: &RequirementTuple) -> Self {
-        crate::metadata::Requirement {
-            name: tuple.0.clone(),
-            flags: tuple.1.clone(),
+impl TryFrom<&RequirementTuple> for crate::metadata::Requirement {
+    type Error = crate::MetadataError;
+
+    fn try_from(tuple: &RequirementTuple) -> Result<Self, Self::Error> {
+        Ok(crate::metadata::Requirement {
+            name: tuple.0.as_str().into(),
+            flags: tuple
+                .1
+                .as_deref()
+                .map(crate::metadata::RequirementType::try_from)
+                .transpose()?,
             epoch: tuple.2.clone(),
             version: tuple.3.clone(),
             release: tuple.4.clone(),
             preinstall: tuple.5,
-        }
+        })
     }
 }
 
 impl From<&crate::metadata::Requirement> for RequirementTuple {
     fn from(req: &crate::metadata::Requirement) -> Self {
         (
-            req.name.clone(),
-            req.flags.clone(),
+            req.name.to_string(),
+            req.flags.map(|flags| <&str>::from(flags).to_owned()),
             req.epoch.clone(),
             req.version.clone(),
             req.release.clone(),
@@ -639,14 +783,14 @@ impl From<&crate::metadata::Requirement> for RequirementTuple {
     }
 }
 
-// Author, Date, Description
+// Author, Timestamp, Description
 type ChangelogTuple = (String, u64, String);
 
 impl From<ChangelogTuple> for crate::metadata::Changelog {
     fn from(tuple: ChangelogTuple) -> Self {
         crate::metadata::Changelog {
             author: tuple.0,
-            date: tuple.1,
+            timestamp: tuple.1,
             description: tuple.2,
         }
     }
@@ -656,7 +800,7 @@ impl From<&crate::metadata::Changelog> for ChangelogTuple {
     fn from(changelog: &crate::metadata::Changelog) -> Self {
         (
             changelog.author.clone(),
-            changelog.date,
+            changelog.timestamp,
             changelog.description.clone(),
         )
     }
@@ -746,7 +890,7 @@ impl From<&crate::metadata::PackageFile> for CrFileTuple {
 
 #[pyclass]
 struct PackageReader {
-    inner: crate::PackageReader,
+    inner: crate::PackageIterator,
 }
 
 #[pymethods]
@@ -754,7 +898,7 @@ impl PackageReader {
     #[new]
     fn new(primary_path: PathBuf, filelists_path: PathBuf, other_path: PathBuf) -> PyResult<Self> {
         let py_pkg_reader = Self {
-            inner: crate::PackageReader::from_files(&primary_path, &filelists_path, &other_path)?,
+            inner: crate::PackageIterator::from_files(&primary_path, &filelists_path, &other_path)?,
         };
         Ok(py_pkg_reader)
     }
@@ -790,13 +934,13 @@ impl PackageReader {
 
 #[pyclass]
 struct UpdateinfoReader {
-    inner: crate::UpdateinfoIterator,
+    inner: crate::repository::UpdateinfoIterator,
 }
 
 #[pymethods]
 impl UpdateinfoReader {
     fn parse_updaterecord(&mut self) -> PyResult<Option<UpdateRecord>> {
-        let rec = self.inner.parse_updaterecord()?;
+        let rec = self.inner.next().transpose()?;
         let py_rec = rec.map(|rec| UpdateRecord { inner: rec });
         Ok(py_rec)
     }
@@ -805,18 +949,476 @@ impl UpdateinfoReader {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<Package>> {
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<UpdateRecord>> {
         slf.parse_updaterecord()
     }
 }
 
 #[pyclass]
 struct UpdateinfoWriter {
-    inner: crate::UpdateinfoXmlWriter,
+    inner: Option<crate::updateinfo::UpdateinfoXmlWriter<Box<dyn std::io::Write + Send>>>,
 }
 
 #[pymethods]
-impl UpdateinfoWriter {}
+impl UpdateinfoWriter {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let (_, writer) = crate::utils::xml_writer_for_path(&path, crate::CompressionType::None)?;
+        let mut updateinfo_writer = crate::UpdateinfoXml::new_writer(writer);
+        updateinfo_writer.write_header()?;
+        Ok(Self {
+            inner: Some(updateinfo_writer),
+        })
+    }
+
+    fn add_advisory(&mut self, record: &UpdateRecord) -> PyResult<()> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("writer already finished"))?
+            .write_updaterecord(&record.inner)?;
+        Ok(())
+    }
+
+    /// Raises RuntimeError if the writer has already been finished.
+    fn finish(&mut self) -> PyResult<()> {
+        let mut writer = self
+            .inner
+            .take()
+            .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("writer already finished"))?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct UpdateRecord {
+    inner: crate::UpdateRecord,
+}
+
+#[pymethods]
+impl UpdateRecord {
+    #[new]
+    fn new() -> Self {
+        Self {
+            inner: crate::UpdateRecord::default(),
+        }
+    }
+
+    #[setter(from_)]
+    fn set_from_(&mut self, from_: &str) {
+        self.inner.from = from_.to_owned();
+    }
+
+    #[getter(from_)]
+    fn from_(&self) -> &str {
+        &self.inner.from
+    }
+
+    #[setter(id)]
+    fn set_id(&mut self, id: &str) {
+        self.inner.id = id.to_owned();
+    }
+
+    #[getter(id)]
+    fn id(&self) -> &str {
+        &self.inner.id
+    }
+
+    #[setter(update_type)]
+    fn set_update_type(&mut self, update_type: &str) {
+        self.inner.update_type = update_type.to_owned();
+    }
+
+    #[getter(update_type)]
+    fn update_type(&self) -> &str {
+        &self.inner.update_type
+    }
+
+    #[setter(status)]
+    fn set_status(&mut self, status: &str) {
+        self.inner.status = status.to_owned();
+    }
+
+    #[getter(status)]
+    fn status(&self) -> &str {
+        &self.inner.status
+    }
+
+    #[setter(version)]
+    fn set_version(&mut self, version: &str) {
+        self.inner.version = version.to_owned();
+    }
+
+    #[getter(version)]
+    fn version(&self) -> &str {
+        &self.inner.version
+    }
+
+    #[setter(title)]
+    fn set_title(&mut self, title: &str) {
+        self.inner.title = title.to_owned();
+    }
+
+    #[getter(title)]
+    fn title(&self) -> &str {
+        &self.inner.title
+    }
+
+    #[setter(rights)]
+    fn set_rights(&mut self, rights: &str) {
+        self.inner.rights = rights.to_owned();
+    }
+
+    #[getter(rights)]
+    fn rights(&self) -> &str {
+        &self.inner.rights
+    }
+
+    #[setter(release)]
+    fn set_release(&mut self, release: &str) {
+        self.inner.release = release.to_owned();
+    }
+
+    #[getter(release)]
+    fn release(&self) -> &str {
+        &self.inner.release
+    }
+
+    #[setter(severity)]
+    fn set_severity(&mut self, severity: &str) {
+        self.inner.severity = severity.to_owned();
+    }
+
+    #[getter(severity)]
+    fn severity(&self) -> &str {
+        &self.inner.severity
+    }
+
+    #[setter(summary)]
+    fn set_summary(&mut self, summary: &str) {
+        self.inner.summary = summary.to_owned();
+    }
+
+    #[getter(summary)]
+    fn summary(&self) -> &str {
+        &self.inner.summary
+    }
+
+    #[setter(description)]
+    fn set_description(&mut self, description: &str) {
+        self.inner.description = description.to_owned();
+    }
+
+    #[getter(description)]
+    fn description(&self) -> &str {
+        &self.inner.description
+    }
+
+    #[setter(solution)]
+    fn set_solution(&mut self, solution: &str) {
+        self.inner.solution = solution.to_owned();
+    }
+
+    #[getter(solution)]
+    fn solution(&self) -> &str {
+        &self.inner.solution
+    }
+
+    #[setter(issued_date)]
+    fn set_issued_date(&mut self, issued_date: Option<&str>) {
+        self.inner.issued_date = issued_date.map(str::to_owned);
+    }
+
+    #[getter(issued_date)]
+    fn issued_date(&self) -> Option<&str> {
+        self.inner.issued_date.as_deref()
+    }
+
+    #[setter(updated_date)]
+    fn set_updated_date(&mut self, updated_date: Option<&str>) {
+        self.inner.updated_date = updated_date.map(str::to_owned);
+    }
+
+    #[getter(updated_date)]
+    fn updated_date(&self) -> Option<&str> {
+        self.inner.updated_date.as_deref()
+    }
+
+    #[setter(pushcount)]
+    fn set_pushcount(&mut self, pushcount: Option<&str>) {
+        self.inner.pushcount = pushcount.map(str::to_owned);
+    }
+
+    #[getter(pushcount)]
+    fn pushcount(&self) -> Option<&str> {
+        self.inner.pushcount.as_deref()
+    }
+
+    #[setter(references)]
+    fn set_references(&mut self, references: Vec<ReferenceTuple>) {
+        self.inner.references = references.into_iter().map(Into::into).collect();
+    }
+
+    #[getter(references)]
+    fn references(&self) -> Vec<ReferenceTuple> {
+        self.inner.references.iter().map(Into::into).collect()
+    }
+
+    #[setter(pkglist)]
+    fn set_pkglist(&mut self, pkglist: Vec<UpdateCollection>) {
+        self.inner.pkglist = pkglist.into_iter().map(|c| c.inner).collect();
+    }
+
+    #[getter(pkglist)]
+    fn pkglist(&self) -> Vec<UpdateCollection> {
+        self.inner
+            .pkglist
+            .iter()
+            .map(|c| UpdateCollection { inner: c.clone() })
+            .collect()
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("<UpdateRecord {:?}>", self.inner.id))
+    }
+}
+
+// href, id, title, type
+type ReferenceTuple = (String, String, String, String);
+
+impl From<ReferenceTuple> for crate::UpdateReference {
+    fn from(tuple: ReferenceTuple) -> Self {
+        crate::UpdateReference {
+            href: tuple.0,
+            id: tuple.1,
+            title: tuple.2,
+            reftype: tuple.3,
+        }
+    }
+}
+
+impl From<&crate::UpdateReference> for ReferenceTuple {
+    fn from(reference: &crate::UpdateReference) -> Self {
+        (
+            reference.href.clone(),
+            reference.id.clone(),
+            reference.title.clone(),
+            reference.reftype.clone(),
+        )
+    }
+}
+
+// name, stream, version, context, arch
+type ModuleTuple = (String, String, u64, String, String);
+
+#[pyclass]
+#[derive(Clone)]
+struct UpdateCollection {
+    inner: crate::UpdateCollection,
+}
+
+#[pymethods]
+impl UpdateCollection {
+    #[new]
+    fn new() -> Self {
+        Self {
+            inner: crate::UpdateCollection::default(),
+        }
+    }
+
+    #[setter(name)]
+    fn set_name(&mut self, name: &str) {
+        self.inner.name = name.to_owned();
+    }
+
+    #[getter(name)]
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    #[setter(shortname)]
+    fn set_shortname(&mut self, shortname: &str) {
+        self.inner.shortname = shortname.to_owned();
+    }
+
+    #[getter(shortname)]
+    fn shortname(&self) -> &str {
+        &self.inner.shortname
+    }
+
+    #[setter(module)]
+    fn set_module(&mut self, module: Option<ModuleTuple>) {
+        self.inner.module = module.map(|m| crate::UpdateCollectionModule {
+            name: m.0,
+            stream: m.1,
+            version: m.2,
+            context: m.3,
+            arch: m.4,
+        });
+    }
+
+    #[getter(module)]
+    fn module(&self) -> Option<ModuleTuple> {
+        self.inner.module.as_ref().map(|m| {
+            (
+                m.name.clone(),
+                m.stream.clone(),
+                m.version,
+                m.context.clone(),
+                m.arch.clone(),
+            )
+        })
+    }
+
+    #[setter(packages)]
+    fn set_packages(&mut self, packages: Vec<UpdateCollectionPackage>) {
+        self.inner.packages = packages.into_iter().map(|p| p.inner).collect();
+    }
+
+    #[getter(packages)]
+    fn packages(&self) -> Vec<UpdateCollectionPackage> {
+        self.inner
+            .packages
+            .iter()
+            .map(|p| UpdateCollectionPackage { inner: p.clone() })
+            .collect()
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct UpdateCollectionPackage {
+    inner: crate::UpdateCollectionPackage,
+}
+
+#[pymethods]
+impl UpdateCollectionPackage {
+    #[new]
+    fn new() -> Self {
+        Self {
+            inner: crate::UpdateCollectionPackage::default(),
+        }
+    }
+
+    #[setter(name)]
+    fn set_name(&mut self, name: &str) {
+        self.inner.name = name.to_owned();
+    }
+
+    #[getter(name)]
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    #[setter(epoch)]
+    fn set_epoch(&mut self, epoch: &str) {
+        self.inner.epoch = epoch.to_owned();
+    }
+
+    #[getter(epoch)]
+    fn epoch(&self) -> &str {
+        &self.inner.epoch
+    }
+
+    #[setter(version)]
+    fn set_version(&mut self, version: &str) {
+        self.inner.version = version.to_owned();
+    }
+
+    #[getter(version)]
+    fn version(&self) -> &str {
+        &self.inner.version
+    }
+
+    #[setter(release)]
+    fn set_release(&mut self, release: &str) {
+        self.inner.release = release.to_owned();
+    }
+
+    #[getter(release)]
+    fn release(&self) -> &str {
+        &self.inner.release
+    }
+
+    #[setter(arch)]
+    fn set_arch(&mut self, arch: &str) {
+        self.inner.arch = arch.to_owned();
+    }
+
+    #[getter(arch)]
+    fn arch(&self) -> &str {
+        &self.inner.arch
+    }
+
+    #[setter(src)]
+    fn set_src(&mut self, src: &str) {
+        self.inner.src = src.to_owned();
+    }
+
+    #[getter(src)]
+    fn src(&self) -> &str {
+        &self.inner.src
+    }
+
+    #[setter(filename)]
+    fn set_filename(&mut self, filename: &str) {
+        self.inner.filename = filename.to_owned();
+    }
+
+    #[getter(filename)]
+    fn filename(&self) -> &str {
+        &self.inner.filename
+    }
+
+    #[setter(reboot_suggested)]
+    fn set_reboot_suggested(&mut self, reboot_suggested: bool) {
+        self.inner.reboot_suggested = reboot_suggested;
+    }
+
+    #[getter(reboot_suggested)]
+    fn reboot_suggested(&self) -> bool {
+        self.inner.reboot_suggested
+    }
+
+    #[setter(restart_suggested)]
+    fn set_restart_suggested(&mut self, restart_suggested: bool) {
+        self.inner.restart_suggested = restart_suggested;
+    }
+
+    #[getter(restart_suggested)]
+    fn restart_suggested(&self) -> bool {
+        self.inner.restart_suggested
+    }
+
+    #[setter(relogin_suggested)]
+    fn set_relogin_suggested(&mut self, relogin_suggested: bool) {
+        self.inner.relogin_suggested = relogin_suggested;
+    }
+
+    #[getter(relogin_suggested)]
+    fn relogin_suggested(&self) -> bool {
+        self.inner.relogin_suggested
+    }
+
+    #[setter(checksum)]
+    fn set_checksum(&mut self, checksum: Option<(&str, &str)>) -> PyResult<()> {
+        self.inner.checksum = checksum
+            .map(|(checksum_type, checksum)| {
+                crate::metadata::Checksum::try_create(checksum_type, checksum)
+            })
+            .transpose()?;
+        Ok(())
+    }
+
+    #[getter(checksum)]
+    fn checksum(&self) -> Option<(&str, &str)> {
+        self.inner
+            .checksum
+            .as_ref()
+            .and_then(|checksum| checksum.to_values().ok())
+    }
+}
 
 #[pyclass]
 struct EVR {
@@ -839,7 +1441,7 @@ impl EVR {
     #[staticmethod]
     fn parse(evr: &str) -> PyResult<Self> {
         let py_evr = EVR {
-            inner: crate::EVR::parse_values(evr)?.try_into()?,
+            inner: crate::EVR::parse(evr),
         };
         Ok(py_evr)
     }
@@ -894,13 +1496,17 @@ impl fmt::Display for EVR {
 #[pymodule]
 fn rpmrepo_metadata(py: Python, m: &PyModule) -> PyResult<()> {
     // m.add_class::<Repository>()?;
-    // m.add_class::<RepositoryWriter>()?;
+    m.add_class::<RepositoryWriter>()?;
     m.add_class::<RepositoryReader>()?;
-    // m.add_class::<RepositoryOptions>()?;
+    m.add_class::<RepositoryOptions>()?;
     m.add_class::<EVR>()?;
     m.add_class::<Package>()?;
     m.add_class::<PackageReader>()?;
     m.add_class::<UpdateinfoReader>()?;
+    m.add_class::<UpdateinfoWriter>()?;
+    m.add_class::<UpdateRecord>()?;
+    m.add_class::<UpdateCollection>()?;
+    m.add_class::<UpdateCollectionPackage>()?;
 
     // m.add_class::<RepomdXml>()?;
     // m.add_class::<PrimaryXml>()?;
@@ -0,0 +1,119 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use indexmap::IndexMap;
+
+use crate::utils::glob_match;
+use crate::Package;
+
+type PackageFilter<'repo> = Box<dyn Fn(&Package) -> bool + 'repo>;
+
+/// A filter over the packages of a [`crate::Repository`], created with [`crate::Repository::query`].
+///
+/// Filters are combined - a package must match all of them to be returned. Packages are returned
+/// in the order they are stored in the repository.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{MetadataError, Package, Repository};
+/// # fn main() -> Result<(), MetadataError> {
+/// let repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// let hello: Vec<&Package> = repo
+///     .query()
+///     .name_glob("hello*")
+///     .arch("x86_64")
+///     .newest_per_name_arch()
+///     .iter()
+///     .collect();
+/// assert_eq!(hello.len(), 1);
+/// assert_eq!(hello[0].name(), "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub struct PackageQuery<'repo> {
+    packages: &'repo IndexMap<String, Package>,
+    filters: Vec<PackageFilter<'repo>>,
+    newest_only: bool,
+}
+
+impl<'repo> PackageQuery<'repo> {
+    pub(crate) fn new(packages: &'repo IndexMap<String, Package>) -> Self {
+        PackageQuery {
+            packages,
+            filters: Vec::new(),
+            newest_only: false,
+        }
+    }
+
+    /// Only match packages with exactly this name.
+    pub fn name(self, name: &str) -> Self {
+        let name = name.to_owned();
+        self.filter(move |pkg| pkg.name() == name)
+    }
+
+    /// Only match packages with a name matching a shell-style glob, e.g. `kernel*`.
+    pub fn name_glob(self, pattern: &str) -> Self {
+        let pattern = pattern.to_owned();
+        self.filter(move |pkg| glob_match(&pattern, pkg.name()))
+    }
+
+    /// Only match packages of this architecture.
+    pub fn arch(self, arch: &str) -> Self {
+        self.arches(&[arch])
+    }
+
+    /// Only match packages of any of these architectures.
+    pub fn arches(self, arches: &[&str]) -> Self {
+        let arches: Vec<String> = arches.iter().map(|a| a.to_string()).collect();
+        self.filter(move |pkg| arches.iter().any(|a| a == pkg.arch()))
+    }
+
+    /// Only match packages for which the provided predicate returns `true`.
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Package) -> bool + 'repo,
+    {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// Of the matching packages, only return the one with the highest EVR for each (name, arch) pair.
+    ///
+    /// Packages with the same name but different architectures are considered separately.
+    pub fn newest_per_name_arch(self) -> Self {
+        Self {
+            newest_only: true,
+            ..self
+        }
+    }
+
+    fn matches(&self, pkg: &Package) -> bool {
+        self.filters.iter().all(|f| f(pkg))
+    }
+
+    /// Iterate over the matching packages.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &'repo Package> + '_> {
+        let matching = self.packages.values().filter(move |pkg| self.matches(pkg));
+
+        if !self.newest_only {
+            return Box::new(matching);
+        }
+
+        let mut newest: IndexMap<(&str, &str), &'repo Package> = IndexMap::new();
+        for pkg in matching {
+            let entry = newest.entry((pkg.name(), pkg.arch())).or_insert(pkg);
+            if pkg.evr() > entry.evr() {
+                *entry = pkg;
+            }
+        }
+        Box::new(newest.into_values())
+    }
+
+    /// Count the matching packages.
+    pub fn count(&self) -> usize {
+        self.iter().count()
+    }
+}
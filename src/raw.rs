@@ -0,0 +1,299 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::metadata::{METADATA_FILELISTS, METADATA_OTHER, METADATA_PRIMARY};
+use crate::{utils, MetadataError, ParseError, EVR};
+
+const TAG_PACKAGE: &[u8] = b"package";
+const TAG_NAME: &[u8] = b"name";
+const TAG_VERSION: &[u8] = b"version";
+const TAG_ARCH: &[u8] = b"arch";
+const TAG_CHECKSUM: &[u8] = b"checksum";
+
+/// A package in the form of its unparsed `<package>` elements from primary.xml, filelists.xml and other.xml.
+///
+/// Only the fields needed to identify the package are parsed. The elements can be written to another
+/// repository as-is with [`crate::RepositoryWriter::add_package_raw`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawPackage {
+    pub pkgid: String,
+    pub name: String,
+    pub evr: EVR,
+    pub arch: String,
+    pub primary_xml: String,
+    /// `None` if filelists.xml was not read
+    pub filelists_xml: Option<String>,
+    /// `None` if other.xml was not read
+    pub other_xml: Option<String>,
+}
+
+impl RawPackage {
+    pub fn nevra(&self) -> String {
+        format!(
+            "{}-{}:{}-{}.{}",
+            self.name, self.evr.epoch, self.evr.version, self.evr.release, self.arch
+        )
+    }
+}
+
+/// Iterator over the packages of a repository as [`RawPackage`]s, for filtering and re-publishing subsets of
+/// large repositories without fully parsing every package.
+///
+/// Like [`crate::PackageIterator`], expects the metadata files to list the packages in the same order.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{MetadataError, MetadataSelection, RepositoryReader, RepositoryWriter};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// # let tmp_dir = tempdir::TempDir::new("doctest_raw")?;
+/// # let (destination, num_pkgs) = (tmp_dir.path(), 3);
+/// let mut writer = RepositoryWriter::new(&destination, num_pkgs)?;
+/// for package in reader.iter_raw_packages(MetadataSelection::default())? {
+///     let package = package?;
+///     if package.arch != "src" {
+///         writer.add_package_raw(
+///             &package.primary_xml,
+///             package.filelists_xml.as_deref().unwrap_or_default(),
+///             package.other_xml.as_deref().unwrap_or_default(),
+///         )?;
+///     }
+/// }
+/// writer.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RawPackageIterator {
+    primary_xml: RawXmlReader,
+    filelists_xml: Option<RawXmlReader>,
+    other_xml: Option<RawXmlReader>,
+    exhausted: bool,
+}
+
+impl RawPackageIterator {
+    pub fn from_files(
+        primary_path: &Path,
+        filelists_path: Option<&Path>,
+        other_path: Option<&Path>,
+    ) -> Result<Self, MetadataError> {
+        Ok(Self::from_readers(
+            utils::reader_from_file(primary_path)?,
+            filelists_path.map(utils::reader_from_file).transpose()?,
+            other_path.map(utils::reader_from_file).transpose()?,
+        ))
+    }
+
+    /// Create an iterator from readers of the (decompressed) contents of the metadata files.
+    pub fn from_readers(
+        primary_xml: Box<dyn Read + Send>,
+        filelists_xml: Option<Box<dyn Read + Send>>,
+        other_xml: Option<Box<dyn Read + Send>>,
+    ) -> Self {
+        Self {
+            primary_xml: RawXmlReader::new(primary_xml),
+            filelists_xml: filelists_xml.map(RawXmlReader::new),
+            other_xml: other_xml.map(RawXmlReader::new),
+            exhausted: false,
+        }
+    }
+
+    pub fn parse_package(&mut self) -> Result<Option<RawPackage>, MetadataError> {
+        if self.exhausted {
+            return Ok(None);
+        }
+        let result = self.parse_next_package();
+        if !matches!(result, Ok(Some(_))) {
+            self.exhausted = true;
+        }
+        result
+    }
+
+    fn parse_next_package(&mut self) -> Result<Option<RawPackage>, MetadataError> {
+        let mut pkgid = String::new();
+        let mut name = String::new();
+        let mut evr = EVR::default();
+        let mut arch = String::new();
+
+        let primary_xml = self
+            .primary_xml
+            .read_package(METADATA_PRIMARY, |reader, tag| {
+                match tag.name() {
+                    TAG_NAME => name = reader.read_text(TAG_NAME, &mut Vec::new())?,
+                    TAG_ARCH => arch = reader.read_text(TAG_ARCH, &mut Vec::new())?,
+                    TAG_CHECKSUM => pkgid = reader.read_text(TAG_CHECKSUM, &mut Vec::new())?,
+                    TAG_VERSION => {
+                        evr = EVR::new(
+                            utils::required_attribute(tag, "epoch")?,
+                            utils::required_attribute(tag, "ver")?,
+                            utils::required_attribute(tag, "rel")?,
+                        )
+                    }
+                    _ => (),
+                }
+                Ok(())
+            })?;
+        let primary_xml = match primary_xml {
+            Some(primary_xml) => primary_xml,
+            None => return Ok(None),
+        };
+
+        let filelists_xml = match &mut self.filelists_xml {
+            Some(reader) => Some(read_secondary_package(reader, METADATA_FILELISTS, &pkgid)?),
+            None => None,
+        };
+        let other_xml = match &mut self.other_xml {
+            Some(reader) => Some(read_secondary_package(reader, METADATA_OTHER, &pkgid)?),
+            None => None,
+        };
+
+        Ok(Some(RawPackage {
+            pkgid,
+            name,
+            evr,
+            arch,
+            primary_xml,
+            filelists_xml,
+            other_xml,
+        }))
+    }
+}
+
+impl Iterator for RawPackageIterator {
+    type Item = Result<RawPackage, MetadataError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parse_package().transpose()
+    }
+}
+
+/// Read the `<package>` element of filelists.xml or other.xml belonging to the package with `pkgid`.
+fn read_secondary_package(
+    reader: &mut RawXmlReader,
+    file: &'static str,
+    pkgid: &str,
+) -> Result<String, MetadataError> {
+    let mut found_pkgid = None;
+    let element = reader.read_package(file, |_, tag| {
+        if tag.name() == TAG_PACKAGE {
+            found_pkgid = Some(utils::required_attribute(tag, "pkgid")?.into_owned());
+        }
+        Ok(())
+    })?;
+
+    match (element, found_pkgid) {
+        (Some(element), Some(found_pkgid)) if found_pkgid == pkgid => Ok(element),
+        (Some(_), found_pkgid) => Err(ParseError::InconsistentMetadata(format!(
+            "expected package with pkgid {} in {}, found {}",
+            pkgid,
+            file,
+            found_pkgid.unwrap_or_default()
+        ))
+        .into()),
+        (None, _) => Err(ParseError::InconsistentMetadata(
+            "Metadata files contain different numbers of packages".to_owned(),
+        )
+        .into()),
+    }
+}
+
+/// The bytes read from a metadata file which haven't been claimed by an element yet.
+#[derive(Default)]
+struct Recording {
+    bytes: Vec<u8>,
+    /// The position in the file of the first byte of `bytes`.
+    offset: usize,
+}
+
+/// Records the bytes read through it, so that the source text of elements can be recovered after parsing.
+struct RecordingReader {
+    inner: Box<dyn Read + Send>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl Read for RecordingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.recording
+            .lock()
+            .unwrap()
+            .bytes
+            .extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+struct RawXmlReader {
+    reader: Reader<BufReader<RecordingReader>>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl RawXmlReader {
+    fn new(inner: Box<dyn Read + Send>) -> Self {
+        let recording = Arc::new(Mutex::new(Recording::default()));
+        let reader = utils::create_xml_reader(BufReader::new(RecordingReader {
+            inner,
+            recording: recording.clone(),
+        }));
+        Self { reader, recording }
+    }
+
+    /// Find the next `<package>` element and return its source text, calling `visit` with the start tag
+    /// of the element and of each element within it.
+    fn read_package<F>(
+        &mut self,
+        file: &'static str,
+        mut visit: F,
+    ) -> Result<Option<String>, MetadataError>
+    where
+        F: FnMut(&mut Reader<BufReader<RecordingReader>>, &BytesStart) -> Result<(), MetadataError>,
+    {
+        let mut buf = Vec::with_capacity(128);
+        let mut start = None;
+
+        loop {
+            let event = self.reader.read_event(&mut buf).map_err(|e| {
+                MetadataError::from(e).at_position(file, self.reader.buffer_position(), None)
+            })?;
+            match event {
+                Event::Start(tag) => {
+                    if tag.name() == TAG_PACKAGE {
+                        // the position is just past the '>' of the start tag
+                        start = Some(self.reader.buffer_position() - tag.len() - 2);
+                    }
+                    if start.is_some() {
+                        visit(&mut self.reader, &tag).map_err(|e| {
+                            e.at_position(file, self.reader.buffer_position(), None)
+                        })?;
+                    }
+                }
+                Event::End(tag) if tag.name() == TAG_PACKAGE => {
+                    if let Some(start) = start {
+                        return self.take(start, self.reader.buffer_position()).map(Some);
+                    }
+                }
+                Event::Eof => return Ok(None),
+                _ => (),
+            }
+            buf.clear();
+        }
+    }
+
+    /// Take the source text between two positions in the file, discarding everything before it.
+    fn take(&self, start: usize, end: usize) -> Result<String, MetadataError> {
+        let mut recording = self.recording.lock().unwrap();
+        let (from, to) = (start - recording.offset, end - recording.offset);
+        let text = std::str::from_utf8(&recording.bytes[from..to])?.to_owned();
+        recording.bytes.drain(..to);
+        recording.offset = end;
+        Ok(text)
+    }
+}
@@ -0,0 +1,418 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::metadata::RepomdXml;
+use crate::{
+    utils, Checksum, ChecksumError, ChecksumType, MetadataError, Package, ParseError,
+    RepoLayoutError, RepomdData, Repository, RepositoryReader,
+};
+
+/// Retrieves files from a remote location for [`RemoteRepository`].
+///
+/// With the `http` feature, `HttpFetcher` downloads over HTTP(S). Otherwise the transport is provided by
+/// the caller - any `Fn(&str, &mut dyn Write) -> Result<(), MetadataError>` closure is a `Fetcher`. A
+/// fetcher would usually wrap an HTTP client, but this one serves `file://` URLs:
+///
+/// ```
+/// # use std::fs::File;
+/// # use std::io::{self, Write};
+/// # use rpmrepo_metadata::{MetadataError, RemoteRepository};
+/// # fn main() -> Result<(), MetadataError> {
+/// let fetcher = |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+///     let path = url.strip_prefix("file://").expect("not a file:// URL");
+///     io::copy(&mut File::open(path)?, writer)?;
+///     Ok(())
+/// };
+/// let remote = RemoteRepository::new("file://tests/assets/doctest_repo", fetcher)?;
+/// assert!(remote.repomd().get_record("primary").is_some());
+/// # Ok(())
+/// # }
+/// ```
+pub trait Fetcher {
+    /// Retrieve the file at `url`, writing its contents into `writer`.
+    fn fetch(&self, url: &str, writer: &mut dyn Write) -> Result<(), MetadataError>;
+
+    /// Retrieve the rest of the file at `url` after the [`PartialDownload::offset`] bytes which have already
+    /// been downloaded, writing it into `download`, to resume an interrupted download, e.g. with an HTTP
+    /// `Range` request. If the whole file is retrieved instead, e.g. because the server ignored the range,
+    /// call [`PartialDownload::restart`] before writing it.
+    ///
+    /// Returns `false` without writing anything if the transport or server can't resume, in which case
+    /// the file is retrieved from the start with [`Fetcher::fetch`]. The default implementation never
+    /// resumes.
+    fn fetch_from(
+        &self,
+        _url: &str,
+        _download: &mut PartialDownload,
+    ) -> Result<bool, MetadataError> {
+        Ok(false)
+    }
+
+    /// Retrieve the file at `url` like [`Fetcher::fetch`], unless it hasn't changed since `validators` were
+    /// obtained, e.g. with HTTP `If-None-Match` and `If-Modified-Since` requests.
+    ///
+    /// The default implementation always retrieves the file, without any validators.
+    fn fetch_if_modified(
+        &self,
+        url: &str,
+        _validators: &CacheValidators,
+        writer: &mut dyn Write,
+    ) -> Result<ConditionalFetch, MetadataError> {
+        self.fetch(url, writer)?;
+        Ok(ConditionalFetch::Modified(CacheValidators::default()))
+    }
+}
+
+/// The validators of a previously retrieved file, which can be used to skip retrieving it again if it hasn't
+/// changed. See [`Fetcher::fetch_if_modified`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheValidators {
+    /// An identifier of the version of the file, e.g. the HTTP `ETag` header.
+    pub etag: Option<String>,
+    /// The time the file was last modified, e.g. the HTTP `Last-Modified` header.
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    /// Whether there are no validators, in which case the file must always be retrieved.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// The outcome of [`Fetcher::fetch_if_modified`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConditionalFetch {
+    /// The file was retrieved, and has these validators.
+    Modified(CacheValidators),
+    /// The file hasn't changed, and nothing was written.
+    NotModified,
+}
+
+/// A file which has been partially downloaded to `<path>.part`, which [`Fetcher::fetch_from`] writes the rest
+/// of the file into.
+pub struct PartialDownload {
+    path: PathBuf,
+    offset: u64,
+    checksum_type: ChecksumType,
+    writer: utils::ChecksumWriter<BufWriter<File>>,
+}
+
+impl PartialDownload {
+    /// Open the partial file at `path` to append to it, or create it if `resume` is false.
+    fn open(path: &Path, checksum_type: ChecksumType, resume: bool) -> Result<Self, MetadataError> {
+        if !resume {
+            return Ok(Self {
+                path: path.to_owned(),
+                offset: 0,
+                checksum_type,
+                writer: utils::ChecksumWriter::new(
+                    BufWriter::new(File::create(path)?),
+                    &[checksum_type],
+                ),
+            });
+        }
+        let mut writer = utils::ChecksumWriter::new(
+            BufWriter::new(OpenOptions::new().append(true).open(path)?),
+            &[checksum_type],
+        );
+        // the checksum is computed while downloading rather than by reading the file again afterwards
+        let offset = writer.update_from(File::open(path)?)?;
+        Ok(Self {
+            path: path.to_owned(),
+            offset,
+            checksum_type,
+            writer,
+        })
+    }
+
+    /// The number of bytes which have already been downloaded.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Discard what has already been downloaded, to write the whole file from the start.
+    pub fn restart(&mut self) -> Result<(), MetadataError> {
+        *self = Self::open(&self.path, self.checksum_type, false)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Checksum, MetadataError> {
+        self.writer.flush()?;
+        let (_, mut checksums) = self.writer.finish();
+        Ok(checksums.remove(0))
+    }
+}
+
+impl Write for PartialDownload {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<F> Fetcher for F
+where
+    F: Fn(&str, &mut dyn Write) -> Result<(), MetadataError>,
+{
+    fn fetch(&self, url: &str, writer: &mut dyn Write) -> Result<(), MetadataError> {
+        self(url, writer)
+    }
+}
+
+/// A repository at a remote location, from which individual types of metadata can be downloaded.
+///
+/// Only `repomd.xml` is retrieved when the `RemoteRepository` is created. Consumers which only need
+/// some of the metadata, e.g. vulnerability scanners which need primary.xml and updateinfo.xml, can
+/// avoid downloading the much larger filelists.xml and other.xml.
+///
+/// ```
+/// # use std::fs::File;
+/// # use std::io::{self, Write};
+/// # use rpmrepo_metadata::{MetadataError, MetadataSelection, RemoteRepository};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let fetcher = |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+/// #     io::copy(&mut File::open(url.strip_prefix("file://").unwrap())?, writer)?;
+/// #     Ok(())
+/// # };
+/// # let cache_dir = tempdir::TempDir::new("doctest_remote")?;
+/// let remote = RemoteRepository::new("file://tests/assets/doctest_repo", fetcher)?;
+/// let reader = remote.fetch_metadata(&["primary", "updateinfo"], cache_dir.path())?;
+///
+/// let selection = MetadataSelection { filelists: false, other: false };
+/// for package in reader.iter_packages_with(selection)? {
+///     // without other.xml, the packages have no changelogs
+///     assert!(package?.changelogs().is_empty());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RemoteRepository<F: Fetcher> {
+    base_url: String,
+    fetcher: F,
+    repomd_xml: Vec<u8>,
+    validators: CacheValidators,
+    repository: Repository,
+}
+
+impl<F: Fetcher> RemoteRepository<F> {
+    /// Retrieve and parse `repodata/repomd.xml` from the repository at `base_url`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(fetcher)))]
+    pub fn new(base_url: &str, fetcher: F) -> Result<Self, MetadataError> {
+        Self::new_if_modified(base_url, fetcher, &CacheValidators::default())?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "repomd.xml reported as not modified without any cache validators",
+            )
+            .into()
+        })
+    }
+
+    /// Retrieve and parse `repodata/repomd.xml` from the repository at `base_url`, unless it hasn't changed
+    /// since `validators` were obtained from [`RemoteRepository::validators`], in which case `None` is
+    /// returned. This makes checking a repository which hasn't changed cheap, if the [`Fetcher`] supports it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(fetcher)))]
+    pub fn new_if_modified(
+        base_url: &str,
+        fetcher: F,
+        validators: &CacheValidators,
+    ) -> Result<Option<Self>, MetadataError> {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+
+        let mut repomd_xml = Vec::new();
+        let validators = match fetcher.fetch_if_modified(
+            &format!("{}/repodata/repomd.xml", base_url),
+            validators,
+            &mut repomd_xml,
+        )? {
+            ConditionalFetch::Modified(validators) => validators,
+            ConditionalFetch::NotModified => return Ok(None),
+        };
+        let mut repository = Repository::new();
+        repository.load_metadata_bytes::<RepomdXml>(&repomd_xml)?;
+
+        Ok(Some(Self {
+            base_url,
+            fetcher,
+            repomd_xml,
+            validators,
+            repository,
+        }))
+    }
+
+    /// The base URL of the repository.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The validators of `repomd.xml`, to pass to [`RemoteRepository::new_if_modified`] the next time the
+    /// repository is checked.
+    pub fn validators(&self) -> &CacheValidators {
+        &self.validators
+    }
+
+    /// The contents of `repomd.xml`.
+    pub fn repomd(&self) -> &RepomdData {
+        self.repository.repomd()
+    }
+
+    /// Download all of the metadata files listed in `repomd.xml` to `destination`, including types which this
+    /// library doesn't parse (e.g. "susedata" or "appdata"), and return a [`RepositoryReader`] for them.
+    ///
+    /// See [`RemoteRepository::fetch_metadata`].
+    pub fn fetch_all_metadata(
+        &self,
+        destination: &Path,
+    ) -> Result<RepositoryReader, MetadataError> {
+        let names: Vec<&str> = self
+            .repomd()
+            .records()
+            .iter()
+            .map(|record| record.metadata_name.as_str())
+            .collect();
+        self.fetch_metadata(&names, destination)
+    }
+
+    /// Download the metadata files of the given types (e.g. "primary" or "updateinfo") to `destination`,
+    /// along with `repomd.xml`, and return a [`RepositoryReader`] for them.
+    ///
+    /// Files are placed at the same relative locations as in the remote repository, and their checksums
+    /// are verified against `repomd.xml`. Types which are not present in `repomd.xml` are skipped.
+    ///
+    /// The reader only has access to the downloaded files - if filelists.xml or other.xml were not fetched,
+    /// packages must be read with [`RepositoryReader::iter_packages_with`] and a [`crate::MetadataSelection`]
+    /// excluding them. Likewise, advisories can only be read if "updateinfo" was fetched.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(base_url = %self.base_url))
+    )]
+    pub fn fetch_metadata(
+        &self,
+        names: &[&str],
+        destination: &Path,
+    ) -> Result<RepositoryReader, MetadataError> {
+        let repodata_dir = destination.join("repodata");
+        std::fs::create_dir_all(&repodata_dir)?;
+
+        for name in names {
+            let record = match self.repomd().get_record(name) {
+                Some(record) => record,
+                None => continue,
+            };
+            if !utils::path_is_contained(&record.location_href) {
+                return Err(RepoLayoutError::MetadataLocation(
+                    record.location_href.display().to_string(),
+                )
+                .into());
+            }
+
+            self.download(
+                record.location_base.as_deref(),
+                &record.location_href.to_string_lossy(),
+                &destination.join(&record.location_href),
+                &record.checksum,
+            )?;
+        }
+
+        // written last, so that an interrupted download doesn't leave behind a repomd.xml pointing to missing files
+        std::fs::write(repodata_dir.join("repomd.xml"), &self.repomd_xml)?;
+
+        RepositoryReader::new_from_directory(destination)
+    }
+
+    /// Download the file of `package` to its `location_href` within the repository at `destination`,
+    /// verifying it against the package checksum. Returns the path of the file.
+    ///
+    /// Packages with a `location_base` are downloaded from there rather than from this repository. Like
+    /// metadata files, interrupted downloads are resumed and files already present are not downloaded again.
+    pub fn download_package(
+        &self,
+        package: &Package,
+        destination: &Path,
+    ) -> Result<PathBuf, MetadataError> {
+        let href = package.location_href();
+        if !utils::path_is_contained(Path::new(href)) {
+            return Err(RepoLayoutError::UnsafeHref(href.to_owned()).into());
+        }
+        let checksum = package
+            .checksum()
+            .ok_or(ParseError::MissingField("checksum"))?;
+        let path = destination.join(href);
+        self.download(package.location_base(), href, &path, checksum)?;
+        Ok(path)
+    }
+
+    /// Download the file at `location_href` (relative to `location_base` if provided, the repository
+    /// otherwise) to `path`, verifying it against `checksum`.
+    ///
+    /// The file is downloaded to `<path>.part` and only moved into place once it has been verified. If the
+    /// download fails, the partial file is kept, and the next download resumes from where it stopped with
+    /// [`Fetcher::fetch_from`]. A file which is already present at `path` with the expected checksum is not
+    /// downloaded again.
+    pub(crate) fn download(
+        &self,
+        location_base: Option<&str>,
+        location_href: &str,
+        path: &Path,
+        checksum: &Checksum,
+    ) -> Result<(), MetadataError> {
+        let base_url = location_base.unwrap_or(&self.base_url);
+        let url = format!("{}/{}", base_url.trim_end_matches('/'), location_href);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".part");
+        let partial_path = PathBuf::from(partial_path);
+
+        if path.is_file() && utils::checksum_file(path, checksum.checksum_type())? == *checksum {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(%url, "already downloaded");
+            return Ok(());
+        }
+
+        let mut download = None;
+        if partial_path.metadata().map_or(0, |m| m.len()) > 0 {
+            let mut partial = PartialDownload::open(&partial_path, checksum.checksum_type(), true)?;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(%url, offset = partial.offset(), "resuming download");
+            if self.fetcher.fetch_from(&url, &mut partial)? {
+                download = Some(partial);
+            }
+        }
+        let download = match download {
+            Some(download) => download,
+            None => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(%url, "downloading");
+                let mut download =
+                    PartialDownload::open(&partial_path, checksum.checksum_type(), false)?;
+                self.fetcher.fetch(&url, &mut download)?;
+                download
+            }
+        };
+        let actual = download.finish()?;
+
+        if actual != *checksum {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(%url, expected = ?checksum, ?actual, "checksum mismatch");
+            // the partial file can't be resumed from
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(ChecksumError::Mismatch(url).into());
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%url, "downloaded and verified");
+        std::fs::rename(&partial_path, path)?;
+        Ok(())
+    }
+}
@@ -6,7 +6,6 @@
 
 use std::convert::{TryFrom, TryInto};
 use std::io::{BufRead, Write};
-use std::os::unix::prelude::OsStrExt;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
@@ -16,9 +15,10 @@ use quick_xml::{Reader, Writer};
 
 use super::metadata::RepomdData;
 use super::metadata::{
-    Checksum, MetadataError, RepomdRecord, RepomdXml, RpmMetadata, XML_NS_REPO, XML_NS_RPM,
+    Checksum, MetadataError, ParseError, RepomdRecord, RepomdXml, RpmMetadata, TargetClient,
+    XML_NS_REPO, XML_NS_RPM,
 };
-use super::Repository;
+use super::{utils, Repository};
 
 // RepoMd
 const TAG_REPOMD: &[u8] = b"repomd";
@@ -58,7 +58,7 @@ impl RpmMetadata for RepomdXml {
         writer: Writer<W>,
     ) -> Result<(), MetadataError> {
         let mut writer = writer;
-        write_repomd_xml(repository.repomd(), &mut writer)?;
+        write_repomd_xml(repository.repomd(), &mut writer, TargetClient::default())?;
         Ok(())
     }
 }
@@ -68,7 +68,16 @@ impl RepomdXml {
         repomd_data: &RepomdData,
         writer: &mut Writer<W>,
     ) -> Result<(), MetadataError> {
-        write_repomd_xml(repomd_data, writer)
+        write_repomd_xml(repomd_data, writer, TargetClient::default())
+    }
+
+    /// Write `repomd.xml`, omitting the elements and records which `target` doesn't support.
+    pub fn write_data_for_target<W: Write>(
+        repomd_data: &RepomdData,
+        writer: &mut Writer<W>,
+        target: TargetClient,
+    ) -> Result<(), MetadataError> {
+        write_repomd_xml(repomd_data, writer, target)
     }
 
     pub fn read_data<R: BufRead>(reader: Reader<R>) -> Result<RepomdData, MetadataError> {
@@ -101,15 +110,15 @@ impl TryFrom<RepomdRecordBuilder> for RepomdRecord {
         record.metadata_name = builder.metadata_name;
         record.location_href = builder
             .location_href
-            .ok_or_else(|| MetadataError::MissingFieldError("location_href"))?;
+            .ok_or_else(|| ParseError::MissingField("location_href"))?;
         record.location_base = builder.location_base;
         record.timestamp = builder
             .timestamp
-            .ok_or_else(|| MetadataError::MissingFieldError("timestamp"))?;
+            .ok_or_else(|| ParseError::MissingField("timestamp"))?;
         record.size = builder.size;
         record.checksum = builder
             .checksum
-            .ok_or_else(|| MetadataError::MissingFieldError("checksum"))?;
+            .ok_or_else(|| ParseError::MissingField("checksum"))?;
         record.open_size = builder.open_size;
         record.open_checksum = builder.open_checksum; // TODO: do these need to be conditionally required?
         record.header_size = builder.header_size;
@@ -177,6 +186,7 @@ fn read_repomd_xml<R: BufRead>(
                             },
 
                             Event::End(e) if e.name().as_ref() == TAG_TAGS => break,
+                            Event::Eof => return Err(utils::unexpected_eof(TAG_TAGS)),
                             _ => (),
                         }
                         text_buf.clear();
@@ -213,7 +223,7 @@ pub fn parse_repomdrecord<R: BufRead>(
 
     let record_type = open_tag
         .try_get_attribute("type")?
-        .ok_or_else(|| MetadataError::MissingAttributeError("type"))?
+        .ok_or_else(|| ParseError::MissingAttribute("type"))?
         .value
         .iter()
         .cloned()
@@ -229,7 +239,7 @@ pub fn parse_repomdrecord<R: BufRead>(
                 TAG_CHECKSUM => {
                     let checksum_type = e
                         .try_get_attribute("type")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("type"))?;
+                        .ok_or_else(|| ParseError::MissingAttribute("type"))?;
                     let checksum_value = reader.read_text(e.name(), &mut record_buf)?;
                     let checksum = Checksum::try_create(
                         checksum_type.value.as_ref(),
@@ -240,7 +250,7 @@ pub fn parse_repomdrecord<R: BufRead>(
                 TAG_OPEN_CHECKSUM => {
                     let checksum_type = e
                         .try_get_attribute("type")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("type"))?;
+                        .ok_or_else(|| ParseError::MissingAttribute("type"))?;
                     let checksum_value = reader.read_text(e.name(), &mut record_buf)?;
                     let checksum = Checksum::try_create(
                         checksum_type.value.as_ref(),
@@ -251,7 +261,7 @@ pub fn parse_repomdrecord<R: BufRead>(
                 TAG_HEADER_CHECKSUM => {
                     let checksum_type = e
                         .try_get_attribute("type")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("type"))?;
+                        .ok_or_else(|| ParseError::MissingAttribute("type"))?;
                     let checksum_value = reader.read_text(e.name(), &mut record_buf)?;
                     let checksum = Checksum::try_create(
                         checksum_type.value.as_ref(),
@@ -262,7 +272,7 @@ pub fn parse_repomdrecord<R: BufRead>(
                 TAG_LOCATION => {
                     let location = e
                         .try_get_attribute("href")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("href"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("href"))?
                         .unescape_and_decode_value(reader)?
                         .into();
                     record_builder.location_href = Some(location);
@@ -290,6 +300,7 @@ pub fn parse_repomdrecord<R: BufRead>(
                 _ => (),
             },
             Event::End(e) if e.name().as_ref() == TAG_DATA => break,
+            Event::Eof => return Err(utils::unexpected_eof(TAG_DATA)),
             _ => (),
         }
         record_buf.clear();
@@ -300,6 +311,7 @@ pub fn parse_repomdrecord<R: BufRead>(
 fn write_repomd_xml<W: Write>(
     repomd_data: &RepomdData,
     writer: &mut Writer<W>,
+    target: TargetClient,
 ) -> Result<(), MetadataError> {
     // <?xml version="1.0" encoding="UTF-8"?>
     writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
@@ -314,8 +326,7 @@ fn write_repomd_xml<W: Write>(
     let get_current_time = || {
         SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("system clock failure")
-            .as_secs()
+            .map_or(0, |now| now.as_secs())
             .to_string()
     };
     let revision = if let Some(revision) = repomd_data.revision() {
@@ -329,7 +340,9 @@ fn write_repomd_xml<W: Write>(
 
     write_tags(repomd_data, writer)?;
     for record in repomd_data.records() {
-        write_data(record, writer)?;
+        if target.supports_record(&record.metadata_name) {
+            write_data(record, writer, target)?;
+        }
     }
 
     // </repomd>
@@ -396,7 +409,11 @@ fn write_tags<W: Write>(
 ///    <size>5830735</size>
 ///    <open-size>53965949</open-size>
 ///  </data>
-fn write_data<W: Write>(data: &RepomdRecord, writer: &mut Writer<W>) -> Result<(), MetadataError> {
+fn write_data<W: Write>(
+    data: &RepomdRecord,
+    writer: &mut Writer<W>,
+    target: TargetClient,
+) -> Result<(), MetadataError> {
     // <data>
     let mut data_tag = BytesStart::borrowed_name(TAG_DATA);
     data_tag.push_attribute(("type".as_bytes(), data.metadata_name.as_bytes()));
@@ -419,7 +436,11 @@ fn write_data<W: Write>(data: &RepomdRecord, writer: &mut Writer<W>) -> Result<(
     }
 
     // <header-checksum type="sha256">afdc6dc379e58d097ed0b350536812bc6a604bbce50c5c109d8d98e28301dc4b</header-checksum> (maybe)
-    if let Some(header_checksum) = &data.header_checksum {
+    if let Some(header_checksum) = data
+        .header_checksum
+        .as_ref()
+        .filter(|_| target.supports_header_info())
+    {
         let (checksum_type, checksum_value) = header_checksum.to_values()?;
         writer
             .create_element(TAG_HEADER_CHECKSUM)
@@ -428,9 +449,16 @@ fn write_data<W: Write>(data: &RepomdRecord, writer: &mut Writer<W>) -> Result<(
     }
 
     // <location href="repodata/primary.xml.gz">
+    // hrefs always use forward slashes, regardless of the platform's path separator
+    let location_href = data
+        .location_href
+        .iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
     writer
         .create_element(TAG_LOCATION)
-        .with_attribute(("href".as_bytes(), data.location_href.as_os_str().as_bytes()))
+        .with_attribute(("href", location_href.as_str()))
         .write_empty()?;
 
     // <timestamp>1602869947</timestamp>
@@ -441,28 +469,31 @@ fn write_data<W: Write>(data: &RepomdRecord, writer: &mut Writer<W>) -> Result<(
         ))?;
 
     // <size>123987</size> (maybe)
-    if let Some(size) = data.size {
+    if let Some(size) = data.size.filter(|_| target.supports_sizes()) {
         writer
             .create_element(TAG_SIZE)
             .write_text_content(BytesText::from_plain_str(&size.to_string()))?;
     }
 
     // <open-size>68652</open-size> (maybe)
-    if let Some(open_size) = data.open_size {
+    if let Some(open_size) = data.open_size.filter(|_| target.supports_sizes()) {
         writer
             .create_element(TAG_OPEN_SIZE)
             .write_text_content(BytesText::from_plain_str(&open_size.to_string()))?;
     }
 
     // <header-size>761487</header-size> (maybe)
-    if let Some(size_header) = data.header_size {
+    if let Some(size_header) = data.header_size.filter(|_| target.supports_header_info()) {
         writer
             .create_element(TAG_HEADER_SIZE)
             .write_text_content(BytesText::from_plain_str(&size_header.to_string()))?;
     }
 
     // <database_version>10</database_version>
-    if let Some(database_version) = data.database_version {
+    if let Some(database_version) = data
+        .database_version
+        .filter(|_| target.supports_database_version())
+    {
         writer
             .create_element(TAG_DATABASE_VERSION)
             .write_text_content(BytesText::from_plain_str(&database_version.to_string()))?;
@@ -4,33 +4,56 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
+use crate::cache::MetadataCache;
+use crate::common::{split_nevra, EVR};
+use crate::metadata::{
+    is_generated_record, METADATA_FILELISTS, METADATA_OTHER, METADATA_PRIMARY, METADATA_UPDATEINFO,
+};
+use crate::progress::{ProgressEvent, ProgressHandler, ProgressReader};
+use crate::query::PackageQuery;
+use crate::raw::RawPackageIterator;
 use crate::updateinfo::{UpdateinfoXmlReader, UpdateinfoXmlWriter};
+use crate::utils::{DecodingPolicy, HrefPolicy, LossyUtf8Reader, ParseLimits, XmlLimitReader};
+use crate::warning::{MetadataWarning, WarningHandler};
 use crate::UpdateinfoXml;
-use crate::{utils, PackageIterator};
+use crate::{utils, PackageCountMismatchPolicy, PackageIterator, PackageMatchPolicy};
 
 use super::filelist::FilelistsXmlWriter;
 use super::metadata::{
+    ChangelogTimestampPolicy,
+    Checksum,
     ChecksumType,
     CompressionType,
     FilelistsXml,
     OtherXml,
     Package,
+    PreinstallPolicy,
     PrimaryXml,
     RepomdData,
     RepomdRecord,
     RepomdXml,
     RpmMetadata,
+    TargetClient,
+    UpdateCollectionPackage,
     UpdateRecord, // DistroTag, MetadataType
 };
 use super::other::OtherXmlWriter;
 use super::primary::PrimaryXmlWriter;
-use super::MetadataError;
+use super::schema::{validate_schema_file, MetadataSchema};
+use super::{ChecksumError, MetadataError, ParseError, RepoLayoutError, WriteError};
+use indexmap::map::Entry;
 use indexmap::IndexMap;
 
+/// The most memory (in bytes) which is allocated up front based on sizes stated in the metadata.
+const MAX_PREALLOCATION: u64 = 256 * 1024 * 1024;
+
 /// A high level API for working with RPM repositories.
 ///
 /// This struct attempts to uphold invariants such as
@@ -40,20 +63,26 @@ use indexmap::IndexMap;
 /// Helpers are also provided for keeping packages ordered (helps with the metadata compression ratio).
 ///
 /// All metadata is maintained in working memory (this can be large).
-#[derive(Debug, PartialEq, Default)]
+#[derive(Debug, Default)]
 pub struct Repository {
     repomd_data: RepomdData,
     packages: IndexMap<String, Package>,
     advisories: IndexMap<String, UpdateRecord>,
+    // normalized NEVRA -> pkgid, built on demand and discarded whenever the packages may have been modified
+    nevra_index: OnceLock<HashMap<String, String>>,
+}
+
+impl PartialEq for Repository {
+    fn eq(&self, other: &Self) -> bool {
+        self.repomd_data == other.repomd_data
+            && self.packages == other.packages
+            && self.advisories == other.advisories
+    }
 }
 
 // TODO: worth doing any allocation tricks? (probably not)
 // TODO: probably consolidate package_checksum_type and metadata_checksum_type, no real need for both
-// TODO: provide a way to e.g. remove N old packages from the repo
 // TODO: what to do with updateinfo, groups, modules when packages added or removed?
-// TODO: uphold invariants
-// a) no duplicate NEVRA (normalized for epoch)
-// b) no duplicate advisory IDs
 // TODO:
 
 // configuration options for writing metadata:
@@ -78,20 +107,323 @@ impl Repository {
         &self.packages
     }
 
-    // TODO: better API for package access (entry-like)
+    /// Direct access to the packages, keyed by pkgid.
+    ///
+    /// Unlike [`Repository::add_package`], this does not check for duplicate NEVRAs.
     pub fn packages_mut(&mut self) -> &mut IndexMap<String, Package> {
+        self.nevra_index = OnceLock::new();
         &mut self.packages
     }
 
+    /// Get the entry of the package with the given pkgid, for in-place insertion or modification.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, Repository};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let mut repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// # let mut pkg = repo.packages().values().next().unwrap().clone();
+    /// # pkg.set_location_href("Packages/h/hello-2.10-1.fc36.x86_64.rpm");
+    /// repo.package_entry(pkg.pkgid())
+    ///     .and_modify(|existing| {
+    ///         existing.set_location_href(pkg.location_href());
+    ///     })
+    ///     .or_insert(pkg);
+    /// # assert_eq!(repo.packages().len(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn package_entry(&mut self, pkgid: &str) -> Entry<'_, String, Package> {
+        self.nevra_index = OnceLock::new();
+        self.packages.entry(pkgid.to_owned())
+    }
+
+    /// Get the package with the given NEVRA, e.g. `bash-0:5.1.16-3.fc36.x86_64`.
+    ///
+    /// The epoch may be omitted, in which case it is treated as "0".
+    pub fn get_package_by_nevra(&self, nevra: &str) -> Result<Option<&Package>, MetadataError> {
+        let index = self.package_index_by_nevra(nevra)?;
+        Ok(index.map(|idx| &self.packages[idx]))
+    }
+
+    /// Remove the package with the given NEVRA from the repository and return it.
+    ///
+    /// The order of the remaining packages is preserved.
+    pub fn remove_package_by_nevra(
+        &mut self,
+        nevra: &str,
+    ) -> Result<Option<Package>, MetadataError> {
+        let index = match self.package_index_by_nevra(nevra)? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let (_, package) = self.packages.shift_remove_index(index).unwrap();
+        if let Some(nevra_index) = self.nevra_index.get_mut() {
+            nevra_index.remove(&normalized_nevra(&package));
+        }
+        Ok(Some(package))
+    }
+
+    /// Get all packages with the given name, of any version and architecture.
+    pub fn packages_by_name(&self, name: &str) -> Vec<&Package> {
+        self.packages
+            .values()
+            .filter(|pkg| pkg.name() == name)
+            .collect()
+    }
+
+    /// The NEVRA index, built from the packages if it was discarded.
+    fn nevra_index(&self) -> &HashMap<String, String> {
+        self.nevra_index.get_or_init(|| {
+            let mut index = HashMap::with_capacity(self.packages.len());
+            for (pkgid, pkg) in &self.packages {
+                // packages added through packages_mut() may share a NEVRA, the first one wins
+                index
+                    .entry(normalized_nevra(pkg))
+                    .or_insert_with(|| pkgid.clone());
+            }
+            index
+        })
+    }
+
+    fn package_index_by_nevra(&self, nevra: &str) -> Result<Option<usize>, MetadataError> {
+        let (name, evr, arch) =
+            split_nevra(nevra).ok_or_else(|| ParseError::InvalidNevra(nevra.to_owned()))?;
+        Ok(self
+            .nevra_index()
+            .get(&nevra_key(name, &evr, arch))
+            .and_then(|pkgid| self.packages.get_index_of(pkgid)))
+    }
+
     pub fn advisories(&self) -> &IndexMap<String, UpdateRecord> {
         &self.advisories
     }
 
+    /// Direct access to the advisories, keyed by ID.
+    ///
+    /// Unlike [`Repository::add_advisory`], this does not check for duplicate IDs.
     // TODO: better API for package access (entry-like)
     pub fn advisories_mut(&mut self) -> &mut IndexMap<String, UpdateRecord> {
         &mut self.advisories
     }
 
+    /// Add a package to the repository.
+    ///
+    /// Fails if a package with the same NEVRA is already present, treating an empty epoch as "0", or if a
+    /// package with the same pkgid is present.
+    pub fn add_package(&mut self, package: Package) -> Result<(), MetadataError> {
+        let nevra = normalized_nevra(&package);
+        if self.nevra_index().contains_key(&nevra) || self.packages.contains_key(package.pkgid()) {
+            return Err(WriteError::DuplicatePackage(package.nevra()).into());
+        }
+        if let Some(index) = self.nevra_index.get_mut() {
+            index.insert(nevra, package.pkgid().to_owned());
+        }
+        self.packages.insert(package.pkgid().to_owned(), package);
+        Ok(())
+    }
+
+    /// Add an advisory to the repository.
+    ///
+    /// Fails if an advisory with the same ID is already present. Use [`Repository::merge_advisories`] to
+    /// update existing advisories instead.
+    pub fn add_advisory(&mut self, advisory: UpdateRecord) -> Result<(), MetadataError> {
+        if self.advisories.contains_key(&advisory.id) {
+            return Err(WriteError::DuplicateAdvisory(advisory.id).into());
+        }
+        self.advisories.insert(advisory.id.clone(), advisory);
+        Ok(())
+    }
+
+    /// Check that no two packages share a NEVRA and no two advisories share an ID.
+    ///
+    /// Packages and advisories added through [`Repository::packages_mut`] and
+    /// [`Repository::advisories_mut`] are not checked when they are inserted, so this is also done before
+    /// the repository is written.
+    pub fn check_duplicates(&self) -> Result<(), MetadataError> {
+        let mut nevras = HashSet::new();
+        for pkg in self.packages.values() {
+            if !nevras.insert(normalized_nevra(pkg)) {
+                return Err(WriteError::DuplicatePackage(pkg.nevra()).into());
+            }
+        }
+        let mut ids = HashSet::new();
+        for advisory in self.advisories.values() {
+            if !ids.insert(advisory.id.as_str()) {
+                return Err(WriteError::DuplicateAdvisory(advisory.id.clone()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a [`PackageQuery`] for filtering the packages of this repository.
+    pub fn query(&self) -> PackageQuery<'_> {
+        PackageQuery::new(&self.packages)
+    }
+
+    /// Find the source packages in the repository which the packages of an advisory were built from.
+    pub fn advisory_source_packages(&self, advisory: &UpdateRecord) -> Vec<&Package> {
+        let source_rpms = advisory.source_rpms();
+        self.packages
+            .values()
+            .filter(|pkg| matches!(pkg.arch(), "src" | "nosrc"))
+            .filter(|pkg| {
+                let filename = pkg.location_href().rsplit('/').next().unwrap_or_default();
+                source_rpms.contains(&filename)
+            })
+            .collect()
+    }
+
+    /// Find the advisories which provide an update for the package with the given NEVRA.
+    ///
+    /// An advisory applies if its pkglist contains a package with the same name and architecture and a
+    /// newer EVR. The NEVRA may omit the epoch, e.g. `bash-5.1.16-3.fc36.x86_64`.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, Repository};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// for advisory in repo.advisories_for_package("hello-2.9-1.fc36.x86_64")? {
+    ///     assert_eq!(advisory.id, "EXAMPLE-2022-0001");
+    ///     assert_eq!(advisory.severity, "Low");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn advisories_for_package(&self, nevra: &str) -> Result<Vec<&UpdateRecord>, MetadataError> {
+        let (name, evr, arch) =
+            split_nevra(nevra).ok_or_else(|| ParseError::InvalidNevra(nevra.to_owned()))?;
+        Ok(self
+            .advisories
+            .values()
+            .filter(|advisory| {
+                advisory
+                    .pkglist
+                    .iter()
+                    .flat_map(|c| &c.packages)
+                    .any(|pkg| pkg.name == name && pkg.arch == arch && pkg.evr() > evr)
+            })
+            .collect())
+    }
+
+    /// Find the packages in the repository which are listed in the pkglist of the advisory with this ID.
+    ///
+    /// Packages are matched by name, architecture and EVR. Returns `None` if there is no such advisory.
+    pub fn packages_for_advisory(&self, id: &str) -> Option<Vec<&Package>> {
+        let advisory = self.advisories.get(id)?;
+        let entries: Vec<&UpdateCollectionPackage> =
+            advisory.pkglist.iter().flat_map(|c| &c.packages).collect();
+        let packages = self
+            .packages
+            .values()
+            .filter(|pkg| entries.iter().any(|entry| entry.refers_to(pkg)))
+            .collect();
+        Some(packages)
+    }
+
+    /// Merge advisories, e.g. from an upstream updateinfo feed, into this repository.
+    ///
+    /// Advisories are identified by their ID. Advisories which aren't present yet are added, and conflicts
+    /// with existing advisories are resolved according to `policy`.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MergePolicy, MetadataError, Repository};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let mut repo = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// # let upstream = Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// let report = repo.merge_advisories(upstream.advisories().values().cloned(), MergePolicy::Newest);
+    /// assert_eq!(report.unchanged, ["EXAMPLE-2022-0001"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn merge_advisories<I: IntoIterator<Item = UpdateRecord>>(
+        &mut self,
+        advisories: I,
+        policy: MergePolicy,
+    ) -> MergeReport {
+        let mut report = MergeReport::default();
+
+        for advisory in advisories {
+            let existing = match self.advisories.get_mut(&advisory.id) {
+                Some(existing) => existing,
+                None => {
+                    report.added.push(advisory.id.clone());
+                    self.advisories.insert(advisory.id.clone(), advisory);
+                    continue;
+                }
+            };
+
+            let replace = *existing != advisory
+                && match policy {
+                    MergePolicy::Newest => {
+                        compare_update_dates(&advisory, existing) == cmp::Ordering::Greater
+                    }
+                    MergePolicy::KeepExisting => false,
+                    MergePolicy::Replace => true,
+                };
+
+            if replace {
+                report.updated.push(advisory.id.clone());
+                *existing = advisory;
+            } else {
+                report.unchanged.push(advisory.id);
+            }
+        }
+
+        report
+    }
+
+    /// Remove all but the newest `n` versions of each package, returning the packages which were removed.
+    ///
+    /// Packages with the same name but different architectures are considered separately. Packages which
+    /// share the same EVR count as one version, so a rebuild with a different checksum doesn't displace
+    /// an older version. This keeps continuously-updated repositories from growing without bound.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, Repository};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let tmp_dir = tempdir::TempDir::new("doctest_retain")?;
+    /// # let path = tmp_dir.path();
+    /// # Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?.write_to_directory(path)?;
+    /// let mut repo = Repository::load_from_directory(path)?;
+    /// for pkg in repo.retain_newest_versions(3) {
+    ///     std::fs::remove_file(path.join(pkg.location_href()))?;
+    /// }
+    /// repo.write_to_directory(path)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retain_newest_versions(&mut self, n: usize) -> Vec<Package> {
+        let mut versions: HashMap<(String, String), Vec<EVR>> = HashMap::new();
+        for pkg in self.packages.values() {
+            let evrs = versions
+                .entry((pkg.name().to_owned(), pkg.arch().to_owned()))
+                .or_default();
+            if !evrs.contains(pkg.evr()) {
+                evrs.push(pkg.evr().clone());
+            }
+        }
+        for evrs in versions.values_mut() {
+            evrs.sort_by(|a, b| b.cmp(a));
+            evrs.truncate(n);
+        }
+
+        self.nevra_index = OnceLock::new();
+        let mut removed = Vec::new();
+        for (key, pkg) in std::mem::take(&mut self.packages) {
+            let newest = &versions[&(pkg.name().to_owned(), pkg.arch().to_owned())];
+            if newest.contains(pkg.evr()) {
+                self.packages.insert(key, pkg);
+            } else {
+                removed.push(pkg);
+            }
+        }
+        removed
+    }
+
     /// Sorts the package entries by `location_href`.
     ///
     /// Helps with compression ratios for certain types of compression, and makes it more easily searchable.
@@ -102,13 +434,19 @@ impl Repository {
 
     /// Create a new [`Repository`] from a path pointing to an RPM repository.
     ///
-    /// Will fail if the RPM repository is not valid.
+    /// Will fail if the RPM repository is not valid, or if two of its packages share a NEVRA. Values of package fields which repeat across many
+    /// packages, such as the architecture and license, are shared between packages to reduce memory use.
     pub fn load_from_directory(path: &Path) -> Result<Self, MetadataError> {
-        let reader = RepositoryReader::new_from_directory(path)?;
+        let mut reader = RepositoryReader::new_from_directory(path)?;
+        reader.set_string_interning(true);
         Ok(reader.into_repo()?)
     }
 
     /// Load a metadata file into an existing repository.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(path = %path.display()))
+    )]
     pub fn load_metadata_file<M: RpmMetadata>(&mut self, path: &Path) -> Result<(), MetadataError> {
         let reader = utils::xml_reader_from_file(path)?;
         M::load_metadata(self, reader)
@@ -140,6 +478,7 @@ impl Repository {
         path: &Path,
         options: RepositoryOptions,
     ) -> Result<(), MetadataError> {
+        self.check_duplicates()?;
         let mut writer = RepositoryWriter::new_with_options(path, self.packages().len(), options)?;
 
         for (_, pkg) in self.packages() {
@@ -182,18 +521,131 @@ impl Repository {
     }
 }
 
+/// How [`Repository::merge_advisories`] resolves conflicts between advisories with the same ID.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the most recently updated version of the advisory, based on `updated_date` (or `issued_date`
+    /// if it was never updated). If neither is newer, the existing advisory is kept.
+    #[default]
+    Newest,
+    /// Always keep the existing advisory.
+    KeepExisting,
+    /// Always replace the existing advisory.
+    Replace,
+}
+
+/// The IDs of the advisories changed by [`Repository::merge_advisories`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Advisories which were not present before.
+    pub added: Vec<String>,
+    /// Existing advisories which were replaced.
+    pub updated: Vec<String>,
+    /// Advisories which were kept as they were, because they were identical or not newer.
+    pub unchanged: Vec<String>,
+}
+
+/// The NEVRA of a package, with an empty epoch written as "0" so that equivalent packages compare equal.
+fn normalized_nevra(pkg: &Package) -> String {
+    nevra_key(pkg.name(), pkg.evr(), pkg.arch())
+}
+
+fn nevra_key(name: &str, evr: &EVR, arch: &str) -> String {
+    let epoch = if evr.epoch().is_empty() {
+        "0"
+    } else {
+        evr.epoch()
+    };
+    format!(
+        "{}-{}:{}-{}.{}",
+        name,
+        epoch,
+        evr.version(),
+        evr.release(),
+        arch
+    )
+}
+
+/// Compare when two advisories were last updated.
+///
+/// Dates are usually formatted as `2020-05-27 04:10:31`, which can be compared as strings, but some
+/// producers use UNIX timestamps instead. Advisories without a date are the oldest.
+fn compare_update_dates(a: &UpdateRecord, b: &UpdateRecord) -> cmp::Ordering {
+    fn last_update(record: &UpdateRecord) -> Option<&str> {
+        record
+            .updated_date
+            .as_deref()
+            .or(record.issued_date.as_deref())
+            .map(str::trim)
+            .filter(|date| !date.is_empty())
+    }
+
+    match (last_update(a), last_update(b)) {
+        (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        },
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
 /// Options for writing RPM repository metadata.
 ///
-/// - `simple_metadata_filenames` - Determines whether filenames should be bare e.g. `filelists.xml` or should include the file checksum.
+/// - `simple_metadata_filenames` - Determines whether filenames should be bare e.g. `filelists.xml` or should
+///   include the file checksum. Bare filenames are reused by every revision of the metadata, so clients of a
+///   repository rewritten in place may briefly see mismatched files, see `snapshot_metadata`.
 /// - `metadata_compression_type` - The type of compression to use for repository metadata.
+/// - `metadata_compression_level` - The compression level of the metadata files. Defaults to 9.
+/// - `zstd_long_distance_matching` - Whether to enable zstd long distance matching. Off by default.
+/// - `compression_threads` - The number of threads used to compress each metadata file. Defaults to 1.
 /// - `metadata_checksum_type` - The type of checksums to use for metadata.
 /// - `package_checksum_type` - The type of checksums to use for packages.
+/// - `write_primary`, `write_filelists`, `write_other` - Which of the core metadata files to write. All are
+///   written by default.
+/// - `changelog_limit` - The number of changelog entries to keep for packages loaded from RPM files, newest
+///   first. Defaults to 10, like createrepo_c.
+/// - `changelog_timestamp_policy` - How changelog timestamps beyond `i32::MAX` are written to other.xml, see
+///   [`ChangelogTimestampPolicy`].
+/// - `normalize_dependencies` - Whether to filter the requires of packages loaded from RPM files like
+///   createrepo_c, see [`Package::normalize_dependencies`].
+/// - `require_signature` - Whether to refuse to add RPM files which are not signed.
+/// - `signing_key_id` - If set, refuse to add RPM files which are not signed by the key with this ID, e.g.
+///   `0x199e2f91fd431d51`.
+/// - `target_client` - The oldest client the repository is published for, see [`TargetClient`].
+/// - `rewrite_package_count` - Correct the package count in the metadata headers when finishing if it
+///   doesn't match the number of packages added, instead of returning an error. Uncompressed metadata only.
+/// - `snapshot_metadata` - Publish the metadata in a `repodata-<revision>/` directory and atomically point a
+///   `repodata` symlink at it when finishing. The revision may only contain `[A-Za-z0-9._-]`. Previous
+///   snapshots are left in place. Unix only.
+/// - `href_policy` - How packages with a `location_href` outside of the repository are handled, see
+///   [`HrefPolicy`].
+/// - `preinstall_policy` - Which dependencies `pre="1"` is written for in primary.xml, see
+///   [`PreinstallPolicy`].
+/// - `validate_schema` - Check the metadata files against the createrepo XML schemas before publishing them,
+///   see [`crate::validate_schema`].
 #[derive(Copy, Clone, Debug)]
 pub struct RepositoryOptions {
     pub simple_metadata_filenames: bool,
     pub metadata_compression_type: CompressionType,
+    pub metadata_compression_level: Option<u32>,
+    pub zstd_long_distance_matching: bool,
+    pub compression_threads: u32,
     pub metadata_checksum_type: ChecksumType,
     pub package_checksum_type: ChecksumType,
+    pub write_primary: bool,
+    pub write_filelists: bool,
+    pub write_other: bool,
+    pub changelog_limit: Option<usize>,
+    pub changelog_timestamp_policy: ChangelogTimestampPolicy,
+    pub normalize_dependencies: bool,
+    pub require_signature: bool,
+    pub signing_key_id: Option<u64>,
+    pub rewrite_package_count: bool,
+    pub target_client: TargetClient,
+    pub snapshot_metadata: bool,
+    pub href_policy: HrefPolicy,
+    pub preinstall_policy: PreinstallPolicy,
+    pub validate_schema: bool,
 }
 
 impl Default for RepositoryOptions {
@@ -201,8 +653,25 @@ impl Default for RepositoryOptions {
         Self {
             simple_metadata_filenames: false,
             metadata_compression_type: CompressionType::Zstd,
+            metadata_compression_level: None,
+            zstd_long_distance_matching: false,
+            compression_threads: 1,
             metadata_checksum_type: ChecksumType::Sha256,
             package_checksum_type: ChecksumType::Sha256,
+            write_primary: true,
+            write_filelists: true,
+            write_other: true,
+            changelog_limit: Some(10),
+            changelog_timestamp_policy: ChangelogTimestampPolicy::default(),
+            normalize_dependencies: false,
+            require_signature: false,
+            signing_key_id: None,
+            rewrite_package_count: false,
+            target_client: TargetClient::default(),
+            snapshot_metadata: false,
+            href_policy: HrefPolicy::default(),
+            preinstall_policy: PreinstallPolicy::default(),
+            validate_schema: false,
         }
     }
 }
@@ -229,12 +698,194 @@ impl RepositoryOptions {
         }
     }
 
+    /// The compression level of the metadata files, 1-9 for gzip, xz and bz2 and 1-22 for zstd. Higher
+    /// levels compress better but more slowly. Defaults to 9.
+    pub fn metadata_compression_level(self, level: Option<u32>) -> Self {
+        Self {
+            metadata_compression_level: level,
+            ..self
+        }
+    }
+
+    /// Enable zstd long distance matching, which improves the compression of large metadata files which
+    /// contain similar entries far apart. Decompressing them requires a larger window as well, which some
+    /// older zstd versions don't allow by default.
+    pub fn zstd_long_distance_matching(self, val: bool) -> Self {
+        Self {
+            zstd_long_distance_matching: val,
+            ..self
+        }
+    }
+
+    /// The number of threads used to compress each metadata file. Only zstd and xz compression can use
+    /// more than one thread, gzip and bz2 are always single-threaded. Defaults to 1.
+    ///
+    /// Multithreaded xz output is split into blocks, which makes it slightly larger.
+    pub fn compression_threads(self, threads: u32) -> Self {
+        Self {
+            compression_threads: threads,
+            ..self
+        }
+    }
+
     pub fn simple_metadata_filenames(self, val: bool) -> Self {
         Self {
             simple_metadata_filenames: val,
             ..self
         }
     }
+
+    pub fn write_primary(self, val: bool) -> Self {
+        Self {
+            write_primary: val,
+            ..self
+        }
+    }
+
+    pub fn write_filelists(self, val: bool) -> Self {
+        Self {
+            write_filelists: val,
+            ..self
+        }
+    }
+
+    pub fn write_other(self, val: bool) -> Self {
+        Self {
+            write_other: val,
+            ..self
+        }
+    }
+
+    pub fn changelog_limit(self, limit: Option<usize>) -> Self {
+        Self {
+            changelog_limit: limit,
+            ..self
+        }
+    }
+
+    pub fn changelog_timestamp_policy(self, policy: ChangelogTimestampPolicy) -> Self {
+        Self {
+            changelog_timestamp_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn normalize_dependencies(self, val: bool) -> Self {
+        Self {
+            normalize_dependencies: val,
+            ..self
+        }
+    }
+
+    pub fn require_signature(self, val: bool) -> Self {
+        Self {
+            require_signature: val,
+            ..self
+        }
+    }
+
+    pub fn signing_key_id(self, key_id: Option<u64>) -> Self {
+        Self {
+            signing_key_id: key_id,
+            ..self
+        }
+    }
+
+    pub fn rewrite_package_count(self, val: bool) -> Self {
+        Self {
+            rewrite_package_count: val,
+            ..self
+        }
+    }
+
+    pub fn target_client(self, target: TargetClient) -> Self {
+        Self {
+            target_client: target,
+            ..self
+        }
+    }
+
+    pub fn snapshot_metadata(self, val: bool) -> Self {
+        Self {
+            snapshot_metadata: val,
+            ..self
+        }
+    }
+
+    pub fn href_policy(self, policy: HrefPolicy) -> Self {
+        Self {
+            href_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn preinstall_policy(self, policy: PreinstallPolicy) -> Self {
+        Self {
+            preinstall_policy: policy,
+            ..self
+        }
+    }
+
+    pub fn validate_schema(self, val: bool) -> Self {
+        Self {
+            validate_schema: val,
+            ..self
+        }
+    }
+
+    /// Read an RPM package file the same way as [`RepositoryWriter::read_rpm_file`] would with these options,
+    /// e.g. to filter the packages before creating a [`RepositoryWriter`] with their number.
+    #[cfg(feature = "read_rpm")]
+    pub fn read_rpm_file(
+        &self,
+        path: &Path,
+        location_href: &str,
+    ) -> Result<Package, MetadataError> {
+        let mut pkg = Package::from_rpm_file(path, self.package_checksum_type, location_href)?;
+        self.check_signature(&pkg)?;
+        if let Some(limit) = self.changelog_limit {
+            pkg.truncate_changelogs(limit);
+        }
+        if self.normalize_dependencies {
+            pkg.normalize_dependencies();
+        }
+        Ok(pkg)
+    }
+
+    #[cfg(feature = "read_rpm")]
+    fn check_signature(&self, pkg: &Package) -> Result<(), MetadataError> {
+        let error = |reason: String| {
+            MetadataError::from(WriteError::SignatureRequirement(
+                pkg.location_href().to_owned(),
+                reason,
+            ))
+        };
+        if (self.require_signature || self.signing_key_id.is_some()) && !pkg.is_signed() {
+            return Err(error("the package is not signed".to_owned()));
+        }
+        if let Some(required) = self.signing_key_id {
+            let required = format!("{:016x}", required);
+            match pkg.signature_key_id() {
+                Some(key_id) if key_id == required => (),
+                Some(key_id) => {
+                    return Err(error(format!(
+                        "signed with key {}, not {}",
+                        key_id, required
+                    )))
+                }
+                None => return Err(error("the signing key could not be determined".to_owned())),
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn encoder_settings(&self) -> utils::EncoderSettings {
+        utils::EncoderSettings {
+            level: self.metadata_compression_level,
+            long_distance_matching: self.zstd_long_distance_matching,
+            threads: self.compression_threads,
+        }
+    }
 }
 
 /// Helper for writing RPM repository metadata manually.
@@ -242,6 +893,9 @@ impl RepositoryOptions {
 /// A complete RPM repository can represent a significant amount of metadata split across multiple files.
 /// This API provides a way to write different types of metadata separately and without needing to keep
 /// everything in memory by storing it in a [`Repository`] first.
+///
+/// Metadata is written to a hidden staging directory within the repository, and only moved into `repodata/`
+/// by [`RepositoryWriter::finish`]. A writer which is dropped without finishing leaves the repository untouched.
 pub struct RepositoryWriter {
     options: RepositoryOptions,
     path: PathBuf,
@@ -253,8 +907,15 @@ pub struct RepositoryWriter {
 
     num_pkgs_written: usize,
     num_pkgs: usize,
+    num_advisories_written: usize,
 
     repomd_data: RepomdData,
+    /// The repository the metadata is published to when finishing. `path` is a staging directory within it.
+    destination: PathBuf,
+    progress_handler: Option<Arc<dyn ProgressHandler>>,
+    arches: Option<Vec<String>>,
+    location_base: Option<String>,
+    location_prefix: Option<String>,
 }
 
 impl RepositoryWriter {
@@ -269,82 +930,370 @@ impl RepositoryWriter {
         num_pkgs: usize,
         options: RepositoryOptions,
     ) -> Result<Self, MetadataError> {
-        let repodata_dir = path.join("repodata");
-        std::fs::create_dir_all(&repodata_dir)?;
+        // the package count can only be rewritten in place if the files are seekable
+        if options.rewrite_package_count
+            && options.metadata_compression_type != CompressionType::None
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "rewrite_package_count requires uncompressed metadata",
+            )
+            .into());
+        }
 
-        let (_primary_path, primary_writer) = utils::xml_writer_for_path(
-            &repodata_dir.join("primary.xml"),
-            options.metadata_compression_type,
-        )?;
-        let (_filelists_path, filelists_writer) = utils::xml_writer_for_path(
-            &repodata_dir.join("filelists.xml"),
-            options.metadata_compression_type,
-        )?;
-        let (_other_path, other_writer) = utils::xml_writer_for_path(
-            &repodata_dir.join("other.xml"),
-            options.metadata_compression_type,
-        )?;
+        // a directory symlink can't be atomically renamed over another one on Windows
+        if options.snapshot_metadata && !cfg!(unix) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "snapshot_metadata is only supported on Unix",
+            )
+            .into());
+        }
+
+        // the snapshot can't be published atomically if "repodata" is a directory rather than a symlink
+        if options.snapshot_metadata {
+            let link = path.join("repodata");
+            if link.exists() && !link.is_symlink() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} is a directory, it must be moved aside before publishing snapshots",
+                        link.display()
+                    ),
+                )
+                .into());
+            }
+        }
+
+        // everything is written to a staging directory first, and moved into place when finishing
+        static STAGING_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let destination = path.to_owned();
+        let path = destination.join(format!(
+            ".repodata-staging-{}-{}",
+            std::process::id(),
+            STAGING_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
 
-        let mut primary_xml_writer = PrimaryXml::new_writer(primary_writer);
-        let mut filelists_xml_writer = FilelistsXml::new_writer(filelists_writer);
-        let mut other_xml_writer = OtherXml::new_writer(other_writer);
+        let repodata_dir = path.join("repodata");
+        std::fs::create_dir_all(&repodata_dir)?;
 
-        primary_xml_writer.write_header(num_pkgs)?;
-        filelists_xml_writer.write_header(num_pkgs)?;
-        other_xml_writer.write_header(num_pkgs)?;
+        let primary_xml_writer = if options.write_primary {
+            let (_primary_path, primary_writer) = utils::xml_writer_for_path_with(
+                &repodata_dir.join("primary.xml"),
+                options.metadata_compression_type,
+                options.encoder_settings(),
+            )?;
+            let mut primary_xml_writer = PrimaryXml::new_writer(primary_writer);
+            primary_xml_writer.set_preinstall_policy(options.preinstall_policy);
+            if options.rewrite_package_count {
+                primary_xml_writer.write_resizable_header(num_pkgs)?;
+            } else {
+                primary_xml_writer.write_header(num_pkgs)?;
+            }
+            Some(primary_xml_writer)
+        } else {
+            None
+        };
+        let filelists_xml_writer = if options.write_filelists {
+            let (_filelists_path, filelists_writer) = utils::xml_writer_for_path_with(
+                &repodata_dir.join("filelists.xml"),
+                options.metadata_compression_type,
+                options.encoder_settings(),
+            )?;
+            let mut filelists_xml_writer = FilelistsXml::new_writer(filelists_writer);
+            if options.rewrite_package_count {
+                filelists_xml_writer.write_resizable_header(num_pkgs)?;
+            } else {
+                filelists_xml_writer.write_header(num_pkgs)?;
+            }
+            Some(filelists_xml_writer)
+        } else {
+            None
+        };
+        let other_xml_writer = if options.write_other {
+            let (_other_path, other_writer) = utils::xml_writer_for_path_with(
+                &repodata_dir.join("other.xml"),
+                options.metadata_compression_type,
+                options.encoder_settings(),
+            )?;
+            let mut other_xml_writer = OtherXml::new_writer(other_writer);
+            other_xml_writer.set_changelog_timestamp_policy(options.changelog_timestamp_policy);
+            if options.rewrite_package_count {
+                other_xml_writer.write_resizable_header(num_pkgs)?;
+            } else {
+                other_xml_writer.write_header(num_pkgs)?;
+            }
+            Some(other_xml_writer)
+        } else {
+            None
+        };
 
         Ok(Self {
             options,
-            path: path.to_owned(),
+            path,
 
-            primary_xml_writer: Some(primary_xml_writer),
-            filelists_xml_writer: Some(filelists_xml_writer),
-            other_xml_writer: Some(other_xml_writer),
+            primary_xml_writer,
+            filelists_xml_writer,
+            other_xml_writer,
             updateinfo_xml_writer: None,
 
             num_pkgs: num_pkgs,
             num_pkgs_written: 0,
+            num_advisories_written: 0,
 
             repomd_data: RepomdData::default(),
+            destination,
+            progress_handler: None,
+            arches: None,
+            location_base: None,
+            location_prefix: None,
         })
     }
 
+    /// Open an existing repository to replace or add individual metadata files, without touching the rest,
+    /// like `modifyrepo_c`. For example, errata publishers can swap in a new updateinfo.xml:
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{
+    /// #     CompressionType, MetadataError, Repository, RepositoryOptions, RepositoryWriter,
+    /// # };
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let tmp_dir = tempdir::TempDir::new("doctest_modify")?;
+    /// # let repo_path = tmp_dir.path();
+    /// # Repository::load_from_directory(Path::new("tests/assets/doctest_repo"))?
+    /// #     .write_to_directory(repo_path)?;
+    /// # let updateinfo_path = Path::new("tests/assets/doctest_repo/repodata/updateinfo.xml");
+    /// let mut writer = RepositoryWriter::modify_existing(repo_path, RepositoryOptions::default())?;
+    /// writer.add_metadata_file("updateinfo", updateinfo_path, CompressionType::Zstd)?;
+    /// writer.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Records added with [`RepositoryWriter::add_metadata_file`] or [`RepositoryWriter::add_advisory`]
+    /// replace existing records of the same type, and the files of the replaced records are removed when
+    /// finishing. The revision in `repomd.xml` is bumped. No packages can be added.
+    pub fn modify_existing(path: &Path, options: RepositoryOptions) -> Result<Self, MetadataError> {
+        let options = options
+            .write_primary(false)
+            .write_filelists(false)
+            .write_other(false)
+            .rewrite_package_count(false);
+        Self::open_existing(path, 0, options)
+    }
+
+    /// Open an existing repository to replace its packages, e.g. to add or remove individual packages
+    /// without regenerating the repository from the RPM files.
+    ///
+    /// Like [`RepositoryWriter::modify_existing`], except that primary.xml, filelists.xml and other.xml are
+    /// written from the `num_pkgs` packages added to the writer, which must include the existing packages
+    /// to be kept. All other records and the tags are preserved, and the revision in `repomd.xml` is bumped.
+    pub fn modify_existing_packages(
+        path: &Path,
+        num_pkgs: usize,
+        options: RepositoryOptions,
+    ) -> Result<Self, MetadataError> {
+        Self::open_existing(path, num_pkgs, options)
+    }
+
+    fn open_existing(
+        path: &Path,
+        num_pkgs: usize,
+        options: RepositoryOptions,
+    ) -> Result<Self, MetadataError> {
+        let mut repomd_data = RepositoryReader::new_from_directory(path)?.repomd().clone();
+        let revision = next_revision(repomd_data.revision());
+        repomd_data.set_revision(&revision);
+
+        // the unchanged metadata files are not part of a new snapshot
+        let options = options.snapshot_metadata(false);
+        let mut writer = Self::new_with_options(path, num_pkgs, options)?;
+        writer.repomd_data = repomd_data;
+        Ok(writer)
+    }
+
     /// Mutable accessor for the [`RepomdData`] struct which is written as repomd.xml later.
     pub fn repomd_mut(&mut self) -> &mut RepomdData {
         &mut self.repomd_data
     }
 
+    /// The repository the metadata is published to, rather than the staging directory it is written to.
+    pub(crate) fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Report progress to `handler` as packages, advisories and metadata files are written.
+    pub fn set_progress_handler(&mut self, handler: Arc<dyn ProgressHandler>) {
+        self.progress_handler = Some(handler);
+    }
+
+    /// Only include packages with one of the given architectures, e.g. `["x86_64", "noarch"]`.
+    ///
+    /// Packages of other architectures passed to [`RepositoryWriter::add_package`] or
+    /// [`RepositoryWriter::add_rpm_file`] are skipped, and don't count towards the number of packages
+    /// declared when creating the writer.
+    pub fn set_arches(&mut self, arches: &[&str]) {
+        self.arches = Some(arches.iter().map(|arch| arch.to_string()).collect());
+    }
+
+    /// Set the `location_base` of all packages added afterwards, e.g. the URL of a separate package mirror.
+    /// `None` keeps the `location_base` of the packages as they are.
+    pub fn set_location_base(&mut self, location_base: Option<&str>) {
+        self.location_base = location_base.map(str::to_owned);
+    }
+
+    /// Prefix the `location_href` of all packages added afterwards with a subdirectory, e.g. `Packages/`
+    /// for packages stored in a subdirectory of the repository. An empty prefix leaves the locations
+    /// as they are.
+    ///
+    /// The prefixed locations are checked against [`RepositoryOptions::href_policy`].
+    pub fn set_location_prefix(&mut self, prefix: &str) {
+        let prefix = prefix.trim_matches('/');
+        self.location_prefix = if prefix.is_empty() {
+            None
+        } else {
+            Some(format!("{}/", prefix))
+        };
+    }
+
+    fn report_progress(&self, event: ProgressEvent) {
+        if let Some(handler) = &self.progress_handler {
+            handler.on_progress(&event);
+        }
+    }
+
     /// Write a `Package` to the repo metadata.
+    ///
+    /// Packages which don't pass the arch filter (see [`RepositoryWriter::set_arches`]) are skipped. The
+    /// location set with [`RepositoryWriter::set_location_base`] and [`RepositoryWriter::set_location_prefix`]
+    /// is applied, and the `location_href` of the package is checked against [`RepositoryOptions::href_policy`].
     pub fn add_package(&mut self, pkg: &Package) -> Result<(), MetadataError> {
-        self.num_pkgs_written += 1;
-        assert!(
-            self.num_pkgs_written <= self.num_pkgs,
-            "Num packages written {} is more than number of packages declared in the header {}",
-            self.num_pkgs_written,
-            self.num_pkgs
-        );
+        if !utils::arch_matches(self.arches.as_deref(), pkg.arch()) {
+            return Ok(());
+        }
+        let relocated;
+        let pkg = if self.location_base.is_some() || self.location_prefix.is_some() {
+            let mut copy = pkg.clone();
+            if let Some(prefix) = &self.location_prefix {
+                copy.set_location_href(format!("{}{}", prefix, pkg.location_href()));
+            }
+            if let Some(location_base) = &self.location_base {
+                copy.set_location_base(Some(location_base.as_str()));
+            }
+            relocated = copy;
+            &relocated
+        } else {
+            pkg
+        };
+        let sanitized;
+        let pkg = match utils::check_href(pkg.location_href(), self.options.href_policy)? {
+            Some(href) => {
+                let mut copy = pkg.clone();
+                copy.set_location_href(href);
+                sanitized = copy;
+                &sanitized
+            }
+            None => pkg,
+        };
+        self.count_package()?;
 
-        self.primary_xml_writer
-            .as_mut()
-            .unwrap()
-            .write_package(pkg)?;
-        self.filelists_xml_writer
-            .as_mut()
-            .unwrap()
-            .write_package(pkg)?;
-        self.other_xml_writer.as_mut().unwrap().write_package(pkg)?;
+        if let Some(primary_xml_writer) = &mut self.primary_xml_writer {
+            primary_xml_writer.write_package(pkg)?;
+        }
+        if let Some(filelists_xml_writer) = &mut self.filelists_xml_writer {
+            filelists_xml_writer.write_package(pkg)?;
+        }
+        if let Some(other_xml_writer) = &mut self.other_xml_writer {
+            other_xml_writer.write_package(pkg)?;
+        }
+
+        self.report_progress(ProgressEvent::PackageWritten {
+            count: self.num_pkgs_written,
+            total: self.num_pkgs,
+        });
+
+        Ok(())
+    }
+
+    /// Add a package from pre-serialized `<package>` elements of primary.xml, filelists.xml and other.xml,
+    /// e.g. copied from the metadata of another repository when merging repositories.
+    ///
+    /// The fragments are written out as-is, without being parsed, which is much faster than parsing and
+    /// re-serializing the package. The caller is responsible for the fragments being valid and describing
+    /// the same package - only the outermost tags are checked. The architecture filter and
+    /// [`HrefPolicy`] are not applied.
+    pub fn add_package_raw(
+        &mut self,
+        primary_xml: &str,
+        filelists_xml: &str,
+        other_xml: &str,
+    ) -> Result<(), MetadataError> {
+        for fragment in [primary_xml, filelists_xml, other_xml] {
+            utils::raw_package_fragment(fragment)?;
+        }
+        self.count_package()?;
+
+        if let Some(primary_xml_writer) = &mut self.primary_xml_writer {
+            primary_xml_writer.write_package_raw(primary_xml)?;
+        }
+        if let Some(filelists_xml_writer) = &mut self.filelists_xml_writer {
+            filelists_xml_writer.write_package_raw(filelists_xml)?;
+        }
+        if let Some(other_xml_writer) = &mut self.other_xml_writer {
+            other_xml_writer.write_package_raw(other_xml)?;
+        }
 
+        self.report_progress(ProgressEvent::PackageWritten {
+            count: self.num_pkgs_written,
+            total: self.num_pkgs,
+        });
+
+        Ok(())
+    }
+
+    fn count_package(&mut self) -> Result<(), MetadataError> {
+        if self.num_pkgs_written == self.num_pkgs && !self.options.rewrite_package_count {
+            return Err(WriteError::PackageCountMismatch {
+                declared: self.num_pkgs,
+                written: self.num_pkgs_written + 1,
+            }
+            .into());
+        }
+        self.num_pkgs_written += 1;
         Ok(())
     }
 
+    /// Read an RPM package file and add it to the repo metadata, located at `location_href`.
+    ///
+    /// The package checksum type, changelog limit, dependency normalization and signature requirements
+    /// from the [`RepositoryOptions`] are applied.
+    #[cfg(feature = "read_rpm")]
+    pub fn add_rpm_file(&mut self, path: &Path, location_href: &str) -> Result<(), MetadataError> {
+        let pkg = self.read_rpm_file(path, location_href)?;
+        self.add_package(&pkg)
+    }
+
+    /// Read an RPM package file the same way as [`RepositoryWriter::add_rpm_file`], without adding it,
+    /// so that the package can be adjusted (e.g. its `location_base`) before [`RepositoryWriter::add_package`].
+    #[cfg(feature = "read_rpm")]
+    pub fn read_rpm_file(
+        &self,
+        path: &Path,
+        location_href: &str,
+    ) -> Result<Package, MetadataError> {
+        self.options.read_rpm_file(path, location_href)
+    }
+
     /// Write an `UpdateRecord` to the repo metadata.
     pub fn add_advisory(&mut self, record: &UpdateRecord) -> Result<(), MetadataError> {
         // TODO: clean this up
         if self.updateinfo_xml_writer.is_none() {
             let repodata_dir = self.path.join("repodata");
-            let (updateinfo_path, updateinfo_writer) = utils::xml_writer_for_path(
+            let (_updateinfo_path, updateinfo_writer) = utils::xml_writer_for_path_with(
                 &repodata_dir.join("updateinfo.xml"),
                 self.options.metadata_compression_type,
+                self.options.encoder_settings(),
             )?;
 
             let mut updateinfo_xml_writer = UpdateinfoXml::new_writer(updateinfo_writer);
@@ -358,23 +1307,273 @@ impl RepositoryWriter {
             .unwrap()
             .write_updaterecord(record)?;
 
+        self.num_advisories_written += 1;
+        self.report_progress(ProgressEvent::AdvisoryWritten {
+            count: self.num_advisories_written,
+        });
+
         Ok(())
     }
 
+    /// Add an arbitrary metadata file, such as `comps.xml`, `modules.yaml` or `productid`, to the repository.
+    ///
+    /// The file at `path` is compressed and written under `repodata/`, and a record of type `name` pointing
+    /// to it is added to `repomd.xml`. This is equivalent to `modifyrepo_c`.
+    ///
+    /// Files which are already compressed, e.g. `comps.xml.gz`, are copied as they are instead, like with
+    /// [`RepositoryWriter::copy_metadata_file`]. The file at `path` itself is never modified or moved.
+    pub fn add_metadata_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        compression: CompressionType,
+    ) -> Result<(), MetadataError> {
+        let filename = path.file_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a file", path.display()),
+            )
+        })?;
+
+        let mut reader = std::fs::File::open(path)?;
+        if utils::is_compressed_file(path, &mut reader)? {
+            let href = PathBuf::from("repodata").join(original_metadata_filename(path)?);
+            std::fs::copy(path, self.path.join(&href))?;
+            return self.add_file_record(name, &href);
+        }
+
+        // always a new file in the staging directory, so renaming it to the hashed filename is safe
+        let href =
+            utils::apply_compression_suffix(&PathBuf::from("repodata").join(filename), compression);
+        let (_, mut writer) = utils::writer_to_file_with(
+            &self.path.join("repodata").join(filename),
+            compression,
+            self.options.encoder_settings(),
+        )?;
+        std::io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+        // the compressed stream is only complete once the writer is dropped
+        drop(writer);
+
+        self.add_file_record(name, &href)
+    }
+
+    /// Copy an existing metadata file into the repository as-is, e.g. to carry over `susedata` or `appdata`
+    /// records from another repository without parsing them.
+    ///
+    /// Unlike [`RepositoryWriter::add_metadata_file`], the file is not recompressed. A checksum prefix on the
+    /// filename (e.g. `<checksum>-appdata.xml.gz`) is removed before the checksum of the copy is applied.
+    pub fn copy_metadata_file(&mut self, name: &str, path: &Path) -> Result<(), MetadataError> {
+        let href = PathBuf::from("repodata").join(original_metadata_filename(path)?);
+        std::fs::copy(path, self.path.join(&href))?;
+        self.add_file_record(name, &href)
+    }
+
+    /// Copy an existing (possibly compressed) metadata file into the repository, recompressing it with
+    /// `compression`.
+    ///
+    /// As with [`RepositoryWriter::copy_metadata_file`], a checksum prefix on the filename is removed. The
+    /// compression suffix is replaced, e.g. `productid.gz` becomes `productid.zst`.
+    pub fn recompress_metadata_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        compression: CompressionType,
+    ) -> Result<(), MetadataError> {
+        let filename = original_metadata_filename(path)?;
+        let filename = [".gz", ".xz", ".bz2", ".zst"]
+            .iter()
+            .find_map(|suffix| filename.strip_suffix(suffix))
+            .unwrap_or(filename);
+        let href = PathBuf::from("repodata").join(filename);
+
+        let mut reader = utils::reader_from_file(path)?;
+        let (_, mut writer) = utils::writer_to_file_with(
+            &self.path.join(&href),
+            compression,
+            self.options.encoder_settings(),
+        )?;
+        std::io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+        // the compressed stream is only complete once the writer is dropped
+        drop(writer);
+
+        self.add_file_record(name, &utils::apply_compression_suffix(&href, compression))
+    }
+
+    /// Add a [`RepomdRecord`] for a file under `repodata/`, first renaming the file to include its
+    /// checksum (e.g. `<checksum>-primary.xml.zst`) unless `simple_metadata_filenames` is set.
+    fn add_file_record(&mut self, name: &str, href: &Path) -> Result<(), MetadataError> {
+        let mut record =
+            RepomdRecord::new(name, href, &self.path, self.options.metadata_checksum_type)?;
+
+        if !self.options.simple_metadata_filenames {
+            let (_, checksum) = record.checksum.to_values()?;
+            let mut filename = std::ffi::OsString::from(format!("{}-", checksum));
+            filename.push(href.file_name().unwrap_or_default());
+            let hashed_href = PathBuf::from("repodata").join(filename);
+
+            std::fs::rename(self.path.join(href), self.path.join(&hashed_href))?;
+            record.location_href = hashed_href;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(name, path = %record.location_href.display(), "wrote metadata file");
+        self.report_progress(ProgressEvent::MetadataFileWritten {
+            name,
+            path: &self.path.join(&record.location_href),
+        });
+        self.repomd_mut().replace_record(record);
+        Ok(())
+    }
+
+    /// Check the staged metadata files against their schemas, see [`RepositoryOptions::validate_schema`].
+    fn validate_staged_schema(&self, repomd_path: &Path) -> Result<(), MetadataError> {
+        let mut problems = validate_schema_file(repomd_path, MetadataSchema::Repomd, "repomd")?;
+        for record in self.repomd_data.records() {
+            let schema = match MetadataSchema::for_record(&record.metadata_name) {
+                Some(schema) => schema,
+                None => continue,
+            };
+            let path = self.path.join(&record.location_href);
+            if record.location_base.is_none() && path.is_file() {
+                problems.extend(validate_schema_file(&path, schema, &record.metadata_name)?);
+            }
+        }
+        match problems.first() {
+            Some(problem) => Err(WriteError::SchemaViolation(format!(
+                "{} ({} violations in total)",
+                problem,
+                problems.len()
+            ))
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Move the staged metadata to `repodata-<revision>/` and atomically point the `repodata` symlink at it.
+    fn publish_snapshot(&self) -> Result<(), MetadataError> {
+        static LINK_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let root = &self.destination;
+        // the revision may come from another repository, so it must not be able to name another directory
+        let revision = self.repomd_data.revision().unwrap_or_default();
+        let valid_revision = !revision.is_empty()
+            && revision
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if !valid_revision {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "revision \"{}\" can't be used as a snapshot name, only [A-Za-z0-9._-] are allowed",
+                    revision
+                ),
+            )
+            .into());
+        }
+        let snapshot_name = format!("repodata-{}", revision);
+        let snapshot_dir = root.join(&snapshot_name);
+        if snapshot_dir.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("snapshot {} already exists", snapshot_dir.display()),
+            )
+            .into());
+        }
+        std::fs::rename(self.path.join("repodata"), &snapshot_dir)?;
+        std::fs::remove_dir(&self.path)?;
+
+        // renaming over the existing symlink replaces it atomically
+        let staged_link = root.join(format!(
+            ".repodata-link-{}-{}",
+            std::process::id(),
+            LINK_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        utils::symlink_dir(Path::new(&snapshot_name), &staged_link)?;
+        std::fs::rename(&staged_link, root.join("repodata"))?;
+        Ok(())
+    }
+
+    /// Move the staged metadata into `repodata/`, replacing `repomd.xml` last so that it never refers to files
+    /// which aren't in place yet, and remove the files of `previous` metadata which are no longer referenced.
+    ///
+    /// This is only atomic if the metadata files have hashed names. Files with simple names replace those of
+    /// the previous metadata, which the previous `repomd.xml` still refers to until it is replaced.
+    fn publish_in_place(&self, previous: Option<&RepomdData>) -> Result<(), MetadataError> {
+        let staged_dir = self.path.join("repodata");
+        let repodata_dir = self.destination.join("repodata");
+        std::fs::create_dir_all(&repodata_dir)?;
+
+        for entry in std::fs::read_dir(&staged_dir)? {
+            let entry = entry?;
+            if entry.file_name() != "repomd.xml" {
+                std::fs::rename(entry.path(), repodata_dir.join(entry.file_name()))?;
+            }
+        }
+        std::fs::rename(
+            staged_dir.join("repomd.xml"),
+            repodata_dir.join("repomd.xml"),
+        )?;
+        std::fs::remove_dir_all(&self.path)?;
+
+        let stale_records = previous
+            .into_iter()
+            .flat_map(|repomd| repomd.records())
+            .filter(|old| {
+                old.location_base.is_none()
+                    && utils::path_is_contained(&old.location_href)
+                    && !self
+                        .repomd_data
+                        .records()
+                        .iter()
+                        .any(|new| new.location_href == old.location_href)
+            });
+        for record in stale_records {
+            match std::fs::remove_file(self.destination.join(&record.location_href)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Correct the package count declared in the header of a finished metadata file under `repodata/`.
+    fn rewrite_package_count(&self, href: &Path) -> Result<(), MetadataError> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.path.join(href))?;
+        utils::rewrite_package_count(&mut file, self.num_pkgs_written)
+    }
+
     /// Consume the [`RepositoryWriter`], and finish writing the repository metadata to disk.
     ///
-    /// - Checks that the number of packages written matches the number of packages declared.
+    /// - Checks that the number of packages written matches the number of packages declared, or corrects
+    ///   the declared number if `rewrite_package_count` is set.
     /// - Completes all metadata files.
     /// - Writes `repomd.xml`.
+    /// - Moves the metadata from the staging directory into `repodata/` (`repomd.xml` last, so that clients
+    ///   never see a partially written repository) and removes the files of the previous metadata which
+    ///   are no longer referenced. With `snapshot_metadata`, publishes a new snapshot instead.
+    ///
+    /// With `simple_metadata_filenames`, the new metadata files replace the previous ones of the same name
+    /// one at a time, so publishing in place is not atomic. Combine it with `snapshot_metadata` if clients
+    /// may read the repository while it is being rewritten.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %self.destination.display()))
+    )]
     pub fn finish(mut self) -> Result<(), MetadataError> {
-        assert_eq!(
-            self.num_pkgs_written, self.num_pkgs,
-            "Number of packages written {} is different from the number declared in the header {}.",
-            self.num_pkgs_written, self.num_pkgs
-        );
+        let count_mismatch = self.num_pkgs_written != self.num_pkgs;
+        if count_mismatch && !self.options.rewrite_package_count {
+            return Err(WriteError::PackageCountMismatch {
+                declared: self.num_pkgs,
+                written: self.num_pkgs_written,
+            }
+            .into());
+        }
 
         // TODO: this is a mess
-        let path = self.path.clone();
         let repodata_dir = self.path.join("repodata");
         let primary_path = utils::apply_compression_suffix(
             &PathBuf::from("repodata").join("primary.xml"),
@@ -389,40 +1588,35 @@ impl RepositoryWriter {
             self.options.metadata_compression_type,
         );
 
-        self.primary_xml_writer.as_mut().unwrap().finish()?;
-        self.filelists_xml_writer.as_mut().unwrap().finish()?;
-        self.other_xml_writer.as_mut().unwrap().finish()?;
-
         // TODO: maybe clean this up?
         // All of the ceremony, including making the fields in the struct optional, is required to
         // be able to drop() the writers, because the underlying encoders do not finish their work unless
         // dropped. The underlying compression encoders do have methods to finish encoding, however, we
         // do not have access to those because it's behind Box<dyn Read>.
-        drop(self.primary_xml_writer.take());
-        drop(self.filelists_xml_writer.take());
-        drop(self.other_xml_writer.take());
-
-        let primary_xml = RepomdRecord::new(
-            "primary",
-            &primary_path.as_ref(),
-            &path,
-            self.options.metadata_checksum_type,
-        )?;
-        self.repomd_mut().add_record(primary_xml);
-        let filelists_xml = RepomdRecord::new(
-            "filelists",
-            &filelists_path.as_ref(),
-            &path,
-            self.options.metadata_checksum_type,
-        )?;
-        self.repomd_mut().add_record(filelists_xml);
-        let other_xml = RepomdRecord::new(
-            "other",
-            &other_path.as_ref(),
-            &path,
-            self.options.metadata_checksum_type,
-        )?;
-        self.repomd_mut().add_record(other_xml);
+        if let Some(mut primary_xml_writer) = self.primary_xml_writer.take() {
+            primary_xml_writer.finish()?;
+            drop(primary_xml_writer);
+            if count_mismatch {
+                self.rewrite_package_count(&primary_path)?;
+            }
+            self.add_file_record("primary", &primary_path)?;
+        }
+        if let Some(mut filelists_xml_writer) = self.filelists_xml_writer.take() {
+            filelists_xml_writer.finish()?;
+            drop(filelists_xml_writer);
+            if count_mismatch {
+                self.rewrite_package_count(&filelists_path)?;
+            }
+            self.add_file_record("filelists", &filelists_path)?;
+        }
+        if let Some(mut other_xml_writer) = self.other_xml_writer.take() {
+            other_xml_writer.finish()?;
+            drop(other_xml_writer);
+            if count_mismatch {
+                self.rewrite_package_count(&other_path)?;
+            }
+            self.add_file_record("other", &other_path)?;
+        }
 
         if let Some(updateinfo_xml_writer) = &mut self.updateinfo_xml_writer {
             updateinfo_xml_writer.finish()?;
@@ -431,25 +1625,115 @@ impl RepositoryWriter {
                 &PathBuf::from("repodata").join("updateinfo.xml"),
                 self.options.metadata_compression_type,
             );
-            let updateinfo_xml = RepomdRecord::new(
-                "updateinfo",
-                &updateinfo_path.as_ref(),
-                &path,
-                self.options.metadata_checksum_type,
-            )?;
-            self.repomd_mut().add_record(updateinfo_xml);
+            self.add_file_record("updateinfo", &updateinfo_path)?;
         }
 
-        let (_, mut repomd_writer) =
+        if self.options.snapshot_metadata && self.repomd_data.revision().is_none() {
+            self.repomd_data.set_revision(&next_revision(None));
+        }
+
+        let (repomd_path, mut repomd_writer) =
             utils::xml_writer_for_path(&repodata_dir.join("repomd.xml"), CompressionType::None)?;
-        RepomdXml::write_data(&self.repomd_data, &mut repomd_writer)?;
+        RepomdXml::write_data_for_target(
+            &self.repomd_data,
+            &mut repomd_writer,
+            self.options.target_client,
+        )?;
+        drop(repomd_writer);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(name = "repomd", path = %repomd_path.display(), "wrote metadata file");
+        self.report_progress(ProgressEvent::MetadataFileWritten {
+            name: "repomd",
+            path: &repomd_path,
+        });
+
+        if self.options.validate_schema {
+            self.validate_staged_schema(&repomd_path)?;
+        }
+
+        if self.options.snapshot_metadata {
+            self.publish_snapshot()?;
+        } else {
+            let previous = RepositoryReader::new_from_directory(&self.destination)
+                .ok()
+                .map(|reader| reader.repomd().clone());
+            self.publish_in_place(previous.as_ref())?;
+        }
 
         // TODO: a report of the files created?
 
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            packages = self.num_pkgs_written,
+            "finished writing repository metadata"
+        );
+
         Ok(())
     }
 }
 
+impl Drop for RepositoryWriter {
+    fn drop(&mut self) {
+        // clean up after a writer which was never finished, or failed to finish
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The revision following `current`: the current time, unless a numeric revision is already at or past it.
+fn next_revision(current: Option<&str>) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |t| t.as_secs());
+    match current.and_then(|r| r.parse::<u64>().ok()) {
+        Some(revision) if revision >= now => (revision + 1).to_string(),
+        _ => now.to_string(),
+    }
+}
+
+/// The name of a metadata file without the checksum prefix applied by `RepositoryWriter`, if any, e.g.
+/// `appdata.xml.gz` for `repodata/<checksum>-appdata.xml.gz`.
+fn original_metadata_filename(path: &Path) -> Result<&str, MetadataError> {
+    let filename = path.file_name().and_then(|f| f.to_str()).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a file", path.display()),
+        )
+    })?;
+    let filename = match filename.split_once('-') {
+        Some((prefix, rest))
+            if !rest.is_empty()
+                && prefix.len() >= 32
+                && prefix.bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            rest
+        }
+        _ => filename,
+    };
+    Ok(filename)
+}
+
+/// Which of the optional package metadata files are read by [`RepositoryReader::iter_packages_with`].
+///
+/// Skipping filelists.xml and other.xml saves a substantial amount of time and memory, at the cost of the
+/// packages yielded missing their full file lists and changelogs respectively.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MetadataSelection {
+    pub filelists: bool,
+    pub other: bool,
+}
+
+impl Default for MetadataSelection {
+    fn default() -> Self {
+        Self {
+            filelists: true,
+            other: true,
+        }
+    }
+}
+
+/// A reader of the decompressed contents of a metadata file.
+type MetadataFileReader = Box<dyn std::io::Read + Send>;
+
 /// Helper for reading metadata from an RPM repository manually.
 ///
 /// A complete RPM repository can represent a significant amount of metadata split across multiple files.
@@ -460,12 +1744,30 @@ pub struct RepositoryReader {
     // but need to figure out how to generically support loading metadata files
     repository: Repository,
     path: PathBuf,
+    count_mismatch_policy: PackageCountMismatchPolicy,
+    match_policy: PackageMatchPolicy,
+    memory_budget: Option<u64>,
+    cache: Option<MetadataCache>,
+    decoding_policy: DecodingPolicy,
+    decoding_replacements: Arc<AtomicUsize>,
+    progress_handler: Option<Arc<dyn ProgressHandler>>,
+    warning_handler: Option<Arc<dyn WarningHandler>>,
+    arches: Option<Vec<String>>,
+    href_policy: HrefPolicy,
+    parse_limits: ParseLimits,
+    string_interning: bool,
+    #[cfg(feature = "mmap")]
+    memory_map: bool,
 }
 
 impl RepositoryReader {
     /// Create a new `RepositoryReader` for a given directory `path`.
     ///
     /// If `repodata/repomd.xml` cannot be found or if it cannot be parsed, this will fail.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(path = %path.display()))
+    )]
     pub fn new_from_directory(path: &Path) -> Result<Self, MetadataError> {
         let mut repo = Repository::new();
         repo.load_metadata_file::<RepomdXml>(&path.join("repodata/repomd.xml"))?;
@@ -473,26 +1775,392 @@ impl RepositoryReader {
         Ok(Self {
             repository: repo,
             path: path.to_owned(),
+            count_mismatch_policy: PackageCountMismatchPolicy::default(),
+            match_policy: PackageMatchPolicy::default(),
+            memory_budget: None,
+            cache: None,
+            decoding_policy: DecodingPolicy::default(),
+            decoding_replacements: Arc::new(AtomicUsize::new(0)),
+            progress_handler: None,
+            warning_handler: None,
+            arches: None,
+            href_policy: HrefPolicy::default(),
+            parse_limits: ParseLimits::default(),
+            string_interning: false,
+            #[cfg(feature = "mmap")]
+            memory_map: false,
         })
     }
 
+    /// Set the policy for handling invalid UTF-8 in the metadata files. By default, it is an error.
+    ///
+    /// With [`DecodingPolicy::Lossy`], invalid sequences are replaced with U+FFFD and counted - see
+    /// [`RepositoryReader::decoding_replacements`].
+    pub fn set_decoding_policy(&mut self, policy: DecodingPolicy) {
+        self.decoding_policy = policy;
+    }
+
+    /// The number of invalid UTF-8 sequences which have been replaced while reading metadata so far.
+    pub fn decoding_replacements(&self) -> usize {
+        self.decoding_replacements.load(Ordering::Relaxed)
+    }
+
+    /// Set the policy for handling package metadata files which declare different package counts.
+    ///
+    /// See [`PackageCountMismatchPolicy`]. When the counts don't match, the policies other than `Error`
+    /// match up packages by pkgid regardless of [`RepositoryReader::set_match_policy`], which can buffer
+    /// large parts of filelists.xml and other.xml in memory.
+    pub fn set_count_mismatch_policy(&mut self, policy: PackageCountMismatchPolicy) {
+        self.count_mismatch_policy = policy;
+    }
+
+    /// Set how the entries of filelists.xml and other.xml are matched up with the packages of primary.xml.
+    ///
+    /// See [`PackageMatchPolicy`].
+    pub fn set_match_policy(&mut self, policy: PackageMatchPolicy) {
+        self.match_policy = policy;
+    }
+
+    /// Set the policy for package locations which point outside of the repository.
+    ///
+    /// See [`HrefPolicy`]. By default, such packages are an error.
+    pub fn set_href_policy(&mut self, policy: HrefPolicy) {
+        self.href_policy = policy;
+    }
+
+    /// Share the values of repetitive package fields between packages. See [`PackageIterator::with_string_interning`].
+    pub fn set_string_interning(&mut self, val: bool) {
+        self.string_interning = val;
+    }
+
+    /// Set the limits applied while parsing the metadata files. See [`ParseLimits`].
+    ///
+    /// `repomd.xml` has already been read with the default limits when the reader is created.
+    pub fn set_parse_limits(&mut self, limits: ParseLimits) {
+        self.parse_limits = limits;
+    }
+
+    /// Report progress to `handler` as metadata files are read and packages are parsed.
+    pub fn set_progress_handler(&mut self, handler: Arc<dyn ProgressHandler>) {
+        self.progress_handler = Some(handler);
+    }
+
+    /// Report data quality issues which don't prevent the repository from being read to `handler`. See
+    /// [`MetadataWarning`].
+    ///
+    /// Without a handler, warnings are dropped. Use a [`crate::WarningCollector`] to inspect them afterwards.
+    pub fn set_warning_handler(&mut self, handler: Arc<dyn WarningHandler>) {
+        self.warning_handler = Some(handler);
+    }
+
+    fn warn(&self, warning: MetadataWarning) {
+        if let Some(handler) = &self.warning_handler {
+            handler.on_warning(&warning);
+        }
+    }
+
+    /// Only yield packages with one of the given architectures, e.g. `["x86_64", "noarch"]`, when iterating
+    /// over packages or loading the full repository.
+    pub fn set_arches(&mut self, arches: &[&str]) {
+        self.arches = Some(arches.iter().map(|arch| arch.to_string()).collect());
+    }
+
+    /// Read decompressed metadata files through a [`MetadataCache`].
+    pub fn set_cache(&mut self, cache: MetadataCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Read uncompressed metadata files (and decompressed files from the [`MetadataCache`], if one is set)
+    /// using memory-mapped IO rather than buffered reads, which reduces the startup cost of iterating
+    /// over very large repositories. Compressed metadata is read as usual.
+    ///
+    /// The metadata files must not be modified or truncated while they are being read.
+    #[cfg(feature = "mmap")]
+    pub fn set_memory_map(&mut self, val: bool) {
+        self.memory_map = val;
+    }
+
+    /// Set the amount of memory (in bytes) which loading the full repository with [`RepositoryReader::into_repo`]
+    /// is expected to stay within. A warning is emitted if the estimated memory usage exceeds it.
+    pub fn set_memory_budget(&mut self, budget: u64) {
+        self.memory_budget = Some(budget);
+    }
+
+    /// Estimate the amount of memory (in bytes) needed to load the full repository.
+    ///
+    /// This is based on the decompressed size of the metadata files, taken from `repomd.xml` where
+    /// available and otherwise from [`utils::estimated_decompressed_size`]. It is a rough estimate.
+    pub fn estimated_memory_usage(&self) -> Result<u64, MetadataError> {
+        let mut total = 0;
+        for name in [
+            METADATA_PRIMARY,
+            METADATA_FILELISTS,
+            METADATA_OTHER,
+            METADATA_UPDATEINFO,
+        ] {
+            if let Some(record) = self.repomd().get_record(name) {
+                total += self.estimated_record_size(record)?;
+            }
+        }
+        Ok(total)
+    }
+
+    /// The decompressed size of a metadata file, taken from `repomd.xml` where available and otherwise
+    /// from [`utils::estimated_decompressed_size`], falling back to the compressed size.
+    fn estimated_record_size(&self, record: &RepomdRecord) -> Result<u64, MetadataError> {
+        let size = match record.open_size {
+            Some(open_size) => Some(open_size),
+            None => utils::estimated_decompressed_size(&self.path.join(&record.location_href))?,
+        };
+        Ok(size.or(record.size).unwrap_or(0))
+    }
+
     /// Return the contents of `repomd.xml` in a `RepomdData` struct.
     pub fn repomd(&self) -> &RepomdData {
         &self.repository.repomd()
     }
 
+    /// The base directory of the repository.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// Iterate over the packages of the repo.
     ///
     /// Create an iterator over the package metadata which will yield packages until completion or error.
     pub fn iter_packages(&self) -> Result<PackageIterator, MetadataError> {
-        PackageIterator::from_repodata(&self.path, self.repository.repomd())
+        self.iter_packages_with(MetadataSelection::default())
+    }
+
+    /// Iterate over the packages of the repo, reading only the selected metadata files.
+    ///
+    /// Metadata files which are selected but not present in `repomd.xml` are skipped, only primary.xml is required.
+    pub fn iter_packages_with(
+        &self,
+        selection: MetadataSelection,
+    ) -> Result<PackageIterator, MetadataError> {
+        let primary_xml = self
+            .open_xml_reader(METADATA_PRIMARY)?
+            .ok_or(RepoLayoutError::MissingRecord(METADATA_PRIMARY))?;
+        let filelists_xml = if selection.filelists {
+            self.open_xml_reader(METADATA_FILELISTS)?
+        } else {
+            None
+        };
+        let other_xml = if selection.other {
+            self.open_xml_reader(METADATA_OTHER)?
+        } else {
+            None
+        };
+
+        let mut packages = PackageIterator::from_optional_readers(
+            PrimaryXml::new_reader(primary_xml),
+            filelists_xml.map(FilelistsXml::new_reader),
+            other_xml.map(OtherXml::new_reader),
+        )?
+        .with_count_mismatch_policy(self.count_mismatch_policy)
+        .with_match_policy(self.match_policy)
+        .with_href_policy(self.href_policy)
+        .with_max_packages(self.parse_limits.max_packages)
+        .with_string_interning(self.string_interning);
+        if let Some(handler) = &self.progress_handler {
+            packages = packages.with_progress_handler(handler.clone());
+        }
+        if let Some(handler) = &self.warning_handler {
+            packages = packages.with_warning_handler(handler.clone());
+        }
+        if let Some(arches) = &self.arches {
+            let arches: Vec<&str> = arches.iter().map(String::as_str).collect();
+            packages = packages.with_arches(&arches);
+        }
+        Ok(packages)
+    }
+
+    /// Iterate over the packages of the repo as unparsed `<package>` elements. See [`RawPackageIterator`].
+    ///
+    /// Only the selected metadata files are read, primary.xml is required.
+    pub fn iter_raw_packages(
+        &self,
+        selection: MetadataSelection,
+    ) -> Result<RawPackageIterator, MetadataError> {
+        let primary_xml = self
+            .open_limited_reader(METADATA_PRIMARY)?
+            .ok_or(RepoLayoutError::MissingRecord(METADATA_PRIMARY))?;
+        let filelists_xml = if selection.filelists {
+            self.open_limited_reader(METADATA_FILELISTS)?
+        } else {
+            None
+        };
+        let other_xml = if selection.other {
+            self.open_limited_reader(METADATA_OTHER)?
+        } else {
+            None
+        };
+        Ok(RawPackageIterator::from_readers(
+            primary_xml,
+            filelists_xml,
+            other_xml,
+        ))
     }
 
     /// Iterate over the advisories of the repo.
     ///
     /// Create an iterator over "advisory" / updateinfo metadata which will yield updaterecords until completion or error.
     pub fn iter_advisories(&self) -> Result<UpdateinfoIterator, MetadataError> {
-        UpdateinfoIterator::from_metadata(&self.path, self.repository.repomd())
+        Ok(UpdateinfoIterator {
+            updateinfo: self
+                .open_xml_reader(METADATA_UPDATEINFO)?
+                .map(UpdateinfoXml::new_reader),
+        })
+    }
+
+    /// The path of the metadata file of the given type (e.g. "primary" or "group"), as listed in `repomd.xml`.
+    ///
+    /// Returns `None` if there is no record of the metadata file in `repomd.xml`.
+    pub fn record_path(&self, name: &str) -> Option<PathBuf> {
+        self.repomd()
+            .get_record(name)
+            .map(|record| self.path.join(&record.location_href))
+    }
+
+    /// Open the metadata file of the given type for reading, e.g. to parse "group" (comps.xml) metadata
+    /// with a custom parser.
+    ///
+    /// The checksum of the file is verified against `repomd.xml` before it is opened. The contents are
+    /// decompressed and decoded according to the decoding policy, and the cache is used if one is set.
+    ///
+    /// Returns `None` if there is no record of the metadata file in `repomd.xml`.
+    ///
+    /// ```
+    /// # use std::io::Read;
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, RepositoryReader};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// if let Some(mut updateinfo) = reader.open_record("updateinfo")? {
+    ///     let mut contents = String::new();
+    ///     updateinfo.read_to_string(&mut contents)?;
+    ///     assert!(contents.contains("<id>EXAMPLE-2022-0001</id>"));
+    /// }
+    /// assert!(reader.open_record("group")?.is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn open_record(
+        &self,
+        name: &str,
+    ) -> Result<Option<Box<dyn BufRead + Send>>, MetadataError> {
+        let record = match self.repomd().get_record(name) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        let path = self.path.join(&record.location_href);
+        let checksum_type = record.checksum.checksum_type();
+        let actual = utils::checksum_file(&path, checksum_type)?;
+        if actual != record.checksum {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                path = %path.display(),
+                expected = ?record.checksum,
+                ?actual,
+                "checksum mismatch"
+            );
+            return Err(ChecksumError::Mismatch(path.display().to_string()).into());
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(path = %path.display(), "verified checksum");
+
+        Ok(Some(Box::new(BufReader::new(
+            self.open_record_reader(record)?,
+        ))))
+    }
+
+    /// Read the full contents of the metadata file of the given type, like [`RepositoryReader::open_record`].
+    ///
+    /// The buffer is allocated up front based on the estimated decompressed size of the file.
+    ///
+    /// Returns `None` if there is no record of the metadata file in `repomd.xml`.
+    pub fn read_record(&self, name: &str) -> Result<Option<Vec<u8>>, MetadataError> {
+        let estimate = match self.repomd().get_record(name) {
+            Some(record) => self.estimated_record_size(record)?,
+            None => return Ok(None),
+        };
+        let mut reader = match self.open_record(name)? {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        // the estimate comes from the metadata itself, so it can't be trusted with an arbitrary allocation
+        let mut contents = Vec::with_capacity(estimate.min(MAX_PREALLOCATION) as usize);
+        reader.read_to_end(&mut contents)?;
+        Ok(Some(contents))
+    }
+
+    /// Open the metadata file with the given name for reading, through the cache if one is set.
+    ///
+    /// Returns `None` if there is no record of the metadata file in `repomd.xml`.
+    pub(crate) fn open_xml_reader(
+        &self,
+        name: &str,
+    ) -> Result<Option<quick_xml::Reader<BufReader<MetadataFileReader>>>, MetadataError> {
+        Ok(self
+            .open_limited_reader(name)?
+            .map(|reader| utils::create_xml_reader(BufReader::new(reader))))
+    }
+
+    /// Open the metadata file with the given name for reading, applying the parse limits.
+    fn open_limited_reader(
+        &self,
+        name: &str,
+    ) -> Result<Option<Box<dyn std::io::Read + Send>>, MetadataError> {
+        match self.repomd().get_record(name) {
+            Some(record) => Ok(Some(Box::new(XmlLimitReader::new(
+                self.open_record_reader(record)?,
+                self.parse_limits,
+            )))),
+            None => Ok(None),
+        }
+    }
+
+    fn open_record_reader(
+        &self,
+        record: &RepomdRecord,
+    ) -> Result<Box<dyn std::io::Read + Send>, MetadataError> {
+        let path = self.path.join(&record.location_href);
+        let mut reader = self.open_file(&path, &record.checksum)?;
+        if self.decoding_policy == DecodingPolicy::Lossy {
+            reader = Box::new(LossyUtf8Reader::with_counter(
+                reader,
+                self.decoding_replacements.clone(),
+            ));
+        }
+        if let Some(handler) = &self.progress_handler {
+            reader = Box::new(ProgressReader::new(
+                reader,
+                &record.metadata_name,
+                record.open_size,
+                handler.clone(),
+            ));
+        }
+        Ok(reader)
+    }
+
+    fn open_file(
+        &self,
+        path: &Path,
+        checksum: &Checksum,
+    ) -> Result<Box<dyn std::io::Read + Send>, MetadataError> {
+        #[cfg(feature = "mmap")]
+        if self.memory_map {
+            return match &self.cache {
+                Some(cache) => cache.open_mmap(path, checksum),
+                None => utils::mmap_reader_from_file(path),
+            };
+        }
+        match &self.cache {
+            Some(cache) => cache.open(path, checksum),
+            None => utils::reader_from_file(path),
+        }
     }
 
     // pub fn iter_comps(&self) -> Result<> {
@@ -500,27 +2168,82 @@ impl RepositoryReader {
     // }
 
     /// Consume the `RepositoryReader` and yield a [`Repository`] struct with the full repository contents.
+    ///
+    /// Like [`Repository::add_package`], fails with [`WriteError::DuplicatePackage`] if two packages share a
+    /// NEVRA. Use [`RepositoryReader::iter_packages`] to read such repositories.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(path = %self.path.display()))
+    )]
     pub fn into_repo(mut self) -> Result<Repository, MetadataError> {
+        if let Some(budget) = self.memory_budget {
+            let estimate = self.estimated_memory_usage()?;
+            if estimate > budget {
+                self.warn(MetadataWarning::MemoryBudgetExceeded { estimate, budget });
+            }
+        }
+
+        for record in self.repository.repomd().records() {
+            if !is_generated_record(&record.metadata_name) {
+                self.warn(MetadataWarning::UnknownRecord {
+                    name: record.metadata_name.clone(),
+                });
+            }
+        }
+
         let packages = self.iter_packages()?;
+        // the count comes from the metadata itself, so it can't be trusted with an arbitrary allocation
+        let max_reserved = MAX_PREALLOCATION as usize / std::mem::size_of::<(String, Package)>();
         self.repository
             .packages_mut()
-            .reserve(packages.total_packages());
+            .reserve(packages.total_packages().min(max_reserved));
 
         for package in packages {
             let package = package?;
-            self.repository
+            // a package listed twice is replaced, but distinct packages with the same NEVRA are rejected like
+            // Repository::add_package() does, rather than when the repository is written
+            if !self.repository.packages.contains_key(package.pkgid()) {
+                self.repository.add_package(package)?;
+                continue;
+            }
+            let replaced = self
+                .repository
                 .packages_mut()
                 .insert(package.pkgid().to_owned(), package);
+            if let Some(replaced) = replaced {
+                self.warn(MetadataWarning::DuplicatePackage {
+                    pkgid: replaced.pkgid().to_owned(),
+                    nevra: replaced.nevra(),
+                });
+            }
         }
 
         let advisories = self.iter_advisories()?;
         for advisory in advisories {
             let advisory = advisory?;
-            self.repository
+            let replaced = self
+                .repository
                 .advisories_mut()
                 .insert(advisory.id.to_owned(), advisory);
+            if let Some(replaced) = replaced {
+                self.warn(MetadataWarning::DuplicateAdvisory { id: replaced.id });
+            }
         }
 
+        let replacements = self.decoding_replacements();
+        if replacements > 0 {
+            self.warn(MetadataWarning::InvalidUtf8Replaced {
+                count: replacements,
+            });
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            packages = self.repository.packages().len(),
+            advisories = self.repository.advisories().len(),
+            "loaded repository"
+        );
+
         Ok(self.repository)
     }
 }
@@ -529,25 +2252,6 @@ pub struct UpdateinfoIterator {
     updateinfo: Option<UpdateinfoXmlReader<BufReader<Box<dyn std::io::Read + Send>>>>,
 }
 
-impl UpdateinfoIterator {
-    fn from_metadata(base: &Path, repomd: &RepomdData) -> Result<Self, MetadataError> {
-        let updateinfo_href = repomd
-            .get_record(crate::metadata::METADATA_UPDATEINFO)
-            .map(|u| base.join(&u.location_href));
-
-        let reader = if let Some(updateinfo_href) = updateinfo_href {
-            let reader = UpdateinfoXml::new_reader(utils::xml_reader_from_file(
-                &base.join(updateinfo_href),
-            )?);
-            Some(reader)
-        } else {
-            None
-        };
-
-        Ok(Self { updateinfo: reader })
-    }
-}
-
 impl Iterator for UpdateinfoIterator {
     type Item = Result<UpdateRecord, MetadataError>;
 
@@ -0,0 +1,201 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::{utils, HrefPolicy, MetadataError, RepoLayoutError, RepositoryWriter};
+
+/// Options for finding the RPMs of a directory with [`RepositoryWriter::scan_directory`].
+///
+/// - `includes` - If not empty, only RPMs matching one of these shell-style globs are found.
+/// - `excludes` - RPMs matching one of these shell-style globs are skipped, e.g. `*-debuginfo-*`.
+/// - `recursive` - Whether subdirectories are scanned. Enabled by default.
+/// - `follow_symlinks` - Whether symlinks to RPMs and directories are followed, otherwise they are skipped.
+///   Enabled by default, like createrepo_c. Each directory is only scanned once, even if it can be reached
+///   through several symlinks.
+/// - `skip_hidden` - Whether hidden directories (starting with `.`) are skipped. Enabled by default.
+///
+/// Patterns are matched against both the path of the RPM relative to the directory and its filename,
+/// see [`utils::glob_match`] for the syntax.
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+    pub recursive: bool,
+    pub follow_symlinks: bool,
+    pub skip_hidden: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            recursive: true,
+            follow_symlinks: true,
+            skip_hidden: true,
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.includes.push(pattern.to_owned());
+        self
+    }
+
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.excludes.push(pattern.to_owned());
+        self
+    }
+
+    pub fn recursive(self, val: bool) -> Self {
+        Self {
+            recursive: val,
+            ..self
+        }
+    }
+
+    pub fn follow_symlinks(self, val: bool) -> Self {
+        Self {
+            follow_symlinks: val,
+            ..self
+        }
+    }
+
+    pub fn skip_hidden(self, val: bool) -> Self {
+        Self {
+            skip_hidden: val,
+            ..self
+        }
+    }
+
+    /// Whether an RPM at the relative location `href` passes the include and exclude filters.
+    pub fn matches(&self, href: &str) -> bool {
+        let filename = href.rsplit('/').next().unwrap_or(href);
+        let matches = |pattern: &String| {
+            utils::glob_match(pattern, href) || utils::glob_match(pattern, filename)
+        };
+        (self.includes.is_empty() || self.includes.iter().any(matches))
+            && !self.excludes.iter().any(matches)
+    }
+}
+
+impl RepositoryWriter {
+    /// Find the RPMs in a directory and its subdirectories, e.g. to pass to
+    /// [`RepositoryWriter::add_rpm_file`].
+    ///
+    /// Returns the paths of the RPMs relative to `path`, separated by `/` so that they can be used as
+    /// `location_href`. They are sorted, so that the result doesn't depend on the order in which the
+    /// filesystem lists directories. "repodata" directories are always skipped. See [`ScanOptions`].
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use rpmrepo_metadata::{MetadataError, RepositoryWriter, ScanOptions};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let tmp_dir = tempdir::TempDir::new("doctest_scan")?;
+    /// # let path = tmp_dir.path();
+    /// # fs::create_dir_all(path.join("Packages/h"))?;
+    /// # fs::write(path.join("Packages/h/hello-2.10-1.fc36.x86_64.rpm"), b"")?;
+    /// # fs::write(path.join("Packages/h/hello-debuginfo-2.10-1.fc36.x86_64.rpm"), b"")?;
+    /// let hrefs = RepositoryWriter::scan_directory(path, &ScanOptions::default().exclude("*-debuginfo-*"))?;
+    /// assert_eq!(hrefs, ["Packages/h/hello-2.10-1.fc36.x86_64.rpm"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scan_directory(
+        path: &Path,
+        options: &ScanOptions,
+    ) -> Result<Vec<String>, MetadataError> {
+        let mut hrefs = Vec::new();
+        let mut visited = HashSet::new();
+        visited.insert(path.canonicalize()?);
+        scan(path, "", options, &mut visited, &mut hrefs)?;
+        hrefs.sort();
+        Ok(hrefs)
+    }
+
+    /// Read a package list, like `createrepo_c --pkglist`: one path per line, relative to `path`. Empty
+    /// lines and lines starting with `#` are ignored.
+    ///
+    /// Returns the listed paths in order, without duplicates, so that they can be used as `location_href`.
+    /// Unlike createrepo_c, the list is honored strictly: if any listed package does not exist, this fails
+    /// with [`RepoLayoutError::MissingPackages`] naming all of them, and paths pointing outside of `path`
+    /// fail with [`RepoLayoutError::UnsafeHref`].
+    pub fn read_package_list(path: &Path, list: &Path) -> Result<Vec<String>, MetadataError> {
+        let contents = std::fs::read_to_string(list)?;
+        let mut hrefs = Vec::new();
+        let mut seen = HashSet::new();
+        let mut missing = Vec::new();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let href = line.trim_start_matches("./");
+            utils::check_href(href, HrefPolicy::Reject)?;
+            if !seen.insert(href) {
+                continue;
+            }
+            if path.join(href).is_file() {
+                hrefs.push(href.to_owned());
+            } else {
+                missing.push(href.to_owned());
+            }
+        }
+        if !missing.is_empty() {
+            return Err(RepoLayoutError::MissingPackages(missing).into());
+        }
+        Ok(hrefs)
+    }
+}
+
+fn scan(
+    dir: &Path,
+    prefix: &str,
+    options: &ScanOptions,
+    visited: &mut HashSet<PathBuf>,
+    hrefs: &mut Vec<String>,
+) -> Result<(), MetadataError> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let href = format!("{}{}", prefix, name);
+
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            if !options.follow_symlinks {
+                continue;
+            }
+            // broken symlinks are skipped
+            file_type = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+        }
+
+        if file_type.is_dir() {
+            // skip previous generations of metadata and the writer's staging directories
+            if !options.recursive
+                || name == "repodata"
+                || name.starts_with(".repodata-")
+                || (options.skip_hidden && name.starts_with('.'))
+            {
+                continue;
+            }
+            // guards against symlink loops
+            if !visited.insert(path.canonicalize()?) {
+                continue;
+            }
+            scan(&path, &format!("{}/", href), options, visited, hrefs)?;
+        } else if file_type.is_file() && name.ends_with(".rpm") && options.matches(&href) {
+            hrefs.push(href);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,920 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Check metadata files against the createrepo XML schemas, without libxml2.
+//!
+//! The rules of the RelaxNG schemas shipped with createrepo_c are encoded in tables below: which elements
+//! may appear where and how often, which attributes are required, the formats of attribute values and
+//! text, and the namespaces declared by the root elements. The order of elements is not checked, as
+//! producers disagree on it and clients don't depend on it.
+//!
+//! Files are checked while streaming through them, so only the path from the root to the current element
+//! is kept in memory.
+
+use std::io::BufRead;
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::metadata::{
+    METADATA_FILELISTS, METADATA_OTHER, METADATA_PRIMARY, METADATA_UPDATEINFO, XML_NS_COMMON,
+    XML_NS_FILELISTS, XML_NS_OTHER, XML_NS_REPO, XML_NS_RPM,
+};
+use crate::{
+    utils, Checksum, ChecksumType, MetadataError, RepositoryReader, RequirementType,
+    ValidationProblem, ValidationReport,
+};
+use Occurs::{One, OneOrMore, Optional, ZeroOrMore};
+
+/// The XML schema of a metadata file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MetadataSchema {
+    Repomd,
+    Primary,
+    Filelists,
+    Other,
+    Updateinfo,
+}
+
+impl MetadataSchema {
+    /// The schema of the metadata record `name`, e.g. "primary". `None` for records without a known schema,
+    /// such as "group" or "modules".
+    pub fn for_record(name: &str) -> Option<Self> {
+        match name {
+            "repomd" => Some(MetadataSchema::Repomd),
+            METADATA_PRIMARY => Some(MetadataSchema::Primary),
+            METADATA_FILELISTS => Some(MetadataSchema::Filelists),
+            METADATA_OTHER => Some(MetadataSchema::Other),
+            METADATA_UPDATEINFO => Some(MetadataSchema::Updateinfo),
+            _ => None,
+        }
+    }
+
+    fn root(self) -> &'static str {
+        match self {
+            MetadataSchema::Repomd => "repomd",
+            MetadataSchema::Primary => "metadata",
+            MetadataSchema::Filelists => "filelists",
+            MetadataSchema::Other => "otherdata",
+            MetadataSchema::Updateinfo => "updates",
+        }
+    }
+
+    fn elements(self) -> &'static [Element] {
+        match self {
+            MetadataSchema::Repomd => REPOMD,
+            MetadataSchema::Primary => PRIMARY,
+            MetadataSchema::Filelists => FILELISTS,
+            MetadataSchema::Other => OTHER,
+            MetadataSchema::Updateinfo => UPDATEINFO,
+        }
+    }
+
+    fn element(self, name: &str) -> Option<&'static Element> {
+        self.elements().iter().find(|element| element.name == name)
+    }
+}
+
+/// Check a metadata file against `schema`, reporting violations as [`ValidationProblem::SchemaViolation`]
+/// for the metadata record `file`.
+///
+/// Checking stops at the first malformed XML, which is reported as a violation as well.
+pub fn validate_schema<R: BufRead>(
+    reader: &mut Reader<R>,
+    schema: MetadataSchema,
+    file: &str,
+) -> Vec<ValidationProblem> {
+    let mut validator = Validator {
+        schema,
+        file,
+        stack: Vec::new(),
+        skip_depth: 0,
+        seen_root: false,
+        problems: Vec::new(),
+    };
+    let mut buf = Vec::new();
+    loop {
+        let position = reader.buffer_position();
+        let event = match reader.read_event(&mut buf) {
+            Ok(event) => event,
+            Err(e) => {
+                validator.violation(position, format!("malformed XML: {}", e));
+                break;
+            }
+        };
+        match event {
+            Event::Start(e) => validator.start(&e, position),
+            Event::Empty(e) => {
+                validator.start(&e, position);
+                validator.end(reader.buffer_position());
+            }
+            Event::End(_) => validator.end(reader.buffer_position()),
+            Event::Text(e) => match e.unescape_and_decode(reader) {
+                Ok(text) => validator.text(&text, position),
+                Err(e) => validator.violation(position, format!("malformed text: {}", e)),
+            },
+            Event::CData(e) => validator.text(&String::from_utf8_lossy(&e), position),
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    if !validator.seen_root {
+        let message = format!("missing root element <{}>", schema.root());
+        validator.violation(reader.buffer_position(), message);
+    }
+    validator.problems
+}
+
+impl RepositoryReader {
+    /// Check repomd.xml and the metadata files of the records with a known schema (primary, filelists,
+    /// other and updateinfo) against the createrepo XML schemas. See [`validate_schema`].
+    ///
+    /// Unlike [`RepositoryReader::validate`], this doesn't check the consistency of the files with each other.
+    pub fn validate_schema(&self) -> Result<ValidationReport, MetadataError> {
+        let mut report = ValidationReport::default();
+
+        let mut repomd_xml = utils::xml_reader_from_file(&self.path().join("repodata/repomd.xml"))?;
+        report.problems.extend(validate_schema(
+            &mut repomd_xml,
+            MetadataSchema::Repomd,
+            "repomd",
+        ));
+
+        for record in self.repomd().records() {
+            let schema = match MetadataSchema::for_record(&record.metadata_name) {
+                Some(schema) => schema,
+                None => continue,
+            };
+            // stored elsewhere
+            if record.location_base.is_some() {
+                continue;
+            }
+            let path = self.path().join(&record.location_href);
+            if !path.is_file() {
+                report.problems.push(ValidationProblem::MissingFile {
+                    record: record.metadata_name.clone(),
+                    path: path.display().to_string(),
+                });
+                continue;
+            }
+            if let Some(mut reader) = self.open_xml_reader(&record.metadata_name)? {
+                report
+                    .problems
+                    .extend(validate_schema(&mut reader, schema, &record.metadata_name));
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Check a metadata file written to `path` against `schema`.
+pub(crate) fn validate_schema_file(
+    path: &Path,
+    schema: MetadataSchema,
+    file: &str,
+) -> Result<Vec<ValidationProblem>, MetadataError> {
+    Ok(validate_schema(
+        &mut utils::xml_reader_from_file(path)?,
+        schema,
+        file,
+    ))
+}
+
+struct Validator<'a> {
+    schema: MetadataSchema,
+    file: &'a str,
+    stack: Vec<Frame>,
+    /// The depth within an unexpected element, whose contents are not checked.
+    skip_depth: usize,
+    seen_root: bool,
+    problems: Vec<ValidationProblem>,
+}
+
+/// An element which is currently open.
+struct Frame {
+    element: &'static Element,
+    /// The number of times each child of `element` occurred so far, in the order of `element.children`.
+    occurrences: Vec<usize>,
+    text: String,
+    text_position: usize,
+    /// The value of the `type` attribute of checksum elements.
+    checksum_type: Option<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn violation(&mut self, position: usize, message: String) {
+        self.problems.push(ValidationProblem::SchemaViolation {
+            file: self.file.to_owned(),
+            position,
+            message,
+        });
+    }
+
+    fn start(&mut self, tag: &BytesStart, position: usize) {
+        if self.skip_depth > 0 {
+            self.skip_depth += 1;
+            return;
+        }
+        let name = String::from_utf8_lossy(tag.name()).into_owned();
+
+        let element = match self.stack.last_mut() {
+            None if self.seen_root => {
+                self.violation(
+                    position,
+                    format!("unexpected element <{}> after the root element", name),
+                );
+                None
+            }
+            None if name != self.schema.root() => {
+                let message = format!(
+                    "expected root element <{}>, found <{}>",
+                    self.schema.root(),
+                    name
+                );
+                self.violation(position, message);
+                None
+            }
+            None => {
+                self.seen_root = true;
+                self.schema.element(&name)
+            }
+            Some(parent) => match parent.element.children.iter().position(|c| c.name == name) {
+                Some(idx) => {
+                    parent.occurrences[idx] += 1;
+                    let child = &parent.element.children[idx];
+                    let too_many = parent.occurrences[idx] == 2
+                        && matches!(child.occurs, Occurs::One | Occurs::Optional);
+                    let parent_name = parent.element.name;
+                    if too_many {
+                        let message =
+                            format!("<{}> may only occur once in <{}>", name, parent_name);
+                        self.violation(position, message);
+                    }
+                    self.schema.element(&name)
+                }
+                None => {
+                    let message =
+                        format!("unexpected element <{}> in <{}>", name, parent.element.name);
+                    self.violation(position, message);
+                    None
+                }
+            },
+        };
+        let element = match element {
+            Some(element) => element,
+            None => {
+                self.skip_depth = 1;
+                return;
+            }
+        };
+
+        let checksum_type = self.check_attributes(element, tag, position);
+        self.stack.push(Frame {
+            element,
+            occurrences: vec![0; element.children.len()],
+            text: String::new(),
+            text_position: position,
+            checksum_type,
+        });
+    }
+
+    /// Check the attributes of `tag`. Returns the value of its checksum type attribute, if any.
+    fn check_attributes(
+        &mut self,
+        element: &Element,
+        tag: &BytesStart,
+        position: usize,
+    ) -> Option<String> {
+        let mut present = Vec::new();
+        let mut checksum_type = None;
+        for attr in tag.attributes() {
+            let attr = match attr {
+                Ok(attr) => attr,
+                Err(e) => {
+                    self.violation(position, format!("malformed attribute: {}", e));
+                    continue;
+                }
+            };
+            let key = String::from_utf8_lossy(attr.key).into_owned();
+            let value = match utils::decode_attribute(&attr) {
+                Ok(value) => value,
+                Err(e) => {
+                    self.violation(position, format!("malformed attribute {}: {}", key, e));
+                    continue;
+                }
+            };
+            let rule = match element.attributes.iter().find(|a| a.name == key) {
+                Some(rule) => rule,
+                None => {
+                    let message = format!("unexpected attribute \"{}\" of <{}>", key, element.name);
+                    self.violation(position, message);
+                    continue;
+                }
+            };
+            if let Err(reason) = rule.format.check(&value) {
+                let message = format!(
+                    "invalid value \"{}\" of attribute \"{}\" of <{}>: {}",
+                    value, key, element.name, reason
+                );
+                self.violation(position, message);
+            } else if rule.format == Format::ChecksumType {
+                checksum_type = Some(value.into_owned());
+            }
+            present.push(rule.name);
+        }
+
+        for rule in element.attributes.iter().filter(|a| a.required) {
+            if !present.contains(&rule.name) {
+                let message = format!(
+                    "missing required attribute \"{}\" of <{}>",
+                    rule.name, element.name
+                );
+                self.violation(position, message);
+            }
+        }
+        checksum_type
+    }
+
+    fn text(&mut self, text: &str, position: usize) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        let frame = match self.stack.last_mut() {
+            Some(frame) => frame,
+            // whitespace and stray text around the root element
+            None => return,
+        };
+        if frame.element.text.is_none() && !text.trim().is_empty() {
+            let message = format!("unexpected text in <{}>", frame.element.name);
+            self.violation(position, message);
+            return;
+        }
+        if frame.text.is_empty() {
+            frame.text_position = position;
+        }
+        frame.text.push_str(text);
+    }
+
+    fn end(&mut self, position: usize) {
+        if self.skip_depth > 0 {
+            self.skip_depth -= 1;
+            return;
+        }
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let element = frame.element;
+
+        for (child, count) in element.children.iter().zip(&frame.occurrences) {
+            if *count == 0 && matches!(child.occurs, Occurs::One | Occurs::OneOrMore) {
+                let message = format!(
+                    "missing required element <{}> in <{}>",
+                    child.name, element.name
+                );
+                self.violation(position, message);
+            }
+        }
+
+        if let Some(format) = element.text {
+            let result = match (format, &frame.checksum_type) {
+                (Format::Digest, Some(checksum_type)) => {
+                    Checksum::parse(checksum_type, frame.text.trim())
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                }
+                _ => format.check(&frame.text),
+            };
+            if let Err(reason) = result {
+                let message = format!(
+                    "invalid text \"{}\" of <{}>: {}",
+                    frame.text, element.name, reason
+                );
+                self.violation(frame.text_position, message);
+            }
+        }
+    }
+}
+
+/// The rules for an element, which is identified by its name within a schema.
+struct Element {
+    name: &'static str,
+    attributes: &'static [Attr],
+    children: &'static [Child],
+    /// The format of the text content, `None` if the element may only contain whitespace.
+    text: Option<Format>,
+}
+
+struct Attr {
+    name: &'static str,
+    required: bool,
+    format: Format,
+}
+
+struct Child {
+    name: &'static str,
+    occurs: Occurs,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Occurs {
+    One,
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Any,
+    NonEmpty,
+    /// An unsigned integer.
+    Integer,
+    /// A checksum digest, of the length of the checksum type of the element.
+    Digest,
+    ChecksumType,
+    /// The flags of a dependency, e.g. "EQ".
+    Flags,
+    Boolean,
+    /// One of the given values.
+    OneOf(&'static [&'static str]),
+}
+
+impl Format {
+    fn check(self, value: &str) -> Result<(), String> {
+        let valid = match self {
+            Format::Any => true,
+            Format::NonEmpty => !value.trim().is_empty(),
+            Format::Integer => value.trim().parse::<u64>().is_ok(),
+            Format::Digest => {
+                !value.trim().is_empty() && value.trim().chars().all(|c| c.is_ascii_hexdigit())
+            }
+            Format::ChecksumType => {
+                let checksum_type: Result<ChecksumType, _> = value.try_into();
+                checksum_type.is_ok()
+            }
+            Format::Flags => RequirementType::try_from(value).is_ok(),
+            Format::Boolean => {
+                matches!(value.to_lowercase().as_str(), "0" | "1" | "true" | "false")
+            }
+            Format::OneOf(values) => values.contains(&value),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(self.expected().to_owned())
+        }
+    }
+
+    fn expected(self) -> &'static str {
+        match self {
+            Format::Any => "",
+            Format::NonEmpty => "expected a non-empty value",
+            Format::Integer => "expected an unsigned integer",
+            Format::Digest => "expected a hexadecimal checksum",
+            Format::ChecksumType => "expected a supported checksum type",
+            Format::Flags => "expected one of EQ, LT, GT, LE or GE",
+            Format::Boolean => "expected 0, 1, true or false",
+            Format::OneOf(_) => "not one of the allowed values",
+        }
+    }
+}
+
+const fn attr(name: &'static str, format: Format) -> Attr {
+    Attr {
+        name,
+        required: true,
+        format,
+    }
+}
+
+const fn optional_attr(name: &'static str, format: Format) -> Attr {
+    Attr {
+        name,
+        required: false,
+        format,
+    }
+}
+
+const fn child(name: &'static str, occurs: Occurs) -> Child {
+    Child { name, occurs }
+}
+
+const fn text_element(name: &'static str, format: Format) -> Element {
+    Element {
+        name,
+        attributes: &[],
+        children: &[],
+        text: Some(format),
+    }
+}
+
+const fn empty_element(name: &'static str, attributes: &'static [Attr]) -> Element {
+    Element {
+        name,
+        attributes,
+        children: &[],
+        text: None,
+    }
+}
+
+const fn parent_element(
+    name: &'static str,
+    attributes: &'static [Attr],
+    children: &'static [Child],
+) -> Element {
+    Element {
+        name,
+        attributes,
+        children,
+        text: None,
+    }
+}
+
+// <version epoch="0" ver="4.1" rel="1"/>
+const VERSION: Element = empty_element(
+    "version",
+    &[
+        attr("epoch", Format::Integer),
+        attr("ver", Format::NonEmpty),
+        attr("rel", Format::Any),
+    ],
+);
+
+// <file type="dir">/etc/fonts/conf.avail</file>
+const FILE: Element = Element {
+    name: "file",
+    attributes: &[optional_attr(
+        "type",
+        Format::OneOf(&["file", "dir", "ghost"]),
+    )],
+    children: &[],
+    text: Some(Format::NonEmpty),
+};
+
+const DEPENDENCY_ENTRIES: &[Child] = &[child("rpm:entry", OneOrMore)];
+
+const fn dependencies(name: &'static str) -> Element {
+    parent_element(name, &[], DEPENDENCY_ENTRIES)
+}
+
+const PRIMARY: &[Element] = &[
+    parent_element(
+        "metadata",
+        &[
+            attr("xmlns", Format::OneOf(&[XML_NS_COMMON])),
+            attr("xmlns:rpm", Format::OneOf(&[XML_NS_RPM])),
+            attr("packages", Format::Integer),
+        ],
+        &[child("package", ZeroOrMore)],
+    ),
+    parent_element(
+        "package",
+        &[attr("type", Format::OneOf(&["rpm"]))],
+        &[
+            child("name", One),
+            child("arch", One),
+            child("version", One),
+            child("checksum", One),
+            child("summary", One),
+            child("description", One),
+            child("packager", Optional),
+            child("url", Optional),
+            child("time", One),
+            child("size", One),
+            child("location", One),
+            child("format", One),
+        ],
+    ),
+    text_element("name", Format::NonEmpty),
+    text_element("arch", Format::NonEmpty),
+    VERSION,
+    Element {
+        name: "checksum",
+        attributes: &[
+            attr("type", Format::ChecksumType),
+            optional_attr("pkgid", Format::OneOf(&["YES", "NO"])),
+        ],
+        children: &[],
+        text: Some(Format::Digest),
+    },
+    text_element("summary", Format::Any),
+    text_element("description", Format::Any),
+    text_element("packager", Format::Any),
+    text_element("url", Format::Any),
+    empty_element(
+        "time",
+        &[
+            attr("file", Format::Integer),
+            attr("build", Format::Integer),
+        ],
+    ),
+    empty_element(
+        "size",
+        &[
+            attr("package", Format::Integer),
+            attr("installed", Format::Integer),
+            attr("archive", Format::Integer),
+        ],
+    ),
+    empty_element(
+        "location",
+        &[
+            attr("href", Format::NonEmpty),
+            optional_attr("xml:base", Format::NonEmpty),
+        ],
+    ),
+    parent_element(
+        "format",
+        &[],
+        &[
+            child("rpm:license", Optional),
+            child("rpm:vendor", Optional),
+            child("rpm:group", Optional),
+            child("rpm:buildhost", Optional),
+            child("rpm:sourcerpm", Optional),
+            child("rpm:header-range", Optional),
+            child("rpm:provides", Optional),
+            child("rpm:requires", Optional),
+            child("rpm:conflicts", Optional),
+            child("rpm:obsoletes", Optional),
+            child("rpm:suggests", Optional),
+            child("rpm:enhances", Optional),
+            child("rpm:recommends", Optional),
+            child("rpm:supplements", Optional),
+            child("file", ZeroOrMore),
+        ],
+    ),
+    text_element("rpm:license", Format::Any),
+    text_element("rpm:vendor", Format::Any),
+    text_element("rpm:group", Format::Any),
+    text_element("rpm:buildhost", Format::Any),
+    text_element("rpm:sourcerpm", Format::Any),
+    empty_element(
+        "rpm:header-range",
+        &[attr("start", Format::Integer), attr("end", Format::Integer)],
+    ),
+    dependencies("rpm:provides"),
+    dependencies("rpm:requires"),
+    dependencies("rpm:conflicts"),
+    dependencies("rpm:obsoletes"),
+    dependencies("rpm:suggests"),
+    dependencies("rpm:enhances"),
+    dependencies("rpm:recommends"),
+    dependencies("rpm:supplements"),
+    // <rpm:entry name="horse" flags="EQ" epoch="0" ver="4.1" rel="1"/>
+    empty_element(
+        "rpm:entry",
+        &[
+            attr("name", Format::NonEmpty),
+            optional_attr("flags", Format::Flags),
+            optional_attr("epoch", Format::Integer),
+            optional_attr("ver", Format::Any),
+            optional_attr("rel", Format::Any),
+            optional_attr("pre", Format::Boolean),
+        ],
+    ),
+    FILE,
+];
+
+const FILELISTS: &[Element] = &[
+    parent_element(
+        "filelists",
+        &[
+            attr("xmlns", Format::OneOf(&[XML_NS_FILELISTS])),
+            attr("packages", Format::Integer),
+        ],
+        &[child("package", ZeroOrMore)],
+    ),
+    parent_element(
+        "package",
+        &[
+            attr("pkgid", Format::Digest),
+            attr("name", Format::NonEmpty),
+            attr("arch", Format::NonEmpty),
+        ],
+        &[child("version", One), child("file", ZeroOrMore)],
+    ),
+    VERSION,
+    FILE,
+];
+
+const OTHER: &[Element] = &[
+    parent_element(
+        "otherdata",
+        &[
+            attr("xmlns", Format::OneOf(&[XML_NS_OTHER])),
+            attr("packages", Format::Integer),
+        ],
+        &[child("package", ZeroOrMore)],
+    ),
+    parent_element(
+        "package",
+        &[
+            attr("pkgid", Format::Digest),
+            attr("name", Format::NonEmpty),
+            attr("arch", Format::NonEmpty),
+        ],
+        &[child("version", One), child("changelog", ZeroOrMore)],
+    ),
+    VERSION,
+    // <changelog author="dalley &lt;dalley@redhat.com&gt; - 2.7.2-1" date="1251720000">- Update to 2.7.2</changelog>
+    Element {
+        name: "changelog",
+        attributes: &[attr("author", Format::Any), attr("date", Format::Integer)],
+        children: &[],
+        text: Some(Format::Any),
+    },
+];
+
+const CHECKSUM_ATTRIBUTES: &[Attr] = &[attr("type", Format::ChecksumType)];
+
+// <checksum type="sha256">afdc6dc379e58d097ed0b350536812bc6a604bbce50c5c109d8d98e28301dc4b</checksum>
+const fn checksum_element(name: &'static str) -> Element {
+    Element {
+        name,
+        attributes: CHECKSUM_ATTRIBUTES,
+        children: &[],
+        text: Some(Format::Digest),
+    }
+}
+
+const REPOMD: &[Element] = &[
+    parent_element(
+        "repomd",
+        &[
+            attr("xmlns", Format::OneOf(&[XML_NS_REPO])),
+            optional_attr("xmlns:rpm", Format::OneOf(&[XML_NS_RPM])),
+        ],
+        &[
+            child("revision", Optional),
+            child("tags", Optional),
+            child("data", ZeroOrMore),
+        ],
+    ),
+    text_element("revision", Format::NonEmpty),
+    parent_element(
+        "tags",
+        &[],
+        &[
+            child("content", ZeroOrMore),
+            child("repo", ZeroOrMore),
+            child("distro", ZeroOrMore),
+        ],
+    ),
+    text_element("content", Format::Any),
+    text_element("repo", Format::Any),
+    Element {
+        name: "distro",
+        attributes: &[optional_attr("cpeid", Format::Any)],
+        children: &[],
+        text: Some(Format::Any),
+    },
+    parent_element(
+        "data",
+        &[attr("type", Format::NonEmpty)],
+        &[
+            child("checksum", One),
+            child("open-checksum", Optional),
+            child("header-checksum", Optional),
+            child("location", One),
+            child("timestamp", One),
+            child("size", Optional),
+            child("open-size", Optional),
+            child("header-size", Optional),
+            child("database_version", Optional),
+        ],
+    ),
+    checksum_element("checksum"),
+    checksum_element("open-checksum"),
+    checksum_element("header-checksum"),
+    empty_element(
+        "location",
+        &[
+            attr("href", Format::NonEmpty),
+            optional_attr("xml:base", Format::NonEmpty),
+        ],
+    ),
+    text_element("timestamp", Format::Integer),
+    text_element("size", Format::Integer),
+    text_element("open-size", Format::Integer),
+    text_element("header-size", Format::Integer),
+    text_element("database_version", Format::Integer),
+];
+
+const DATE_ATTRIBUTES: &[Attr] = &[optional_attr("date", Format::NonEmpty)];
+
+// <issued date="2020-05-27 04:10:31"/>, some producers put the date in the text instead
+const fn update_date(name: &'static str) -> Element {
+    Element {
+        name,
+        attributes: DATE_ATTRIBUTES,
+        children: &[],
+        text: Some(Format::Any),
+    }
+}
+
+const UPDATEINFO: &[Element] = &[
+    parent_element("updates", &[], &[child("update", ZeroOrMore)]),
+    // <update from="updates@fedoraproject.org" status="stable" type="bugfix" version="2.0">
+    parent_element(
+        "update",
+        &[
+            optional_attr("from", Format::Any),
+            attr("status", Format::NonEmpty),
+            attr("type", Format::NonEmpty),
+            optional_attr("version", Format::Any),
+        ],
+        &[
+            child("id", One),
+            child("title", Optional),
+            child("issued", Optional),
+            child("updated", Optional),
+            child("rights", Optional),
+            child("copyright", Optional),
+            child("release", Optional),
+            child("severity", Optional),
+            child("summary", Optional),
+            child("description", Optional),
+            child("solution", Optional),
+            child("reboot_suggested", Optional),
+            child("restart_suggested", Optional),
+            child("relogin_suggested", Optional),
+            child("references", Optional),
+            child("pkglist", Optional),
+        ],
+    ),
+    text_element("id", Format::NonEmpty),
+    text_element("title", Format::Any),
+    update_date("issued"),
+    update_date("updated"),
+    text_element("rights", Format::Any),
+    text_element("copyright", Format::Any),
+    text_element("release", Format::Any),
+    text_element("severity", Format::Any),
+    text_element("summary", Format::Any),
+    text_element("description", Format::Any),
+    text_element("solution", Format::Any),
+    text_element("reboot_suggested", Format::Any),
+    text_element("restart_suggested", Format::Any),
+    text_element("relogin_suggested", Format::Any),
+    parent_element("references", &[], &[child("reference", ZeroOrMore)]),
+    empty_element(
+        "reference",
+        &[
+            attr("href", Format::Any),
+            optional_attr("id", Format::Any),
+            optional_attr("type", Format::Any),
+            optional_attr("title", Format::Any),
+        ],
+    ),
+    parent_element("pkglist", &[], &[child("collection", ZeroOrMore)]),
+    parent_element(
+        "collection",
+        &[optional_attr("short", Format::Any)],
+        &[
+            child("name", Optional),
+            child("module", Optional),
+            child("package", ZeroOrMore),
+        ],
+    ),
+    text_element("name", Format::Any),
+    // <module stream="3.0" version="8000020190425181943" arch="x86_64" name="freeradius" context="75ec4169" />
+    empty_element(
+        "module",
+        &[
+            attr("name", Format::NonEmpty),
+            attr("stream", Format::NonEmpty),
+            attr("version", Format::Integer),
+            attr("context", Format::NonEmpty),
+            attr("arch", Format::NonEmpty),
+        ],
+    ),
+    // <package src="kexec-tools-2.0.4-32.el7_0.1.src.rpm" name="kexec-tools" epoch="0" version="2.0.4" release="32.el7" arch="x86_64">
+    parent_element(
+        "package",
+        &[
+            attr("name", Format::NonEmpty),
+            attr("version", Format::NonEmpty),
+            attr("release", Format::NonEmpty),
+            optional_attr("epoch", Format::Integer),
+            attr("arch", Format::NonEmpty),
+            optional_attr("src", Format::Any),
+        ],
+        &[
+            child("filename", One),
+            child("sum", Optional),
+            child("reboot_suggested", Optional),
+            child("restart_suggested", Optional),
+            child("relogin_suggested", Optional),
+        ],
+    ),
+    text_element("filename", Format::NonEmpty),
+    checksum_element("sum"),
+];
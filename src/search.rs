@@ -0,0 +1,99 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{BufReader, Read};
+
+use crate::filelist::FilelistsXmlReader;
+use crate::metadata::METADATA_FILELISTS;
+use crate::{utils, FilelistsXml, MetadataError, RepositoryReader};
+
+/// A package which contains files matching the pattern passed to [`RepositoryReader::find_file_owners`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileOwner {
+    pub pkgid: String,
+    pub nevra: String,
+    /// The paths of the matching files.
+    pub paths: Vec<String>,
+}
+
+/// Iterator over the packages containing files which match a glob pattern.
+///
+/// See [`RepositoryReader::find_file_owners`].
+pub struct FileOwnerIterator {
+    filelists: Option<FilelistsXmlReader<BufReader<Box<dyn Read + Send>>>>,
+    pattern: String,
+}
+
+impl Iterator for FileOwnerIterator {
+    type Item = Result<FileOwner, MetadataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pattern = &self.pattern;
+        let filelists = self.filelists.as_mut()?;
+
+        loop {
+            let mut package = None;
+            let found = filelists.read_package_with_files(&mut package, |file| {
+                utils::glob_match(pattern, &file.path)
+            });
+            match (found, package) {
+                (Ok(true), Some(package)) if !package.files().is_empty() => {
+                    return Some(Ok(FileOwner {
+                        pkgid: package.pkgid().to_owned(),
+                        nevra: package.nevra(),
+                        paths: package.files().iter().map(|f| f.path.clone()).collect(),
+                    }));
+                }
+                (Ok(true), _) => continue,
+                (Ok(false), _) => {
+                    self.filelists = None;
+                    return None;
+                }
+                (Err(e), _) => {
+                    self.filelists = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl RepositoryReader {
+    /// Find the packages which contain files matching a glob pattern, like `dnf provides '*/bin/foo'`.
+    ///
+    /// filelists.xml is streamed and only the matching paths are kept, so this works with very large
+    /// repositories. See [`utils::glob_match`] for the pattern syntax - note that `*` also matches `/`.
+    /// Yields nothing if the repository has no filelists.xml.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, RepositoryReader};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// for owner in reader.find_file_owners("*/bin/hello")? {
+    ///     let owner = owner?;
+    ///     assert_eq!(owner.nevra, "hello-0:2.10-1.fc36.x86_64");
+    ///     assert_eq!(owner.paths, ["/usr/bin/hello"]);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn find_file_owners(&self, pattern: &str) -> Result<FileOwnerIterator, MetadataError> {
+        let filelists = match self.open_xml_reader(METADATA_FILELISTS)? {
+            Some(reader) => {
+                let mut filelists = FilelistsXml::new_reader(reader);
+                filelists.read_header()?;
+                Some(filelists)
+            }
+            None => None,
+        };
+
+        Ok(FileOwnerIterator {
+            filelists,
+            pattern: pattern.to_owned(),
+        })
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::layout::transfer_file;
+use crate::metadata::{METADATA_FILELISTS, METADATA_OTHER, METADATA_PRIMARY, METADATA_UPDATEINFO};
+use crate::remote::{Fetcher, RemoteRepository};
+use crate::{
+    utils, Field, MetadataError, MetadataSelection, ParseError, RepositoryOptions,
+    RepositoryReader, RepositoryWriter, TransferMode,
+};
+
+/// The outcome of [`RepoSyncer::sync`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncReport {
+    /// The local repository already had the same revision as the remote one, so nothing was done.
+    pub up_to_date: bool,
+    /// The locations of the packages which were downloaded.
+    pub downloaded: Vec<String>,
+    /// The locations of the packages which were removed, as they are no longer in the remote repository.
+    pub removed: Vec<String>,
+    /// The number of packages which were already present locally.
+    pub unchanged: usize,
+    /// The locations of the packages which were taken from the previous generation of the repository instead
+    /// of being downloaded, see [`RepoSyncer::reuse_packages_from`].
+    pub reused: Vec<String>,
+}
+
+/// Mirrors a remote repository into a local directory, transferring only what has changed.
+///
+/// Packages already present locally (by pkgid and location) are kept, new and changed packages are
+/// downloaded, and packages which are no longer in the remote repository are removed. The metadata is
+/// then regenerated with the [`RepositoryOptions`], keeping the revision of the remote repository. If the
+/// local repository already has the same revision as the remote one, nothing is downloaded at all.
+///
+/// ```no_run
+/// # use std::io::Write;
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{MetadataError, RemoteRepository, RepoSyncer};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let fetcher = |_: &str, _: &mut dyn Write| -> Result<(), MetadataError> { unimplemented!() };
+/// let remote = RemoteRepository::new("https://mirror.example.com/fedora/36/x86_64/os/", fetcher)?;
+/// let report = RepoSyncer::new(remote, Path::new("/srv/mirror/fedora/36")).sync()?;
+/// println!("downloaded {} packages, removed {}", report.downloaded.len(), report.removed.len());
+/// # Ok(())
+/// # }
+/// ```
+pub struct RepoSyncer<F: Fetcher> {
+    remote: RemoteRepository<F>,
+    destination: PathBuf,
+    options: RepositoryOptions,
+    previous: Option<(PathBuf, TransferMode)>,
+    arches: Option<Vec<String>>,
+}
+
+impl<F: Fetcher> RepoSyncer<F> {
+    pub fn new(remote: RemoteRepository<F>, destination: &Path) -> Self {
+        Self {
+            remote,
+            destination: destination.to_owned(),
+            options: RepositoryOptions::default(),
+            previous: None,
+            arches: None,
+        }
+    }
+
+    /// Set the options used to regenerate the metadata of the local repository.
+    pub fn with_options(self, options: RepositoryOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    /// Take the packages which are unchanged from a previous generation of the repository at `path`, e.g.
+    /// when publishing each sync to a new dated snapshot directory, instead of downloading them again.
+    ///
+    /// Packages are matched by pkgid. With [`TransferMode::Hardlink`] or [`TransferMode::Reflink`], the
+    /// unchanged packages take no additional disk space; they fall back to copying when not possible.
+    ///
+    /// ```no_run
+    /// # use std::io::Write;
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, RemoteRepository, RepoSyncer, TransferMode};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let fetcher = |_: &str, _: &mut dyn Write| -> Result<(), MetadataError> { unimplemented!() };
+    /// # let remote = RemoteRepository::new("https://mirror.example.com/fedora/36/x86_64/os/", fetcher)?;
+    /// let report = RepoSyncer::new(remote, Path::new("/srv/mirror/fedora/36-2024-02-01"))
+    ///     .reuse_packages_from(Path::new("/srv/mirror/fedora/36-2024-01-01"), TransferMode::Hardlink)
+    ///     .sync()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reuse_packages_from(self, path: &Path, mode: TransferMode) -> Self {
+        Self {
+            previous: Some((path.to_owned(), mode)),
+            ..self
+        }
+    }
+
+    /// Only mirror packages with one of the given architectures, e.g. `["x86_64", "noarch"]`.
+    ///
+    /// Packages of other architectures are neither downloaded nor included in the local metadata, and are
+    /// removed from the local repository if they were mirrored before.
+    pub fn with_arches(self, arches: &[&str]) -> Self {
+        Self {
+            arches: Some(arches.iter().map(|arch| arch.to_string()).collect()),
+            ..self
+        }
+    }
+
+    /// Bring the local repository up to date with the remote one.
+    pub fn sync(&self) -> Result<SyncReport, MetadataError> {
+        let mut report = SyncReport::default();
+
+        let local = if self.destination.join("repodata/repomd.xml").exists() {
+            Some(RepositoryReader::new_from_directory(&self.destination)?)
+        } else {
+            None
+        };
+
+        let remote_revision = self.remote.repomd().revision();
+        if let Some(local) = &local {
+            if remote_revision.is_some() && local.repomd().revision() == remote_revision {
+                report.up_to_date = true;
+                return Ok(report);
+            }
+        }
+
+        let local_packages = match &local {
+            Some(local) => package_locations(local)?,
+            None => HashMap::new(),
+        };
+        let previous_packages = match &self.previous {
+            Some((path, _)) => package_locations(&RepositoryReader::new_from_directory(path)?)?,
+            None => HashMap::new(),
+        };
+
+        let staging = self.destination.join(".sync-remote-metadata");
+        let result = self.sync_packages(&staging, &local_packages, &previous_packages, &mut report);
+        let _ = std::fs::remove_dir_all(&staging);
+        let remote_hrefs = result?;
+
+        // only once the new metadata is in place, so that it never references removed packages
+        for href in local_packages.into_values() {
+            if remote_hrefs.contains(&href) || !utils::path_is_contained(Path::new(&href)) {
+                continue;
+            }
+            match std::fs::remove_file(self.destination.join(&href)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => report.removed.push(href),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Download the packages of the remote repository which are missing locally and write the new metadata.
+    ///
+    /// Returns the locations of all of the packages in the remote repository.
+    fn sync_packages(
+        &self,
+        staging: &Path,
+        local_packages: &HashMap<String, String>,
+        previous_packages: &HashMap<String, String>,
+        report: &mut SyncReport,
+    ) -> Result<HashSet<String>, MetadataError> {
+        let remote = self.remote.fetch_metadata(
+            &[
+                METADATA_PRIMARY,
+                METADATA_FILELISTS,
+                METADATA_OTHER,
+                METADATA_UPDATEINFO,
+            ],
+            staging,
+        )?;
+
+        let mut packages = remote.iter_packages()?;
+        let mut num_packages = packages.total_packages();
+        if let Some(arches) = &self.arches {
+            let arches: Vec<&str> = arches.iter().map(String::as_str).collect();
+            // the writer needs to know upfront how many packages pass the filter
+            let selection = MetadataSelection {
+                filelists: false,
+                other: false,
+            };
+            num_packages = 0;
+            for package in remote
+                .iter_packages_with(selection)?
+                .project(&[Field::Arch])
+                .with_arches(&arches)
+            {
+                package?;
+                num_packages += 1;
+            }
+            packages = packages.with_arches(&arches);
+        }
+        let mut writer =
+            RepositoryWriter::new_with_options(&self.destination, num_packages, self.options)?;
+        if let Some(revision) = self.remote.repomd().revision() {
+            writer.repomd_mut().set_revision(revision);
+        }
+
+        let mut remote_hrefs = HashSet::new();
+        for package in packages {
+            let mut package = package?;
+            let href = package.location_href().to_owned();
+            let path = self.destination.join(&href);
+
+            let present = local_packages.get(package.pkgid()) == Some(&href) && path.exists();
+            let previous = match (&self.previous, previous_packages.get(package.pkgid())) {
+                (Some((previous_dir, mode)), Some(previous_href)) => {
+                    Some((previous_dir.join(previous_href), *mode))
+                        .filter(|(previous_path, _)| previous_path.is_file())
+                }
+                _ => None,
+            };
+            if present {
+                report.unchanged += 1;
+            } else if let Some((previous_path, mode)) = previous {
+                transfer_file(&previous_path, &path, mode)?;
+                report.reused.push(href.clone());
+            } else {
+                let checksum = package
+                    .checksum()
+                    .ok_or(ParseError::MissingField("checksum"))?;
+                self.remote
+                    .download(package.location_base(), &href, &path, checksum)?;
+                report.downloaded.push(href.clone());
+            }
+
+            // the package is now served from the local repository
+            package.set_location_base(None::<String>);
+            writer.add_package(&package)?;
+            remote_hrefs.insert(href);
+        }
+
+        for advisory in remote.iter_advisories()? {
+            writer.add_advisory(&advisory?)?;
+        }
+        writer.finish()?;
+
+        Ok(remote_hrefs)
+    }
+}
+
+/// pkgid -> location of the packages in a local repository, for those stored in the repository itself.
+fn package_locations(reader: &RepositoryReader) -> Result<HashMap<String, String>, MetadataError> {
+    let selection = MetadataSelection {
+        filelists: false,
+        other: false,
+    };
+    let mut locations = HashMap::new();
+    for package in reader
+        .iter_packages_with(selection)?
+        .project(&[Field::Location])
+    {
+        let package = package?;
+        if package.location_base().is_none()
+            && utils::path_is_contained(Path::new(package.location_href()))
+        {
+            locations.insert(
+                package.pkgid().to_owned(),
+                package.location_href().to_owned(),
+            );
+        }
+    }
+    Ok(locations)
+}
@@ -0,0 +1,213 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read};
+use std::path::{Component, Path, PathBuf};
+
+use crate::{utils, MetadataError, RepositoryReader};
+
+const BLOCK_SIZE: usize = 512;
+
+impl RepositoryReader {
+    /// Create a new `RepositoryReader` for a repository stored in a (possibly compressed) tar archive.
+    ///
+    /// Only the files in the `repodata/` directory of the archive are extracted, into `dest`, so packages
+    /// stored alongside the metadata are never written to disk. The repository may be at the top level of
+    /// the archive or in a subdirectory, e.g. `baseos/repodata/repomd.xml`.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, RepositoryReader};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// let tmp_dir = tempdir::TempDir::new("doctest_tarball")?;
+    /// let reader =
+    ///     RepositoryReader::from_tarball(Path::new("tests/assets/doctest_repo.tar.gz"), tmp_dir.path())?;
+    /// assert_eq!(reader.iter_packages()?.count(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_tarball(tarball: &Path, dest: &Path) -> Result<Self, MetadataError> {
+        extract_repodata(utils::reader_from_file(tarball)?, dest)?;
+        Self::new_from_directory(dest)
+    }
+}
+
+fn invalid_archive(message: &str) -> MetadataError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid tar archive: {}", message),
+    )
+    .into()
+}
+
+/// Extract the entries of a tar archive located directly within a `repodata/` directory into `dest/repodata/`
+fn extract_repodata<R: Read>(mut archive: R, dest: &Path) -> Result<(), MetadataError> {
+    let repodata_dir = dest.join("repodata");
+    fs::create_dir_all(&repodata_dir)?;
+
+    let mut repo_root: Option<PathBuf> = None;
+    let mut long_name: Option<String> = None;
+    let mut header = [0u8; BLOCK_SIZE];
+
+    loop {
+        if !read_block(&mut archive, &mut header)? || header.iter().all(|b| *b == 0) {
+            break;
+        }
+        if !header_checksum_is_valid(&header) {
+            return Err(invalid_archive("header checksum mismatch"));
+        }
+
+        let size = parse_size(&header[124..136])?;
+        let padded_size = size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64;
+        let mut data = (&mut archive).take(padded_size);
+
+        let path = match long_name.take() {
+            Some(name) => name,
+            None => entry_name(&header),
+        };
+
+        match header[156] {
+            // GNU long name and pax extended headers override the path of the following entry
+            b'L' => {
+                let mut name = Vec::new();
+                (&mut data).take(size).read_to_end(&mut name)?;
+                let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+                long_name = Some(String::from_utf8_lossy(&name[..end]).into_owned());
+            }
+            b'x' => {
+                let mut records = Vec::new();
+                (&mut data).take(size).read_to_end(&mut records)?;
+                long_name = pax_path(&records);
+            }
+            b'0' | b'\0' | b'7' => {
+                if let Some((root, filename)) = split_repodata_path(Path::new(&path)) {
+                    match &repo_root {
+                        Some(existing) if existing != &root => {
+                            return Err(invalid_archive(
+                                "contains more than one repodata/ directory",
+                            ))
+                        }
+                        _ => repo_root = Some(root),
+                    }
+                    let mut writer = BufWriter::new(File::create(repodata_dir.join(filename))?);
+                    io::copy(&mut (&mut data).take(size), &mut writer)?;
+                }
+            }
+            _ => (),
+        }
+
+        // skip the rest of the entry, including padding
+        io::copy(&mut data, &mut io::sink())?;
+    }
+
+    if repo_root.is_none() {
+        return Err(invalid_archive("no repodata/ directory found"));
+    }
+
+    Ok(())
+}
+
+/// Read a full block. Returns false at the end of the archive.
+fn read_block<R: Read>(
+    archive: &mut R,
+    block: &mut [u8; BLOCK_SIZE],
+) -> Result<bool, MetadataError> {
+    let mut filled = 0;
+    while filled < BLOCK_SIZE {
+        let read = archive.read(&mut block[filled..])?;
+        if read == 0 {
+            if filled == 0 {
+                return Ok(false);
+            }
+            return Err(invalid_archive("truncated header"));
+        }
+        filled += read;
+    }
+    Ok(true)
+}
+
+fn header_checksum_is_valid(header: &[u8; BLOCK_SIZE]) -> bool {
+    // the checksum is calculated with the checksum field itself filled with spaces
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            if (148..156).contains(&i) {
+                b' ' as u64
+            } else {
+                *b as u64
+            }
+        })
+        .sum();
+    matches!(parse_octal(&header[148..156]), Some(expected) if expected == sum)
+}
+
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let digits = std::str::from_utf8(field).ok()?;
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(digits, 8).ok()
+}
+
+fn parse_size(field: &[u8]) -> Result<u64, MetadataError> {
+    // GNU tar stores sizes which don't fit the octal field as big-endian base-256
+    if field[0] & 0x80 != 0 {
+        let mut size = (field[0] & 0x7f) as u64;
+        for b in &field[1..] {
+            size = size
+                .checked_mul(256)
+                .ok_or_else(|| invalid_archive("entry size too large"))?
+                + *b as u64;
+        }
+        return Ok(size);
+    }
+    parse_octal(field).ok_or_else(|| invalid_archive("invalid entry size"))
+}
+
+fn entry_name(header: &[u8; BLOCK_SIZE]) -> String {
+    let field_str = |field: &[u8]| {
+        let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..end]).into_owned()
+    };
+
+    let name = field_str(&header[0..100]);
+    if &header[257..262] == b"ustar" {
+        let prefix = field_str(&header[345..500]);
+        if !prefix.is_empty() {
+            return format!("{}/{}", prefix, name);
+        }
+    }
+    name
+}
+
+/// The `path` of a pax extended header, which consists of records like `"30 path=repodata/repomd.xml\n"`
+fn pax_path(records: &[u8]) -> Option<String> {
+    let records = String::from_utf8_lossy(records);
+    records.lines().find_map(|record| {
+        let (_length, keyword_value) = record.split_once(' ')?;
+        keyword_value.strip_prefix("path=").map(str::to_owned)
+    })
+}
+
+/// Split a path like `baseos/repodata/repomd.xml` into the repository root and the file name
+fn split_repodata_path(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    if !utils::path_is_contained(path) {
+        return None;
+    }
+    let components: Vec<Component> = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+    match components.as_slice() {
+        [root @ .., Component::Normal(dir), Component::Normal(filename)] if *dir == "repodata" => {
+            Some((root.iter().collect(), PathBuf::from(filename)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::path::Path;
+
+use crate::layout::transfer_file;
+use crate::metadata::is_generated_record;
+use crate::{
+    utils, MetadataError, Package, RepoLayoutError, RepomdRecord, RepositoryOptions,
+    RepositoryReader, RepositoryWriter, TransferMode, UpdateRecord, WriteError,
+};
+
+/// Copy the packages of an existing repository into a new one, filtering or modifying them along the way.
+///
+/// Packages are streamed from the source repository into a [`RepositoryWriter`], so no more than one
+/// package is held in memory at a time. This makes it possible to create e.g. arch-filtered subsets of
+/// very large repositories on small machines.
+///
+/// Because the number of packages must be known before writing begins, the source metadata is read twice:
+/// once to count the packages which pass the filter, and once to write them. The transformation must
+/// therefore make the same decisions each time it is called with the same package. If the
+/// `rewrite_package_count` option is set (which requires uncompressed metadata), the metadata is only
+/// read once.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{MetadataError, RepositoryOptions, RepositoryReader, RepositoryTransformer};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let out_dir = tempdir::TempDir::new("doctest_transform")?;
+/// let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// let report = RepositoryTransformer::new(reader)
+///     .options(RepositoryOptions::default().simple_metadata_filenames(true))
+///     .transform(out_dir.path(), |pkg| {
+///         matches!(pkg.arch(), "x86_64").then(|| pkg)
+///     })?;
+/// assert_eq!(report.packages_read, 3);
+/// assert_eq!(report.packages_written, 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct RepositoryTransformer {
+    reader: RepositoryReader,
+    options: RepositoryOptions,
+    copy_advisories: bool,
+    unknown_records: UnknownRecordPolicy,
+    package_files: Option<TransferMode>,
+}
+
+/// What [`RepositoryTransformer`] does with metadata records which it doesn't generate from the packages and
+/// advisories, such as "group", "modules", "productid" or "appdata".
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UnknownRecordPolicy {
+    /// Copy the files byte-for-byte, keeping their compression.
+    Preserve,
+    /// Recompress the files with the `metadata_compression_type` of the new repository.
+    Recompress,
+    /// Leave the records out of the new repository.
+    #[default]
+    Skip,
+    /// Fail with [`RepoLayoutError::UnknownRecord`] before writing anything.
+    Error,
+}
+
+/// The number of packages and advisories processed by [`RepositoryTransformer::transform`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransformReport {
+    pub packages_read: usize,
+    pub packages_written: usize,
+    pub advisories_written: usize,
+    /// The number of package files placed in the new repository, see
+    /// [`RepositoryTransformer::package_files`].
+    pub package_files_transferred: usize,
+    /// How many of those were copied, either with [`TransferMode::Copy`] or because hardlinking or reflinking
+    /// them was not possible.
+    pub package_files_copied: usize,
+}
+
+impl RepositoryTransformer {
+    pub fn new(reader: RepositoryReader) -> Self {
+        Self {
+            reader,
+            options: RepositoryOptions::default(),
+            copy_advisories: true,
+            unknown_records: UnknownRecordPolicy::default(),
+            package_files: None,
+        }
+    }
+
+    /// The options used to write the new repository. See [`RepositoryOptions`].
+    pub fn options(self, options: RepositoryOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    /// Whether to copy the advisories (updateinfo) of the source repository. Enabled by default.
+    pub fn copy_advisories(self, val: bool) -> Self {
+        Self {
+            copy_advisories: val,
+            ..self
+        }
+    }
+
+    /// What to do with metadata records which aren't generated from the packages and advisories, such as
+    /// "group", "modules" or "productid". See [`UnknownRecordPolicy`]. They are skipped by default.
+    pub fn unknown_records(self, policy: UnknownRecordPolicy) -> Self {
+        Self {
+            unknown_records: policy,
+            ..self
+        }
+    }
+
+    /// Also place the files of the packages written in the new repository, by default only the metadata is
+    /// written. Use [`TransferMode::Hardlink`] or [`TransferMode::Reflink`] to publish a new generation of a
+    /// large repository without using additional disk space; they fall back to copying when not possible.
+    ///
+    /// Files are taken from their original location in the source repository and placed at the location
+    /// returned by the transformation. Packages with a `location_base` are not stored locally and are
+    /// skipped. [`TransferMode::Move`] removes the files from the source repository.
+    pub fn package_files(self, mode: TransferMode) -> Self {
+        Self {
+            package_files: Some(mode),
+            ..self
+        }
+    }
+
+    /// Write a new repository to `path` containing the packages for which `transform` returns `Some`.
+    ///
+    /// The returned package is written in place of the original, so packages can be modified as well as
+    /// filtered. The changelog limit and dependency normalization from the [`RepositoryOptions`] are applied
+    /// to the packages written.
+    pub fn transform<F>(&self, path: &Path, transform: F) -> Result<TransformReport, MetadataError>
+    where
+        F: FnMut(Package) -> Option<Package>,
+    {
+        self.transform_with_advisories(path, transform, |_| self.copy_advisories)
+    }
+
+    /// Write a new repository to `path` containing only the given advisories and the packages listed in
+    /// their pkglists, e.g. to build a hotfix repository.
+    ///
+    /// Packages are matched to the pkglist entries by name, architecture and EVR. Returns an error if any of
+    /// the advisories are not present in the source repository.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use rpmrepo_metadata::{MetadataError, RepositoryReader, RepositoryTransformer};
+    /// # fn main() -> Result<(), MetadataError> {
+    /// # let out_dir = tempdir::TempDir::new("doctest_extract")?;
+    /// # let reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+    /// let report = RepositoryTransformer::new(reader)
+    ///     .extract_advisories(out_dir.path(), &["EXAMPLE-2022-0001"])?;
+    /// assert_eq!(report.advisories_written, 1);
+    /// assert_eq!(report.packages_written, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn extract_advisories(
+        &self,
+        path: &Path,
+        ids: &[&str],
+    ) -> Result<TransformReport, MetadataError> {
+        let mut advisories = Vec::new();
+        for advisory in self.reader.iter_advisories()? {
+            let advisory = advisory?;
+            if ids.contains(&advisory.id.as_str()) {
+                advisories.push(advisory);
+            }
+        }
+        if let Some(missing) = ids
+            .iter()
+            .find(|id| !advisories.iter().any(|a| a.id == **id))
+        {
+            return Err(WriteError::AdvisoryNotFound(missing.to_string()).into());
+        }
+
+        let is_referenced = |pkg: &Package| {
+            advisories
+                .iter()
+                .flat_map(|a| &a.pkglist)
+                .flat_map(|c| &c.packages)
+                .any(|entry| entry.refers_to(pkg))
+        };
+        self.transform_with_advisories(
+            path,
+            |pkg| is_referenced(&pkg).then_some(pkg),
+            |advisory| ids.contains(&advisory.id.as_str()),
+        )
+    }
+
+    /// The records of the source repository which aren't generated from the packages and advisories.
+    ///
+    /// Records whose files are located elsewhere (with a `location_base`) are not included.
+    fn extra_records(&self) -> impl Iterator<Item = &RepomdRecord> {
+        self.reader.repomd().records().iter().filter(|record| {
+            !is_generated_record(&record.metadata_name) && record.location_base.is_none()
+        })
+    }
+
+    fn transform_with_advisories<F, A>(
+        &self,
+        path: &Path,
+        mut transform: F,
+        mut keep_advisory: A,
+    ) -> Result<TransformReport, MetadataError>
+    where
+        F: FnMut(Package) -> Option<Package>,
+        A: FnMut(&UpdateRecord) -> bool,
+    {
+        if self.unknown_records == UnknownRecordPolicy::Error {
+            if let Some(record) = self.extra_records().next() {
+                return Err(RepoLayoutError::UnknownRecord(record.metadata_name.clone()).into());
+            }
+        }
+
+        let mut report = TransformReport::default();
+
+        let num_pkgs = if self.options.rewrite_package_count {
+            self.reader.iter_packages()?.total_packages()
+        } else {
+            let mut count = 0;
+            for package in self.reader.iter_packages()? {
+                if transform(package?).is_some() {
+                    count += 1;
+                }
+            }
+            count
+        };
+
+        let mut writer = RepositoryWriter::new_with_options(path, num_pkgs, self.options)?;
+        for package in self.reader.iter_packages()? {
+            report.packages_read += 1;
+            let package = package?;
+            let href = Path::new(package.location_href());
+            let source = (package.location_base().is_none() && utils::path_is_contained(href))
+                .then(|| self.reader.path().join(href));
+            if let Some(mut package) = transform(package) {
+                if let Some(limit) = self.options.changelog_limit {
+                    package.truncate_changelogs(limit);
+                }
+                if self.options.normalize_dependencies {
+                    package.normalize_dependencies();
+                }
+                writer.add_package(&package)?;
+                report.packages_written += 1;
+
+                if let (Some(mode), Some(source)) = (self.package_files, source) {
+                    let href = package.location_href();
+                    if package.location_base().is_none()
+                        && utils::path_is_contained(Path::new(href))
+                    {
+                        let transferred = transfer_file(&source, &path.join(href), mode)?;
+                        report.package_files_transferred += 1;
+                        if transferred == TransferMode::Copy {
+                            report.package_files_copied += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        for advisory in self.reader.iter_advisories()? {
+            let advisory = advisory?;
+            if keep_advisory(&advisory) {
+                writer.add_advisory(&advisory)?;
+                report.advisories_written += 1;
+            }
+        }
+
+        for record in self.extra_records() {
+            let path = self.reader.path().join(&record.location_href);
+            match self.unknown_records {
+                UnknownRecordPolicy::Preserve => {
+                    writer.copy_metadata_file(&record.metadata_name, &path)?
+                }
+                UnknownRecordPolicy::Recompress => writer.recompress_metadata_file(
+                    &record.metadata_name,
+                    &path,
+                    self.options.metadata_compression_type,
+                )?,
+                UnknownRecordPolicy::Skip | UnknownRecordPolicy::Error => (),
+            }
+        }
+
+        writer.finish()?;
+
+        Ok(report)
+    }
+}
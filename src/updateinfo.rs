@@ -4,17 +4,18 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, Write};
 
-use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Reader, Writer};
 
 use crate::metadata::{
-    UpdateCollection, UpdateCollectionModule, UpdateCollectionPackage, UpdateReference,
+    Checksum, UpdateCollection, UpdateCollectionModule, UpdateCollectionPackage, UpdateReference,
+    METADATA_UPDATEINFO,
 };
 
 use super::metadata::{RpmMetadata, UpdateRecord, UpdateinfoXml};
-use super::{MetadataError, Repository};
+use super::{utils, MetadataError, ParseError, Repository};
 
 const TAG_UPDATES: &[u8] = b"updates";
 const TAG_UPDATE: &[u8] = b"update";
@@ -34,7 +35,10 @@ const TAG_NAME: &[u8] = b"name";
 const TAG_MODULE: &[u8] = b"module";
 const TAG_PACKAGE: &[u8] = b"package";
 const TAG_FILENAME: &[u8] = b"filename";
+const TAG_SUM: &[u8] = b"sum";
 const TAG_REBOOT_SUGGESTED: &[u8] = b"reboot_suggested";
+const TAG_RESTART_SUGGESTED: &[u8] = b"restart_suggested";
+const TAG_RELOGIN_SUGGESTED: &[u8] = b"relogin_suggested";
 const TAG_REFERENCES: &[u8] = b"references";
 const TAG_REFERENCE: &[u8] = b"reference";
 
@@ -71,8 +75,29 @@ impl RpmMetadata for UpdateinfoXml {
     }
 }
 
+/// The free-text fields of an [`UpdateRecord`], which may contain markup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateinfoTextField {
+    Title,
+    Rights,
+    Summary,
+    Description,
+    Solution,
+}
+
+/// How the text content of an element is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextEscaping {
+    /// Escape special characters such as `<` and `&`
+    #[default]
+    Escape,
+    /// Wrap the text in a CDATA section, leaving it unescaped
+    CData,
+}
+
 pub struct UpdateinfoXmlWriter<W: Write> {
     writer: Writer<W>,
+    cdata_fields: Vec<UpdateinfoTextField>,
 }
 
 impl<W: Write> UpdateinfoXmlWriter<W> {
@@ -89,8 +114,18 @@ impl<W: Write> UpdateinfoXmlWriter<W> {
         Ok(())
     }
 
+    /// Set how the text of a field is written. By default all fields are escaped.
+    ///
+    /// Both escaped text and CDATA sections are accepted when reading, regardless of this setting.
+    pub fn set_text_escaping(&mut self, field: UpdateinfoTextField, escaping: TextEscaping) {
+        self.cdata_fields.retain(|f| *f != field);
+        if escaping == TextEscaping::CData {
+            self.cdata_fields.push(field);
+        }
+    }
+
     pub fn write_updaterecord(&mut self, record: &UpdateRecord) -> Result<(), MetadataError> {
-        write_updaterecord(record, &mut self.writer)
+        write_updaterecord(record, &self.cdata_fields, &mut self.writer)
     }
 
     pub fn finish(&mut self) -> Result<(), MetadataError> {
@@ -119,7 +154,16 @@ pub struct UpdateinfoXmlReader<R: BufRead> {
 
 impl<R: BufRead> UpdateinfoXmlReader<R> {
     pub fn read_update(&mut self) -> Result<Option<UpdateRecord>, MetadataError> {
-        parse_updaterecord(&mut self.reader)
+        let mut record = UpdateRecord::default();
+        match parse_updaterecord(&mut self.reader, &mut record) {
+            Ok(true) => Ok(Some(record)),
+            Ok(false) => Ok(None),
+            Err(e) => Err(e.at_position(
+                METADATA_UPDATEINFO,
+                self.reader.buffer_position(),
+                Some(record.id).filter(|id| !id.is_empty()),
+            )),
+        }
     }
 }
 
@@ -131,9 +175,31 @@ impl<R: BufRead> Iterator for UpdateinfoXmlReader<R> {
     }
 }
 
+/// Read the advisories of a single (possibly compressed) updateinfo.xml file, without a [`Repository`].
+///
+/// ```
+/// # use std::fs::File;
+/// # fn main() -> Result<(), rpmrepo_metadata::MetadataError> {
+/// let file = File::open("tests/assets/doctest_repo/repodata/updateinfo.xml")?;
+/// for record in rpmrepo_metadata::read_update_records(file)? {
+///     assert_eq!(record?.id, "EXAMPLE-2022-0001");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_update_records<R: io::Read + Send + 'static>(
+    reader: R,
+) -> Result<UpdateinfoXmlReader<io::BufReader<Box<dyn io::Read + Send>>>, MetadataError> {
+    let reader = utils::create_xml_reader(io::BufReader::new(utils::reader_from_reader(reader)?));
+    Ok(UpdateinfoXml::new_reader(reader))
+}
+
 impl UpdateinfoXml {
     pub fn new_writer<W: Write>(writer: quick_xml::Writer<W>) -> UpdateinfoXmlWriter<W> {
-        UpdateinfoXmlWriter { writer }
+        UpdateinfoXmlWriter {
+            writer,
+            cdata_fields: Vec::new(),
+        }
     }
 
     pub fn new_reader<R: BufRead>(reader: quick_xml::Reader<R>) -> UpdateinfoXmlReader<R> {
@@ -141,15 +207,16 @@ impl UpdateinfoXml {
     }
 }
 
+/// Parse the next update record into `record`.
+///
+/// Returns `false` if there were no more records to read.
 fn parse_updaterecord<R: BufRead>(
     reader: &mut Reader<R>,
-) -> Result<Option<UpdateRecord>, MetadataError> {
+    record: &mut UpdateRecord,
+) -> Result<bool, MetadataError> {
     let mut buf = Vec::new();
     let mut format_text_buf = Vec::new();
 
-    let mut record = UpdateRecord::default();
-
-    // TODO: get rid of unwraps, various branches could happen in wrong order
     loop {
         match reader.read_event(&mut buf)? {
             Event::End(e) if e.name().as_ref() == TAG_UPDATE => break,
@@ -169,26 +236,26 @@ fn parse_updaterecord<R: BufRead>(
 
                     record.status = e
                         .try_get_attribute("status")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("status"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("status"))?
                         .unescape_and_decode_value(reader)?;
                     record.from = e
                         .try_get_attribute("from")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("from"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("from"))?
                         .unescape_and_decode_value(reader)?;
                     record.update_type = e
                         .try_get_attribute("type")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("type"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("type"))?
                         .unescape_and_decode_value(reader)?;
                     record.version = e
                         .try_get_attribute("version")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("version"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("version"))?
                         .unescape_and_decode_value(reader)?;
                 }
                 TAG_ID => {
                     record.id = reader.read_text(TAG_ID, &mut format_text_buf)?;
                 }
                 TAG_TITLE => {
-                    record.title = reader.read_text(TAG_TITLE, &mut format_text_buf)?;
+                    record.title = read_text_content(reader, TAG_TITLE, &mut format_text_buf)?;
                 }
                 TAG_ISSUED => {
                     record.issued_date = Some(reader.read_text(TAG_ISSUED, &mut format_text_buf)?);
@@ -198,7 +265,7 @@ fn parse_updaterecord<R: BufRead>(
                         Some(reader.read_text(TAG_UPDATED, &mut format_text_buf)?);
                 }
                 TAG_RIGHTS => {
-                    record.rights = reader.read_text(TAG_RIGHTS, &mut format_text_buf)?;
+                    record.rights = read_text_content(reader, TAG_RIGHTS, &mut format_text_buf)?;
                 }
                 TAG_RELEASE => {
                     record.release = reader.read_text(TAG_RELEASE, &mut format_text_buf)?;
@@ -207,15 +274,28 @@ fn parse_updaterecord<R: BufRead>(
                     record.severity = reader.read_text(TAG_SEVERITY, &mut format_text_buf)?;
                 }
                 TAG_SUMMARY => {
-                    record.summary = reader.read_text(TAG_SUMMARY, &mut format_text_buf)?;
+                    record.summary = read_text_content(reader, TAG_SUMMARY, &mut format_text_buf)?;
                 }
                 TAG_DESCRIPTION => {
-                    record.description = reader.read_text(TAG_DESCRIPTION, &mut format_text_buf)?;
+                    record.description =
+                        read_text_content(reader, TAG_DESCRIPTION, &mut format_text_buf)?;
                 }
                 TAG_SOLUTION => {
-                    record.solution = reader.read_text(TAG_SOLUTION, &mut format_text_buf)?;
+                    record.solution =
+                        read_text_content(reader, TAG_SOLUTION, &mut format_text_buf)?;
+                }
+                TAG_REBOOT_SUGGESTED => {
+                    record.reboot_suggested =
+                        parse_bool(&reader.read_text(TAG_REBOOT_SUGGESTED, &mut format_text_buf)?);
+                }
+                TAG_RESTART_SUGGESTED => {
+                    record.restart_suggested =
+                        parse_bool(&reader.read_text(TAG_RESTART_SUGGESTED, &mut format_text_buf)?);
+                }
+                TAG_RELOGIN_SUGGESTED => {
+                    record.relogin_suggested =
+                        parse_bool(&reader.read_text(TAG_RELOGIN_SUGGESTED, &mut format_text_buf)?);
                 }
-                // reboot_suggested, not clear if it needs to be parsed
                 TAG_REFERENCES => {
                     loop {
                         match reader.read_event(&mut buf)? {
@@ -225,23 +305,24 @@ fn parse_updaterecord<R: BufRead>(
                                 // let attr = attr?;
                                 reference.href = e
                                     .try_get_attribute("href")?
-                                    .ok_or_else(|| MetadataError::MissingAttributeError("href"))?
+                                    .ok_or_else(|| ParseError::MissingAttribute("href"))?
                                     .unescape_and_decode_value(reader)?;
                                 reference.id = e
                                     .try_get_attribute("id")?
-                                    .ok_or_else(|| MetadataError::MissingAttributeError("id"))?
+                                    .ok_or_else(|| ParseError::MissingAttribute("id"))?
                                     .unescape_and_decode_value(reader)?;
                                 reference.reftype = e
                                     .try_get_attribute("type")?
-                                    .ok_or_else(|| MetadataError::MissingAttributeError("type"))?
+                                    .ok_or_else(|| ParseError::MissingAttribute("type"))?
                                     .unescape_and_decode_value(reader)?;
                                 reference.title = e
                                     .try_get_attribute("title")?
-                                    .ok_or_else(|| MetadataError::MissingAttributeError("title"))?
+                                    .ok_or_else(|| ParseError::MissingAttribute("title"))?
                                     .unescape_and_decode_value(reader)?;
                                 record.references.push(reference);
                             }
                             Event::End(e) if e.name().as_ref() == TAG_REFERENCES => break,
+                            Event::Eof => return Err(utils::unexpected_eof(TAG_REFERENCES)),
                             _ => (), // TODO
                         }
                     }
@@ -249,14 +330,14 @@ fn parse_updaterecord<R: BufRead>(
                 TAG_PKGLIST => record.pkglist = parse_pkglist(reader)?,
                 _ => (),
             },
-            Event::Eof => return Ok(None),
+            Event::Eof => return Ok(false),
             _ => (),
         }
         buf.clear();
         format_text_buf.clear();
     }
 
-    Ok(Some(record))
+    Ok(true)
 }
 
 pub fn parse_pkglist<R: BufRead>(
@@ -271,37 +352,53 @@ pub fn parse_pkglist<R: BufRead>(
     loop {
         match reader.read_event(&mut buf)? {
             Event::End(e) if e.name().as_ref() == TAG_PKGLIST => break,
+            Event::Eof => return Err(utils::unexpected_eof(TAG_PKGLIST)),
             Event::Start(e) if e.name().as_ref() == TAG_COLLECTION => {
                 current_collection = Some(UpdateCollection::default());
             }
             Event::End(e) if e.name().as_ref() == TAG_COLLECTION => {
-                collections.push(current_collection.take().unwrap());
+                let collection = current_collection
+                    .take()
+                    .ok_or_else(|| ParseError::UnexpectedElement("collection".to_owned()))?;
+                collections.push(collection);
+            }
+            Event::End(e) if e.name() == TAG_PACKAGE => {
+                let mut package: UpdateCollectionPackage = current_package
+                    .take()
+                    .ok_or_else(|| ParseError::UnexpectedElement("package".to_owned()))?;
+                // source packages are not required to list themselves as their own source
+                if package.src.is_empty() && package.is_source() {
+                    package.src = package.filename.clone();
+                }
+                utils::enclosing(&mut current_collection, TAG_PACKAGE)?
+                    .packages
+                    .push(package);
             }
             Event::Start(e) => match e.name().as_ref() {
                 TAG_NAME => {
-                    current_collection.as_mut().unwrap().name =
+                    utils::enclosing(&mut current_collection, TAG_NAME)?.name =
                         reader.read_text(TAG_NAME, &mut text_buf)?
                 }
                 TAG_MODULE => {
                     let name = e
                         .try_get_attribute("name")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("name"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("name"))?
                         .unescape_and_decode_value(reader)?;
                     let stream = e
                         .try_get_attribute("stream")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("stream"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("stream"))?
                         .unescape_and_decode_value(reader)?;
                     let version = e
                         .try_get_attribute("version")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("version"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("version"))?
                         .unescape_and_decode_value(reader)?;
                     let context = e
                         .try_get_attribute("context")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("context"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("context"))?
                         .unescape_and_decode_value(reader)?;
                     let arch = e
                         .try_get_attribute("arch")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("arch"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("arch"))?
                         .unescape_and_decode_value(reader)?;
 
                     let version = version.parse()?;
@@ -313,34 +410,34 @@ pub fn parse_pkglist<R: BufRead>(
                         context,
                         arch,
                     };
-                    current_collection.as_mut().unwrap().module = Some(module);
+                    utils::enclosing(&mut current_collection, TAG_MODULE)?.module = Some(module);
                 }
                 TAG_PACKAGE => {
                     let mut package = UpdateCollectionPackage::default();
 
                     let name = e
                         .try_get_attribute("name")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("name"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("name"))?
                         .unescape_and_decode_value(reader)?;
                     let version = e
                         .try_get_attribute("version")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("version"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("version"))?
                         .unescape_and_decode_value(reader)?;
                     let epoch = e
                         .try_get_attribute("epoch")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("epoch"))?
-                        .unescape_and_decode_value(reader)?;
-                    let src = e
-                        .try_get_attribute("src")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("src"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("epoch"))?
                         .unescape_and_decode_value(reader)?;
+                    let src = match e.try_get_attribute("src")? {
+                        Some(src) => src.unescape_and_decode_value(reader)?,
+                        None => String::new(),
+                    };
                     let release = e
                         .try_get_attribute("release")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("release"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("release"))?
                         .unescape_and_decode_value(reader)?;
                     let arch = e
                         .try_get_attribute("arch")?
-                        .ok_or_else(|| MetadataError::MissingAttributeError("arch"))?
+                        .ok_or_else(|| ParseError::MissingAttribute("arch"))?
                         .unescape_and_decode_value(reader)?;
 
                     package.name = name;
@@ -350,13 +447,37 @@ pub fn parse_pkglist<R: BufRead>(
                     package.epoch = epoch;
                     package.src = src;
                     current_package = Some(package);
-                    // current_collection.unwrap().packages.push(package);
                 }
                 TAG_FILENAME => {
-                    current_package.as_mut().unwrap().filename =
+                    utils::enclosing(&mut current_package, TAG_FILENAME)?.filename =
                         reader.read_text(TAG_FILENAME, &mut text_buf)?;
                 }
-                e @ _ => panic!("{}", dbg!(std::str::from_utf8(e).unwrap())),
+                TAG_SUM => {
+                    let checksum_type = e
+                        .try_get_attribute("type")?
+                        .ok_or(ParseError::MissingAttribute("type"))?
+                        .unescape_and_decode_value(reader)?;
+                    let value = reader.read_text(TAG_SUM, &mut text_buf)?;
+                    utils::enclosing(&mut current_package, TAG_SUM)?.checksum = Some(
+                        Checksum::try_create(checksum_type.as_str(), value.as_str())?,
+                    );
+                }
+                TAG_REBOOT_SUGGESTED => {
+                    utils::enclosing(&mut current_package, TAG_REBOOT_SUGGESTED)?
+                        .reboot_suggested =
+                        parse_bool(&reader.read_text(TAG_REBOOT_SUGGESTED, &mut text_buf)?);
+                }
+                TAG_RESTART_SUGGESTED => {
+                    utils::enclosing(&mut current_package, TAG_RESTART_SUGGESTED)?
+                        .restart_suggested =
+                        parse_bool(&reader.read_text(TAG_RESTART_SUGGESTED, &mut text_buf)?);
+                }
+                TAG_RELOGIN_SUGGESTED => {
+                    utils::enclosing(&mut current_package, TAG_RELOGIN_SUGGESTED)?
+                        .relogin_suggested =
+                        parse_bool(&reader.read_text(TAG_RELOGIN_SUGGESTED, &mut text_buf)?);
+                }
+                _ => (),
             },
             _ => (), // TODO
         }
@@ -367,8 +488,54 @@ pub fn parse_pkglist<R: BufRead>(
     Ok(collections)
 }
 
+/// Read the text content of an element, which may consist of escaped text, CDATA sections, or both.
+fn read_text_content<R: BufRead>(
+    reader: &mut Reader<R>,
+    end: &[u8],
+    buf: &mut Vec<u8>,
+) -> Result<String, MetadataError> {
+    let mut text = String::new();
+    loop {
+        match reader.read_event(buf)? {
+            Event::Text(e) => text.push_str(&e.unescape_and_decode(reader)?),
+            Event::CData(e) => text.push_str(reader.decode(&e)?),
+            Event::End(e) if e.name() == end => break,
+            Event::Eof => return Err(utils::unexpected_eof(end)),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(text)
+}
+
+fn write_text_field<W: Write>(
+    writer: &mut Writer<W>,
+    cdata_fields: &[UpdateinfoTextField],
+    tag: &[u8],
+    field: UpdateinfoTextField,
+    text: &str,
+) -> Result<(), MetadataError> {
+    if cdata_fields.contains(&field) {
+        let element = BytesStart::borrowed_name(tag);
+        writer.write_event(Event::Start(element.to_borrowed()))?;
+        writer.write_event(Event::CData(BytesCData::new(text.as_bytes())))?;
+        writer.write_event(Event::End(element.to_end()))?;
+    } else {
+        writer
+            .create_element(tag)
+            .write_text_content(BytesText::from_plain_str(text))?;
+    }
+    Ok(())
+}
+
+fn parse_bool(value: &str) -> bool {
+    let value = value.trim();
+    value == "1" || value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("yes")
+}
+
 fn write_updaterecord<W: Write>(
     record: &UpdateRecord,
+    cdata_fields: &[UpdateinfoTextField],
     writer: &mut Writer<W>,
 ) -> Result<(), MetadataError> {
     // <update from="updates@fedoraproject.org" status="stable" type="bugfix" version="2.0">
@@ -385,9 +552,13 @@ fn write_updaterecord<W: Write>(
         .write_text_content(BytesText::from_plain_str(record.id.as_str()))?;
 
     // <title>nano-4.9.3-1.fc32</title>
-    writer
-        .create_element(TAG_TITLE)
-        .write_text_content(BytesText::from_plain_str(record.title.as_str()))?;
+    write_text_field(
+        writer,
+        cdata_fields,
+        TAG_TITLE,
+        UpdateinfoTextField::Title,
+        record.title.as_str(),
+    )?;
 
     // <issued date="2020-05-27 04:10:31"/>
     if let Some(issued_date) = &record.issued_date {
@@ -404,9 +575,13 @@ fn write_updaterecord<W: Write>(
     }
 
     // <rights>Copyright (C) 2021 blah blah blah.</rights>
-    writer
-        .create_element(TAG_RIGHTS)
-        .write_text_content(BytesText::from_plain_str(record.rights.as_str()))?;
+    write_text_field(
+        writer,
+        cdata_fields,
+        TAG_RIGHTS,
+        UpdateinfoTextField::Rights,
+        record.rights.as_str(),
+    )?;
 
     // <release>Fedora 32</release>
     writer
@@ -419,27 +594,44 @@ fn write_updaterecord<W: Write>(
         .write_text_content(BytesText::from_plain_str(record.severity.as_str()))?;
 
     // <summary>nano-4.9.3-1.fc32 bugfix update</summary>
-    writer
-        .create_element(TAG_SUMMARY)
-        .write_text_content(BytesText::from_plain_str(record.summary.as_str()))?;
+    write_text_field(
+        writer,
+        cdata_fields,
+        TAG_SUMMARY,
+        UpdateinfoTextField::Summary,
+        record.summary.as_str(),
+    )?;
 
     // <description>- update to the latest upstream bugfix release</description>
-    writer
-        .create_element(TAG_DESCRIPTION)
-        .write_text_content(BytesText::from_plain_str(record.description.as_str()))?;
+    write_text_field(
+        writer,
+        cdata_fields,
+        TAG_DESCRIPTION,
+        UpdateinfoTextField::Description,
+        record.description.as_str(),
+    )?;
 
     // <solution>Another description, usually about how the update should be applied</solution>
-    writer
-        .create_element(TAG_SOLUTION)
-        .write_text_content(BytesText::from_plain_str(record.solution.as_str()))?;
-
-    // It's not clear that any metadata actually uses this
-    // // <reboot_suggested>True</reboot_suggestion> (optional)
-    // if record.reboot_suggested {
-    //     writer
-    //         .create_element(TAG_REBOOT_SUGGESTED)
-    //         .write_text_content(BytesText::from_plain_str("True"))?;
-    // }
+    write_text_field(
+        writer,
+        cdata_fields,
+        TAG_SOLUTION,
+        UpdateinfoTextField::Solution,
+        record.solution.as_str(),
+    )?;
+
+    // <reboot_suggested>True</reboot_suggested> (optional, only written when set)
+    for (tag, suggested) in [
+        (TAG_REBOOT_SUGGESTED, record.reboot_suggested),
+        (TAG_RESTART_SUGGESTED, record.restart_suggested),
+        (TAG_RELOGIN_SUGGESTED, record.relogin_suggested),
+    ] {
+        if suggested {
+            writer
+                .create_element(tag)
+                .write_text_content(BytesText::from_plain_str("True"))?;
+        }
+    }
 
     let tag_references = BytesStart::borrowed_name(TAG_REFERENCES);
     if !record.references.is_empty() {
@@ -500,7 +692,9 @@ fn write_updaterecord<W: Write>(
                 package_tag.push_attribute(("release", package.release.as_str()));
                 package_tag.push_attribute(("epoch", package.epoch.to_string().as_str()));
                 package_tag.push_attribute(("arch", package.arch.as_str()));
-                package_tag.push_attribute(("src", package.src.as_str()));
+                if !package.src.is_empty() {
+                    package_tag.push_attribute(("src", package.src.as_str()));
+                }
                 writer.write_event(Event::Start(package_tag.to_borrowed()))?;
 
                 // <filename>pypy-7.3.6-1.fc35.src.rpm</filename>
@@ -512,23 +706,23 @@ fn write_updaterecord<W: Write>(
                 if let Some(checksum) = &package.checksum {
                     let (checksum_type, value) = checksum.to_values()?;
                     writer
-                        .create_element("sum")
+                        .create_element(TAG_SUM)
                         .with_attribute(("type", checksum_type))
                         .write_text_content(BytesText::from_plain_str(value))?;
                 }
                 if package.reboot_suggested {
                     writer
-                        .create_element("reboot_suggested")
+                        .create_element(TAG_REBOOT_SUGGESTED)
                         .write_text_content(BytesText::from_plain_str("1"))?;
                 }
                 if package.restart_suggested {
                     writer
-                        .create_element("restart_suggested")
+                        .create_element(TAG_RESTART_SUGGESTED)
                         .write_text_content(BytesText::from_plain_str("1"))?;
                 }
                 if package.relogin_suggested {
                     writer
-                        .create_element("relogin_suggested")
+                        .create_element(TAG_RELOGIN_SUGGESTED)
                         .write_text_content(BytesText::from_plain_str("1"))?;
                 }
 
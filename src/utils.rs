@@ -4,84 +4,405 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
-use std::path::{Path, PathBuf};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use digest;
 use hex;
+#[cfg(feature = "compression")]
 use niffler;
 use quick_xml;
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::BytesStart;
 use sha1;
 use sha2;
 
-use crate::{Checksum, ChecksumType, CompressionType, MetadataError};
+use crate::decompress;
+use crate::{Checksum, ChecksumType, CompressionType, MetadataError, ParseError, RepoLayoutError};
 
-// TODO: these Box<dyn Read> shouldn't be necessary
-fn get_digest<D: digest::Digest>(mut reader: Box<dyn Read>) -> Result<String, MetadataError> {
-    let mut buffer = [0; 4096];
-    let mut hasher = D::new();
+/// The state of an in-progress digest computation of any supported type
+enum DigestState {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha224(sha2::Sha224),
+    Sha256(sha2::Sha256),
+    Sha384(sha2::Sha384),
+    Sha512(sha2::Sha512),
+}
+
+impl DigestState {
+    fn new(checksum_type: ChecksumType) -> Self {
+        use digest::Digest;
+
+        match checksum_type {
+            ChecksumType::Md5 => DigestState::Md5(md5::Md5::new()),
+            ChecksumType::Sha1 => DigestState::Sha1(sha1::Sha1::new()),
+            ChecksumType::Sha224 => DigestState::Sha224(sha2::Sha224::new()),
+            ChecksumType::Sha256 => DigestState::Sha256(sha2::Sha256::new()),
+            ChecksumType::Sha384 => DigestState::Sha384(sha2::Sha384::new()),
+            ChecksumType::Sha512 => DigestState::Sha512(sha2::Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        use digest::Digest;
+
+        match self {
+            DigestState::Md5(hasher) => hasher.update(data),
+            DigestState::Sha1(hasher) => hasher.update(data),
+            DigestState::Sha224(hasher) => hasher.update(data),
+            DigestState::Sha256(hasher) => hasher.update(data),
+            DigestState::Sha384(hasher) => hasher.update(data),
+            DigestState::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> Checksum {
+        use digest::Digest;
+
+        match self {
+            DigestState::Md5(hasher) => Checksum::Md5(hex::encode(hasher.finalize())),
+            DigestState::Sha1(hasher) => Checksum::Sha1(hex::encode(hasher.finalize())),
+            DigestState::Sha224(hasher) => Checksum::Sha224(hex::encode(hasher.finalize())),
+            DigestState::Sha256(hasher) => Checksum::Sha256(hex::encode(hasher.finalize())),
+            DigestState::Sha384(hasher) => Checksum::Sha384(hex::encode(hasher.finalize())),
+            DigestState::Sha512(hasher) => Checksum::Sha512(hex::encode(hasher.finalize())),
+        }
+    }
+}
+
+/// A writer which computes checksums of all data written through it, e.g. while a file is downloaded.
+///
+/// ```
+/// # use std::io;
+/// # use rpmrepo_metadata::utils::ChecksumWriter;
+/// # use rpmrepo_metadata::{Checksum, ChecksumType};
+/// # fn main() -> io::Result<()> {
+/// # let mut response: &[u8] = b"hello";
+/// let mut writer = ChecksumWriter::new(Vec::new(), &[ChecksumType::Sha256, ChecksumType::Sha512]);
+/// io::copy(&mut response, &mut writer)?;
+/// let (data, checksums) = writer.finish();
+/// assert_eq!(data, b"hello");
+/// assert_eq!(
+///     checksums[0],
+///     Checksum::Sha256("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_owned())
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChecksumWriter<W: io::Write> {
+    inner: W,
+    digests: Vec<DigestState>,
+}
+
+impl<W: io::Write> ChecksumWriter<W> {
+    pub fn new(inner: W, checksum_types: &[ChecksumType]) -> Self {
+        Self {
+            inner,
+            digests: checksum_types
+                .iter()
+                .map(|t| DigestState::new(*t))
+                .collect(),
+        }
+    }
 
-    loop {
-        let count = reader.read(&mut buffer)?;
-        if count == 0 {
-            break;
+    /// Include the data read from `reader` in the checksums without writing it to the inner writer, e.g. the
+    /// existing contents of a file which is being appended to.
+    pub(crate) fn update_from<R: Read>(&mut self, mut reader: R) -> io::Result<u64> {
+        let mut buf = [0u8; 8192];
+        let mut total = 0;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(total),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            for digest in &mut self.digests {
+                digest.update(&buf[..n]);
+            }
+            total += n as u64;
         }
-        hasher.update(&buffer[..count]);
     }
 
-    Ok(hex::encode(hasher.finalize().as_ref()))
+    /// Return the inner writer and the checksums, in the order of the types passed to [`ChecksumWriter::new`].
+    pub fn finish(self) -> (W, Vec<Checksum>) {
+        let checksums = self.digests.into_iter().map(DigestState::finish).collect();
+        (self.inner, checksums)
+    }
+}
+
+impl<W: io::Write> io::Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for digest in &mut self.digests {
+            digest.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Compute the checksum of all data read from `reader`.
+pub fn checksum_reader<R: Read>(
+    reader: R,
+    checksum_type: ChecksumType,
+) -> Result<Checksum, MetadataError> {
+    let mut checksums = checksums_reader(reader, &[checksum_type])?;
+    Ok(checksums.remove(0))
+}
+
+/// Compute checksums of several types of all data read from `reader` in a single pass, e.g. for mirrors
+/// which publish both sha256 and sha512 checksums.
+pub fn checksums_reader<R: Read>(
+    mut reader: R,
+    checksum_types: &[ChecksumType],
+) -> Result<Vec<Checksum>, MetadataError> {
+    let mut writer = ChecksumWriter::new(io::sink(), checksum_types);
+    io::copy(&mut reader, &mut writer)?;
+    Ok(writer.finish().1)
 }
 
 pub fn checksum_file(path: &Path, checksum_type: ChecksumType) -> Result<Checksum, MetadataError> {
-    let reader = Box::new(BufReader::new(File::open(path).unwrap())) as Box<dyn Read>;
-
-    let result = match checksum_type {
-        ChecksumType::Md5 => Checksum::Md5(get_digest::<md5::Md5>(reader)?),
-        ChecksumType::Sha1 => Checksum::Sha1(get_digest::<sha1::Sha1>(reader)?),
-        ChecksumType::Sha224 => Checksum::Sha224(get_digest::<sha2::Sha224>(reader)?),
-        ChecksumType::Sha256 => Checksum::Sha256(get_digest::<sha2::Sha256>(reader)?),
-        ChecksumType::Sha384 => Checksum::Sha384(get_digest::<sha2::Sha384>(reader)?),
-        ChecksumType::Sha512 => Checksum::Sha512(get_digest::<sha2::Sha512>(reader)?),
-        ChecksumType::Unknown => panic!("Cannot create digest using type Checksum::Unknown"),
-    };
+    checksum_reader(File::open(path)?, checksum_type)
+}
+
+/// Compute the checksums of many files, e.g. packages, using `threads` threads. With 0 threads, the number
+/// of threads is chosen based on the available parallelism.
+///
+/// The results are in the same order as `paths`.
+pub fn checksum_file_parallel<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    checksum_type: ChecksumType,
+    threads: usize,
+) -> Vec<Result<Checksum, MetadataError>> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(paths.len())
+    .max(1);
+
+    let next = AtomicUsize::new(0);
+    // MetadataError isn't necessarily Send, so the workers only deal in IO errors
+    let mut results: Vec<(usize, io::Result<Checksum>)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let path = match paths.get(index) {
+                            Some(path) => path,
+                            None => break,
+                        };
+                        let result = File::open(path.as_ref()).and_then(|mut file| {
+                            let mut writer = ChecksumWriter::new(io::sink(), &[checksum_type]);
+                            io::copy(&mut file, &mut writer)?;
+                            Ok(writer.finish().1.remove(0))
+                        });
+                        results.push((index, result));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("checksum worker panicked"))
+            .collect()
+    });
 
-    Ok(result)
+    results.sort_by_key(|(index, _)| *index);
+    results
+        .into_iter()
+        .map(|(_, result)| result.map_err(MetadataError::from))
+        .collect()
 }
-// TODO: not efficient to iterate the file twice
 
-pub fn checksum_inner_file(
+/// The checksum and size of a (possibly compressed) metadata file, and of its decompressed contents -
+/// the values which describe the file in `repomd.xml`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataFileDigest {
+    pub checksum: Checksum,
+    pub size: u64,
+    /// `None` if the file is not compressed
+    pub open_checksum: Option<Checksum>,
+    /// `None` if the file is not compressed
+    pub open_size: Option<u64>,
+}
+
+/// Compute the checksum and size of a metadata file, along with the checksum and size of its decompressed
+/// contents if it is compressed.
+///
+/// Useful for tools which add externally created metadata files to a repository, as `modifyrepo` does.
+///
+/// ```
+/// # use std::path::Path;
+/// # use rpmrepo_metadata::{utils, ChecksumType, MetadataError, RepomdRecord};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let path = Path::new("tests/assets/doctest_repo/repodata/updateinfo.xml");
+/// # let mut record = RepomdRecord::default();
+/// let digest = utils::digest_metadata_file(path, ChecksumType::Sha256)?;
+/// record.checksum = digest.checksum;
+/// record.open_checksum = digest.open_checksum;
+/// // the file isn't compressed
+/// assert!(record.open_checksum.is_none());
+/// # Ok(())
+/// # }
+/// ```
+pub fn digest_metadata_file(
     path: &Path,
     checksum_type: ChecksumType,
-) -> Result<Option<Checksum>, MetadataError> {
-    let (reader, format) = niffler::from_path(path)?;
+) -> Result<MetadataFileDigest, MetadataError> {
+    let mut writer = ChecksumWriter::new(io::sink(), &[checksum_type]);
+    let size = io::copy(&mut File::open(path)?, &mut writer)?;
+    let checksum = writer.finish().1.remove(0);
+
+    let (open_checksum, open_size) = match digest_inner_file(path, &[checksum_type])? {
+        Some((mut open_checksums, open_size)) => (Some(open_checksums.remove(0)), Some(open_size)),
+        None => (None, None),
+    };
+
+    Ok(MetadataFileDigest {
+        checksum,
+        size,
+        open_checksum,
+        open_size,
+    })
+}
+
+/// Compute the checksums and size of the decompressed contents of a file, in a single pass.
+///
+/// Returns `None` if the file is not compressed.
+fn digest_inner_file(
+    path: &Path,
+    checksum_types: &[ChecksumType],
+) -> Result<Option<(Vec<Checksum>, u64)>, MetadataError> {
+    let mut reader = match decompress::open_registered(path)? {
+        Some(reader) => reader,
+        None => match builtin_inner_reader(path)? {
+            Some(reader) => reader,
+            None => return Ok(None),
+        },
+    };
+
+    let mut writer = ChecksumWriter::new(io::sink(), checksum_types);
+    let size = io::copy(&mut reader, &mut writer)?;
+    Ok(Some((writer.finish().1, size)))
+}
 
+/// Open the decompressed contents of a file compressed with one of the built-in formats.
+#[cfg(feature = "compression")]
+fn builtin_inner_reader(path: &Path) -> Result<Option<Box<dyn io::Read>>, MetadataError> {
+    let (reader, format) = niffler::from_path(path)?;
     if format == niffler::Format::No {
         return Ok(None);
     }
+    Ok(Some(reader))
+}
 
-    let result = match checksum_type {
-        ChecksumType::Md5 => Checksum::Md5(get_digest::<md5::Md5>(reader)?),
-        ChecksumType::Sha1 => Checksum::Sha1(get_digest::<sha1::Sha1>(reader)?),
-        ChecksumType::Sha224 => Checksum::Sha224(get_digest::<sha2::Sha224>(reader)?),
-        ChecksumType::Sha256 => Checksum::Sha256(get_digest::<sha2::Sha256>(reader)?),
-        ChecksumType::Sha384 => Checksum::Sha384(get_digest::<sha2::Sha384>(reader)?),
-        ChecksumType::Sha512 => Checksum::Sha512(get_digest::<sha2::Sha512>(reader)?),
-        ChecksumType::Unknown => panic!("Cannot create digest using type Checksum::Unknown"),
-    };
+/// Without the `compression` feature, there are no built-in formats.
+#[cfg(not(feature = "compression"))]
+fn builtin_inner_reader(_path: &Path) -> Result<Option<Box<dyn io::Read>>, MetadataError> {
+    Ok(None)
+}
 
-    Ok(Some(result))
+pub fn checksum_inner_file(
+    path: &Path,
+    checksum_type: ChecksumType,
+) -> Result<Option<Checksum>, MetadataError> {
+    Ok(digest_inner_file(path, &[checksum_type])?.map(|(mut checksums, _)| checksums.remove(0)))
 }
 
 pub fn size_inner_file(path: &Path) -> Result<Option<u64>, MetadataError> {
-    let (reader, format) = niffler::from_path(path)?;
+    Ok(digest_inner_file(path, &[])?.map(|(_, size)| size))
+}
+
+/// Estimate the decompressed size of a (possibly compressed) file without decompressing it.
+///
+/// Uses the hints stored by the compression format where available - the `ISIZE` trailer of gzip
+/// files and the frame content size of zstd files. Uncompressed files return their actual size.
+/// Returns `None` if no hint is available, e.g. for xz and bz2 files.
+///
+/// The `open_size` of a [`crate::RepomdRecord`], where present, is more reliable than this estimate.
+pub fn estimated_decompressed_size(path: &Path) -> Result<Option<u64>, MetadataError> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+
+    // long enough for the largest possible zstd frame header
+    let mut header = Vec::with_capacity(18);
+    (&mut file).take(18).read_to_end(&mut header)?;
+
+    let estimate = if header.starts_with(&[0x1f, 0x8b]) {
+        // a gzip member consists of at least a 10 byte header and an 8 byte trailer
+        if file_size < 18 {
+            return Ok(None);
+        }
+        // ISIZE is the size of the uncompressed input modulo 2^32
+        let mut isize = [0u8; 4];
+        file.seek(SeekFrom::End(-4))?;
+        file.read_exact(&mut isize)?;
+        Some(u32::from_le_bytes(isize) as u64)
+    } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        zstd_frame_content_size(&header[4..])
+    } else if decompress::is_builtin_format(&header)
+        || decompress::is_registered_format(path, &header)
+    {
+        // xz, bz2 and registered formats
+        None
+    } else {
+        Some(file_size)
+    };
+
+    Ok(estimate)
+}
 
-    let inner_size = match format {
-        niffler::Format::No => None,
-        _ => Some(reader.bytes().into_iter().count() as u64),
+/// Whether a file is compressed in one of the supported formats, judging by its content. The position of
+/// `file` is restored afterwards.
+pub(crate) fn is_compressed_file(path: &Path, file: &mut File) -> Result<bool, MetadataError> {
+    let mut header = Vec::with_capacity(6);
+    (&mut *file).take(6).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(decompress::is_builtin_format(&header) || decompress::is_registered_format(path, &header))
+}
+
+/// Read the Frame_Content_Size field from a zstd frame header (following the magic number), if present.
+fn zstd_frame_content_size(header: &[u8]) -> Option<u64> {
+    let descriptor = *header.first()?;
+    let single_segment = descriptor & 0x20 != 0;
+
+    let fcs_len = match descriptor >> 6 {
+        0 if single_segment => 1,
+        0 => return None,
+        1 => 2,
+        2 => 4,
+        _ => 8,
     };
+    let dict_id_len = match descriptor & 0x03 {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        _ => 4,
+    };
+    let window_descriptor_len = if single_segment { 0 } else { 1 };
+
+    let start = 1 + window_descriptor_len + dict_id_len;
+    let field = header.get(start..start + fcs_len)?;
+    let mut bytes = [0u8; 8];
+    bytes[..fcs_len].copy_from_slice(field);
+    let size = u64::from_le_bytes(bytes);
 
-    Ok(inner_size)
+    // the 2-byte variant is stored with an offset of 256
+    Some(if fcs_len == 2 { size + 256 } else { size })
 }
 
 pub fn create_xml_reader<R: io::BufRead>(inner: R) -> quick_xml::Reader<R> {
@@ -90,20 +411,591 @@ pub fn create_xml_reader<R: io::BufRead>(inner: R) -> quick_xml::Reader<R> {
     reader
 }
 
+/// Decode the value of an attribute, borrowing it from the event buffer unless it contains escape sequences.
+pub(crate) fn decode_attribute<'a>(attr: &Attribute<'a>) -> Result<Cow<'a, str>, MetadataError> {
+    match attr.value {
+        Cow::Borrowed(bytes) if !bytes.contains(&b'&') => {
+            Ok(Cow::Borrowed(std::str::from_utf8(bytes)?))
+        }
+        _ => {
+            let bytes = attr.unescaped_value()?.into_owned();
+            Ok(Cow::Owned(
+                String::from_utf8(bytes).map_err(|e| e.utf8_error())?,
+            ))
+        }
+    }
+}
+
+/// The decoded value of the attribute `name` of a start tag, if present. See [`decode_attribute`].
+pub(crate) fn attribute_value<'a>(
+    tag: &'a BytesStart,
+    name: &'static str,
+) -> Result<Option<Cow<'a, str>>, MetadataError> {
+    match tag.try_get_attribute(name)? {
+        Some(attr) => Ok(Some(decode_attribute(&attr)?)),
+        None => Ok(None),
+    }
+}
+
+/// The decoded value of the attribute `name` of a start tag, failing with
+/// [`ParseError::MissingAttribute`] if it is not present.
+pub(crate) fn required_attribute<'a>(
+    tag: &'a BytesStart,
+    name: &'static str,
+) -> Result<Cow<'a, str>, MetadataError> {
+    attribute_value(tag, name)?.ok_or(ParseError::MissingAttribute(name).into())
+}
+
+/// The error for a metadata file which ends before the element `tag` is closed.
+pub(crate) fn unexpected_eof(tag: &[u8]) -> MetadataError {
+    quick_xml::Error::UnexpectedEof(String::from_utf8_lossy(tag).into_owned()).into()
+}
+
+/// The element which is being parsed, e.g. the package, for an element `tag` which must be nested in it.
+/// Malformed metadata may contain the child element without the parent.
+pub(crate) fn enclosing<'a, T>(
+    element: &'a mut Option<T>,
+    tag: &[u8],
+) -> Result<&'a mut T, MetadataError> {
+    element.as_mut().ok_or_else(|| {
+        ParseError::UnexpectedElement(String::from_utf8_lossy(tag).into_owned()).into()
+    })
+}
+
 pub fn create_xml_writer<W: io::Write + Send>(inner: W) -> quick_xml::Writer<W> {
     quick_xml::Writer::new_with_indent(inner, b' ', 2)
 }
 
+/// Check that a pre-serialized metadata fragment consists of a single `<package>` element, returning it
+/// without surrounding whitespace.
+///
+/// Only the outermost tags of the fragment are checked, the contents are trusted to be valid.
+pub(crate) fn raw_package_fragment(fragment: &str) -> Result<&str, MetadataError> {
+    let fragment = fragment.trim();
+    if !fragment.starts_with("<package") || !fragment.ends_with("</package>") {
+        return Err(ParseError::InconsistentMetadata(
+            "a raw package fragment must consist of a single <package> element".to_owned(),
+        )
+        .into());
+    }
+    Ok(fragment)
+}
+
+/// Write a pre-serialized `<package>` element, e.g. one copied verbatim from the metadata of another repository.
+pub(crate) fn write_raw_package<W: io::Write>(
+    writer: &mut quick_xml::Writer<W>,
+    fragment: &str,
+) -> Result<(), MetadataError> {
+    let fragment = raw_package_fragment(fragment)?;
+    // match the indentation of the packages written by the writer itself
+    let inner = writer.inner();
+    inner.write_all(b"\n  ")?;
+    inner.write_all(fragment.as_bytes())?;
+    Ok(())
+}
+
+/// Number of bytes reserved in resizable metadata headers for the package count to grow into.
+pub(crate) const PACKAGE_COUNT_PADDING: usize = 20;
+
+/// Append `padding` bytes of whitespace after the attributes of a start tag.
+pub(crate) fn pad_start_tag(tag: BytesStart, padding: usize) -> BytesStart<'static> {
+    let name_len = tag.name().as_ref().len();
+    let mut content = tag.to_vec();
+    content.resize(content.len() + padding, b' ');
+    BytesStart::owned(content, name_len)
+}
+
+/// Correct the package count in the header of an uncompressed primary.xml, filelists.xml or other.xml
+/// file, which was written with a resizable header (see `write_resizable_header()` on the metadata writers).
+///
+/// The file is modified in place, and afterwards the position is at the end of the file.
+pub fn rewrite_package_count<F: io::Read + io::Write + Seek>(
+    file: &mut F,
+    num_pkgs: usize,
+) -> Result<(), MetadataError> {
+    const ATTRIBUTE: &[u8] = b"packages=\"";
+
+    let mut header = Vec::with_capacity(1024);
+    file.seek(SeekFrom::Start(0))?;
+    io::Read::by_ref(file).take(1024).read_to_end(&mut header)?;
+
+    let start = header
+        .windows(ATTRIBUTE.len())
+        .position(|window| window == ATTRIBUTE)
+        .ok_or(ParseError::MissingHeader)?;
+    let end = header[start..]
+        .iter()
+        .position(|&b| b == b'>')
+        .map(|offset| start + offset)
+        .ok_or(ParseError::MissingHeader)?;
+
+    // the attribute is overwritten in place, the remaining space is filled with whitespace
+    let mut replacement = format!("packages=\"{}\"", num_pkgs).into_bytes();
+    if replacement.len() > end - start {
+        return Err(ParseError::InconsistentMetadata(
+            "not enough space in the metadata header to rewrite the package count".to_owned(),
+        )
+        .into());
+    }
+    replacement.resize(end - start, b' ');
+
+    file.seek(SeekFrom::Start(start as u64))?;
+    file.write_all(&replacement)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
+/// Open a (possibly compressed) metadata file for reading, decompressing it if necessary.
+///
+/// Registered [`crate::Decompressor`]s are tried before the built-in formats.
 pub fn reader_from_file(path: &Path) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    match decompress::open_registered(path)? {
+        Some(reader) => Ok(reader),
+        None => builtin_reader_from_file(path),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn builtin_reader_from_file(path: &Path) -> Result<Box<dyn io::Read + Send>, MetadataError> {
     let (compress_reader, _compression) = niffler::send::from_path(path)?;
     Ok(compress_reader)
 }
 
+/// Without the `compression` feature, only uncompressed metadata can be read.
+#[cfg(not(feature = "compression"))]
+fn builtin_reader_from_file(path: &Path) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+/// Open a metadata file for reading using memory-mapped IO if it is uncompressed.
+///
+/// Compressed files are read with [`reader_from_file`] instead, as the decompressor needs to copy the
+/// data anyway.
+///
+/// The file must not be modified or truncated while the returned reader is alive.
+#[cfg(feature = "mmap")]
+pub fn mmap_reader_from_file(path: &Path) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    if let Some(reader) = decompress::open_registered(path)? {
+        return Ok(reader);
+    }
+    let mut file = File::open(path)?;
+    let mut magic = Vec::with_capacity(6);
+    (&mut file).take(6).read_to_end(&mut magic)?;
+    if decompress::is_builtin_format(&magic) {
+        return builtin_reader_from_file(path);
+    }
+    mmap_reader(file)
+}
+
+/// Read an (uncompressed) file using memory-mapped IO.
+///
+/// The file must not be modified or truncated while the returned reader is alive.
+#[cfg(feature = "mmap")]
+pub fn mmap_reader(file: File) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    // zero-length files cannot be mapped
+    if file.metadata()?.len() == 0 {
+        return Ok(Box::new(io::empty()));
+    }
+    // SAFETY: the caller guarantees that the file is not modified while it is mapped
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    #[cfg(unix)]
+    let _ = map.advise(memmap2::Advice::Sequential);
+    Ok(Box::new(io::Cursor::new(map)))
+}
+
+/// Wrap a reader of (possibly compressed) metadata, decompressing it if necessary.
+///
+/// The compression type is detected from the content. Registered [`crate::Decompressor`]s are tried
+/// before the built-in formats.
+///
+/// Without the `compression` feature, content in the built-in formats is passed through unchanged.
+pub fn reader_from_reader<R: io::Read + Send + 'static>(
+    inner: R,
+) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    match decompress::decompress_registered(Box::new(inner))? {
+        (reader, true) => Ok(reader),
+        (reader, false) => builtin_reader_from_reader(reader),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn builtin_reader_from_reader(
+    inner: Box<dyn io::Read + Send>,
+) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    let (compress_reader, _compression) = niffler::send::get_reader(inner)?;
+    Ok(compress_reader)
+}
+
+#[cfg(not(feature = "compression"))]
+fn builtin_reader_from_reader(
+    inner: Box<dyn io::Read + Send>,
+) -> Result<Box<dyn io::Read + Send>, MetadataError> {
+    Ok(inner)
+}
+
+/// How invalid UTF-8 in metadata is handled when reading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodingPolicy {
+    /// Fail with an error.
+    #[default]
+    Strict,
+    /// Replace invalid sequences with U+FFFD (the Unicode replacement character).
+    Lossy,
+}
+
+const REPLACEMENT_CHARACTER: &[u8] = "\u{FFFD}".as_bytes();
+
+/// A reader which replaces invalid UTF-8 sequences with U+FFFD, the same way as [`String::from_utf8_lossy`].
+///
+/// Because the replacement happens before the XML is parsed, it applies to both text content and attributes.
+/// The number of replacements made is counted, so that they can be reported.
+pub struct LossyUtf8Reader<R: Read> {
+    inner: R,
+    pending: Vec<u8>,
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+    eof: bool,
+    replacements: Arc<AtomicUsize>,
+}
+
+impl<R: Read> LossyUtf8Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_counter(inner, Arc::new(AtomicUsize::new(0)))
+    }
+
+    /// Create a reader which adds the number of replacements it makes to `replacements`.
+    pub fn with_counter(inner: R, replacements: Arc<AtomicUsize>) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+            eof: false,
+            replacements,
+        }
+    }
+
+    /// The number of invalid sequences which have been replaced so far.
+    pub fn replacements(&self) -> usize {
+        self.replacements.load(Ordering::Relaxed)
+    }
+
+    fn decode_pending(&mut self) {
+        let mut input = &self.pending[..];
+        loop {
+            match std::str::from_utf8(input) {
+                Ok(valid) => {
+                    self.decoded.extend_from_slice(valid.as_bytes());
+                    input = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, rest) = input.split_at(e.valid_up_to());
+                    self.decoded.extend_from_slice(valid);
+                    match e.error_len() {
+                        Some(len) => {
+                            self.decoded.extend_from_slice(REPLACEMENT_CHARACTER);
+                            self.replacements.fetch_add(1, Ordering::Relaxed);
+                            input = &rest[len..];
+                        }
+                        // an incomplete sequence at the end of the input might be completed by the next read
+                        None if !self.eof => {
+                            input = rest;
+                            break;
+                        }
+                        None => {
+                            self.decoded.extend_from_slice(REPLACEMENT_CHARACTER);
+                            self.replacements.fetch_add(1, Ordering::Relaxed);
+                            input = &[];
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let consumed = self.pending.len() - input.len();
+        self.pending.drain(..consumed);
+    }
+}
+
+impl<R: Read> Read for LossyUtf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded_pos == self.decoded.len() {
+            if self.eof && self.pending.is_empty() {
+                return Ok(0);
+            }
+            self.decoded.clear();
+            self.decoded_pos = 0;
+            if !self.eof {
+                let mut chunk = [0u8; 8192];
+                let count = self.inner.read(&mut chunk)?;
+                if count == 0 {
+                    self.eof = true;
+                } else {
+                    self.pending.extend_from_slice(&chunk[..count]);
+                }
+            }
+            self.decode_pending();
+        }
+        let count = buf.len().min(self.decoded.len() - self.decoded_pos);
+        buf[..count].copy_from_slice(&self.decoded[self.decoded_pos..self.decoded_pos + count]);
+        self.decoded_pos += count;
+        Ok(count)
+    }
+}
+
+/// Limits applied while parsing metadata, so that a malicious or corrupt repository can't make the parser
+/// consume unbounded amounts of memory. The defaults are far above what legitimate repositories need.
+///
+/// - `max_depth` - The maximum nesting depth of XML elements. Defaults to 64.
+/// - `max_text_length` - The maximum length in bytes of a single text node, or of a single tag including its attributes. Defaults to 64 MiB.
+/// - `max_packages` - The maximum number of packages in primary.xml, filelists.xml and other.xml. Unlimited by default.
+///
+/// XML document type declarations are always rejected, as metadata never needs them and they are the
+/// basis of entity expansion attacks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+    pub max_depth: usize,
+    pub max_text_length: usize,
+    pub max_packages: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_text_length: 64 * 1024 * 1024,
+            max_packages: usize::MAX,
+        }
+    }
+}
+
+impl ParseLimits {
+    pub fn max_depth(self, depth: usize) -> Self {
+        Self {
+            max_depth: depth,
+            ..self
+        }
+    }
+
+    pub fn max_text_length(self, length: usize) -> Self {
+        Self {
+            max_text_length: length,
+            ..self
+        }
+    }
+
+    pub fn max_packages(self, count: usize) -> Self {
+        Self {
+            max_packages: count,
+            ..self
+        }
+    }
+}
+
+/// A violation of [`ParseLimits`] detected by [`XmlLimitReader`].
+///
+/// It is carried through the XML parser as an I/O error, and converted back into the corresponding
+/// [`ParseError`] variant when the parser error is converted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum XmlLimitError {
+    Depth(usize),
+    TextLength(usize),
+    Doctype,
+}
+
+impl std::fmt::Display for XmlLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", ParseError::from(*self))
+    }
+}
+
+impl std::error::Error for XmlLimitError {}
+
+impl From<XmlLimitError> for ParseError {
+    fn from(error: XmlLimitError) -> Self {
+        match error {
+            XmlLimitError::Depth(max) => ParseError::DepthLimit(max),
+            XmlLimitError::TextLength(max) => ParseError::TextLengthLimit(max),
+            XmlLimitError::Doctype => ParseError::Doctype,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum XmlLexState {
+    Text,
+    // after "<"
+    TagOpen,
+    Tag {
+        end_tag: bool,
+        quote: Option<u8>,
+        prev: u8,
+    },
+    // after "<!", until it is known whether this is a comment or CDATA section
+    Markup,
+    Comment,
+    CData,
+    Instruction,
+}
+
+/// A reader which enforces [`ParseLimits`] on the XML passing through it, before it reaches the parser.
+///
+/// Only the structure of the document is tracked - elements, text, comments, CDATA sections and
+/// processing instructions - which is enough to bound the nesting depth and the size of any one token.
+pub struct XmlLimitReader<R: Read> {
+    inner: R,
+    limits: ParseLimits,
+    state: XmlLexState,
+    depth: usize,
+    // length of the current text node or tag
+    token_length: usize,
+    markup: Vec<u8>,
+    // the last two bytes, to find the terminators of comments, CDATA sections and processing instructions
+    recent: [u8; 2],
+}
+
+impl<R: Read> XmlLimitReader<R> {
+    pub fn new(inner: R, limits: ParseLimits) -> Self {
+        Self {
+            inner,
+            limits,
+            state: XmlLexState::Text,
+            depth: 0,
+            token_length: 0,
+            markup: Vec::new(),
+            recent: [0; 2],
+        }
+    }
+
+    fn scan(&mut self, byte: u8) -> Result<(), XmlLimitError> {
+        self.token_length += 1;
+        self.state = match self.state {
+            XmlLexState::Text if byte == b'<' => {
+                self.token_length = 1;
+                XmlLexState::TagOpen
+            }
+            XmlLexState::Text => XmlLexState::Text,
+            XmlLexState::TagOpen => match byte {
+                b'/' => XmlLexState::Tag {
+                    end_tag: true,
+                    quote: None,
+                    prev: byte,
+                },
+                b'!' => {
+                    self.markup.clear();
+                    XmlLexState::Markup
+                }
+                b'?' => XmlLexState::Instruction,
+                _ => {
+                    self.depth += 1;
+                    if self.depth > self.limits.max_depth {
+                        return Err(XmlLimitError::Depth(self.limits.max_depth));
+                    }
+                    XmlLexState::Tag {
+                        end_tag: false,
+                        quote: None,
+                        prev: byte,
+                    }
+                }
+            },
+            XmlLexState::Tag {
+                end_tag,
+                quote: Some(quote),
+                ..
+            } => XmlLexState::Tag {
+                end_tag,
+                quote: if byte == quote { None } else { Some(quote) },
+                prev: byte,
+            },
+            XmlLexState::Tag {
+                end_tag,
+                quote: None,
+                prev,
+            } => match byte {
+                b'>' => {
+                    // end tags and empty-element tags both close an element
+                    if end_tag || prev == b'/' {
+                        self.depth = self.depth.saturating_sub(1);
+                    }
+                    self.token_length = 0;
+                    XmlLexState::Text
+                }
+                b'"' | b'\'' => XmlLexState::Tag {
+                    end_tag,
+                    quote: Some(byte),
+                    prev: byte,
+                },
+                _ => XmlLexState::Tag {
+                    end_tag,
+                    quote: None,
+                    prev: byte,
+                },
+            },
+            XmlLexState::Markup => {
+                self.markup.push(byte);
+                if self.markup == b"--" {
+                    XmlLexState::Comment
+                } else if self.markup == b"[CDATA[" {
+                    XmlLexState::CData
+                } else if b"--".starts_with(&self.markup) || b"[CDATA[".starts_with(&self.markup) {
+                    XmlLexState::Markup
+                } else {
+                    // <!DOCTYPE ...>, or one of the declarations which may only appear inside of it
+                    return Err(XmlLimitError::Doctype);
+                }
+            }
+            XmlLexState::Comment if self.recent == *b"--" && byte == b'>' => {
+                self.token_length = 0;
+                XmlLexState::Text
+            }
+            XmlLexState::CData if self.recent == *b"]]" && byte == b'>' => {
+                self.token_length = 0;
+                XmlLexState::Text
+            }
+            XmlLexState::Instruction if self.recent[1] == b'?' && byte == b'>' => {
+                self.token_length = 0;
+                XmlLexState::Text
+            }
+            state => state,
+        };
+        if self.token_length > self.limits.max_text_length {
+            return Err(XmlLimitError::TextLength(self.limits.max_text_length));
+        }
+        self.recent = [self.recent[1], byte];
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for XmlLimitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        for &byte in &buf[..count] {
+            self.scan(byte)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(count)
+    }
+}
+
 pub fn xml_reader_from_file(
     path: &Path,
 ) -> Result<quick_xml::Reader<BufReader<Box<dyn io::Read + Send>>>, MetadataError> {
     let compress_reader = reader_from_file(path)?;
-    Ok(create_xml_reader(BufReader::new(compress_reader)))
+    let limited_reader: Box<dyn io::Read + Send> =
+        Box::new(XmlLimitReader::new(compress_reader, ParseLimits::default()));
+    Ok(create_xml_reader(BufReader::new(limited_reader)))
+}
+
+/// Tuning for the compression of metadata files written to disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct EncoderSettings {
+    /// The compression level, if not the default of 9.
+    pub level: Option<u32>,
+    /// Whether to enable zstd long distance matching.
+    pub long_distance_matching: bool,
+    /// The number of worker threads for zstd and xz, 0 or 1 for single-threaded compression.
+    pub threads: u32,
 }
 
 // TODO: maybe split this up so that it just configures the writer, but takes a Box<dyn Write> which can be pre-configured with compression
@@ -111,7 +1003,15 @@ pub fn xml_writer_for_path(
     path: &Path,
     compression: CompressionType,
 ) -> Result<(PathBuf, quick_xml::Writer<Box<dyn io::Write + Send>>), MetadataError> {
-    let (filename, inner_writer) = writer_to_file(path, compression)?;
+    xml_writer_for_path_with(path, compression, EncoderSettings::default())
+}
+
+pub(crate) fn xml_writer_for_path_with(
+    path: &Path,
+    compression: CompressionType,
+    settings: EncoderSettings,
+) -> Result<(PathBuf, quick_xml::Writer<Box<dyn io::Write + Send>>), MetadataError> {
+    let (filename, inner_writer) = writer_to_file_with(path, compression, settings)?;
     let writer = create_xml_writer(inner_writer);
     Ok((filename, writer))
 }
@@ -128,17 +1028,388 @@ pub fn writer_to_file(
     path: &Path,
     compression: CompressionType,
 ) -> Result<(PathBuf, Box<dyn io::Write + Send>), MetadataError> {
+    writer_to_file_with(path, compression, EncoderSettings::default())
+}
+
+#[cfg(feature = "compression")]
+pub(crate) fn writer_to_file_with(
+    path: &Path,
+    compression: CompressionType,
+    settings: EncoderSettings,
+) -> Result<(PathBuf, Box<dyn io::Write + Send>), MetadataError> {
+    let level = settings.level.unwrap_or(9);
+    let max_level = match compression {
+        CompressionType::None => u32::MAX,
+        CompressionType::Gzip | CompressionType::Xz | CompressionType::Bz2 => 9,
+        CompressionType::Zstd => 22,
+    };
+    if level < 1 || level > max_level {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "compression level {} is not supported for {:?}, the maximum is {}",
+                level, compression, max_level
+            ),
+        )
+        .into());
+    }
+
     let filename = apply_compression_suffix(path, compression);
-    let format = match compression {
-        CompressionType::None => niffler::send::compression::Format::No,
-        CompressionType::Gzip => niffler::send::compression::Format::Gzip,
-        CompressionType::Xz => niffler::send::compression::Format::Lzma,
-        CompressionType::Bz2 => niffler::send::compression::Format::Bzip,
-        CompressionType::Zstd => niffler::send::compression::Format::Zstd,
+    let file = io::BufWriter::new(File::create(&filename)?);
+    // the encoders finish the compressed stream when dropped
+    let writer: Box<dyn io::Write + Send> = match compression {
+        CompressionType::None => Box::new(file),
+        CompressionType::Gzip => Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::new(level),
+        )),
+        CompressionType::Xz if settings.threads > 1 => {
+            let stream = xz2::stream::MtStreamBuilder::new()
+                .threads(settings.threads)
+                .preset(level)
+                .check(xz2::stream::Check::Crc64)
+                .encoder()
+                .map_err(io::Error::from)?;
+            Box::new(xz2::write::XzEncoder::new_stream(file, stream))
+        }
+        CompressionType::Xz => Box::new(xz2::write::XzEncoder::new(file, level)),
+        CompressionType::Bz2 => Box::new(bzip2::write::BzEncoder::new(
+            file,
+            bzip2::Compression::new(level),
+        )),
+        CompressionType::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(file, level as i32)?;
+            if settings.long_distance_matching {
+                encoder.long_distance_matching(true)?;
+            }
+            if settings.threads > 1 {
+                encoder.multithread(settings.threads)?;
+            }
+            Box::new(encoder.auto_finish())
+        }
     };
-    let writer = niffler::send::to_path(&filename, format, niffler::Level::Nine)?;
     Ok((filename, writer))
 }
 
+/// Without the `compression` feature, only uncompressed metadata can be written.
+#[cfg(not(feature = "compression"))]
+pub(crate) fn writer_to_file_with(
+    path: &Path,
+    compression: CompressionType,
+    _settings: EncoderSettings,
+) -> Result<(PathBuf, Box<dyn io::Write + Send>), MetadataError> {
+    if compression != CompressionType::None {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "compressed metadata requires the \"compression\" feature",
+        )
+        .into());
+    }
+    let filename = apply_compression_suffix(path, compression);
+    let writer = Box::new(std::io::BufWriter::new(File::create(&filename)?));
+    Ok((filename, writer))
+}
+
+/// Extract the ID of the signing key from an OpenPGP signature packet, such as those found in the
+/// RSA, PGP, DSA and GPG tags of an RPM signature header.
+///
+/// Both v3 and v4 signatures are supported. For v4 signatures, the key ID is taken from the issuer
+/// subpacket, or the issuer fingerprint subpacket if there is no issuer subpacket.
+pub fn pgp_signature_key_id(signature: &[u8]) -> Option<u64> {
+    let (&packet_tag, rest) = signature.split_first()?;
+    if packet_tag & 0x80 == 0 {
+        return None;
+    }
+
+    let (tag, body) = if packet_tag & 0x40 != 0 {
+        // new format packet header
+        let (len, rest) = pgp_length(rest, false)?;
+        (packet_tag & 0x3f, rest.get(..len)?)
+    } else {
+        // old format packet header
+        let (len, rest) = match packet_tag & 0x03 {
+            0 => (*rest.first()? as usize, &rest[1..]),
+            1 => (
+                u16::from_be_bytes(rest.get(..2)?.try_into().ok()?) as usize,
+                &rest[2..],
+            ),
+            2 => (
+                u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize,
+                &rest[4..],
+            ),
+            _ => (rest.len(), rest),
+        };
+        ((packet_tag >> 2) & 0x0f, rest.get(..len)?)
+    };
+
+    // only signature packets are of interest
+    if tag != 2 {
+        return None;
+    }
+
+    match *body.first()? {
+        3 => Some(u64::from_be_bytes(body.get(7..15)?.try_into().ok()?)),
+        4 => {
+            let hashed_len = u16::from_be_bytes(body.get(4..6)?.try_into().ok()?) as usize;
+            let hashed = body.get(6..6 + hashed_len)?;
+            let rest = &body[6 + hashed_len..];
+            let unhashed_len = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?) as usize;
+            let unhashed = rest.get(2..2 + unhashed_len)?;
+            pgp_issuer_key_id(hashed).or_else(|| pgp_issuer_key_id(unhashed))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a new format packet length or a signature subpacket length
+fn pgp_length(data: &[u8], subpacket: bool) -> Option<(usize, &[u8])> {
+    let (&first, rest) = data.split_first()?;
+    match first {
+        0..=191 => Some((first as usize, rest)),
+        192..=223 => {
+            let (&second, rest) = rest.split_first()?;
+            Some((
+                (((first as usize) - 192) << 8) + second as usize + 192,
+                rest,
+            ))
+        }
+        255 => Some((
+            u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize,
+            &rest[4..],
+        )),
+        // subpackets have no partial lengths, so the two octet range is larger
+        224..=254 if subpacket => {
+            let (&second, rest) = rest.split_first()?;
+            Some((
+                (((first as usize) - 192) << 8) + second as usize + 192,
+                rest,
+            ))
+        }
+        // partial body lengths aren't used for signature packets
+        _ => None,
+    }
+}
+
+fn pgp_issuer_key_id(mut subpackets: &[u8]) -> Option<u64> {
+    let mut fingerprint_key_id = None;
+    while !subpackets.is_empty() {
+        let (len, rest) = pgp_length(subpackets, true)?;
+        let subpacket = rest.get(..len)?;
+        subpackets = &rest[len..];
+
+        let (&subpacket_type, data) = match subpacket.split_first() {
+            Some(split) => split,
+            None => continue,
+        };
+        // the high bit marks the subpacket as critical
+        match subpacket_type & 0x7f {
+            // issuer
+            16 => return Some(u64::from_be_bytes(data.get(..8)?.try_into().ok()?)),
+            // issuer fingerprint - a version byte followed by the fingerprint, which ends with the key ID
+            33 if data.len() >= 9 => {
+                fingerprint_key_id =
+                    Some(u64::from_be_bytes(data[data.len() - 8..].try_into().ok()?));
+            }
+            _ => (),
+        }
+    }
+    fingerprint_key_id
+}
+
+/// Create a symlink at `link` pointing to the directory `target`.
+#[cfg(unix)]
+pub(crate) fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symlink at `link` pointing to the directory `target`.
+///
+/// Not supported elsewhere, as directory symlinks can't be atomically renamed over one another on Windows.
+#[cfg(not(unix))]
+pub(crate) fn symlink_dir(_target: &Path, link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot create symlink {}", link.display()),
+    ))
+}
+
+/// Whether a relative path stays within the directory it is relative to, i.e. it is not absolute and
+/// `..` components never climb above the starting point.
+pub(crate) fn path_is_contained(path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// Deduplicates strings which repeat across many packages, such as architectures, licenses and vendors, so
+/// that every package with the same value shares one allocation.
+#[derive(Debug, Default)]
+pub(crate) struct StringInterner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl StringInterner {
+    pub(crate) fn intern(&mut self, value: String) -> Arc<str> {
+        if let Some(existing) = self.strings.get(value.as_str()) {
+            return existing.clone();
+        }
+        let value: Arc<str> = value.into();
+        self.strings.insert(value.clone());
+        value
+    }
+}
+
+/// How package `location_href` values which could escape the repository are handled, i.e. absolute paths,
+/// paths with `..` components climbing above the repository root, and URLs.
+///
+/// Tools which download packages based on untrusted metadata, such as mirroring tools, could otherwise be
+/// made to write files outside of the destination directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HrefPolicy {
+    /// Fail with [`RepoLayoutError::UnsafeHref`].
+    #[default]
+    Reject,
+    /// Strip leading slashes and `.` components, and drop `..` components which would climb above the
+    /// repository root. URLs can't be sanitized and are rejected.
+    Sanitize,
+    /// Accept any `location_href`.
+    Allow,
+}
+
+/// Check `href` against `policy`, returning the sanitized href if it had to be changed.
+pub(crate) fn check_href(href: &str, policy: HrefPolicy) -> Result<Option<String>, MetadataError> {
+    let is_url = href.contains("://");
+    if policy == HrefPolicy::Allow || (!is_url && path_is_contained(Path::new(href))) {
+        return Ok(None);
+    }
+    if policy == HrefPolicy::Reject || is_url {
+        return Err(RepoLayoutError::UnsafeHref(href.to_owned()).into());
+    }
+
+    let mut components = Vec::new();
+    for component in href.split(['/', '\\']) {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return Err(RepoLayoutError::UnsafeHref(href.to_owned()).into());
+    }
+    Ok(Some(components.join("/")))
+}
+
+/// Whether a package architecture passes an arch filter. No filter (`None`) accepts every architecture.
+pub fn arch_matches(arches: Option<&[String]>, arch: &str) -> bool {
+    arches.map_or(true, |arches| arches.iter().any(|a| a == arch))
+}
+
+/// Match `text` against a shell-style glob `pattern`.
+///
+/// Supports `*` (any sequence of characters), `?` (any single character) and character classes
+/// such as `[abc]`, `[a-z]` and `[!abc]`. Unlike shell globbing, `*` also matches `/`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // position in the pattern after the most recent `*`, and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                '*' => {
+                    backtrack = Some((p + 1, t));
+                    p += 1;
+                    continue;
+                }
+                '?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                '[' => {
+                    if let Some((matched, class_end)) = match_char_class(&pattern[p..], text[t]) {
+                        if matched {
+                            p += class_end;
+                            t += 1;
+                            continue;
+                        }
+                    } else if text[t] == '[' {
+                        // unterminated class, treat '[' literally
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c if c == text[t] => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => (),
+            }
+        }
+
+        match backtrack {
+            Some((star_p, star_t)) => {
+                p = star_p;
+                t = star_t + 1;
+                backtrack = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match a single character against a character class at the start of `pattern`.
+///
+/// Returns whether the character matched and the length of the class, or `None` if the class is unterminated.
+fn match_char_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&e| e != ']') {
+            if pattern[i] <= c && c <= pattern[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if pattern[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
+        first = false;
+    }
+
+    None
+}
+
 #[cfg(feature = "read_rpm")]
 pub use crate::package::rpm_parsing::load_rpm_package;
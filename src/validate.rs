@@ -0,0 +1,543 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::metadata::{METADATA_FILELISTS, METADATA_OTHER, METADATA_PRIMARY};
+use crate::{
+    utils, Checksum, ChecksumType, FilelistsXml, HrefPolicy, MetadataError, MetadataSelection,
+    OtherXml, Package, PrimaryXml, RepoLayoutError, RepomdRecord, Repository, RepositoryReader,
+};
+
+/// A problem found while validating a repository.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationProblem {
+    /// A metadata file listed in `repomd.xml` does not exist.
+    MissingFile { record: String, path: String },
+    /// The checksum of a metadata file does not match `repomd.xml`.
+    ChecksumMismatch {
+        record: String,
+        expected: Checksum,
+        actual: Checksum,
+    },
+    /// The size of a metadata file does not match `repomd.xml`.
+    SizeMismatch {
+        record: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// A metadata file declares a different number of packages in its header than it contains.
+    PackageCountMismatch {
+        file: &'static str,
+        declared: usize,
+        actual: usize,
+    },
+    /// A package in filelists.xml or other.xml is not present in primary.xml.
+    UnknownPackage { file: &'static str, pkgid: String },
+    /// A package in primary.xml is not present in filelists.xml or other.xml.
+    MissingPackage { file: &'static str, pkgid: String },
+    /// A location href points outside of the repository.
+    HrefOutsideRepository { href: String },
+    /// A checksum has the wrong length for its type or isn't hexadecimal.
+    InvalidChecksum { pkgid: String, checksum: Checksum },
+    /// A package has no checksum.
+    MissingChecksum { package: String },
+    /// A package is stored under a different key than its pkgid.
+    PkgidMismatch { key: String, pkgid: String },
+    /// A package listed in primary.xml does not exist in the repository directory.
+    MissingPackageFile { href: String },
+    /// The size of a package file does not match primary.xml.
+    PackageSizeMismatch {
+        href: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// The checksum of a package file does not match primary.xml.
+    PackageChecksumMismatch {
+        href: String,
+        expected: Checksum,
+        actual: Checksum,
+    },
+    /// An RPM in the repository directory is not listed in primary.xml.
+    OrphanedPackageFile { href: String },
+    /// A metadata file does not conform to its XML schema, see [`crate::validate_schema`]. The position is
+    /// the byte offset in the uncompressed file just before the offending element or text.
+    SchemaViolation {
+        file: String,
+        position: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationProblem::MissingFile { record, path } => {
+                write!(f, "{} metadata file {} does not exist", record, path)
+            }
+            ValidationProblem::ChecksumMismatch {
+                record,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum of {} metadata is {:?}, repomd.xml lists {:?}",
+                record, actual, expected
+            ),
+            ValidationProblem::SizeMismatch {
+                record,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "size of {} metadata is {} bytes, repomd.xml lists {} bytes",
+                record, actual, expected
+            ),
+            ValidationProblem::PackageCountMismatch {
+                file,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "{} declares {} packages but contains {}",
+                file, declared, actual
+            ),
+            ValidationProblem::UnknownPackage { file, pkgid } => {
+                write!(f, "package {} in {} is not present in primary", pkgid, file)
+            }
+            ValidationProblem::MissingPackage { file, pkgid } => {
+                write!(f, "package {} in primary is not present in {}", pkgid, file)
+            }
+            ValidationProblem::HrefOutsideRepository { href } => {
+                write!(f, "location {} points outside of the repository", href)
+            }
+            ValidationProblem::InvalidChecksum { pkgid, checksum } => {
+                write!(
+                    f,
+                    "package {} has an invalid checksum {:?}",
+                    pkgid, checksum
+                )
+            }
+            ValidationProblem::MissingChecksum { package } => {
+                write!(f, "package {} has no checksum", package)
+            }
+            ValidationProblem::PkgidMismatch { key, pkgid } => {
+                write!(f, "package {} is stored under pkgid {}", pkgid, key)
+            }
+            ValidationProblem::MissingPackageFile { href } => {
+                write!(f, "package file {} does not exist", href)
+            }
+            ValidationProblem::PackageSizeMismatch {
+                href,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "size of package file {} is {} bytes, primary lists {} bytes",
+                href, actual, expected
+            ),
+            ValidationProblem::PackageChecksumMismatch {
+                href,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum of package file {} is {:?}, primary lists {:?}",
+                href, actual, expected
+            ),
+            ValidationProblem::OrphanedPackageFile { href } => {
+                write!(f, "package file {} is not listed in primary", href)
+            }
+            ValidationProblem::SchemaViolation {
+                file,
+                position,
+                message,
+            } => write!(f, "{} at byte {}: {}", file, position, message),
+        }
+    }
+}
+
+/// The result of validating a repository with [`Repository::validate`] or [`RepositoryReader::validate`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub problems: Vec<ValidationProblem>,
+}
+
+impl ValidationReport {
+    /// Whether no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Repository {
+    /// Check the repository for internal consistency.
+    ///
+    /// This checks that package checksums are valid, that packages are stored under their pkgid, and
+    /// that no location href points outside of the repository. Problems with the metadata files
+    /// themselves, such as checksums or package counts, can be found with [`RepositoryReader::validate`].
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        for record in self.repomd().records() {
+            validate_record_href(record, &mut report.problems);
+        }
+        for (key, package) in self.packages() {
+            validate_package(package, &mut report.problems);
+            if !package.pkgid().is_empty() && key != package.pkgid() {
+                report.problems.push(ValidationProblem::PkgidMismatch {
+                    key: key.clone(),
+                    pkgid: package.pkgid().to_owned(),
+                });
+            }
+        }
+
+        report
+    }
+}
+
+impl RepositoryReader {
+    /// Check the repository on disk for internal consistency.
+    ///
+    /// In addition to the checks of [`Repository::validate`], this verifies the checksums and sizes of
+    /// all metadata files listed in `repomd.xml`, that the package counts declared by primary.xml,
+    /// filelists.xml and other.xml match their contents, and that all three files contain the same packages.
+    ///
+    /// Metadata is streamed, only the pkgids of the packages are kept in memory. An error is returned if
+    /// the metadata cannot be read or parsed at all.
+    pub fn validate(&self) -> Result<ValidationReport, MetadataError> {
+        let mut report = ValidationReport::default();
+
+        let mut valid_files = HashSet::new();
+        for record in self.repomd().records() {
+            validate_record_href(record, &mut report.problems);
+            if validate_record_file(self.path(), record, &mut report.problems)? {
+                valid_files.insert(record.metadata_name.as_str());
+            }
+        }
+
+        // Packages can't be compared if primary.xml is missing
+        if !valid_files.contains(METADATA_PRIMARY) {
+            return Ok(report);
+        }
+
+        let mut primary_xml = PrimaryXml::new_reader(
+            self.open_xml_reader(METADATA_PRIMARY)?
+                .ok_or(RepoLayoutError::MissingRecord(METADATA_PRIMARY))?,
+        );
+        let declared = primary_xml.read_header()?;
+        let mut pkgids = HashSet::new();
+        let mut actual = 0;
+        loop {
+            let mut package = None;
+            primary_xml.read_package(&mut package)?;
+            let package = match package {
+                Some(package) => package,
+                None => break,
+            };
+            actual += 1;
+            validate_package(&package, &mut report.problems);
+            if !package.pkgid().is_empty() {
+                pkgids.insert(package.pkgid().to_owned());
+            }
+        }
+        if declared != actual {
+            report
+                .problems
+                .push(ValidationProblem::PackageCountMismatch {
+                    file: METADATA_PRIMARY,
+                    declared,
+                    actual,
+                });
+        }
+
+        if valid_files.contains(METADATA_FILELISTS) {
+            let mut filelists_xml = FilelistsXml::new_reader(
+                self.open_xml_reader(METADATA_FILELISTS)?
+                    .ok_or(RepoLayoutError::MissingRecord(METADATA_FILELISTS))?,
+            );
+            let declared = filelists_xml.read_header()?;
+            validate_package_ids(
+                METADATA_FILELISTS,
+                declared,
+                &pkgids,
+                |package| filelists_xml.read_package(package),
+                &mut report.problems,
+            )?;
+        }
+        if valid_files.contains(METADATA_OTHER) {
+            let mut other_xml = OtherXml::new_reader(
+                self.open_xml_reader(METADATA_OTHER)?
+                    .ok_or(RepoLayoutError::MissingRecord(METADATA_OTHER))?,
+            );
+            let declared = other_xml.read_header()?;
+            validate_package_ids(
+                METADATA_OTHER,
+                declared,
+                &pkgids,
+                |package| other_xml.read_package(package),
+                &mut report.problems,
+            )?;
+        }
+
+        Ok(report)
+    }
+
+    /// Check the package files in the repository directory against primary.xml, e.g. to audit a mirror.
+    ///
+    /// Packages which are missing, or whose size or checksum differ from primary.xml, are reported, as
+    /// are RPMs which aren't listed in primary.xml. Packages with a `location_base` are stored elsewhere
+    /// and are skipped. Checksums are computed using `threads` threads, with 0 the number of threads is
+    /// chosen based on the available parallelism.
+    pub fn verify_package_files(&self, threads: usize) -> Result<ValidationReport, MetadataError> {
+        let mut report = ValidationReport::default();
+
+        // only primary.xml is needed, and only the location, size and checksum of each package are kept
+        let selection = MetadataSelection {
+            filelists: false,
+            other: false,
+        };
+        let mut listed = HashSet::new();
+        let mut to_checksum: Vec<(ChecksumType, Vec<(String, Checksum)>)> = Vec::new();
+        for package in self.iter_packages_with(selection)? {
+            let package = package?;
+            if package.location_base().is_some() {
+                continue;
+            }
+            let href = package.location_href().to_owned();
+            listed.insert(href.clone());
+            if utils::check_href(&href, HrefPolicy::Reject).is_err() {
+                report
+                    .problems
+                    .push(ValidationProblem::HrefOutsideRepository { href });
+                continue;
+            }
+
+            let path = self.path().join(&href);
+            if !path.is_file() {
+                report
+                    .problems
+                    .push(ValidationProblem::MissingPackageFile { href });
+                continue;
+            }
+            let actual = path.metadata()?.len();
+            if actual != package.size_package() {
+                report
+                    .problems
+                    .push(ValidationProblem::PackageSizeMismatch {
+                        href,
+                        expected: package.size_package(),
+                        actual,
+                    });
+                continue;
+            }
+
+            if let Some(checksum) = package.checksum() {
+                let checksum_type = checksum.checksum_type();
+                let group = match to_checksum.iter().position(|(t, _)| *t == checksum_type) {
+                    Some(idx) => idx,
+                    None => {
+                        to_checksum.push((checksum_type, Vec::new()));
+                        to_checksum.len() - 1
+                    }
+                };
+                to_checksum[group].1.push((href, checksum.clone()));
+            }
+        }
+
+        for (checksum_type, packages) in to_checksum {
+            let paths: Vec<PathBuf> = packages
+                .iter()
+                .map(|(href, _)| self.path().join(href))
+                .collect();
+            let checksums = utils::checksum_file_parallel(&paths, checksum_type, threads);
+            for ((href, expected), actual) in packages.into_iter().zip(checksums) {
+                let actual = actual?;
+                if actual != expected {
+                    report
+                        .problems
+                        .push(ValidationProblem::PackageChecksumMismatch {
+                            href,
+                            expected,
+                            actual,
+                        });
+                }
+            }
+        }
+
+        let mut files = Vec::new();
+        find_rpm_files(self.path(), &mut files)?;
+        files.sort();
+        for path in files {
+            let href = path
+                .strip_prefix(self.path())
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if !listed.contains(&href) {
+                report
+                    .problems
+                    .push(ValidationProblem::OrphanedPackageFile { href });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Recursively collect the paths of the RPMs in `dir`.
+fn find_rpm_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), MetadataError> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_rpm_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rpm") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Check that the packages of filelists.xml or other.xml match those of primary.xml
+fn validate_package_ids<F>(
+    file: &'static str,
+    declared: usize,
+    primary_pkgids: &HashSet<String>,
+    mut read_package: F,
+    problems: &mut Vec<ValidationProblem>,
+) -> Result<(), MetadataError>
+where
+    F: FnMut(&mut Option<Package>) -> Result<bool, MetadataError>,
+{
+    let mut seen = HashSet::new();
+    let mut actual = 0;
+    loop {
+        let mut package = None;
+        if !read_package(&mut package)? {
+            break;
+        }
+        let pkgid = match package {
+            Some(package) => package.pkgid().to_owned(),
+            None => break,
+        };
+        if !primary_pkgids.contains(&pkgid) {
+            problems.push(ValidationProblem::UnknownPackage {
+                file,
+                pkgid: pkgid.clone(),
+            });
+        }
+        actual += 1;
+        seen.insert(pkgid);
+    }
+
+    if declared != actual {
+        problems.push(ValidationProblem::PackageCountMismatch {
+            file,
+            declared,
+            actual,
+        });
+    }
+    for pkgid in primary_pkgids {
+        if !seen.contains(pkgid) {
+            problems.push(ValidationProblem::MissingPackage {
+                file,
+                pkgid: pkgid.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_package(package: &Package, problems: &mut Vec<ValidationProblem>) {
+    match package.checksum() {
+        Some(checksum) if !checksum_is_valid(checksum) => {
+            problems.push(ValidationProblem::InvalidChecksum {
+                pkgid: package.pkgid().to_owned(),
+                checksum: checksum.clone(),
+            });
+        }
+        Some(_) => (),
+        None => problems.push(ValidationProblem::MissingChecksum {
+            package: package.nevra(),
+        }),
+    }
+    if utils::check_href(package.location_href(), HrefPolicy::Reject).is_err() {
+        problems.push(ValidationProblem::HrefOutsideRepository {
+            href: package.location_href().to_owned(),
+        });
+    }
+}
+
+fn validate_record_href(record: &RepomdRecord, problems: &mut Vec<ValidationProblem>) {
+    if !utils::path_is_contained(&record.location_href) {
+        problems.push(ValidationProblem::HrefOutsideRepository {
+            href: record.location_href.to_string_lossy().into_owned(),
+        });
+    }
+}
+
+/// Verify the size and checksum of a metadata file. Returns whether the file can be used for further checks.
+fn validate_record_file(
+    base: &Path,
+    record: &RepomdRecord,
+    problems: &mut Vec<ValidationProblem>,
+) -> Result<bool, MetadataError> {
+    let path = base.join(&record.location_href);
+    if !path.is_file() {
+        problems.push(ValidationProblem::MissingFile {
+            record: record.metadata_name.clone(),
+            path: path.display().to_string(),
+        });
+        return Ok(false);
+    }
+
+    let mut valid = true;
+    if let Some(expected) = record.size {
+        let actual = path.metadata()?.len();
+        if actual != expected {
+            problems.push(ValidationProblem::SizeMismatch {
+                record: record.metadata_name.clone(),
+                expected,
+                actual,
+            });
+            valid = false;
+        }
+    }
+
+    let checksum_type = record.checksum.checksum_type();
+    let actual = utils::checksum_file(&path, checksum_type)?;
+    if actual != record.checksum {
+        problems.push(ValidationProblem::ChecksumMismatch {
+            record: record.metadata_name.clone(),
+            expected: record.checksum.clone(),
+            actual,
+        });
+        valid = false;
+    }
+
+    Ok(valid)
+}
+
+fn checksum_is_valid(checksum: &Checksum) -> bool {
+    let digest = checksum.digest();
+    digest.len() == checksum.checksum_type().digest_len()
+        && digest.chars().all(|c| c.is_ascii_hexdigit())
+}
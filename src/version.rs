@@ -0,0 +1,104 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Parsing, formatting and comparison of RPM versions and NEVRAs, following the rules of `rpmvercmp()`.
+//!
+//! None of this depends on any repository metadata.
+//!
+//! ```
+//! # use std::cmp::Ordering;
+//! # use rpmrepo_metadata::version::{rpmvercmp, Nevra, EVR};
+//! # fn main() -> Result<(), rpmrepo_metadata::MetadataError> {
+//! let nevra = Nevra::parse("bash-0:5.1-2.fc35.x86_64")?;
+//! assert_eq!(nevra.name, "bash");
+//! assert!(nevra.evr < EVR::parse("5.1-10.fc35"));
+//! assert_eq!(rpmvercmp("1.0~rc1-1", "1.0-1"), Ordering::Less);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::common::split_nevra;
+use crate::{MetadataError, Package, ParseError};
+
+pub use crate::common::{rpmvercmp, EVR};
+
+/// The name, EVR and architecture of a package, e.g. `bash-0:5.1-2.fc35.x86_64`.
+///
+/// NEVRAs are equal if they compare as equal, following `rpmvercmp()`, so `foo-1.01-1.noarch` and
+/// `foo-1.1-1.noarch` are the same. `Nevra` doesn't implement `Hash` for that reason, use an ordered
+/// collection or the string form as a key instead.
+#[derive(Clone, Debug, Default)]
+pub struct Nevra {
+    pub name: String,
+    pub evr: EVR,
+    pub arch: String,
+}
+
+impl Nevra {
+    pub fn new(name: impl Into<String>, evr: EVR, arch: impl Into<String>) -> Self {
+        Nevra {
+            name: name.into(),
+            evr,
+            arch: arch.into(),
+        }
+    }
+
+    /// Parse a NEVRA string like `bash-0:5.1-2.fc35.x86_64`, or an NVRA without the epoch.
+    pub fn parse(nevra: &str) -> Result<Self, MetadataError> {
+        let (name, evr, arch) =
+            split_nevra(nevra).ok_or_else(|| ParseError::InvalidNevra(nevra.to_owned()))?;
+        Ok(Nevra::new(name, evr, arch))
+    }
+}
+
+impl FromStr for Nevra {
+    type Err = MetadataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Nevra::parse(s)
+    }
+}
+
+impl From<&Package> for Nevra {
+    fn from(package: &Package) -> Self {
+        Nevra::new(package.name(), package.evr().clone(), package.arch())
+    }
+}
+
+/// Formatted like the input it was parsed from: the epoch is only included if it is not empty.
+impl fmt::Display for Nevra {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}.{}", self.name, self.evr, self.arch)
+    }
+}
+
+impl PartialEq for Nevra {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Nevra {}
+
+impl PartialOrd for Nevra {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Ordered by name, then by EVR (following `rpmvercmp()`), then by architecture.
+impl Ord for Nevra {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name
+            .cmp(&other.name)
+            .then_with(|| self.evr.cmp(&other.evr))
+            .then_with(|| self.arch.cmp(&other.arch))
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::sync::Mutex;
+
+/// A data quality issue which doesn't prevent the metadata from being read, reported to a
+/// [`WarningHandler`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataWarning {
+    /// repomd.xml lists a record which this library doesn't parse, e.g. "group" or "modules". It is left
+    /// untouched.
+    UnknownRecord { name: String },
+    /// An element has an attribute which isn't part of the metadata format. It was ignored.
+    UnknownAttribute {
+        element: &'static str,
+        attribute: String,
+    },
+    /// Several packages share a pkgid, only the last one read was kept.
+    DuplicatePackage { pkgid: String, nevra: String },
+    /// Several advisories share an ID, only the last one read was kept.
+    DuplicateAdvisory { id: String },
+    /// The package counts declared by primary.xml, filelists.xml and other.xml disagree.
+    PackageCountMismatch {
+        primary: usize,
+        filelists: usize,
+        other: usize,
+    },
+    /// A package in primary.xml is missing from filelists.xml or other.xml, and was skipped. `pkgid` is
+    /// `None` if all remaining packages were skipped because `file` ran out of packages.
+    PackageSkipped {
+        pkgid: Option<String>,
+        file: &'static str,
+    },
+    /// Invalid UTF-8 sequences were replaced, see [`crate::DecodingPolicy::Lossy`].
+    InvalidUtf8Replaced { count: usize },
+    /// Loading the repository is estimated to use more memory than the budget allows.
+    MemoryBudgetExceeded { estimate: u64, budget: u64 },
+}
+
+impl fmt::Display for MetadataWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataWarning::UnknownRecord { name } => {
+                write!(f, "metadata record of unknown type: {}", name)
+            }
+            MetadataWarning::UnknownAttribute { element, attribute } => {
+                write!(f, "ignored unknown attribute {} of <{}>", attribute, element)
+            }
+            MetadataWarning::DuplicatePackage { pkgid, nevra } => write!(
+                f,
+                "package {} with pkgid {} is listed more than once",
+                nevra, pkgid
+            ),
+            MetadataWarning::DuplicateAdvisory { id } => {
+                write!(f, "advisory {} is listed more than once", id)
+            }
+            MetadataWarning::PackageCountMismatch {
+                primary,
+                filelists,
+                other,
+            } => write!(
+                f,
+                "metadata package counts don't match: primary {}, filelists {}, other {}",
+                primary, filelists, other
+            ),
+            MetadataWarning::PackageSkipped {
+                pkgid: Some(pkgid),
+                file,
+            } => write!(f, "skipped package {} missing from {}", pkgid, file),
+            MetadataWarning::PackageSkipped { pkgid: None, file } => {
+                write!(f, "skipped the remaining packages, {} has no more", file)
+            }
+            MetadataWarning::InvalidUtf8Replaced { count } => {
+                write!(f, "replaced {} invalid UTF-8 sequences", count)
+            }
+            MetadataWarning::MemoryBudgetExceeded { estimate, budget } => write!(
+                f,
+                "loading the repository is estimated to require {} bytes of memory, exceeding the budget of {} bytes",
+                estimate, budget
+            ),
+        }
+    }
+}
+
+/// Receives [`MetadataWarning`]s from a [`crate::RepositoryReader`], [`crate::PackageIterator`] or
+/// [`crate::PrimaryXmlReader`], e.g. to surface data quality issues to users.
+///
+/// Any `Fn(&MetadataWarning)` closure is a handler. To inspect the warnings afterwards instead, use a
+/// [`WarningCollector`].
+///
+/// ```
+/// # use std::path::Path;
+/// # use std::sync::Arc;
+/// # use rpmrepo_metadata::{MetadataError, MetadataWarning, RepositoryReader};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let mut reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// reader.set_warning_handler(Arc::new(|warning: &MetadataWarning| {
+///     eprintln!("warning: {}", warning);
+/// }));
+/// # Ok(())
+/// # }
+/// ```
+pub trait WarningHandler: Send + Sync {
+    fn on_warning(&self, warning: &MetadataWarning);
+}
+
+impl<F> WarningHandler for F
+where
+    F: Fn(&MetadataWarning) + Send + Sync,
+{
+    fn on_warning(&self, warning: &MetadataWarning) {
+        self(warning)
+    }
+}
+
+/// A [`WarningHandler`] which keeps every warning it receives.
+///
+/// ```
+/// # use std::path::Path;
+/// # use std::sync::Arc;
+/// # use rpmrepo_metadata::{MetadataError, RepositoryReader, WarningCollector};
+/// # fn main() -> Result<(), MetadataError> {
+/// # let mut reader = RepositoryReader::new_from_directory(Path::new("tests/assets/doctest_repo"))?;
+/// let warnings = Arc::new(WarningCollector::new());
+/// reader.set_warning_handler(warnings.clone());
+/// let repo = reader.into_repo()?;
+/// for warning in warnings.take() {
+///     println!("warning: {}", warning);
+/// }
+/// # assert_eq!(repo.packages().len(), 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct WarningCollector {
+    warnings: Mutex<Vec<MetadataWarning>>,
+}
+
+impl WarningCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The warnings received so far.
+    pub fn warnings(&self) -> Vec<MetadataWarning> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    /// Remove and return the warnings received so far.
+    pub fn take(&self) -> Vec<MetadataWarning> {
+        std::mem::take(&mut *self.warnings.lock().unwrap())
+    }
+}
+
+impl WarningHandler for WarningCollector {
+    fn on_warning(&self, warning: &MetadataWarning) {
+        self.warnings.lock().unwrap().push(warning.clone());
+    }
+}
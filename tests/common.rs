@@ -5,7 +5,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use once_cell::sync::Lazy;
-use rpmrepo_metadata::{Checksum, FileType, Package, Requirement, EVR};
+use rpmrepo_metadata::{Checksum, FileType, Package, Requirement, RequirementType, EVR};
 
 // const FIXTURE_FILELIST_PATH: &str = "./tests/assets/complex_repo/repodata/filelists.xml.gz";
 
@@ -41,65 +41,65 @@ pub static COMPLEX_PACKAGE: Lazy<Package> = Lazy::new(|| {
 
     package.set_provides(vec![
         Requirement {
-            name: "/usr/bin/ls".to_owned(),
+            name: "/usr/bin/ls".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "complex-package".to_owned(),
-            flags: Some("EQ".to_owned()),
+            name: "complex-package".into(),
+            flags: Some(RequirementType::EQ),
             epoch: Some("1".to_owned()),
             version: Some("2.3.4".to_owned()),
             release: Some("5.el8".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "complex-package(x86-64)".to_owned(),
-            flags: Some("EQ".to_owned()),
+            name: "complex-package(x86-64)".into(),
+            flags: Some(RequirementType::EQ),
             epoch: Some("1".to_owned()),
             version: Some("2.3.4".to_owned()),
             release: Some("5.el8".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "laughter".to_owned(),
-            flags: Some("EQ".to_owned()),
+            name: "laughter".into(),
+            flags: Some(RequirementType::EQ),
             epoch: Some("0".to_owned()),
             version: Some("33".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "narration(ronhoward)".to_owned(),
+            name: "narration(ronhoward)".into(),
             ..Requirement::default()
         },
     ]);
     package.set_requires(vec![
         Requirement {
-            name: "/usr/bin/bash".to_owned(),
+            name: "/usr/bin/bash".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "/usr/sbin/useradd".to_owned(),
+            name: "/usr/sbin/useradd".into(),
             preinstall: true,
             ..Requirement::default()
         },
         Requirement {
-            name: "arson".to_owned(),
-            flags: Some("GE".to_owned()),
+            name: "arson".into(),
+            flags: Some(RequirementType::GE),
             epoch: Some("0".to_owned()),
             version: Some("1.0.0".to_owned()),
             release: Some("1".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "fur".to_owned(),
-            flags: Some("LE".to_owned()),
+            name: "fur".into(),
+            flags: Some(RequirementType::LE),
             epoch: Some("0".to_owned()),
             version: Some("2".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "staircar".to_owned(),
-            flags: Some("LE".to_owned()),
+            name: "staircar".into(),
+            flags: Some(RequirementType::LE),
             epoch: Some("0".to_owned()),
             version: Some("99.1".to_owned()),
             release: Some("3".to_owned()),
@@ -108,24 +108,24 @@ pub static COMPLEX_PACKAGE: Lazy<Package> = Lazy::new(|| {
     ]);
 
     package.set_conflicts(vec![Requirement {
-        name: "foxnetwork".to_owned(),
-        flags: Some("GT".to_owned()),
+        name: "foxnetwork".into(),
+        flags: Some(RequirementType::GT),
         epoch: Some("0".to_owned()),
         version: Some("5555".to_owned()),
         ..Requirement::default()
     }]);
     package.set_obsoletes(vec![
         Requirement {
-            name: "bluemangroup".to_owned(),
-            flags: Some("LT".to_owned()),
+            name: "bluemangroup".into(),
+            flags: Some(RequirementType::LT),
             epoch: Some("0".to_owned()),
             version: Some("32.1".to_owned()),
             release: Some("0".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "cornballer".to_owned(),
-            flags: Some("LT".to_owned()),
+            name: "cornballer".into(),
+            flags: Some(RequirementType::LT),
             epoch: Some("0".to_owned()),
             version: Some("444".to_owned()),
             ..Requirement::default()
@@ -133,34 +133,34 @@ pub static COMPLEX_PACKAGE: Lazy<Package> = Lazy::new(|| {
     ]);
     package.set_suggests(vec![
         Requirement {
-            name: "(bobloblaw >= 1.1 if maritimelaw else anyone < 0.5.1-2)".to_owned(),
+            name: "(bobloblaw >= 1.1 if maritimelaw else anyone < 0.5.1-2)".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "(dove and return)".to_owned(),
+            name: "(dove and return)".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "(job or money > 9000)".to_owned(),
+            name: "(job or money > 9000)".into(),
             ..Requirement::default()
         },
     ]);
     package.set_enhances(vec![Requirement {
-        name: "(bananas or magic)".to_owned(),
+        name: "(bananas or magic)".into(),
         ..Requirement::default()
     }]);
     package.set_recommends(vec![
         Requirement {
-            name: "((hiding and attic) if light-treason)".to_owned(),
+            name: "((hiding and attic) if light-treason)".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "GeneParmesan(PI)".to_owned(),
+            name: "GeneParmesan(PI)".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "yacht".to_owned(),
-            flags: Some("GT".to_owned()),
+            name: "yacht".into(),
+            flags: Some(RequirementType::GT),
             epoch: Some("9".to_owned()),
             version: Some("11.0".to_owned()),
             release: Some("0".to_owned()),
@@ -169,12 +169,12 @@ pub static COMPLEX_PACKAGE: Lazy<Package> = Lazy::new(|| {
     ]);
     package.set_supplements(vec![
         Requirement {
-            name: "((hiding and illusion) unless alliance-of-magicians)".to_owned(),
+            name: "((hiding and illusion) unless alliance-of-magicians)".into(),
             ..Requirement::default()
         },
         Requirement {
-            name: "comedy".to_owned(),
-            flags: Some("EQ".to_owned()),
+            name: "comedy".into(),
+            flags: Some(RequirementType::EQ),
             epoch: Some("0".to_owned()),
             version: Some("11.1".to_owned()),
             release: Some("4".to_owned()),
@@ -236,8 +236,8 @@ within text content, and not all XML libraries do so. However, it is generally r
     package.set_rpm_header_range(4504, 6445);
 
     package.set_provides(vec![Requirement {
-        name: "rpm-with-invalid-chars".to_owned(),
-        flags: Some("EQ".to_owned()),
+        name: "rpm-with-invalid-chars".into(),
+        flags: Some(RequirementType::EQ),
         epoch: Some("0".to_owned()),
         version: Some("1".to_owned()),
         release: Some("1.fc33".to_owned()),
@@ -272,16 +272,16 @@ pub static RPM_EMPTY: Lazy<Package> = Lazy::new(|| {
 
     package.set_provides(vec![
         Requirement {
-            name: "rpm-empty".to_owned(),
-            flags: Some("EQ".to_owned()),
+            name: "rpm-empty".into(),
+            flags: Some(RequirementType::EQ),
             epoch: Some("0".to_owned()),
             version: Some("0".to_owned()),
             release: Some("0".to_owned()),
             ..Requirement::default()
         },
         Requirement {
-            name: "rpm-empty(x86-64)".to_owned(),
-            flags: Some("EQ".to_owned()),
+            name: "rpm-empty(x86-64)".into(),
+            flags: Some(RequirementType::EQ),
             epoch: Some("0".to_owned()),
             version: Some("0".to_owned()),
             release: Some("0".to_owned()),
@@ -332,8 +332,8 @@ See: http://www.unicode.org/charts/"##,
     package.set_rpm_header_range(4504, 6389);
 
     package.set_provides(vec![Requirement {
-        name: "rpm-with-non-ascii".to_owned(),
-        flags: Some("EQ".to_owned()),
+        name: "rpm-with-non-ascii".into(),
+        flags: Some(RequirementType::EQ),
         epoch: Some("0".to_owned()),
         version: Some("1".to_owned()),
         release: Some("1.fc33".to_owned()),
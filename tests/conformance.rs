@@ -0,0 +1,44 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+extern crate rpmrepo_metadata;
+
+use rpmrepo_metadata::conformance::ConformanceCheck;
+use rpmrepo_metadata::*;
+use std::path::Path;
+use tempdir::TempDir;
+
+const PACKAGES_DIR: &str = "./tests/assets/packages/";
+
+#[test]
+fn test_conformance_without_createrepo_c() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_conformance_without_createrepo_c")?;
+    let report = ConformanceCheck::new(Path::new(PACKAGES_DIR))
+        .createrepo_c(Path::new("/nonexistent/createrepo_c"))
+        .run(tmp_dir.path())?;
+    assert!(report.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_conformance() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_conformance")?;
+    let report = match ConformanceCheck::new(Path::new(PACKAGES_DIR)).run(tmp_dir.path())? {
+        Some(report) => report,
+        None => return Ok(()), // createrepo_c isn't installed
+    };
+
+    for divergence in &report.divergences {
+        eprintln!(
+            "{} {:?}:\n  ours:         {}\n  createrepo_c: {}",
+            divergence.location_href, divergence.field, divergence.ours, divergence.createrepo_c
+        );
+    }
+    assert!(report.packages_compared > 0);
+    assert!(report.only_ours.is_empty());
+    assert!(report.only_createrepo_c.is_empty());
+    Ok(())
+}
@@ -0,0 +1,365 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    DependencyIndex, FileType, MetadataError, Package, ParseError, Requirement, RequirementType,
+    RichDependency, EVR,
+};
+
+mod common;
+
+fn provider_names(providers: Vec<&Package>) -> Vec<&str> {
+    providers.iter().map(|p| p.name()).collect()
+}
+
+#[test]
+fn test_whatprovides() -> Result<(), MetadataError> {
+    let packages = [&*common::COMPLEX_PACKAGE, &*common::RPM_EMPTY];
+    let index = DependencyIndex::from_packages(packages);
+
+    assert_eq!(index.packages().len(), 2);
+
+    // plain capabilities, versioned capabilities and files
+    assert_eq!(
+        provider_names(index.whatprovides("narration(ronhoward)")?),
+        vec!["complex-package"]
+    );
+    assert_eq!(
+        provider_names(index.whatprovides("laughter >= 30")?),
+        vec!["complex-package"]
+    );
+    assert!(index.whatprovides("laughter < 33")?.is_empty());
+    assert_eq!(
+        provider_names(index.whatprovides("complex-package = 1:2.3.4-5.el8")?),
+        vec!["complex-package"]
+    );
+    assert!(index.whatprovides("complex-package = 2.3.4")?.is_empty());
+    assert_eq!(
+        provider_names(index.whatprovides("/usr/bin/complex_a")?),
+        vec!["complex-package"]
+    );
+    assert!(index.whatprovides("/usr/bin/nonexistent")?.is_empty());
+
+    // rich dependencies
+    assert_eq!(
+        provider_names(index.whatprovides("(rpm-empty or laughter)")?),
+        vec!["complex-package", "rpm-empty"]
+    );
+    assert_eq!(
+        provider_names(index.whatprovides("(laughter with narration(ronhoward))")?),
+        vec!["complex-package"]
+    );
+    assert!(index
+        .whatprovides("(rpm-empty(x86-64) and laughter)")?
+        .is_empty());
+    assert_eq!(
+        provider_names(index.whatprovides("(rpm-empty if laughter else (laughter > 0:1))")?),
+        vec!["complex-package", "rpm-empty"]
+    );
+
+    assert!(matches!(
+        index.whatprovides("(rpm-empty and laughter or arson)"),
+        Err(MetadataError::Parse(ParseError::InvalidDependency(_, _)))
+    ));
+    assert!(matches!(
+        index.whatprovides("(rpm-empty"),
+        Err(MetadataError::Parse(ParseError::InvalidDependency(_, _)))
+    ));
+
+    let requirement = Requirement {
+        name: "rpm-empty".into(),
+        flags: Some(RequirementType::GE),
+        epoch: Some("0".to_owned()),
+        version: Some("0".to_owned()),
+        ..Requirement::default()
+    };
+    assert_eq!(
+        provider_names(index.whatprovides_requirement(&requirement)?),
+        vec!["rpm-empty"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_normalize_dependencies() {
+    let require = |name: &str| Requirement {
+        name: name.into(),
+        ..Requirement::default()
+    };
+
+    let mut package = Package::default();
+    package.add_file(FileType::File, "/usr/bin/foo");
+    package.add_file(FileType::File, "/usr/share/foo/data");
+    package.set_requires(vec![
+        require("libc.so.6(GLIBC_2.4)(64bit)"),
+        require("rpmlib(CompressedFileNames)"),
+        require("bar"),
+        require("/usr/bin/foo"),
+        require("/usr/share/foo/data"),
+        require("libc.so.6(GLIBC_2.14)(64bit)"),
+        require("bar"),
+        require("libc.so.6(GLIBC_2.2.5)(64bit)"),
+        require("baz"),
+    ]);
+
+    package.normalize_dependencies();
+
+    let names: Vec<&str> = package.requires().iter().map(|r| &*r.name).collect();
+    assert_eq!(
+        names,
+        vec![
+            "bar",
+            "/usr/share/foo/data",
+            "baz",
+            "libc.so.6(GLIBC_2.14)(64bit)"
+        ]
+    );
+}
+
+#[test]
+fn test_requirement_satisfied_by() {
+    let requirement =
+        |flags: Option<RequirementType>, epoch: &str, version: &str, release: &str| {
+            let optional = |value: &str| Some(value.to_owned()).filter(|v| !v.is_empty());
+            Requirement {
+                name: "foo".into(),
+                flags,
+                epoch: optional(epoch),
+                version: optional(version),
+                release: optional(release),
+                ..Requirement::default()
+            }
+        };
+
+    // foo >= 1.2
+    let requires = requirement(Some(RequirementType::GE), "0", "1.2", "");
+    assert_eq!(
+        requires.constraint(),
+        Some((RequirementType::GE, EVR::new("0", "1.2", "")))
+    );
+    assert!(requires.satisfied_by(&EVR::new("0", "1.2", "3.el8")));
+    assert!(requires.satisfied_by(&EVR::new("0", "2.0", "1")));
+    assert!(!requires.satisfied_by(&EVR::new("0", "1.1", "1")));
+
+    // foo < 2.0-1
+    let requires = requirement(Some(RequirementType::LT), "0", "2.0", "1");
+    assert!(requires.satisfied_by(&EVR::new("0", "2.0", "0.rc1")));
+    assert!(!requires.satisfied_by(&EVR::new("0", "2.0", "1")));
+    assert!(!requires.satisfied_by(&EVR::new("1", "1.0", "1")));
+
+    // unversioned requirements are satisfied by anything
+    let requires = requirement(None, "", "", "");
+    assert_eq!(requires.constraint(), None);
+    assert!(requires.satisfied_by(&EVR::new("0", "0.1", "1")));
+
+    // foo = 1.5 satisfies foo >= 1.2, foo > 2 doesn't
+    let requires = requirement(Some(RequirementType::GE), "0", "1.2", "");
+    assert!(requires.satisfied_by_provide(&requirement(
+        Some(RequirementType::EQ),
+        "0",
+        "1.5",
+        "1"
+    )));
+    assert!(!requires.satisfied_by_provide(&requirement(
+        Some(RequirementType::LT),
+        "0",
+        "1.2",
+        ""
+    )));
+    assert!(requires.satisfied_by_provide(&requirement(None, "", "", "")));
+
+    let mut other = requirement(Some(RequirementType::EQ), "0", "1.5", "1");
+    other.name = "bar".into();
+    assert!(!requires.satisfied_by_provide(&other));
+}
+
+#[test]
+fn test_requirement_parse() -> Result<(), MetadataError> {
+    let requirement = Requirement::parse("pkgname >= 1:1.2-3")?;
+    assert_eq!(
+        requirement,
+        Requirement {
+            name: "pkgname".into(),
+            flags: Some(RequirementType::GE),
+            epoch: Some("1".to_owned()),
+            version: Some("1.2".to_owned()),
+            release: Some("3".to_owned()),
+            preinstall: false,
+        }
+    );
+    assert_eq!(requirement.to_string(), "pkgname >= 1:1.2-3");
+
+    // the epoch defaults to 0 and is omitted again when formatting
+    let requirement = Requirement::parse("python3-libs<3.9")?;
+    assert_eq!(requirement.flags, Some(RequirementType::LT));
+    assert_eq!(requirement.epoch.as_deref(), Some("0"));
+    assert_eq!(requirement.release, None);
+    assert_eq!(requirement.to_string(), "python3-libs < 3.9");
+
+    for capability in [
+        "libfoo.so.1()(64bit)",
+        "/usr/bin/bash",
+        "foo = 2.0",
+        "foo <= 2.0-1.fc36",
+        "foo > 3:2.0",
+        "(foo >= 1.0 with foo < 2.0)",
+    ] {
+        assert_eq!(Requirement::parse(capability)?.to_string(), capability);
+    }
+    assert_eq!(Requirement::parse("foo == 2.0")?.to_string(), "foo = 2.0");
+
+    // requirements from the metadata can be formatted
+    let formatted: Vec<String> = common::COMPLEX_PACKAGE
+        .requires()
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+    assert!(formatted.contains(&"arson >= 1.0.0-1".to_owned()));
+    for requirement in common::COMPLEX_PACKAGE.requires() {
+        let parsed = Requirement::parse(&requirement.to_string())?;
+        assert_eq!(parsed.name, requirement.name);
+        assert_eq!(parsed.constraint(), requirement.constraint());
+    }
+
+    for invalid in [
+        "",
+        ">= 1.0",
+        "foo >=",
+        "foo => 1.0",
+        "foo >= 1.0 2.0",
+        "(foo and",
+    ] {
+        assert!(matches!(
+            Requirement::parse(invalid),
+            Err(MetadataError::Parse(ParseError::InvalidDependency(_, _)))
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rich_dependency() -> Result<(), MetadataError> {
+    let requirement =
+        Requirement::parse("(bobloblaw >= 1.1 if maritimelaw else anyone < 0.5.1-2)")?;
+    assert!(requirement.is_rich());
+    let dependency = requirement.rich_dependency()?.unwrap();
+
+    let simple = |capability: &str| {
+        Box::new(RichDependency::Simple(
+            Requirement::parse(capability).unwrap(),
+        ))
+    };
+    assert_eq!(
+        dependency,
+        RichDependency::If(
+            simple("bobloblaw >= 1.1"),
+            simple("maritimelaw"),
+            Some(simple("anyone < 0.5.1-2")),
+        )
+    );
+    assert_eq!(
+        dependency
+            .requirements()
+            .iter()
+            .map(|r| &*r.name)
+            .collect::<Vec<_>>(),
+        vec!["bobloblaw", "maritimelaw", "anyone"]
+    );
+    assert_eq!(dependency.to_requirement(), requirement);
+
+    // chains of the same operator are nested to the left
+    let dependency = RichDependency::parse("(a and b and c)")?;
+    assert_eq!(
+        dependency,
+        RichDependency::And(
+            Box::new(RichDependency::And(simple("a"), simple("b"))),
+            simple("c")
+        )
+    );
+
+    // plain capabilities are not rich dependencies
+    assert_eq!(Requirement::parse("foo >= 1.0")?.rich_dependency()?, None);
+    assert_eq!(RichDependency::parse("foo >= 1.0")?, *simple("foo >= 1.0"));
+    assert!(!RichDependency::parse("foo")?.is_rich());
+
+    // serialization round-trips
+    let package = &*common::COMPLEX_PACKAGE;
+    for requirement in package
+        .suggests()
+        .iter()
+        .chain(package.recommends())
+        .chain(package.supplements())
+        .chain(package.enhances())
+        .filter(|r| r.is_rich())
+    {
+        let dependency = requirement.rich_dependency()?.unwrap();
+        assert_eq!(dependency.to_string(), &*requirement.name);
+        assert_eq!(RichDependency::parse(&dependency.to_string())?, dependency);
+    }
+    for expression in [
+        "(a and b and c)",
+        "((a or b) and c)",
+        "(a without (b with c))",
+        "(libfoo.so.1()(64bit) unless (b > 1:2.0-3 or c))",
+    ] {
+        assert_eq!(RichDependency::parse(expression)?.to_string(), expression);
+    }
+
+    assert!(matches!(
+        RichDependency::parse("(a and b or c)"),
+        Err(MetadataError::Parse(ParseError::InvalidDependency(_, _)))
+    ));
+
+    // deeply nested expressions are rejected instead of overflowing the stack
+    let nested = format!("{}a{}", "(".repeat(200_000), ")".repeat(200_000));
+    assert!(matches!(
+        RichDependency::parse(&nested),
+        Err(MetadataError::Parse(ParseError::InvalidDependency(_, _)))
+    ));
+    let chained = format!("(a{})", " and a".repeat(200_000));
+    assert!(matches!(
+        RichDependency::parse(&chained),
+        Err(MetadataError::Parse(ParseError::InvalidDependency(_, _)))
+    ));
+    let nested = format!("{}a{}", "(".repeat(32), ")".repeat(32));
+    assert_eq!(RichDependency::parse(&nested)?, *simple("a"));
+
+    Ok(())
+}
+
+#[test]
+fn test_dependency_lists_are_independent() {
+    let req = |name: &str| Requirement {
+        name: name.into(),
+        ..Requirement::default()
+    };
+
+    let mut pkg = Package::default();
+    pkg.set_supplements(vec![req("supplemented")]);
+    pkg.set_provides(vec![req("provided-a"), req("provided-b")]);
+    pkg.set_requires(vec![req("required")]);
+    pkg.set_conflicts(vec![req("conflicting")]);
+
+    assert_eq!(pkg.requires(), &[req("required")]);
+    assert_eq!(pkg.provides(), &[req("provided-a"), req("provided-b")]);
+    assert_eq!(pkg.conflicts(), &[req("conflicting")]);
+    assert_eq!(pkg.supplements(), &[req("supplemented")]);
+    assert!(pkg.obsoletes().is_empty());
+    assert!(pkg.suggests().is_empty());
+    assert!(pkg.enhances().is_empty());
+    assert!(pkg.recommends().is_empty());
+
+    // replacing or clearing one list must leave the others intact
+    pkg.set_provides(vec![req("provided-c")]);
+    pkg.set_conflicts(Vec::new());
+    assert_eq!(pkg.requires(), &[req("required")]);
+    assert_eq!(pkg.provides(), &[req("provided-c")]);
+    assert!(pkg.conflicts().is_empty());
+    assert_eq!(pkg.supplements(), &[req("supplemented")]);
+}
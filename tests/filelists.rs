@@ -114,7 +114,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(filelists_xml.read_header()?, 0);
     assert!(matches!(
         filelists_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there are no packages and the footer element doesn't exist (EOF)
@@ -124,7 +124,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(filelists_xml.read_header()?, 0);
     assert!(matches!(
         filelists_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there is no XML declaration at the top
@@ -133,7 +133,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(filelists_xml.read_header()?, 0);
     assert!(matches!(
         filelists_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there is packages
@@ -142,7 +142,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(filelists_xml.read_header()?, 1);
     assert!(matches!(
         filelists_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     Ok(())
@@ -72,6 +72,34 @@ fn test_other_xml_writer_complex_pkg() -> Result<(), MetadataError> {
     Ok(())
 }
 
+#[test]
+fn test_other_xml_writer_changelog_timestamp_policy() -> Result<(), MetadataError> {
+    let mut package = common::COMPLEX_PACKAGE.clone();
+    package.add_changelog("Lucille Bluth", "- From the future", 4102444800);
+
+    let write = |policy: ChangelogTimestampPolicy| -> Result<String, MetadataError> {
+        let mut writer = OtherXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+        writer.set_changelog_timestamp_policy(policy);
+        writer.write_header(1)?;
+        writer.write_package(&package)?;
+        writer.finish()?;
+        Ok(String::from_utf8(writer.into_inner().into_inner()).unwrap())
+    };
+
+    let kept = write(ChangelogTimestampPolicy::Keep)?;
+    assert!(kept.contains(r#"date="4102444800">- From the future"#));
+
+    let clamped = write(ChangelogTimestampPolicy::Clamp)?;
+    assert!(clamped.contains(r#"date="2147483647">- From the future"#));
+
+    // entries with sane timestamps are never affected
+    let dropped = write(ChangelogTimestampPolicy::Drop)?;
+    assert!(!dropped.contains("From the future"));
+    assert_eq!(dropped, COMPLEX_OTHERDATA);
+
+    Ok(())
+}
+
 #[test]
 fn test_other_xml_writer_file() -> Result<(), MetadataError> {
     let working_dir = TempDir::new("")?;
@@ -108,7 +136,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(other_xml.read_header()?, 0);
     assert!(matches!(
         other_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there are no packages and the footer element doesn't exist (EOF)
@@ -118,7 +146,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(other_xml.read_header()?, 0);
     assert!(matches!(
         other_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there is no XML declaration at the top
@@ -127,7 +155,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(other_xml.read_header()?, 0);
     assert!(matches!(
         other_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there is packages
@@ -136,7 +164,7 @@ fn test_filelists_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(other_xml.read_header()?, 1);
     assert!(matches!(
         other_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     Ok(())
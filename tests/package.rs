@@ -21,7 +21,94 @@ pub const COMPLEX_PKG_PATH: &str = "./tests/assets/packages/complex-package-2.3.
 fn test_read_rpm_from_file() -> Result<(), MetadataError> {
     let mut pkg = utils::load_rpm_package(COMPLEX_PKG_PATH)?;
     pkg.location_href = "complex-package-2.3.4-5.el8.x86_64.rpm".to_owned();
+
+    // header tags outside of the repository metadata depend on the rpm version used to build the package
+    let extra = std::mem::take(&mut pkg.extra);
+    if let Some(algo) = extra.get("payloaddigestalgo") {
+        assert_eq!(algo, &vec!["sha256".to_owned()]);
+    }
     assert_eq!(&pkg, &*common::COMPLEX_PACKAGE);
 
     Ok(())
 }
+
+#[test]
+fn test_package_from_rpm_file() -> Result<(), MetadataError> {
+    let location_href = "Packages/c/complex-package-2.3.4-5.el8.x86_64.rpm";
+    let mut pkg = Package::from_rpm_file(
+        Path::new(COMPLEX_PKG_PATH),
+        ChecksumType::Sha512,
+        location_href,
+    )?;
+
+    assert_eq!(pkg.location_href(), location_href);
+    assert!(matches!(pkg.checksum(), Some(Checksum::Sha512(_))));
+    assert_eq!(
+        pkg.checksum(),
+        Some(&utils::checksum_file(
+            Path::new(COMPLEX_PKG_PATH),
+            ChecksumType::Sha512
+        )?)
+    );
+
+    // everything else matches the metadata createrepo_c produces
+    let mut expected = common::COMPLEX_PACKAGE.clone();
+    expected.set_location_href(location_href);
+    expected.set_checksum(pkg.checksum().unwrap().clone());
+    pkg.extra.clear();
+    assert_eq!(&pkg, &expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_changelog_limit() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_changelog_limit")?;
+
+    let options = RepositoryOptions::default()
+        .simple_metadata_filenames(true)
+        .changelog_limit(Some(1));
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    repo_writer.add_rpm_file(
+        Path::new(COMPLEX_PKG_PATH),
+        "complex-package-2.3.4-5.el8.x86_64.rpm",
+    )?;
+    repo_writer.finish()?;
+
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    let pkg = repo.packages().values().next().unwrap();
+    let expected = common::COMPLEX_PACKAGE.changelogs();
+    assert_eq!(pkg.changelogs(), &expected[expected.len() - 1..]);
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_signature_requirements() -> Result<(), MetadataError> {
+    let pkg = Package::from_rpm_file(
+        Path::new(COMPLEX_PKG_PATH),
+        ChecksumType::Sha256,
+        "complex-package-2.3.4-5.el8.x86_64.rpm",
+    )?;
+    assert_eq!(pkg.is_signed(), pkg.signature_key_id().is_some());
+
+    let add_with_options = |options: RepositoryOptions| -> Result<(), MetadataError> {
+        let tmp_dir = TempDir::new("test_signature_requirements")?;
+        let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+        repo_writer.add_rpm_file(Path::new(COMPLEX_PKG_PATH), pkg.location_href())
+    };
+
+    add_with_options(RepositoryOptions::default())?;
+
+    let result = add_with_options(RepositoryOptions::default().require_signature(true));
+    assert_eq!(result.is_ok(), pkg.is_signed());
+
+    // no package is signed by this key
+    let result = add_with_options(RepositoryOptions::default().signing_key_id(Some(0)));
+    assert!(matches!(
+        result,
+        Err(MetadataError::Write(WriteError::SignatureRequirement(_, _)))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,320 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    utils, Field, FilelistsXml, MetadataError, MetadataWarning, OtherXml, Package,
+    PackageCountMismatchPolicy, PackageIterator, PackageMatchPolicy, ParseError, PrimaryXml,
+    RepositoryOptions, RepositoryWriter, WarningCollector,
+};
+use std::io::{BufReader, Cursor, Read};
+use std::sync::Arc;
+use tempdir::TempDir;
+
+mod common;
+
+/// Build a `PackageIterator` over in-memory metadata, in which filelists.xml only contains the
+/// first `num_filelists` packages
+fn in_memory_package_iterator(
+    packages: &[&Package],
+    num_filelists: usize,
+) -> Result<PackageIterator, MetadataError> {
+    in_memory_package_iterator_from_lists(packages, &packages[..num_filelists], packages)
+}
+
+/// Build a `PackageIterator` over in-memory metadata listing the given packages in each file
+fn in_memory_package_iterator_from_lists(
+    primary_packages: &[&Package],
+    filelists_packages: &[&Package],
+    other_packages: &[&Package],
+) -> Result<PackageIterator, MetadataError> {
+    let mut primary = PrimaryXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+    primary.write_header(primary_packages.len())?;
+    let mut filelists = FilelistsXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+    filelists.write_header(filelists_packages.len())?;
+    let mut other = OtherXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+    other.write_header(other_packages.len())?;
+
+    for pkg in primary_packages {
+        primary.write_package(pkg)?;
+    }
+    for pkg in filelists_packages {
+        filelists.write_package(pkg)?;
+    }
+    for pkg in other_packages {
+        other.write_package(pkg)?;
+    }
+
+    primary.finish()?;
+    filelists.finish()?;
+    other.finish()?;
+
+    fn to_reader(buf: Vec<u8>) -> quick_xml::Reader<BufReader<Box<dyn Read + Send>>> {
+        utils::create_xml_reader(BufReader::new(
+            Box::new(Cursor::new(buf)) as Box<dyn Read + Send>
+        ))
+    }
+
+    PackageIterator::from_readers(
+        PrimaryXml::new_reader(to_reader(primary.into_inner().into_inner())),
+        FilelistsXml::new_reader(to_reader(filelists.into_inner().into_inner())),
+        OtherXml::new_reader(to_reader(other.into_inner().into_inner())),
+    )
+}
+
+/// Build a `PackageIterator` over in-memory metadata in which filelists.xml is missing the last package
+fn package_iterator_with_truncated_filelists() -> Result<PackageIterator, MetadataError> {
+    in_memory_package_iterator(&[&*common::RPM_EMPTY, &*common::COMPLEX_PACKAGE], 1)
+}
+
+#[test]
+fn test_package_count_mismatch_error() -> Result<(), MetadataError> {
+    let mut packages = package_iterator_with_truncated_filelists()?;
+
+    assert!(!packages.package_counts_match());
+    assert!(matches!(
+        packages.next(),
+        Some(Err(MetadataError::Parse(ParseError::InconsistentMetadata(
+            _
+        ))))
+    ));
+    assert!(packages.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_package_count_mismatch_warn() -> Result<(), MetadataError> {
+    let warnings = Arc::new(WarningCollector::new());
+    let packages = package_iterator_with_truncated_filelists()?
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::Warn)
+        .with_warning_handler(warnings.clone());
+
+    assert_eq!(packages.total_packages(), 1);
+
+    let packages = packages.collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages, vec![common::RPM_EMPTY.clone()]);
+
+    assert_eq!(
+        warnings.take(),
+        vec![
+            MetadataWarning::PackageCountMismatch {
+                primary: 2,
+                filelists: 1,
+                other: 2
+            },
+            MetadataWarning::PackageSkipped {
+                pkgid: Some(common::COMPLEX_PACKAGE.pkgid().to_owned()),
+                file: "filelists"
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_package_count_mismatch_trust_primary() -> Result<(), MetadataError> {
+    let packages = package_iterator_with_truncated_filelists()?
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::TrustPrimary);
+
+    assert_eq!(packages.total_packages(), 2);
+    // the declared count can't be trusted as an upper bound
+    assert_eq!(packages.size_hint(), (0, None));
+
+    let packages = packages.collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.len(), 2);
+    assert_eq!(&packages[0], &*common::RPM_EMPTY);
+    assert_eq!(packages[1].name(), common::COMPLEX_PACKAGE.name());
+    assert!(packages[1].files().is_empty());
+    assert_eq!(
+        packages[1].changelogs(),
+        common::COMPLEX_PACKAGE.changelogs()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_package_iterator_match_by_pkgid() -> Result<(), MetadataError> {
+    let in_order = [&*common::RPM_EMPTY, &*common::COMPLEX_PACKAGE];
+    let reversed = [&*common::COMPLEX_PACKAGE, &*common::RPM_EMPTY];
+
+    // by default, the files must list the packages in the same order
+    let result = in_memory_package_iterator_from_lists(&in_order, &reversed, &in_order)?
+        .collect::<Result<Vec<Package>, MetadataError>>();
+    assert!(matches!(
+        result.unwrap_err().root_cause(),
+        MetadataError::Parse(ParseError::InconsistentMetadata(_))
+    ));
+
+    let packages = in_memory_package_iterator_from_lists(&in_order, &reversed, &reversed)?
+        .with_match_policy(PackageMatchPolicy::ByPkgid)
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.iter().collect::<Vec<_>>(), in_order.to_vec());
+
+    // a package which is genuinely missing from filelists.xml is still handled according to the policy
+    let missing = [&*common::RPM_EMPTY];
+    let result = in_memory_package_iterator_from_lists(&in_order, &missing, &reversed)?
+        .with_match_policy(PackageMatchPolicy::ByPkgid)
+        .collect::<Result<Vec<Package>, MetadataError>>();
+    assert!(result.is_err());
+
+    let warnings = Arc::new(WarningCollector::new());
+    let packages = in_memory_package_iterator_from_lists(&in_order, &missing, &reversed)?
+        .with_match_policy(PackageMatchPolicy::ByPkgid)
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::Warn)
+        .with_warning_handler(warnings.clone())
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(
+        packages.iter().collect::<Vec<_>>(),
+        vec![&*common::RPM_EMPTY]
+    );
+    assert!(warnings.take().contains(&MetadataWarning::PackageSkipped {
+        pkgid: Some(common::COMPLEX_PACKAGE.pkgid().to_owned()),
+        file: "filelists"
+    }));
+
+    // a package missing from the middle is skipped even in order, as mismatched counts fall back to
+    // matching by pkgid
+    let missing_first = [&*common::COMPLEX_PACKAGE];
+    let warnings = Arc::new(WarningCollector::new());
+    let packages = in_memory_package_iterator_from_lists(&in_order, &missing_first, &in_order)?
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::Warn)
+        .with_warning_handler(warnings.clone())
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(
+        packages.iter().collect::<Vec<_>>(),
+        vec![&*common::COMPLEX_PACKAGE]
+    );
+    assert!(warnings.take().contains(&MetadataWarning::PackageSkipped {
+        pkgid: Some(common::RPM_EMPTY.pkgid().to_owned()),
+        file: "filelists"
+    }));
+    let packages = in_memory_package_iterator_from_lists(&in_order, &missing_first, &in_order)?
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::TrustPrimary)
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.len(), 2);
+    assert_eq!(packages[0].name(), common::RPM_EMPTY.name());
+    assert_eq!(&packages[1], &*common::COMPLEX_PACKAGE);
+
+    // with matching counts, nothing is known to be missing upfront, so the entries must line up
+    let swapped = in_memory_package_iterator_from_lists(&in_order, &reversed, &in_order)?
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::Warn)
+        .collect::<Result<Vec<Package>, MetadataError>>();
+    assert!(matches!(
+        swapped.unwrap_err().root_cause(),
+        MetadataError::Parse(ParseError::InconsistentMetadata(_))
+    ));
+
+    let packages = in_memory_package_iterator_from_lists(&in_order, &missing, &reversed)?
+        .with_match_policy(PackageMatchPolicy::ByPkgid)
+        .with_count_mismatch_policy(PackageCountMismatchPolicy::TrustPrimary)
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.len(), 2);
+    assert_eq!(&packages[0], &*common::RPM_EMPTY);
+    assert_eq!(packages[1].name(), common::COMPLEX_PACKAGE.name());
+    assert!(packages[1].files().is_empty());
+    assert_eq!(
+        packages[1].changelogs(),
+        common::COMPLEX_PACKAGE.changelogs()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_package_iterator_skip_files_and_changelogs() -> Result<(), MetadataError> {
+    let expected = &*common::COMPLEX_PACKAGE;
+
+    let package = in_memory_package_iterator(&[expected], 1)?
+        .skip_files(true)
+        .next()
+        .unwrap()?;
+    assert!(package.files().is_empty());
+    assert_eq!(package.changelogs(), expected.changelogs());
+    assert_eq!(package.requires(), expected.requires());
+
+    let package = in_memory_package_iterator(&[expected], 1)?
+        .skip_changelogs(true)
+        .next()
+        .unwrap()?;
+    assert_eq!(package.files(), expected.files());
+    assert!(package.changelogs().is_empty());
+
+    // other projected fields are left alone
+    let package = in_memory_package_iterator(&[expected], 1)?
+        .project(&[Field::Name, Field::Files, Field::Changelogs])
+        .skip_changelogs(true)
+        .next()
+        .unwrap()?;
+    assert_eq!(package.name(), expected.name());
+    assert_eq!(package.files(), expected.files());
+    assert!(package.changelogs().is_empty());
+    assert!(package.requires().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_package_iterator_projection() -> Result<(), MetadataError> {
+    let packages = in_memory_package_iterator(&[&*common::COMPLEX_PACKAGE], 1)?
+        .project(&[Field::Name, Field::Evr, Field::Arch, Field::Location])
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+
+    assert_eq!(packages.len(), 1);
+    let package = &packages[0];
+    let expected = &*common::COMPLEX_PACKAGE;
+
+    assert_eq!(package.nevra(), expected.nevra());
+    assert_eq!(package.checksum(), expected.checksum());
+    assert_eq!(package.location_href(), expected.location_href());
+    assert_eq!(package.description(), "");
+    assert_eq!(package.size_package(), 0);
+    assert!(package.requires().is_empty());
+    assert!(package.files().is_empty());
+    assert!(package.changelogs().is_empty());
+
+    let packages = in_memory_package_iterator(&[&*common::COMPLEX_PACKAGE], 1)?
+        .project(&[Field::Requires, Field::Files])
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+
+    assert_eq!(packages[0].name(), "");
+    assert_eq!(packages[0].requires(), expected.requires());
+    assert_eq!(packages[0].files(), expected.files());
+    assert!(packages[0].provides().is_empty());
+    assert!(packages[0].changelogs().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_package_iterator_primary_only() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_package_iterator")?;
+
+    let options = RepositoryOptions::default()
+        .simple_metadata_filenames(true)
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::None);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 2, options)?;
+    repo_writer.add_package(&common::RPM_EMPTY)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let primary_path = tmp_dir.path().join("repodata").join("primary.xml");
+    let packages = PackageIterator::from_primary_only(&primary_path)?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+
+    assert_eq!(packages.len(), 2);
+    let package = &packages[1];
+    let expected = &*common::COMPLEX_PACKAGE;
+    assert_eq!(package.nevra(), expected.nevra());
+    assert_eq!(package.requires(), expected.requires());
+    assert_eq!(package.provides(), expected.provides());
+    assert!(package.changelogs().is_empty());
+    assert!(package.files().len() < expected.files().len());
+
+    Ok(())
+}
@@ -125,6 +125,48 @@ fn test_primary_xml_writer_complex_pkg() -> Result<(), MetadataError> {
     Ok(())
 }
 
+#[test]
+fn test_primary_xml_writer_preinstall_policy() -> Result<(), MetadataError> {
+    let mut package = common::COMPLEX_PACKAGE.clone();
+    let mut provides = package.provides().to_vec();
+    provides[0].preinstall = true;
+    package.set_provides(provides);
+
+    let write = |policy: PreinstallPolicy| -> Result<String, MetadataError> {
+        let mut writer = PrimaryXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+        writer.set_preinstall_policy(policy);
+        writer.write_header(1)?;
+        writer.write_package(&package)?;
+        writer.finish()?;
+        Ok(String::from_utf8(writer.into_inner().into_inner()).unwrap())
+    };
+
+    // like createrepo_c, only requires keep the attribute by default
+    let actual = write(PreinstallPolicy::default())?;
+    assert_eq!(actual, COMPLEX_PRIMARY);
+
+    let actual = write(PreinstallPolicy::Preserve)?;
+    assert!(actual.contains(r#"<rpm:entry name="/usr/bin/ls" pre="1"/>"#));
+    assert!(actual.contains(r#"<rpm:entry name="/usr/sbin/useradd" pre="1"/>"#));
+
+    Ok(())
+}
+
+#[test]
+fn test_package_epoch() {
+    let mut package = common::COMPLEX_PACKAGE.clone();
+    assert_eq!(package.epoch().unwrap(), 1);
+
+    package.set_evr(EVR::new("", "1.0", "1"));
+    assert_eq!(package.epoch().unwrap(), 0);
+
+    package.set_evr(EVR::new("x", "1.0", "1"));
+    assert!(matches!(
+        package.epoch(),
+        Err(MetadataError::Parse(ParseError::IntField(_)))
+    ));
+}
+
 #[test]
 fn test_primary_xml_writer_file() -> Result<(), MetadataError> {
     let working_dir = TempDir::new("")?;
@@ -163,7 +205,7 @@ fn test_primary_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(primary_xml.read_header()?, 0);
     assert!(matches!(
         primary_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there are no packages and the footer element doesn't exist (EOF)
@@ -172,7 +214,7 @@ fn test_primary_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(primary_xml.read_header()?, 0);
     assert!(matches!(
         primary_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there is no XML declaration at the top
@@ -181,7 +223,7 @@ fn test_primary_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(primary_xml.read_header()?, 0);
     assert!(matches!(
         primary_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     // Test that the header parses correctly when there is packages
@@ -190,7 +232,7 @@ fn test_primary_xml_read_header() -> Result<(), MetadataError> {
     assert_eq!(primary_xml.read_header()?, 1);
     assert!(matches!(
         primary_xml.read_header(),
-        Err(MetadataError::MissingHeaderError)
+        Err(MetadataError::Parse(ParseError::MissingHeader))
     ));
 
     Ok(())
@@ -227,3 +269,145 @@ fn test_primary_xml_read_package() -> Result<(), MetadataError> {
 
     Ok(())
 }
+
+#[test]
+fn test_read_packages() -> Result<(), MetadataError> {
+    let packages = read_packages(COMPLEX_PRIMARY.as_bytes())?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].nevra(), common::COMPLEX_PACKAGE.nevra());
+    assert_eq!(packages[0].requires(), common::COMPLEX_PACKAGE.requires());
+
+    assert_eq!(read_packages(EMPTY_PRIMARY.as_bytes())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_package_refs() -> Result<(), MetadataError> {
+    let packages = read_package_refs(COMPLEX_PRIMARY.as_bytes())?
+        .collect::<Result<Vec<PackageRef>, MetadataError>>()?;
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].nevra(), common::COMPLEX_PACKAGE.nevra());
+    // values without escape sequences are borrowed from the metadata
+    assert!(matches!(packages[0].name, std::borrow::Cow::Borrowed(_)));
+
+    let owned = read_packages(COMPLEX_PRIMARY.as_bytes())?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages[0].to_package()?, owned[0]);
+
+    let refs = read_package_refs(EMPTY_PRIMARY.as_bytes())?;
+    assert_eq!(refs.total_packages(), 0);
+    assert_eq!(refs.count(), 0);
+    assert!(read_package_refs(EMPTY_PRIMARY_NO_DECL.as_bytes())?
+        .next()
+        .is_none());
+
+    let truncated = &COMPLEX_PRIMARY[..COMPLEX_PRIMARY.len() / 2];
+    let result = read_package_refs(truncated.as_bytes())?
+        .collect::<Result<Vec<PackageRef>, MetadataError>>();
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_package_refs_repository() -> Result<(), MetadataError> {
+    let reader =
+        RepositoryReader::new_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+    let primary_xml = reader.read_record("primary")?.unwrap();
+    let owned = read_packages(Cursor::new(primary_xml.clone()))?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    let borrowed = read_package_refs(&primary_xml)?
+        .map(|pkg| pkg?.to_package())
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(borrowed, owned);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_packages_decoding_policy() -> Result<(), MetadataError> {
+    // an accented character encoded as latin-1, which isn't valid UTF-8
+    let (before, after) = COMPLEX_PRIMARY.split_once("Michael Bluth").unwrap();
+    let invalid = [
+        before.as_bytes(),
+        &b"Micha\xebl Bluth"[..],
+        after.as_bytes(),
+    ]
+    .concat();
+
+    let result = read_packages(Cursor::new(invalid.clone()))?
+        .collect::<Result<Vec<Package>, MetadataError>>();
+    assert!(result.is_err());
+
+    let reader = utils::LossyUtf8Reader::new(Cursor::new(invalid));
+    let packages = read_packages(reader)?.collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages[0].packager(), "Micha\u{FFFD}l Bluth");
+    assert_eq!(packages[0].summary(), common::COMPLEX_PACKAGE.summary());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_packages_error_position() -> Result<(), MetadataError> {
+    let broken = COMPLEX_PRIMARY.replace(r#"<size package="8680""#, r#"<size package="86x0""#);
+    let error_offset = broken.find("<size").unwrap();
+
+    let result = read_packages(Cursor::new(broken.into_bytes()))?
+        .collect::<Result<Vec<Package>, MetadataError>>();
+
+    let error = result.unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("while parsing complex-package-1:2.3.4-5.el8.x86_64"));
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::IntField(_))
+    ));
+
+    if let MetadataError::Located {
+        file,
+        position,
+        context,
+        ..
+    } = error
+    {
+        assert_eq!(file, "primary");
+        assert!(position > error_offset);
+        assert_eq!(
+            context.as_deref(),
+            Some("complex-package-1:2.3.4-5.el8.x86_64")
+        );
+    } else {
+        panic!("expected position information, got {:?}", error);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_primary_xml_unknown_entry_attribute() -> Result<(), MetadataError> {
+    let odd = COMPLEX_PRIMARY.replace(
+        r#"<rpm:entry name="/usr/bin/ls"/>"#,
+        r#"<rpm:entry name="/usr/bin/ls" arch="x86_64"/>"#,
+    );
+
+    // unknown attributes are ignored, and reported if there is a warning handler
+    let warnings = std::sync::Arc::new(WarningCollector::new());
+    let mut reader = PrimaryXml::new_reader(utils::create_xml_reader(odd.as_bytes()));
+    reader.set_warning_handler(warnings.clone());
+    reader.read_header()?;
+    let packages = reader.collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages[0].provides(), common::COMPLEX_PACKAGE.provides());
+
+    assert_eq!(
+        warnings.take(),
+        vec![MetadataWarning::UnknownAttribute {
+            element: "rpm:entry",
+            attribute: "arch".to_owned(),
+        }]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,451 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    utils, CacheValidators, Checksum, ChecksumError, ConditionalFetch, Fetcher, MetadataError,
+    MetadataSelection, Package, PartialDownload, RemoteRepository, RepoLayoutError, RepoSyncer,
+    Repository, RepositoryReader, RepositoryWriter, SyncReport, TransferMode, EVR,
+};
+use std::cell::RefCell;
+use std::io::Write;
+use tempdir::TempDir;
+
+/// Serve the files under `dir` (with a trailing slash) as if they were hosted at "mock://mirror/"
+fn fetch_from_directory(dir: &str, url: &str, writer: &mut dyn Write) -> Result<(), MetadataError> {
+    let path = url.replace("mock://mirror/", dir);
+    std::io::copy(&mut std::fs::File::open(path)?, writer)?;
+    Ok(())
+}
+
+#[test]
+fn test_remote_repository_fetch_metadata() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_remote_repository")?;
+
+    // serve the fixture repository from a fake URL
+    let fetched = RefCell::new(Vec::new());
+    let fetcher = |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+        fetched.borrow_mut().push(url.to_owned());
+        fetch_from_directory("./tests/assets/doctest_repo/", url, writer)
+    };
+
+    let remote = RemoteRepository::new("mock://mirror/", fetcher)?;
+    assert_eq!(remote.base_url(), "mock://mirror");
+    assert!(remote.repomd().get_record("filelists").is_some());
+
+    let reader = remote.fetch_metadata(&["primary", "updateinfo", "group"], tmp_dir.path())?;
+    assert_eq!(
+        *fetched.borrow(),
+        vec![
+            "mock://mirror/repodata/repomd.xml",
+            "mock://mirror/repodata/primary.xml",
+            "mock://mirror/repodata/updateinfo.xml",
+        ]
+    );
+    assert!(tmp_dir.path().join("repodata/repomd.xml").exists());
+    assert!(!tmp_dir.path().join("repodata/filelists.xml").exists());
+
+    let selection = MetadataSelection {
+        filelists: false,
+        other: false,
+    };
+    let packages = reader
+        .iter_packages_with(selection)?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(packages.len(), 3);
+    assert_eq!(reader.iter_advisories()?.count(), 1);
+
+    Ok(())
+}
+
+/// Serves the fixture repository, failing halfway through the first download of primary.xml
+#[derive(Default)]
+struct FlakyFetcher {
+    fetched: RefCell<Vec<String>>,
+    resumed: RefCell<Vec<(String, u64)>>,
+    /// Send the whole file when resuming, like a server which doesn't support ranges
+    ignore_ranges: bool,
+}
+
+impl FlakyFetcher {
+    fn read(url: &str) -> Result<Vec<u8>, MetadataError> {
+        Ok(std::fs::read(url.replace(
+            "mock://mirror/",
+            "./tests/assets/doctest_repo/",
+        ))?)
+    }
+}
+
+impl Fetcher for &FlakyFetcher {
+    fn fetch(&self, url: &str, writer: &mut dyn Write) -> Result<(), MetadataError> {
+        let data = FlakyFetcher::read(url)?;
+        let first_time = !self.fetched.borrow().iter().any(|u| u == url);
+        self.fetched.borrow_mut().push(url.to_owned());
+        if !url.ends_with("primary.xml") || !first_time {
+            writer.write_all(&data)?;
+            return Ok(());
+        }
+        writer.write_all(&data[..data.len() / 2])?;
+        Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset").into())
+    }
+
+    fn fetch_from(&self, url: &str, download: &mut PartialDownload) -> Result<bool, MetadataError> {
+        self.resumed
+            .borrow_mut()
+            .push((url.to_owned(), download.offset()));
+        let data = FlakyFetcher::read(url)?;
+        if self.ignore_ranges {
+            download.restart()?;
+            download.write_all(&data)?;
+        } else {
+            download.write_all(&data[download.offset() as usize..])?;
+        }
+        Ok(true)
+    }
+}
+
+#[test]
+fn test_remote_repository_resume_download() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_remote_repository_resume_download")?;
+    let primary_url = "mock://mirror/repodata/primary.xml";
+    let primary_len = FlakyFetcher::read(primary_url)?.len() as u64;
+    let partial_path = tmp_dir.path().join("repodata/primary.xml.part");
+
+    let fetcher = FlakyFetcher::default();
+    let remote = RemoteRepository::new("mock://mirror/", &fetcher)?;
+    assert!(remote.fetch_metadata(&["primary"], tmp_dir.path()).is_err());
+    // the partial file is kept
+    assert_eq!(partial_path.metadata()?.len(), primary_len / 2);
+
+    let reader = remote.fetch_metadata(&["primary"], tmp_dir.path())?;
+    assert_eq!(
+        *fetcher.resumed.borrow(),
+        vec![(primary_url.to_owned(), primary_len / 2)]
+    );
+    assert!(!partial_path.exists());
+    assert_eq!(
+        reader
+            .iter_packages_with(MetadataSelection {
+                filelists: false,
+                other: false
+            })?
+            .count(),
+        3
+    );
+
+    // files which are already present with the right checksum aren't downloaded again
+    fetcher.fetched.borrow_mut().clear();
+    remote.fetch_metadata(&["primary"], tmp_dir.path())?;
+    assert!(fetcher.fetched.borrow().is_empty());
+
+    // a partial file with the wrong contents is removed once the checksum doesn't match
+    let partial_path = tmp_dir.path().join("repodata/updateinfo.xml.part");
+    std::fs::write(&partial_path, b"garbage")?;
+    let result = remote.fetch_metadata(&["updateinfo"], tmp_dir.path());
+    assert!(matches!(
+        result,
+        Err(MetadataError::Checksum(ChecksumError::Mismatch(_)))
+    ));
+    assert!(!partial_path.exists());
+    remote.fetch_metadata(&["updateinfo"], tmp_dir.path())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_remote_repository_restart_download() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_remote_repository_restart_download")?;
+    let primary_url = "mock://mirror/repodata/primary.xml";
+
+    let fetcher = FlakyFetcher {
+        ignore_ranges: true,
+        ..Default::default()
+    };
+    let remote = RemoteRepository::new("mock://mirror/", &fetcher)?;
+    assert!(remote.fetch_metadata(&["primary"], tmp_dir.path()).is_err());
+    fetcher.fetched.borrow_mut().clear();
+
+    // the whole file replaces the partial one, rather than being downloaded a second time
+    let reader = remote.fetch_metadata(&["primary"], tmp_dir.path())?;
+    assert_eq!(fetcher.resumed.borrow().len(), 1);
+    assert!(fetcher.fetched.borrow().is_empty());
+    assert!(!tmp_dir.path().join("repodata/primary.xml.part").exists());
+    assert_eq!(
+        reader
+            .iter_packages_with(MetadataSelection {
+                filelists: false,
+                other: false
+            })?
+            .count(),
+        3
+    );
+    assert_eq!(
+        std::fs::read(tmp_dir.path().join("repodata/primary.xml"))?,
+        FlakyFetcher::read(primary_url)?
+    );
+
+    Ok(())
+}
+
+/// Serves the fixture repository, with an ETag for repomd.xml
+struct CachingFetcher {
+    etag: &'static str,
+}
+
+impl Fetcher for CachingFetcher {
+    fn fetch(&self, url: &str, writer: &mut dyn Write) -> Result<(), MetadataError> {
+        fetch_from_directory("./tests/assets/doctest_repo/", url, writer)
+    }
+
+    fn fetch_if_modified(
+        &self,
+        url: &str,
+        validators: &CacheValidators,
+        writer: &mut dyn Write,
+    ) -> Result<ConditionalFetch, MetadataError> {
+        if validators.etag.as_deref() == Some(self.etag) {
+            return Ok(ConditionalFetch::NotModified);
+        }
+        self.fetch(url, writer)?;
+        Ok(ConditionalFetch::Modified(CacheValidators {
+            etag: Some(self.etag.to_owned()),
+            last_modified: None,
+        }))
+    }
+}
+
+#[test]
+fn test_remote_repository_if_modified() -> Result<(), MetadataError> {
+    let remote = RemoteRepository::new("mock://mirror/", CachingFetcher { etag: "\"1\"" })?;
+    let validators = remote.validators().clone();
+    assert_eq!(validators.etag.as_deref(), Some("\"1\""));
+
+    let unchanged = RemoteRepository::new_if_modified(
+        "mock://mirror/",
+        CachingFetcher { etag: "\"1\"" },
+        &validators,
+    )?;
+    assert!(unchanged.is_none());
+    let changed = RemoteRepository::new_if_modified(
+        "mock://mirror/",
+        CachingFetcher { etag: "\"2\"" },
+        &validators,
+    )?
+    .unwrap();
+    assert_eq!(changed.validators().etag.as_deref(), Some("\"2\""));
+    assert!(changed.repomd().get_record("primary").is_some());
+
+    // fetchers which don't support conditional requests always retrieve the file
+    let fetcher =
+        |url: &str, writer: &mut dyn Write| CachingFetcher { etag: "" }.fetch(url, writer);
+    let remote =
+        RemoteRepository::new_if_modified("mock://mirror/", fetcher, &validators)?.unwrap();
+    assert!(remote.validators().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_remote_repository_download_package() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_remote_repository_download_package")?;
+
+    let fetcher = |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+        match url {
+            "mock://mirror/Packages/h/hello-2.10-1.fc36.x86_64.rpm" => {
+                writer.write_all(b"hello")?
+            }
+            url => fetch_from_directory("./tests/assets/doctest_repo/", url, writer)?,
+        }
+        Ok(())
+    };
+    let remote = RemoteRepository::new("mock://mirror/", fetcher)?;
+
+    let mut package = Package::default();
+    package
+        .set_name("hello")
+        .set_checksum(Checksum::Sha256(
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_owned(),
+        ))
+        .set_location_href("Packages/h/hello-2.10-1.fc36.x86_64.rpm");
+    let path = remote.download_package(&package, tmp_dir.path())?;
+    assert_eq!(
+        path,
+        tmp_dir
+            .path()
+            .join("Packages/h/hello-2.10-1.fc36.x86_64.rpm")
+    );
+    assert_eq!(std::fs::read(&path)?, b"hello");
+
+    // packages may not be placed outside of the destination
+    package.set_location_href("../hello-2.10-1.fc36.x86_64.rpm");
+    assert!(matches!(
+        remote.download_package(&package, tmp_dir.path()),
+        Err(MetadataError::RepoLayout(RepoLayoutError::UnsafeHref(_)))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_repo_syncer() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repo_syncer")?;
+    let remote_dir = tmp_dir.path().join("remote");
+    let local_dir = tmp_dir.path().join("local");
+
+    // (re)create the remote repository with the given packages, each a file with some unique contents
+    let publish = |names: &[&str], revision: &str| -> Result<(), MetadataError> {
+        let mut writer = RepositoryWriter::new(&remote_dir, names.len())?;
+        writer.repomd_mut().set_revision(revision);
+        for name in names {
+            let href = format!("Packages/{}-1.0-1.noarch.rpm", name);
+            let path = remote_dir.join(&href);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, format!("contents of {}", name))?;
+
+            let mut package = Package::default();
+            package
+                .set_name(*name)
+                .set_arch("noarch")
+                .set_evr(EVR::new("0", "1.0", "1"))
+                .set_checksum(utils::checksum_file(
+                    &path,
+                    rpmrepo_metadata::ChecksumType::Sha256,
+                )?)
+                .set_location_href(href);
+            writer.add_package(&package)?;
+        }
+        writer.finish()
+    };
+    let sync = || -> Result<SyncReport, MetadataError> {
+        let remote_url = format!("{}/", remote_dir.display());
+        let fetcher = move |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+            fetch_from_directory(&remote_url, url, writer)
+        };
+        RepoSyncer::new(
+            RemoteRepository::new("mock://mirror/", fetcher)?,
+            &local_dir,
+        )
+        .sync()
+    };
+    let local_names = || -> Result<Vec<String>, MetadataError> {
+        let repo = Repository::load_from_directory(&local_dir)?;
+        let mut names: Vec<String> = repo
+            .packages()
+            .values()
+            .map(|p| p.name().to_owned())
+            .collect();
+        names.sort();
+        Ok(names)
+    };
+
+    publish(&["alpha", "beta"], "1")?;
+    let report = sync()?;
+    assert_eq!(report.downloaded.len(), 2);
+    assert_eq!(report.unchanged, 0);
+    assert!(local_dir.join("Packages/alpha-1.0-1.noarch.rpm").exists());
+    assert_eq!(local_names()?, vec!["alpha", "beta"]);
+
+    // nothing to do if the revision hasn't changed
+    let report = sync()?;
+    assert!(report.up_to_date);
+    assert!(report.downloaded.is_empty());
+
+    // only the difference is transferred
+    publish(&["beta", "gamma"], "2")?;
+    let report = sync()?;
+    assert!(!report.up_to_date);
+    assert_eq!(report.downloaded, vec!["Packages/gamma-1.0-1.noarch.rpm"]);
+    assert_eq!(report.removed, vec!["Packages/alpha-1.0-1.noarch.rpm"]);
+    assert_eq!(report.unchanged, 1);
+    assert!(!local_dir.join("Packages/alpha-1.0-1.noarch.rpm").exists());
+    assert_eq!(local_names()?, vec!["beta", "gamma"]);
+    let local = RepositoryReader::new_from_directory(&local_dir)?;
+    assert_eq!(local.repomd().revision(), Some("2"));
+
+    // a new snapshot only downloads what isn't in the previous one
+    publish(&["beta", "gamma", "delta"], "3")?;
+    let snapshot_dir = tmp_dir.path().join("snapshot");
+    let remote_url = format!("{}/", remote_dir.display());
+    let fetcher = move |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+        fetch_from_directory(&remote_url, url, writer)
+    };
+    let report = RepoSyncer::new(
+        RemoteRepository::new("mock://mirror/", fetcher)?,
+        &snapshot_dir,
+    )
+    .reuse_packages_from(&local_dir, TransferMode::Hardlink)
+    .sync()?;
+    assert_eq!(report.downloaded, vec!["Packages/delta-1.0-1.noarch.rpm"]);
+    assert_eq!(
+        report.reused,
+        vec![
+            "Packages/beta-1.0-1.noarch.rpm",
+            "Packages/gamma-1.0-1.noarch.rpm"
+        ]
+    );
+    assert!(local_dir.join("Packages/beta-1.0-1.noarch.rpm").exists());
+    assert_eq!(
+        std::fs::read_to_string(snapshot_dir.join("Packages/gamma-1.0-1.noarch.rpm"))?,
+        "contents of gamma"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_repo_syncer_arches() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repo_syncer_arches")?;
+    let remote_dir = tmp_dir.path().join("remote");
+    let local_dir = tmp_dir.path().join("local");
+
+    let packages = [("alpha", "x86_64"), ("beta", "noarch"), ("gamma", "i686")];
+    let mut writer = RepositoryWriter::new(&remote_dir, packages.len())?;
+    for (name, arch) in packages {
+        let href = format!("Packages/{}-1.0-1.{}.rpm", name, arch);
+        let path = remote_dir.join(&href);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, format!("contents of {}", name))?;
+
+        let mut package = Package::default();
+        package
+            .set_name(name)
+            .set_arch(arch)
+            .set_evr(EVR::new("0", "1.0", "1"))
+            .set_checksum(utils::checksum_file(
+                &path,
+                rpmrepo_metadata::ChecksumType::Sha256,
+            )?)
+            .set_location_href(href);
+        writer.add_package(&package)?;
+    }
+    writer.finish()?;
+
+    let remote_url = format!("{}/", remote_dir.display());
+    let fetcher = move |url: &str, writer: &mut dyn Write| -> Result<(), MetadataError> {
+        fetch_from_directory(&remote_url, url, writer)
+    };
+    let report = RepoSyncer::new(
+        RemoteRepository::new("mock://mirror/", fetcher)?,
+        &local_dir,
+    )
+    .with_arches(&["x86_64", "noarch"])
+    .sync()?;
+    assert_eq!(
+        report.downloaded,
+        vec![
+            "Packages/alpha-1.0-1.x86_64.rpm",
+            "Packages/beta-1.0-1.noarch.rpm"
+        ]
+    );
+    assert!(!local_dir.join("Packages/gamma-1.0-1.i686.rpm").exists());
+
+    let repo = Repository::load_from_directory(&local_dir)?;
+    let mut names: Vec<&str> = repo.packages().values().map(|p| p.name()).collect();
+    names.sort();
+    assert_eq!(names, vec!["alpha", "beta"]);
+
+    Ok(())
+}
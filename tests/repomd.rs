@@ -6,14 +6,14 @@
 
 use std::fs::File;
 
-use rpmrepo_metadata::{utils, MetadataError, RepomdData, RepomdRecord, RepomdXml};
+use rpmrepo_metadata::{utils, MetadataError, RepomdData, RepomdRecord, RepomdXml, TargetClient};
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use once_cell::sync::OnceCell;
     use pretty_assertions::assert_eq;
-    use rpmrepo_metadata::Checksum;
+    use rpmrepo_metadata::{Checksum, ChecksumError, ChecksumType, RepoLayoutError};
     use std::{
         io::Read,
         path::{Path, PathBuf},
@@ -162,4 +162,191 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_serialization_target_client() -> Result<(), MetadataError> {
+        let checksum = || {
+            Checksum::Sha256(String::from(
+                "e6104a05bf3101c01321a5af9098d569ff974a8e6a8f72c5982bf074efbaf036",
+            ))
+        };
+        let mut repomd = RepomdData::default();
+        repomd.set_revision("1615686706");
+        for (name, href) in [
+            ("primary", "repodata/primary.xml.gz"),
+            ("primary_db", "repodata/primary.sqlite.bz2"),
+            ("primary_zck", "repodata/primary.xml.zck"),
+        ] {
+            let mut record = RepomdRecord::default();
+            record.metadata_name = String::from(name);
+            record.checksum = checksum();
+            record.open_checksum = Some(checksum());
+            record.timestamp = 1639195237;
+            record.size = Some(1971);
+            record.open_size = Some(6527);
+            record.location_href = PathBuf::from(href);
+            if name == "primary_db" {
+                record.database_version = Some(10);
+            }
+            if name == "primary_zck" {
+                record.header_checksum = Some(checksum());
+                record.header_size = Some(1042);
+            }
+            repomd.add_record(record);
+        }
+
+        let write = |target: TargetClient| -> Result<String, MetadataError> {
+            let mut buffer = Vec::new();
+            RepomdXml::write_data_for_target(
+                &repomd,
+                &mut utils::create_xml_writer(&mut buffer),
+                target,
+            )?;
+            Ok(String::from_utf8(buffer).unwrap())
+        };
+
+        // the default writes everything
+        let mut default = Vec::new();
+        RepomdXml::write_data(&repomd, &mut utils::create_xml_writer(&mut default))?;
+        let dnf4 = write(TargetClient::Dnf4)?;
+        assert_eq!(std::str::from_utf8(&default)?, dnf4);
+        assert!(dnf4.contains("<data type=\"primary_zck\">"));
+        assert!(dnf4.contains("<header-size>1042</header-size>"));
+        assert!(dnf4.contains("<database_version>10</database_version>"));
+
+        let yum3 = write(TargetClient::Yum3)?;
+        assert!(!yum3.contains("primary_zck"));
+        assert!(!yum3.contains("<size>"));
+        assert!(!yum3.contains("<open-size>"));
+        assert!(yum3.contains("<data type=\"primary_db\">"));
+        assert!(yum3.contains("<open-checksum type=\"sha256\">"));
+
+        let dnf5 = write(TargetClient::Dnf5)?;
+        assert!(!dnf5.contains("primary_db"));
+        assert!(!dnf5.contains("database_version"));
+        assert!(dnf5.contains("<header-size>1042</header-size>"));
+
+        // the output can still be read
+        for output in [yum3, dnf5] {
+            let actual = RepomdXml::read_data(utils::create_xml_reader(output.as_bytes()))?;
+            assert_eq!(actual.get_record("primary").unwrap().checksum, checksum());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_parse() {
+        let digest = "6a915b6e1ad740994aa9688d70a67ff2b6b72e0ced668794aeb27b2d0f2e237b";
+        let checksum = Checksum::parse("sha256", digest).unwrap();
+        assert_eq!(checksum, Checksum::Sha256(digest.to_owned()));
+        assert_eq!(checksum.digest(), digest);
+        assert_eq!(checksum.to_values().unwrap(), ("sha256", digest));
+
+        // "sha" is an alias for sha1
+        let sha1 = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        assert_eq!(
+            Checksum::parse("sha", sha1).unwrap(),
+            Checksum::Sha1(sha1.to_owned())
+        );
+
+        assert!(matches!(
+            Checksum::parse("sha256", &digest[1..]),
+            Err(MetadataError::Checksum(ChecksumError::Invalid(_, _)))
+        ));
+        assert!(matches!(
+            Checksum::parse("sha256", &digest.replace('a', "z")),
+            Err(MetadataError::Checksum(ChecksumError::Invalid(_, _)))
+        ));
+        assert!(matches!(
+            Checksum::parse("crc32", "12345678"),
+            Err(MetadataError::Checksum(ChecksumError::UnsupportedType(_)))
+        ));
+    }
+
+    #[test]
+    fn test_remove_and_replace_records() {
+        let mut repomd = fixture_data().clone();
+        let names = |repomd: &RepomdData| -> Vec<String> {
+            repomd
+                .records()
+                .iter()
+                .map(|r| r.metadata_name.clone())
+                .collect()
+        };
+
+        // replacing keeps the position of the existing record
+        let mut record = repomd.get_record("filelists").unwrap().clone();
+        record.location_href = PathBuf::from("repodata/filelists.xml.zst");
+        let old = repomd.replace_record(record).unwrap();
+        assert_eq!(
+            old.location_href,
+            PathBuf::from("repodata/filelists.xml.gz")
+        );
+        assert_eq!(names(&repomd), vec!["primary", "filelists", "other"]);
+        assert_eq!(
+            repomd.get_record("filelists").unwrap().location_href,
+            PathBuf::from("repodata/filelists.xml.zst")
+        );
+
+        // replacing a record which doesn't exist yet adds it
+        let mut record = RepomdRecord::default();
+        record.metadata_name = String::from("updateinfo");
+        assert!(repomd.replace_record(record).is_none());
+        assert_eq!(
+            names(&repomd),
+            vec!["primary", "filelists", "other", "updateinfo"]
+        );
+
+        repomd.get_record_mut("updateinfo").unwrap().size = Some(42);
+        assert_eq!(repomd.get_record("updateinfo").unwrap().size, Some(42));
+
+        repomd.sort_records_by(|a, b| a.metadata_name.cmp(&b.metadata_name));
+        assert_eq!(
+            names(&repomd),
+            vec!["filelists", "other", "primary", "updateinfo"]
+        );
+
+        assert_eq!(
+            repomd.remove_record("other").unwrap().metadata_name,
+            "other"
+        );
+        assert!(repomd.remove_record("other").is_none());
+        assert_eq!(names(&repomd), vec!["filelists", "primary", "updateinfo"]);
+    }
+
+    #[test]
+    fn test_missing_records_and_paths() {
+        let repomd = fixture_data();
+        assert_eq!(repomd.get_primary_data().unwrap().metadata_name, "primary");
+        assert_eq!(repomd.get_other_data().unwrap().metadata_name, "other");
+
+        let empty = RepomdData::default();
+        assert!(matches!(
+            empty.get_filelist_data(),
+            Err(MetadataError::RepoLayout(RepoLayoutError::MissingRecord(
+                "filelists"
+            )))
+        ));
+
+        let result = RepomdRecord::new(
+            "primary",
+            Path::new("elsewhere/primary.xml"),
+            Path::new("."),
+            ChecksumType::Sha256,
+        );
+        assert!(matches!(
+            result,
+            Err(MetadataError::RepoLayout(
+                RepoLayoutError::MetadataLocation(_)
+            ))
+        ));
+
+        // records which weren't created from a file have no path to fill the values from
+        let mut record = repomd.get_primary_data().unwrap().clone();
+        assert!(matches!(
+            record.fill(ChecksumType::Sha256),
+            Err(MetadataError::RepoLayout(RepoLayoutError::MissingBasePath))
+        ));
+    }
 }
@@ -6,9 +6,18 @@
 
 use pretty_assertions::assert_eq;
 use rpmrepo_metadata::{
-    MetadataError, Package, Repository, RepositoryOptions, RepositoryReader, RepositoryWriter,
+    read_packages, register_decompressor, unregister_decompressor, utils, Checksum, ChecksumError,
+    CompressionType, Decompressor, FileOwner, FilelistsXml, HrefPolicy, MetadataCache,
+    MetadataError, MetadataSelection, OtherXml, Package, PackageIterator, PackageQuery, ParseError,
+    ParseLimits, PrimaryXml, ProgressEvent, RawPackage, RepoLayoutError, RepomdXml, Repository,
+    RepositoryOptions, RepositoryReader, RepositoryWriter, UpdateRecord, UpdateinfoXml, WriteError,
+    EVR,
 };
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
 use tempdir::TempDir;
+
 mod common;
 
 static COMPLEX_REPO_PATH: &str = "./tests/assets/fixture_repos/complex_repo/";
@@ -87,7 +96,8 @@ fn test_read_write_uncompressed() -> Result<(), MetadataError> {
 
     let options = RepositoryOptions::default()
         .metadata_checksum_type(rpmrepo_metadata::ChecksumType::Sha1)
-        .metadata_compression_type(rpmrepo_metadata::CompressionType::None);
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::None)
+        .simple_metadata_filenames(true);
     let mut repo_writer = RepositoryWriter::new_with_options(&tmp_dir.path(), 1, options)?;
     repo_writer.add_package(&*common::COMPLEX_PACKAGE)?;
     repo_writer.finish()?;
@@ -124,7 +134,8 @@ fn test_read_write_zstd_compressed() -> Result<(), MetadataError> {
 
     let options = RepositoryOptions::default()
         .metadata_checksum_type(rpmrepo_metadata::ChecksumType::Sha1)
-        .metadata_compression_type(rpmrepo_metadata::CompressionType::Zstd);
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::Zstd)
+        .simple_metadata_filenames(true);
     let mut repo_writer = RepositoryWriter::new_with_options(&tmp_dir.path(), 1, options)?;
     repo_writer.add_package(&*common::COMPLEX_PACKAGE)?;
     repo_writer.finish()?;
@@ -161,7 +172,8 @@ fn test_read_write_xz_compressed() -> Result<(), MetadataError> {
 
     let options = RepositoryOptions::default()
         .metadata_checksum_type(rpmrepo_metadata::ChecksumType::Sha1)
-        .metadata_compression_type(rpmrepo_metadata::CompressionType::Xz);
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::Xz)
+        .simple_metadata_filenames(true);
     let mut repo_writer = RepositoryWriter::new_with_options(&tmp_dir.path(), 1, options)?;
     repo_writer.add_package(&*common::COMPLEX_PACKAGE)?;
     repo_writer.finish()?;
@@ -198,7 +210,8 @@ fn test_read_write_bz2_compressed() -> Result<(), MetadataError> {
 
     let options = RepositoryOptions::default()
         .metadata_checksum_type(rpmrepo_metadata::ChecksumType::Sha1)
-        .metadata_compression_type(rpmrepo_metadata::CompressionType::Bz2);
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::Bz2)
+        .simple_metadata_filenames(true);
     let mut repo_writer = RepositoryWriter::new_with_options(&tmp_dir.path(), 1, options)?;
     repo_writer.add_package(&*common::COMPLEX_PACKAGE)?;
     repo_writer.finish()?;
@@ -227,23 +240,1041 @@ fn test_read_write_bz2_compressed() -> Result<(), MetadataError> {
     Ok(())
 }
 
-// TODO: these tests need to be specific about what is panicking
+#[test]
+fn test_repository_query() {
+    let mut repo = Repository::new();
+    for pkg in [
+        &*common::COMPLEX_PACKAGE,
+        &*common::RPM_EMPTY,
+        &*common::RPM_WITH_INVALID_CHARS,
+        &*common::RPM_WITH_NON_ASCII,
+    ] {
+        repo.packages_mut()
+            .insert(pkg.pkgid().to_owned(), pkg.clone());
+    }
+
+    let mut older_complex_package = common::COMPLEX_PACKAGE.clone();
+    older_complex_package
+        .set_evr(EVR::new("1", "2.3.3", "1.el8"))
+        .set_checksum(Checksum::Sha256(
+            "0000000000000000000000000000000000000000000000000000000000000000".to_owned(),
+        ));
+    repo.packages_mut().insert(
+        older_complex_package.pkgid().to_owned(),
+        older_complex_package,
+    );
+
+    let names = |query: PackageQuery| -> Vec<String> { query.iter().map(|p| p.nevra()).collect() };
+
+    assert_eq!(repo.query().count(), 5);
+    assert_eq!(
+        names(repo.query().name_glob("rpm-with-*")),
+        vec![
+            common::RPM_WITH_INVALID_CHARS.nevra(),
+            common::RPM_WITH_NON_ASCII.nevra()
+        ]
+    );
+    assert_eq!(
+        names(repo.query().arch("x86_64").name_glob("*package")),
+        vec![
+            common::COMPLEX_PACKAGE.nevra(),
+            "complex-package-1:2.3.3-1.el8.x86_64".to_owned()
+        ]
+    );
+    assert_eq!(
+        names(repo.query().name("complex-package").newest_per_name_arch()),
+        vec![common::COMPLEX_PACKAGE.nevra()]
+    );
+    assert_eq!(repo.query().arches(&["noarch", "x86_64"]).count(), 5);
+    assert_eq!(repo.query().name_glob("kernel*").count(), 0);
+    assert_eq!(
+        repo.query()
+            .filter(|p| p.size_package() > 6400)
+            .newest_per_name_arch()
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn test_repository_reader_cache() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_cache")?;
+    let repo_dir = tmp_dir.path().join("repo");
+    let cache_dir = tmp_dir.path().join("cache");
+
+    let options = RepositoryOptions::default()
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::Gzip);
+    let mut repo_writer = RepositoryWriter::new_with_options(&repo_dir, 1, options)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let num_cached = || std::fs::read_dir(&cache_dir).unwrap().count();
+
+    // first read populates the cache, second read is served from it
+    for _ in 0..2 {
+        let mut reader = RepositoryReader::new_from_directory(&repo_dir)?;
+        reader.set_cache(MetadataCache::new(&cache_dir)?);
+        let repo = reader.into_repo()?;
+        assert_eq!(
+            repo.packages().values().collect::<Vec<_>>(),
+            vec![&*common::COMPLEX_PACKAGE]
+        );
+        assert_eq!(num_cached(), 3);
+    }
+
+    // entries are evicted once the cache exceeds its maximum size
+    let mut reader = RepositoryReader::new_from_directory(&repo_dir)?;
+    let cache = MetadataCache::new(&cache_dir)?.with_max_size(1);
+    cache.evict()?;
+    assert_eq!(num_cached(), 0);
+    reader.set_cache(cache);
+    let repo = reader.into_repo()?;
+    assert_eq!(repo.packages().len(), 1);
+    assert_eq!(num_cached(), 0);
 
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
 #[test]
-#[should_panic]
-fn test_repository_writer_not_enough_packages() {
-    let tmp_dir = TempDir::new("test_repository_writer").unwrap();
+fn test_repository_reader_memory_map() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_memory_map")?;
+
+    for compression in [
+        rpmrepo_metadata::CompressionType::None,
+        rpmrepo_metadata::CompressionType::Gzip,
+    ] {
+        let repo_dir = tmp_dir.path().join(format!("repo-{:?}", compression));
+        let options = RepositoryOptions::default().metadata_compression_type(compression);
+        let mut repo_writer = RepositoryWriter::new_with_options(&repo_dir, 1, options)?;
+        repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+        repo_writer.finish()?;
+
+        let mut reader = RepositoryReader::new_from_directory(&repo_dir)?;
+        reader.set_memory_map(true);
+        let repo = reader.into_repo()?;
+        assert_eq!(
+            repo.packages().values().collect::<Vec<_>>(),
+            vec![&*common::COMPLEX_PACKAGE]
+        );
+
+        // decompressed files in the cache are mapped as well
+        let mut reader = RepositoryReader::new_from_directory(&repo_dir)?;
+        reader.set_cache(MetadataCache::new(&tmp_dir.path().join("cache"))?);
+        reader.set_memory_map(true);
+        let repo = reader.into_repo()?;
+        assert_eq!(repo.packages().len(), 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_reader_metadata_selection() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_reader")?;
+
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 1)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let selection = MetadataSelection {
+        filelists: false,
+        other: false,
+    };
+    let packages = reader
+        .iter_packages_with(selection)?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].nevra(), common::COMPLEX_PACKAGE.nevra());
+    assert!(packages[0].changelogs().is_empty());
+    assert!(packages[0].files().len() < common::COMPLEX_PACKAGE.files().len());
+
+    // filelists and other metadata which aren't present in repomd.xml are skipped
+    let tmp_dir = TempDir::new("test_repository_reader")?;
+    let options = RepositoryOptions::default()
+        .write_filelists(false)
+        .write_other(false);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let packages = reader
+        .iter_packages()?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].nevra(), common::COMPLEX_PACKAGE.nevra());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_reader_open_record() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_reader")?;
+
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 1)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let primary_path = reader.record_path("primary").unwrap();
+    assert!(primary_path.starts_with(tmp_dir.path().join("repodata")));
+    assert!(primary_path.exists());
+
+    // the contents are decompressed
+    let mut primary = String::new();
+    reader
+        .open_record("primary")?
+        .unwrap()
+        .read_to_string(&mut primary)?;
+    assert!(primary.starts_with("<?xml"));
+    assert!(primary.contains("<name>complex-package</name>"));
+    assert_eq!(reader.read_record("primary")?.unwrap(), primary.as_bytes());
+
+    assert!(reader.record_path("group").is_none());
+    assert!(reader.open_record("group")?.is_none());
+    assert!(reader.read_record("group")?.is_none());
+
+    // files which don't match the checksum in repomd.xml are rejected
+    std::fs::write(&primary_path, b"corrupted")?;
+    assert!(matches!(
+        reader.open_record("primary"),
+        Err(MetadataError::Checksum(ChecksumError::Mismatch(_)))
+    ));
+
+    Ok(())
+}
+
+/// A toy format which XORs every byte after its magic number.
+struct XorDecompressor;
+
+impl Decompressor for XorDecompressor {
+    fn name(&self) -> &str {
+        "xor"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[".xor"]
+    }
+
+    fn magic(&self) -> &[&[u8]] {
+        &[b"XOR\x01"]
+    }
 
-    let mut repo_writer = RepositoryWriter::new(&tmp_dir.path(), 1).unwrap();
-    repo_writer.finish().unwrap();
+    fn decompress(
+        &self,
+        mut reader: Box<dyn Read + Send>,
+    ) -> std::io::Result<Box<dyn Read + Send>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        data.iter_mut().for_each(|b| *b ^= 0x5a);
+        Ok(Box::new(Cursor::new(data)))
+    }
 }
 
 #[test]
-#[should_panic]
-fn test_repository_writer_too_many_packages() {
-    let tmp_dir = TempDir::new("test_repository_writer").unwrap();
+fn test_register_decompressor() -> Result<(), MetadataError> {
+    let source = std::path::Path::new("./tests/assets/doctest_repo/repodata");
+    let primary_xml = std::fs::read(source.join("primary.xml"))?;
 
-    let mut repo_writer = RepositoryWriter::new(&tmp_dir.path(), 0).unwrap();
-    repo_writer.add_package(&*common::COMPLEX_PACKAGE).unwrap();
-    repo_writer.finish().unwrap();
+    let mut encoded = b"XOR\x01".to_vec();
+    encoded.extend(primary_xml.iter().map(|b| b ^ 0x5a));
+
+    // a copy of the repository with primary.xml in the custom format
+    let tmp_dir = TempDir::new("test_register_decompressor")?;
+    let repodata = tmp_dir.path().join("repodata");
+    std::fs::create_dir(&repodata)?;
+    for name in ["filelists.xml", "other.xml", "updateinfo.xml"] {
+        std::fs::copy(source.join(name), repodata.join(name))?;
+    }
+    std::fs::write(repodata.join("primary.xml.xor"), &encoded)?;
+    let repomd_xml = std::fs::read_to_string(source.join("repomd.xml"))?;
+    std::fs::write(
+        repodata.join("repomd.xml"),
+        repomd_xml.replace("repodata/primary.xml\"", "repodata/primary.xml.xor\""),
+    )?;
+
+    // without the decompressor, the content isn't recognized
+    assert!(read_packages(Cursor::new(encoded.clone())).is_err());
+    assert!(RepositoryReader::new_from_directory(tmp_dir.path())?
+        .iter_packages()
+        .is_err());
+
+    register_decompressor(XorDecompressor);
+
+    // streams are identified by their magic number
+    let packages = read_packages(Cursor::new(encoded.clone()))?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(packages.len(), 3);
+
+    // the readers use it too
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let packages = reader.iter_packages()?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(packages.len(), 3);
+
+    // files are identified by their magic number, or failing that, their extension
+    let magic_path = tmp_dir.path().join("primary.xml.bin");
+    std::fs::write(&magic_path, &encoded)?;
+    for path in [&magic_path, &repodata.join("primary.xml.xor")] {
+        let mut decoded = Vec::new();
+        utils::reader_from_file(path)?.read_to_end(&mut decoded)?;
+        assert_eq!(decoded, primary_xml);
+        assert_eq!(
+            utils::size_inner_file(path)?,
+            Some(primary_xml.len() as u64)
+        );
+    }
+
+    // an extension doesn't override the format identified by the content
+    let (gzip_path, mut writer) =
+        utils::writer_to_file(&tmp_dir.path().join("primary.xml"), CompressionType::Gzip)?;
+    writer.write_all(&primary_xml)?;
+    drop(writer);
+    let mislabeled_path = tmp_dir.path().join("mislabeled.xml.xor");
+    std::fs::rename(&gzip_path, &mislabeled_path)?;
+    let mut decoded = Vec::new();
+    utils::reader_from_file(&mislabeled_path)?.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, primary_xml);
+
+    assert!(unregister_decompressor("xor"));
+    assert!(!unregister_decompressor("xor"));
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_handler() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_progress_handler")?;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let writer_events = events.clone();
+    let options = RepositoryOptions::default().simple_metadata_filenames(true);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 2, options)?;
+    repo_writer.set_progress_handler(Arc::new(move |event: &ProgressEvent| {
+        let description = match event {
+            ProgressEvent::PackageWritten { count, total } => {
+                format!("package {}/{}", count, total)
+            }
+            ProgressEvent::AdvisoryWritten { count } => format!("advisory {}", count),
+            ProgressEvent::MetadataFileWritten { name, path } => {
+                assert!(path.exists());
+                format!("file {}", name)
+            }
+            _ => unreachable!(),
+        };
+        writer_events.lock().unwrap().push(description);
+    }));
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.add_package(&common::RPM_EMPTY)?;
+    repo_writer.add_advisory(&UpdateRecord {
+        id: "RHSA-2022:0001".to_owned(),
+        ..UpdateRecord::default()
+    })?;
+    repo_writer.finish()?;
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "package 1/2",
+            "package 2/2",
+            "advisory 1",
+            "file primary",
+            "file filelists",
+            "file other",
+            "file updateinfo",
+            "file repomd",
+        ]
+    );
+
+    let packages_read = Arc::new(Mutex::new(Vec::new()));
+    let bytes_read = Arc::new(Mutex::new(HashMap::new()));
+    let (reader_packages, reader_bytes) = (packages_read.clone(), bytes_read.clone());
+    let mut reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    reader.set_progress_handler(Arc::new(move |event: &ProgressEvent| match event {
+        ProgressEvent::PackageRead { count, total } => {
+            reader_packages.lock().unwrap().push((*count, *total))
+        }
+        ProgressEvent::BytesRead { file, bytes, total } => {
+            assert!(Some(*bytes) <= *total);
+            reader_bytes
+                .lock()
+                .unwrap()
+                .insert(file.to_string(), (*bytes, *total));
+        }
+        _ => unreachable!(),
+    }));
+    assert_eq!(
+        reader
+            .iter_packages()?
+            .collect::<Result<Vec<_>, _>>()?
+            .len(),
+        2
+    );
+
+    assert_eq!(*packages_read.lock().unwrap(), vec![(1, 2), (2, 2)]);
+    // primary.xml is read to the end, filelists.xml and other.xml only as far as needed
+    let bytes_read = bytes_read.lock().unwrap();
+    let (bytes, total) = bytes_read["primary"];
+    assert_eq!(Some(bytes), total);
+    assert!(bytes_read.contains_key("filelists"));
+    assert!(bytes_read.contains_key("other"));
+
+    Ok(())
+}
+
+#[test]
+fn test_arch_filter() -> Result<(), MetadataError> {
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+
+    let mut reader = RepositoryReader::new_from_directory(source)?;
+    reader.set_arches(&["x86_64"]);
+    let names: Vec<String> = reader
+        .iter_packages()?
+        .map(|pkg| pkg.map(|pkg| pkg.name().to_owned()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(names, vec!["hello"]);
+
+    reader.set_arches(&["noarch", "aarch64"]);
+    let repo = reader.into_repo()?;
+    assert_eq!(repo.packages().len(), 2);
+    assert!(repo.packages().values().all(|pkg| pkg.arch() == "noarch"));
+
+    // packages of other architectures are skipped and don't count towards the declared number
+    let tmp_dir = TempDir::new("test_arch_filter")?;
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 2)?;
+    repo_writer.set_arches(&["noarch"]);
+    for package in RepositoryReader::new_from_directory(source)?.iter_packages()? {
+        repo_writer.add_package(&package?)?;
+    }
+    repo_writer.finish()?;
+
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    assert_eq!(repo.packages().len(), 2);
+    assert!(repo.packages().values().all(|pkg| pkg.arch() == "noarch"));
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_reader_from_tarball() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_reader_from_tarball")?;
+
+    let reader = RepositoryReader::from_tarball(
+        std::path::Path::new("./tests/assets/doctest_repo.tar.gz"),
+        tmp_dir.path(),
+    )?;
+    assert_eq!(reader.iter_packages()?.total_packages(), 3);
+    assert_eq!(reader.iter_advisories()?.count(), 1);
+    assert!(reader.validate()?.is_valid());
+
+    Ok(())
+}
+
+#[test]
+fn test_find_file_owners() -> Result<(), MetadataError> {
+    let reader =
+        RepositoryReader::new_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+
+    let owners = reader
+        .find_file_owners("*/bin/*")?
+        .collect::<Result<Vec<FileOwner>, _>>()?;
+    assert_eq!(owners.len(), 2);
+    assert_eq!(owners[0].nevra, "hello-0:2.10-1.fc36.x86_64");
+    assert_eq!(
+        owners[0].pkgid,
+        "a8c1d2b0e7f35c8e6b5a4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f"
+    );
+    assert_eq!(owners[0].paths, vec!["/usr/bin/hello"]);
+    assert_eq!(owners[1].nevra, "goodbye-0:1.0-3.fc36.noarch");
+    assert_eq!(owners[1].paths, vec!["/usr/bin/goodbye"]);
+
+    let owners = reader
+        .find_file_owners("/usr/share/doc/hello*/*")?
+        .collect::<Result<Vec<FileOwner>, _>>()?;
+    assert_eq!(owners.len(), 2);
+    assert_eq!(
+        owners[1].paths,
+        vec!["/usr/share/doc/hello-docs/manual.html"]
+    );
+
+    assert_eq!(reader.find_file_owners("/nonexistent")?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_advisory_package_queries() -> Result<(), MetadataError> {
+    let repo =
+        Repository::load_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+
+    let ids = |advisories: Vec<&UpdateRecord>| -> Vec<String> {
+        advisories.iter().map(|a| a.id.clone()).collect()
+    };
+
+    // older versions of hello are fixed by the advisory, the same or newer versions are not
+    assert_eq!(
+        ids(repo.advisories_for_package("hello-2.9-3.fc36.x86_64")?),
+        vec!["EXAMPLE-2022-0001"]
+    );
+    assert_eq!(
+        ids(repo.advisories_for_package("hello-0:2.10-1~rc1.fc36.x86_64")?),
+        vec!["EXAMPLE-2022-0001"]
+    );
+    assert!(repo
+        .advisories_for_package("hello-0:2.10-1.fc36.x86_64")?
+        .is_empty());
+    assert!(repo
+        .advisories_for_package("hello-1:1.0-1.fc36.x86_64")?
+        .is_empty());
+    assert!(repo
+        .advisories_for_package("hello-2.9-3.fc36.aarch64")?
+        .is_empty());
+    assert!(matches!(
+        repo.advisories_for_package("hello"),
+        Err(MetadataError::Parse(ParseError::InvalidNevra(_)))
+    ));
+
+    let packages = repo.packages_for_advisory("EXAMPLE-2022-0001").unwrap();
+    assert_eq!(
+        packages.iter().map(|p| p.nevra()).collect::<Vec<_>>(),
+        vec!["hello-0:2.10-1.fc36.x86_64"]
+    );
+    assert!(repo.packages_for_advisory("EXAMPLE-2022-9999").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_retain_newest_versions() {
+    let mut repo = Repository::new();
+    for (i, (version, release, arch)) in [
+        ("1.0", "1", "x86_64"),
+        ("1.0", "2", "x86_64"),
+        ("1.1", "1", "x86_64"),
+        ("1.2", "1", "x86_64"),
+        ("1.0", "1", "aarch64"),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let checksum = Checksum::Sha256(format!("{:064}", i));
+        let pkg = Package::new(
+            "hello",
+            &EVR::new("", *version, *release),
+            arch,
+            &checksum,
+            &format!("Packages/h/hello-{}-{}.{}.rpm", version, release, arch),
+        );
+        repo.packages_mut().insert(pkg.pkgid().to_owned(), pkg);
+    }
+    // a rebuild of the newest version doesn't count as a separate version
+    let mut rebuild = repo.packages().values().nth(3).unwrap().clone();
+    rebuild.set_checksum(Checksum::Sha256(format!("{:064}", 9)));
+    repo.packages_mut()
+        .insert(rebuild.pkgid().to_owned(), rebuild);
+
+    let mut removed: Vec<String> = repo
+        .retain_newest_versions(2)
+        .iter()
+        .map(|p| p.nvra())
+        .collect();
+    removed.sort();
+    assert_eq!(
+        removed,
+        vec![
+            "hello-1.0-1.x86_64".to_owned(),
+            "hello-1.0-2.x86_64".to_owned()
+        ]
+    );
+    let kept: Vec<String> = repo.packages().values().map(|p| p.nvra()).collect();
+    assert_eq!(
+        kept,
+        vec![
+            "hello-1.1-1.x86_64".to_owned(),
+            "hello-1.2-1.x86_64".to_owned(),
+            "hello-1.0-1.aarch64".to_owned(),
+            "hello-1.2-1.x86_64".to_owned(),
+        ]
+    );
+
+    assert!(repo.retain_newest_versions(2).is_empty());
+    assert_eq!(repo.retain_newest_versions(0).len(), 4);
+    assert!(repo.packages().is_empty());
+}
+
+#[test]
+fn test_repository_duplicate_checks() -> Result<(), MetadataError> {
+    let mut repo =
+        Repository::load_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+    repo.check_duplicates()?;
+
+    // same NEVRA, different pkgid - an empty epoch is the same as "0"
+    let mut duplicate = repo.packages()[0].clone();
+    duplicate
+        .set_evr(EVR::new(
+            "",
+            duplicate.evr().version(),
+            duplicate.evr().release(),
+        ))
+        .set_checksum(Checksum::Sha256(format!("{:064}", 0)));
+    assert!(matches!(
+        repo.add_package(duplicate.clone()),
+        Err(MetadataError::Write(WriteError::DuplicatePackage(_)))
+    ));
+
+    let mut newer = duplicate.clone();
+    newer
+        .set_release("2.fc36")
+        .set_checksum(Checksum::Sha256(format!("{:064}", 1)));
+    repo.add_package(newer)?;
+    assert_eq!(repo.packages().len(), 4);
+
+    let advisory = repo.advisories()[0].clone();
+    assert!(matches!(
+        repo.add_advisory(advisory),
+        Err(MetadataError::Write(WriteError::DuplicateAdvisory(_)))
+    ));
+
+    // duplicates inserted directly are caught before writing
+    repo.packages_mut()
+        .insert(duplicate.pkgid().to_owned(), duplicate);
+    assert!(matches!(
+        repo.check_duplicates(),
+        Err(MetadataError::Write(WriteError::DuplicatePackage(_)))
+    ));
+    let out_dir = TempDir::new("duplicate_checks")?;
+    assert!(repo.write_to_directory(out_dir.path()).is_err());
+
+    // and so are duplicates in metadata which is loaded
+    let mut writer = RepositoryWriter::new(out_dir.path(), repo.packages().len())?;
+    for package in repo.packages().values() {
+        writer.add_package(package)?;
+    }
+    writer.finish()?;
+    assert!(matches!(
+        Repository::load_from_directory(out_dir.path()),
+        Err(MetadataError::Write(WriteError::DuplicatePackage(_)))
+    ));
+    assert_eq!(
+        RepositoryReader::new_from_directory(out_dir.path())?
+            .iter_packages()?
+            .count(),
+        repo.packages().len()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_package_access_by_nevra() -> Result<(), MetadataError> {
+    let mut repo =
+        Repository::load_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+
+    let hello = repo
+        .get_package_by_nevra("hello-2.10-1.fc36.x86_64")?
+        .unwrap();
+    assert_eq!(hello.nevra(), "hello-0:2.10-1.fc36.x86_64");
+    assert!(repo
+        .get_package_by_nevra("hello-0:2.10-1.fc36.aarch64")?
+        .is_none());
+    assert!(matches!(
+        repo.get_package_by_nevra("hello"),
+        Err(MetadataError::Parse(ParseError::InvalidNevra(_)))
+    ));
+
+    let names = |pkgs: Vec<&Package>| -> Vec<String> { pkgs.iter().map(|p| p.nvra()).collect() };
+    assert_eq!(
+        names(repo.packages_by_name("hello")),
+        vec!["hello-2.10-1.fc36.x86_64"]
+    );
+    assert!(repo.packages_by_name("hell").is_empty());
+
+    let pkgid = hello.pkgid().to_owned();
+    repo.package_entry(&pkgid).and_modify(|pkg| {
+        pkg.set_location_href("Packages/hello.rpm");
+    });
+    assert_eq!(
+        repo.packages()[&pkgid].location_href(),
+        "Packages/hello.rpm"
+    );
+
+    let removed = repo
+        .remove_package_by_nevra("hello-0:2.10-1.fc36.x86_64")?
+        .unwrap();
+    assert_eq!(removed.pkgid(), pkgid);
+    assert_eq!(repo.packages().len(), 2);
+    assert!(repo
+        .remove_package_by_nevra("hello-0:2.10-1.fc36.x86_64")?
+        .is_none());
+
+    // the package can be added again once it has been removed
+    repo.add_package(removed)?;
+    assert_eq!(repo.packages().len(), 3);
+
+    // lookups follow changes made through packages_mut()
+    repo.packages_mut()[&pkgid].set_release("2.fc36");
+    assert!(repo
+        .get_package_by_nevra("hello-2.10-1.fc36.x86_64")?
+        .is_none());
+    assert_eq!(
+        repo.get_package_by_nevra("hello-2.10-2.fc36.x86_64")?
+            .map(|pkg| pkg.pkgid()),
+        Some(pkgid.as_str())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_location_href_policy() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_location_href_policy")?;
+    let mut package = common::RPM_EMPTY.clone();
+    package.set_location_href("/Packages/../../../etc/passwd");
+
+    let options = RepositoryOptions::default();
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    assert!(matches!(
+        repo_writer.add_package(&package),
+        Err(MetadataError::RepoLayout(RepoLayoutError::UnsafeHref(_)))
+    ));
+    drop(repo_writer);
+
+    let mut url_package = common::RPM_EMPTY.clone();
+    url_package.set_location_href("https://example.com/rpm-empty.rpm");
+    let options = RepositoryOptions::default().href_policy(HrefPolicy::Sanitize);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    assert!(matches!(
+        repo_writer.add_package(&url_package),
+        Err(MetadataError::RepoLayout(RepoLayoutError::UnsafeHref(_)))
+    ));
+    repo_writer.add_package(&package)?;
+    repo_writer.finish()?;
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    assert_eq!(repo.packages()[0].location_href(), "etc/passwd");
+
+    // metadata which was written with the check disabled is rejected when reading
+    let options = RepositoryOptions::default().href_policy(HrefPolicy::Allow);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    repo_writer.add_package(&package)?;
+    repo_writer.finish()?;
+
+    let mut reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    assert!(matches!(
+        reader.iter_packages()?.next(),
+        Some(Err(MetadataError::RepoLayout(RepoLayoutError::UnsafeHref(
+            _
+        ))))
+    ));
+    reader.set_href_policy(HrefPolicy::Sanitize);
+    let pkg = reader.iter_packages()?.next().unwrap()?;
+    assert_eq!(pkg.location_href(), "etc/passwd");
+    reader.set_href_policy(HrefPolicy::Allow);
+    let pkg = reader.iter_packages()?.next().unwrap()?;
+    assert_eq!(pkg.location_href(), "/Packages/../../../etc/passwd");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_limits() -> Result<(), MetadataError> {
+    let path = std::path::Path::new("./tests/assets/doctest_repo");
+
+    let mut reader = RepositoryReader::new_from_directory(path)?;
+    reader.set_parse_limits(ParseLimits::default().max_packages(2));
+    let mut packages = reader.iter_packages()?;
+    assert!(matches!(
+        packages.next(),
+        Some(Err(MetadataError::Parse(ParseError::PackageLimit(2))))
+    ));
+    reader.set_parse_limits(ParseLimits::default().max_packages(3));
+    assert_eq!(reader.iter_packages()?.count(), 3);
+
+    reader.set_parse_limits(ParseLimits::default().max_text_length(8));
+    let error = reader
+        .iter_packages()
+        .and_then(|mut packages| packages.next().unwrap().map(|_| ()))
+        .unwrap_err();
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::TextLengthLimit(8))
+    ));
+
+    reader.set_parse_limits(ParseLimits::default().max_depth(2));
+    let error = reader
+        .iter_packages()
+        .and_then(|mut packages| packages.next().unwrap().map(|_| ()))
+        .unwrap_err();
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::DepthLimit(2))
+    ));
+
+    // document type declarations are always rejected
+    let tmp_dir = TempDir::new("test_parse_limits")?;
+    let primary_path = tmp_dir.path().join("primary.xml");
+    std::fs::write(
+        &primary_path,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE metadata [<!ENTITY lol "lol">]>
+<metadata xmlns="http://linux.duke.edu/metadata/common" packages="0">
+</metadata>
+"#,
+    )?;
+    let error = PackageIterator::from_primary_only(&primary_path)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::Doctype)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_reader_untrusted_package_count() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_reader_untrusted_package_count")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo/repodata");
+    let repodata = tmp_dir.path().join("repodata");
+    std::fs::create_dir(&repodata)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let contents = std::fs::read_to_string(entry.path())?;
+        // a hostile header mustn't be able to force a huge allocation up front
+        let contents = contents.replace("packages=\"3\"", &format!("packages=\"{}\"", usize::MAX));
+        std::fs::write(repodata.join(entry.file_name()), contents)?;
+    }
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    assert_eq!(reader.iter_packages()?.total_packages(), usize::MAX);
+    assert_eq!(reader.into_repo()?.packages().len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_string_interning() -> Result<(), MetadataError> {
+    let path = std::path::Path::new("./tests/assets/doctest_repo");
+    let noarch = |repo: &Repository| -> Vec<Package> {
+        repo.packages()
+            .values()
+            .filter(|p| p.arch() == "noarch")
+            .cloned()
+            .collect()
+    };
+
+    let repo = Repository::load_from_directory(path)?;
+    let packages = noarch(&repo);
+    assert_eq!(packages.len(), 2);
+    assert!(Arc::ptr_eq(&packages[0].arch, &packages[1].arch));
+    // "hello" is provided by one package and required by another
+    let by_name = |name: &str| repo.packages().values().find(|p| p.name() == name).unwrap();
+    let provide = &by_name("hello").provides()[0];
+    let require = &by_name("hello-docs").requires()[0];
+    assert_eq!(&*require.name, "hello");
+    assert!(Arc::ptr_eq(&provide.name, &require.name));
+
+    let reader = RepositoryReader::new_from_directory(path)?;
+    let repo = reader.into_repo()?;
+    let packages = noarch(&repo);
+    assert!(!Arc::ptr_eq(&packages[0].arch, &packages[1].arch));
+
+    // interning doesn't change the parsed values
+    let mut reader = RepositoryReader::new_from_directory(path)?;
+    reader.set_string_interning(true);
+    assert_eq!(reader.into_repo()?, repo);
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_reader_iter_raw_packages() -> Result<(), MetadataError> {
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+    let reader = RepositoryReader::new_from_directory(source)?;
+    let packages = reader
+        .iter_packages()?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    let raw_packages = reader
+        .iter_raw_packages(MetadataSelection::default())?
+        .collect::<Result<Vec<RawPackage>, MetadataError>>()?;
+
+    assert_eq!(raw_packages.len(), packages.len());
+    for (raw, package) in raw_packages.iter().zip(&packages) {
+        assert_eq!(raw.pkgid, package.pkgid());
+        assert_eq!(raw.nevra(), package.nevra());
+        assert!(raw.primary_xml.starts_with("<package"));
+        assert!(raw.primary_xml.ends_with("</package>"));
+        assert!(raw.filelists_xml.as_ref().unwrap().contains(&raw.pkgid));
+        assert!(raw.other_xml.as_ref().unwrap().contains(&raw.pkgid));
+    }
+
+    // re-publish a subset of the packages without parsing them
+    let tmp_dir = TempDir::new("test_iter_raw_packages")?;
+    let subset: Vec<&RawPackage> = raw_packages
+        .iter()
+        .filter(|raw| raw.arch == "noarch")
+        .collect();
+    let mut writer = RepositoryWriter::new(tmp_dir.path(), subset.len())?;
+    for raw in &subset {
+        writer.add_package_raw(
+            &raw.primary_xml,
+            raw.filelists_xml.as_deref().unwrap(),
+            raw.other_xml.as_deref().unwrap(),
+        )?;
+    }
+    writer.finish()?;
+
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    let expected: Vec<&Package> = packages.iter().filter(|p| p.arch() == "noarch").collect();
+    assert_eq!(repo.packages().values().collect::<Vec<_>>(), expected);
+
+    // without filelists.xml and other.xml
+    let selection = MetadataSelection {
+        filelists: false,
+        other: false,
+    };
+    let raw = reader.iter_raw_packages(selection)?.next().unwrap()?;
+    assert_eq!(raw.filelists_xml, None);
+    assert_eq!(raw.other_xml, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_load_malformed_metadata() {
+    const PRIMARY: &str =
+        r#"<metadata xmlns="http://linux.duke.edu/metadata/common" packages="1">"#;
+    const FILELISTS: &str =
+        r#"<filelists xmlns="http://linux.duke.edu/metadata/filelists" packages="1">"#;
+    const OTHER: &str = r#"<otherdata xmlns="http://linux.duke.edu/metadata/other" packages="1">"#;
+    const UPDATEINFO: &str = r#"<updates><update from="x" status="final" type="bugfix" version="1"><id>X-1</id><pkglist>"#;
+
+    let load = |kind: &str, xml: String| {
+        let mut repo = Repository::new();
+        match kind {
+            "primary" => repo.load_metadata_str::<PrimaryXml>(&xml),
+            "filelists" => repo.load_metadata_str::<FilelistsXml>(&xml),
+            "other" => repo.load_metadata_str::<OtherXml>(&xml),
+            "updateinfo" => repo.load_metadata_str::<UpdateinfoXml>(&xml),
+            _ => repo.load_metadata_str::<RepomdXml>(&xml),
+        }
+        .unwrap_err()
+    };
+
+    // elements outside of the element they belong to
+    let error = load("primary", format!("{}<name>foo</name>", PRIMARY));
+    assert!(
+        matches!(error.root_cause(), MetadataError::Parse(ParseError::UnexpectedElement(tag)) if tag == "name")
+    );
+    let error = load(
+        "other",
+        format!("{}<changelog author=\"a\" date=\"1\">b</changelog>", OTHER),
+    );
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::UnexpectedElement(_))
+    ));
+    let error = load("updateinfo", format!("{}<name>foo</name>", UPDATEINFO));
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::UnexpectedElement(_))
+    ));
+
+    // invalid or missing values
+    let error = load(
+        "primary",
+        r#"<metadata xmlns="http://linux.duke.edu/metadata/common">"#.to_owned(),
+    );
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::MissingAttribute("packages"))
+    ));
+    let error = load("primary", format!("{}<package type=\"deb\">", PRIMARY));
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::UnknownAttribute(_))
+    ));
+    let error = load(
+        "filelists",
+        format!(
+            "{}<package pkgid=\"a\" name=\"b\" arch=\"c\"><file type=\"socket\">/x</file>",
+            FILELISTS
+        ),
+    );
+    assert!(
+        matches!(error.root_cause(), MetadataError::Parse(ParseError::InvalidFileType(t)) if t == "socket")
+    );
+    let error = load(
+        "other",
+        format!(
+            "{}<package pkgid=\"a\" name=\"b\" arch=\"c\"><version epoch=\"0\"/>",
+            OTHER
+        ),
+    );
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::MissingAttribute("ver"))
+    ));
+
+    // truncated files, which must not be read forever
+    let error = load(
+        "primary",
+        format!("{}<package type=\"rpm\"><format><rpm:provides>", PRIMARY),
+    );
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::Xml(_))
+    ));
+    let error = load("updateinfo", format!("{}<collection><package name=\"a\" version=\"1\" release=\"1\" epoch=\"0\" arch=\"noarch\">", UPDATEINFO));
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::Xml(_))
+    ));
+    let error = load(
+        "repomd",
+        "<repomd><data type=\"primary\"><size>1</size>".to_owned(),
+    );
+    assert!(matches!(
+        error.root_cause(),
+        MetadataError::Parse(ParseError::Xml(_))
+    ));
+}
+
+#[test]
+fn test_error_categories() -> Result<(), MetadataError> {
+    // consumers can decide how to handle an error based on its category alone
+    let is_transient = |error: &MetadataError| matches!(error.root_cause(), MetadataError::Io(_));
+    let is_corrupt = |error: &MetadataError| {
+        matches!(
+            error.root_cause(),
+            MetadataError::Parse(_) | MetadataError::Checksum(_)
+        )
+    };
+
+    let error = match RepositoryReader::new_from_directory(std::path::Path::new(
+        "./tests/assets/nonexistent_repo",
+    )) {
+        Err(error) => error,
+        Ok(_) => panic!("opening a nonexistent repository succeeded"),
+    };
+    assert!(is_transient(&error));
+    assert!(!is_corrupt(&error));
+
+    let mut repo = Repository::new();
+    let error = repo
+        .load_metadata_str::<PrimaryXml>("<metadata packages=\"1\"><package type=\"rpm\">")
+        .unwrap_err();
+    assert!(is_corrupt(&error));
+    assert!(!is_transient(&error));
+
+    let error = Checksum::parse("sha512", "abc").unwrap_err();
+    assert!(matches!(
+        error,
+        MetadataError::Checksum(ChecksumError::Invalid(_, _))
+    ));
+    assert!(is_corrupt(&error));
+
+    Ok(())
 }
@@ -0,0 +1,177 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cmp::Ordering;
+
+use proptest::prelude::*;
+use rpmrepo_metadata::version::{rpmvercmp, EVR};
+
+/// The test cases of librpm's own test suite, from `tests/rpmvercmp.at` in the rpm repository.
+static RPMVERCMP_VECTORS: &[(&str, &str, Ordering)] = &[
+    ("1.0", "1.0", Ordering::Equal),
+    ("1.0", "2.0", Ordering::Less),
+    ("2.0", "1.0", Ordering::Greater),
+    ("2.0.1", "2.0.1", Ordering::Equal),
+    ("2.0", "2.0.1", Ordering::Less),
+    ("2.0.1", "2.0", Ordering::Greater),
+    ("2.0.1a", "2.0.1a", Ordering::Equal),
+    ("2.0.1a", "2.0.1", Ordering::Greater),
+    ("2.0.1", "2.0.1a", Ordering::Less),
+    ("5.5p1", "5.5p1", Ordering::Equal),
+    ("5.5p1", "5.5p2", Ordering::Less),
+    ("5.5p2", "5.5p1", Ordering::Greater),
+    ("5.5p10", "5.5p10", Ordering::Equal),
+    ("5.5p1", "5.5p10", Ordering::Less),
+    ("5.5p10", "5.5p1", Ordering::Greater),
+    ("10xyz", "10.1xyz", Ordering::Less),
+    ("10.1xyz", "10xyz", Ordering::Greater),
+    ("xyz10", "xyz10", Ordering::Equal),
+    ("xyz10", "xyz10.1", Ordering::Less),
+    ("xyz10.1", "xyz10", Ordering::Greater),
+    ("xyz.4", "xyz.4", Ordering::Equal),
+    ("xyz.4", "8", Ordering::Less),
+    ("8", "xyz.4", Ordering::Greater),
+    ("xyz.4", "2", Ordering::Less),
+    ("2", "xyz.4", Ordering::Greater),
+    ("5.5p2", "5.6p1", Ordering::Less),
+    ("5.6p1", "5.5p2", Ordering::Greater),
+    ("5.6p1", "6.5p1", Ordering::Less),
+    ("6.5p1", "5.6p1", Ordering::Greater),
+    ("6.0.rc1", "6.0", Ordering::Greater),
+    ("6.0", "6.0.rc1", Ordering::Less),
+    ("10b2", "10a1", Ordering::Greater),
+    ("10a2", "10b2", Ordering::Less),
+    ("1.0aa", "1.0aa", Ordering::Equal),
+    ("1.0a", "1.0aa", Ordering::Less),
+    ("1.0aa", "1.0a", Ordering::Greater),
+    ("10.0001", "10.0001", Ordering::Equal),
+    ("10.0001", "10.1", Ordering::Equal),
+    ("10.1", "10.0001", Ordering::Equal),
+    ("10.0001", "10.0039", Ordering::Less),
+    ("10.0039", "10.0001", Ordering::Greater),
+    ("4.999.9", "5.0", Ordering::Less),
+    ("5.0", "4.999.9", Ordering::Greater),
+    ("20101121", "20101121", Ordering::Equal),
+    ("20101121", "20101122", Ordering::Less),
+    ("20101122", "20101121", Ordering::Greater),
+    ("2_0", "2_0", Ordering::Equal),
+    ("2.0", "2_0", Ordering::Equal),
+    ("2_0", "2.0", Ordering::Equal),
+    ("a", "a", Ordering::Equal),
+    ("a+", "a+", Ordering::Equal),
+    ("a+", "a_", Ordering::Equal),
+    ("a_", "a+", Ordering::Equal),
+    ("+a", "+a", Ordering::Equal),
+    ("+a", "_a", Ordering::Equal),
+    ("_a", "+a", Ordering::Equal),
+    ("+_", "+_", Ordering::Equal),
+    ("_+", "+_", Ordering::Equal),
+    ("_+", "_+", Ordering::Equal),
+    ("+", "_", Ordering::Equal),
+    ("_", "+", Ordering::Equal),
+    ("1.0~rc1", "1.0~rc1", Ordering::Equal),
+    ("1.0~rc1", "1.0", Ordering::Less),
+    ("1.0", "1.0~rc1", Ordering::Greater),
+    ("1.0~rc1", "1.0~rc2", Ordering::Less),
+    ("1.0~rc2", "1.0~rc1", Ordering::Greater),
+    ("1.0~rc1~git123", "1.0~rc1~git123", Ordering::Equal),
+    ("1.0~rc1~git123", "1.0~rc1", Ordering::Less),
+    ("1.0~rc1", "1.0~rc1~git123", Ordering::Greater),
+    ("1.0^", "1.0^", Ordering::Equal),
+    ("1.0^", "1.0", Ordering::Greater),
+    ("1.0", "1.0^", Ordering::Less),
+    ("1.0^git1", "1.0^git1", Ordering::Equal),
+    ("1.0^git1", "1.0", Ordering::Greater),
+    ("1.0", "1.0^git1", Ordering::Less),
+    ("1.0^git1", "1.0^git2", Ordering::Less),
+    ("1.0^git2", "1.0^git1", Ordering::Greater),
+    ("1.0^git1", "1.01", Ordering::Less),
+    ("1.01", "1.0^git1", Ordering::Greater),
+    ("1.0^20160101", "1.0^20160101", Ordering::Equal),
+    ("1.0^20160101", "1.0.1", Ordering::Less),
+    ("1.0.1", "1.0^20160101", Ordering::Greater),
+    ("1.0^20160101^git1", "1.0^20160101^git1", Ordering::Equal),
+    ("1.0^20160102", "1.0^20160101^git1", Ordering::Greater),
+    ("1.0^20160101^git1", "1.0^20160102", Ordering::Less),
+    ("1.0~rc1^git1", "1.0~rc1^git1", Ordering::Equal),
+    ("1.0~rc1^git1", "1.0~rc1", Ordering::Greater),
+    ("1.0~rc1", "1.0~rc1^git1", Ordering::Less),
+    ("1.0^git1~pre", "1.0^git1~pre", Ordering::Equal),
+    ("1.0^git1", "1.0^git1~pre", Ordering::Greater),
+    ("1.0^git1~pre", "1.0^git1", Ordering::Less),
+    ("1b.fc17", "1b.fc17", Ordering::Equal),
+    ("1b.fc17", "1.fc17", Ordering::Less),
+    ("1.fc17", "1b.fc17", Ordering::Greater),
+    ("1g.fc17", "1g.fc17", Ordering::Equal),
+    ("1g.fc17", "1.fc17", Ordering::Greater),
+    ("1.fc17", "1g.fc17", Ordering::Less),
+];
+
+#[test]
+fn test_rpmvercmp_librpm_vectors() {
+    for (version1, version2, expected) in RPMVERCMP_VECTORS {
+        assert_eq!(
+            rpmvercmp(version1, version2),
+            *expected,
+            "rpmvercmp({:?}, {:?})",
+            version1,
+            version2
+        );
+    }
+}
+
+// Versions only, no "-" or ":", which would be parsed as the separators of an EVR
+const VERSION: &str = "[0-9a-z.~^_+]{0,10}";
+
+proptest! {
+    #[test]
+    fn test_rpmvercmp_reflexive(version in VERSION) {
+        prop_assert_eq!(rpmvercmp(&version, &version), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_rpmvercmp_antisymmetric(version1 in VERSION, version2 in VERSION) {
+        prop_assert_eq!(
+            rpmvercmp(&version1, &version2),
+            rpmvercmp(&version2, &version1).reverse()
+        );
+    }
+
+    #[test]
+    fn test_rpmvercmp_transitive(version1 in VERSION, version2 in VERSION, version3 in VERSION) {
+        let mut versions = [version1, version2, version3];
+        versions.sort_by(|a, b| rpmvercmp(a, b));
+        prop_assert_ne!(rpmvercmp(&versions[0], &versions[2]), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_rpmvercmp_tilde_sorts_before(version in "[0-9a-z.]{1,10}", suffix in "[0-9a-z.]{0,5}") {
+        let prerelease = format!("{}~{}", version, suffix);
+        prop_assert_eq!(rpmvercmp(&prerelease, &version), Ordering::Less);
+    }
+
+    #[test]
+    fn test_rpmvercmp_caret_sorts_after(version in "[0-9a-z.]{1,10}", suffix in "[0-9a-z.]{0,5}") {
+        let snapshot = format!("{}^{}", version, suffix);
+        prop_assert_eq!(rpmvercmp(&snapshot, &version), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_rpmvercmp_leading_zeroes(version in "[1-9][0-9]{0,5}", zeroes in "0{1,3}") {
+        let padded = format!("{}{}", zeroes, version);
+        prop_assert_eq!(rpmvercmp(&padded, &version), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_evr_parse_display_roundtrip(
+        epoch in "[0-9]{0,3}",
+        version in "[0-9a-z.~^_+]{1,10}",
+        release in "[0-9a-z.~^_+]{1,10}",
+    ) {
+        let evr = EVR::new(epoch, version, release);
+        prop_assert_eq!(EVR::parse(&evr.to_string()), evr);
+    }
+}
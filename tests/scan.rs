@@ -0,0 +1,217 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    utils, ChecksumType, MetadataError, RepoLayoutError, RepositoryReader, RepositoryWriter,
+    ScanOptions, ValidationProblem,
+};
+use tempdir::TempDir;
+
+mod common;
+
+#[test]
+fn test_verify_package_files() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_verify_package_files")?;
+    std::fs::create_dir_all(tmp_dir.path().join("Packages/sub"))?;
+
+    // fake RPMs, the metadata only needs to match their size and checksum
+    let hrefs = [
+        "Packages/good.rpm",
+        "Packages/corrupt.rpm",
+        "Packages/sub/missing.rpm",
+    ];
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), hrefs.len())?;
+    for href in hrefs {
+        let path = tmp_dir.path().join(href);
+        std::fs::write(&path, href)?;
+        let mut package = common::RPM_EMPTY.clone();
+        package
+            .set_name(href)
+            .set_location_href(href)
+            .set_size_package(href.len() as u64)
+            .set_checksum(utils::checksum_file(&path, ChecksumType::Sha256)?);
+        repo_writer.add_package(&package)?;
+    }
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let report = reader.verify_package_files(2)?;
+    assert!(report.is_valid(), "{}", report);
+
+    std::fs::remove_file(tmp_dir.path().join("Packages/sub/missing.rpm"))?;
+    std::fs::write(
+        tmp_dir.path().join("Packages/corrupt.rpm"),
+        "Packages/corrupt.rp_",
+    )?;
+    std::fs::write(tmp_dir.path().join("Packages/orphan.rpm"), "orphan")?;
+
+    let report = reader.verify_package_files(0)?;
+    assert_eq!(report.problems.len(), 3, "{}", report);
+    assert!(report.problems.iter().any(|problem| matches!(
+        problem,
+        ValidationProblem::PackageChecksumMismatch { href, .. } if href == "Packages/corrupt.rpm"
+    )));
+    assert!(report
+        .problems
+        .contains(&ValidationProblem::MissingPackageFile {
+            href: "Packages/sub/missing.rpm".to_owned()
+        }));
+    assert!(report
+        .problems
+        .contains(&ValidationProblem::OrphanedPackageFile {
+            href: "Packages/orphan.rpm".to_owned()
+        }));
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_directory() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_scan_directory")?;
+    for path in [
+        "b/foo-1.0-1.x86_64.rpm",
+        "b/foo-debuginfo-1.0-1.x86_64.rpm",
+        "a/foo-1.0-1.src.rpm",
+        "bar-2.0-1.noarch.rpm",
+        "README",
+        "repodata/stale-1.0-1.noarch.rpm",
+        ".staging/stale-1.0-1.noarch.rpm",
+    ] {
+        let path = tmp_dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, "")?;
+    }
+
+    let hrefs = RepositoryWriter::scan_directory(tmp_dir.path(), &ScanOptions::default())?;
+    assert_eq!(
+        hrefs,
+        vec![
+            "a/foo-1.0-1.src.rpm",
+            "b/foo-1.0-1.x86_64.rpm",
+            "b/foo-debuginfo-1.0-1.x86_64.rpm",
+            "bar-2.0-1.noarch.rpm",
+        ]
+    );
+
+    let options = ScanOptions::default()
+        .exclude("*-debuginfo-*")
+        .exclude("*.src.rpm");
+    let hrefs = RepositoryWriter::scan_directory(tmp_dir.path(), &options)?;
+    assert_eq!(
+        hrefs,
+        vec!["b/foo-1.0-1.x86_64.rpm", "bar-2.0-1.noarch.rpm"]
+    );
+
+    // patterns match the relative path as well as the filename
+    let options = ScanOptions::default()
+        .include("b/*")
+        .exclude("*-debuginfo-*");
+    let hrefs = RepositoryWriter::scan_directory(tmp_dir.path(), &options)?;
+    assert_eq!(hrefs, vec!["b/foo-1.0-1.x86_64.rpm"]);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_directory_options() -> Result<(), MetadataError> {
+    use std::os::unix::fs::symlink;
+
+    let tmp_dir = TempDir::new("test_scan_directory_options")?;
+    for path in [
+        "top-1.0-1.noarch.rpm",
+        "sub/nested-1.0-1.noarch.rpm",
+        ".hidden/hidden-1.0-1.noarch.rpm",
+    ] {
+        let path = tmp_dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, "")?;
+    }
+    symlink(
+        tmp_dir.path().join("top-1.0-1.noarch.rpm"),
+        tmp_dir.path().join("linked-1.0-1.noarch.rpm"),
+    )?;
+    symlink(
+        tmp_dir.path().join("missing.rpm"),
+        tmp_dir.path().join("broken.rpm"),
+    )?;
+    // a loop back to the top directory is only scanned once
+    symlink(tmp_dir.path(), tmp_dir.path().join("sub/loop"))?;
+
+    let hrefs = RepositoryWriter::scan_directory(tmp_dir.path(), &ScanOptions::default())?;
+    assert_eq!(
+        hrefs,
+        vec![
+            "linked-1.0-1.noarch.rpm",
+            "sub/nested-1.0-1.noarch.rpm",
+            "top-1.0-1.noarch.rpm",
+        ]
+    );
+
+    let options = ScanOptions::default()
+        .follow_symlinks(false)
+        .skip_hidden(false);
+    let hrefs = RepositoryWriter::scan_directory(tmp_dir.path(), &options)?;
+    assert_eq!(
+        hrefs,
+        vec![
+            ".hidden/hidden-1.0-1.noarch.rpm",
+            "sub/nested-1.0-1.noarch.rpm",
+            "top-1.0-1.noarch.rpm",
+        ]
+    );
+
+    let options = ScanOptions::default().recursive(false);
+    let hrefs = RepositoryWriter::scan_directory(tmp_dir.path(), &options)?;
+    assert_eq!(
+        hrefs,
+        vec!["linked-1.0-1.noarch.rpm", "top-1.0-1.noarch.rpm"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_read_package_list() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_read_package_list")?;
+    for path in ["a/foo-1.0-1.noarch.rpm", "bar-2.0-1.noarch.rpm"] {
+        let path = tmp_dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, "")?;
+    }
+
+    let list_path = tmp_dir.path().join("pkglist");
+    std::fs::write(
+        &list_path,
+        "# packages\nbar-2.0-1.noarch.rpm\n\n./a/foo-1.0-1.noarch.rpm\nbar-2.0-1.noarch.rpm\n",
+    )?;
+    let hrefs = RepositoryWriter::read_package_list(tmp_dir.path(), &list_path)?;
+    assert_eq!(
+        hrefs,
+        vec!["bar-2.0-1.noarch.rpm", "a/foo-1.0-1.noarch.rpm"]
+    );
+
+    std::fs::write(
+        &list_path,
+        "bar-2.0-1.noarch.rpm\nmissing-1.0-1.noarch.rpm\nb/gone-1.0-1.noarch.rpm\n",
+    )?;
+    let result = RepositoryWriter::read_package_list(tmp_dir.path(), &list_path);
+    assert!(matches!(
+        result,
+        Err(MetadataError::RepoLayout(RepoLayoutError::MissingPackages(missing)))
+            if missing == ["missing-1.0-1.noarch.rpm", "b/gone-1.0-1.noarch.rpm"]
+    ));
+
+    std::fs::write(&list_path, "../outside-1.0-1.noarch.rpm\n")?;
+    let result = RepositoryWriter::read_package_list(tmp_dir.path(), &list_path);
+    assert!(matches!(
+        result,
+        Err(MetadataError::RepoLayout(RepoLayoutError::UnsafeHref(_)))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+#!/usr/bin/env python3
+
+# Tests of the Python bindings, run with pytest after building them with `maturin develop`
+
+import os.path
+
+import pytest
+
+import rpmrepo_metadata as rpmmd
+
+
+def test_repository_writer_finish_twice(tmp_path):
+    writer = rpmmd.RepositoryWriter(str(tmp_path), 0)
+    writer.finish()
+    assert os.path.exists(tmp_path / "repodata" / "repomd.xml")
+
+    with pytest.raises(RuntimeError, match="writer already finished"):
+        writer.finish()
+    with pytest.raises(RuntimeError, match="writer already finished"):
+        writer.add_package(rpmmd.Package())
+
+
+def test_repository_writer_options(tmp_path):
+    options = rpmmd.RepositoryOptions(
+        metadata_compression_type="none", simple_metadata_filenames=True
+    )
+    writer = rpmmd.RepositoryWriter(str(tmp_path), 0, options)
+    writer.finish()
+    assert os.path.exists(tmp_path / "repodata" / "primary.xml")
+
+
+def make_advisory():
+    package = rpmmd.UpdateCollectionPackage()
+    package.name = "hello"
+    package.epoch = "0"
+    package.version = "2.10"
+    package.release = "1.fc36"
+    package.arch = "x86_64"
+    package.src = "hello-2.10-1.fc36.src.rpm"
+    package.filename = "hello-2.10-1.fc36.x86_64.rpm"
+    package.reboot_suggested = True
+    package.checksum = ("sha256", "a" * 64)
+
+    collection = rpmmd.UpdateCollection()
+    collection.name = "Fedora 36"
+    collection.shortname = "F36"
+    collection.packages = [package]
+
+    record = rpmmd.UpdateRecord()
+    record.from_ = "updates@fedoraproject.org"
+    record.id = "FEDORA-2022-1"
+    record.update_type = "bugfix"
+    record.status = "stable"
+    record.version = "2.0"
+    record.title = "hello bugfix update"
+    record.issued_date = "2022-01-01 00:00:00"
+    record.description = "Fixes the greeting"
+    record.references = [("https://bugzilla.redhat.com/1", "1", "Wrong greeting", "bugzilla")]
+    record.pkglist = [collection]
+    return record
+
+
+def test_repository_writer_add_advisory(tmp_path):
+    writer = rpmmd.RepositoryWriter(str(tmp_path), 0)
+    writer.add_advisory(make_advisory())
+    writer.finish()
+
+    reader = rpmmd.RepositoryReader(str(tmp_path))
+    advisories = list(reader.iter_advisories())
+    assert len(advisories) == 1
+    record = advisories[0]
+    assert record.id == "FEDORA-2022-1"
+    assert record.title == "hello bugfix update"
+    assert record.issued_date == "2022-01-01 00:00:00"
+    assert record.references == [
+        ("https://bugzilla.redhat.com/1", "1", "Wrong greeting", "bugzilla")
+    ]
+    [collection] = record.pkglist
+    assert collection.name == "Fedora 36"
+    [package] = collection.packages
+    assert package.filename == "hello-2.10-1.fc36.x86_64.rpm"
+    assert package.reboot_suggested
+    assert package.checksum == ("sha256", "a" * 64)
+
+
+def test_updateinfo_writer(tmp_path):
+    path = tmp_path / "updateinfo.xml"
+    writer = rpmmd.UpdateinfoWriter(str(path))
+    writer.add_advisory(make_advisory())
+    writer.finish()
+
+    xml = path.read_text()
+    assert "<id>FEDORA-2022-1</id>" in xml
+    assert '<collection short="F36">' in xml
+    assert "<filename>hello-2.10-1.fc36.x86_64.rpm</filename>" in xml
+
+
+def test_updateinfo_writer_finish_twice(tmp_path):
+    path = tmp_path / "updateinfo.xml"
+    writer = rpmmd.UpdateinfoWriter(str(path))
+    writer.finish()
+
+    with pytest.raises(RuntimeError, match="writer already finished"):
+        writer.finish()
+    with pytest.raises(RuntimeError, match="writer already finished"):
+        writer.add_advisory(make_advisory())
+    assert path.read_text().count("</updates>") == 1
@@ -0,0 +1,261 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    utils, ChecksumType, CompressionType, MetadataError, Package, RepoLayoutError, Repository,
+    RepositoryOptions, RepositoryReader, RepositoryTransformer, RepositoryWriter, TransferMode,
+    TransformReport, UnknownRecordPolicy, WriteError, EVR,
+};
+use tempdir::TempDir;
+
+#[test]
+fn test_repository_transformer() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_transformer")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+
+    let transformer = RepositoryTransformer::new(RepositoryReader::new_from_directory(source)?);
+    let report = transformer.transform(tmp_dir.path(), |mut pkg| {
+        if pkg.arch() != "noarch" {
+            return None;
+        }
+        pkg.set_location_href(format!("noarch/{}.rpm", pkg.nvra()));
+        Some(pkg)
+    })?;
+    assert_eq!(
+        report,
+        TransformReport {
+            packages_read: 3,
+            packages_written: 2,
+            advisories_written: 1,
+            package_files_transferred: 0,
+            package_files_copied: 0,
+        }
+    );
+
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    let hrefs: Vec<&str> = repo
+        .packages()
+        .values()
+        .map(|p| p.location_href())
+        .collect();
+    assert_eq!(
+        hrefs,
+        vec![
+            "noarch/hello-docs-2.10-1.fc36.noarch.rpm",
+            "noarch/goodbye-1.0-3.fc36.noarch.rpm",
+        ]
+    );
+    assert_eq!(repo.advisories().len(), 1);
+
+    // with rewrite_package_count the source is only read once
+    let tmp_dir = TempDir::new("test_repository_transformer")?;
+    let mut calls = 0;
+    let options = RepositoryOptions::default()
+        .metadata_compression_type(CompressionType::None)
+        .rewrite_package_count(true);
+    let report = RepositoryTransformer::new(RepositoryReader::new_from_directory(source)?)
+        .options(options)
+        .copy_advisories(false)
+        .transform(tmp_dir.path(), |pkg| {
+            calls += 1;
+            (pkg.name() == "goodbye").then_some(pkg)
+        })?;
+    assert_eq!(calls, 3);
+    assert_eq!(report.packages_written, 1);
+    assert_eq!(report.advisories_written, 0);
+
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    assert_eq!(repo.packages().len(), 1);
+    assert!(repo.advisories().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_transformer_package_files() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_transformer_package_files")?;
+    let source_dir = tmp_dir.path().join("source");
+    let hrefs = [
+        "Packages/a/alpha-1.0-1.noarch.rpm",
+        "Packages/b/beta-1.0-1.x86_64.rpm",
+    ];
+
+    let mut writer = RepositoryWriter::new(&source_dir, hrefs.len())?;
+    for href in hrefs {
+        let path = source_dir.join(href);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, href)?;
+
+        let (name, arch) = if href.contains("alpha") {
+            ("alpha", "noarch")
+        } else {
+            ("beta", "x86_64")
+        };
+        let mut package = Package::default();
+        package
+            .set_name(name)
+            .set_arch(arch)
+            .set_evr(EVR::new("0", "1.0", "1"))
+            .set_checksum(utils::checksum_file(&path, ChecksumType::Sha256)?)
+            .set_location_href(href);
+        writer.add_package(&package)?;
+    }
+    writer.finish()?;
+
+    // the files of packages which are moved to another location are placed there
+    let new_dir = tmp_dir.path().join("new");
+    let report = RepositoryTransformer::new(RepositoryReader::new_from_directory(&source_dir)?)
+        .package_files(TransferMode::Hardlink)
+        .transform(&new_dir, |mut pkg| {
+            if pkg.arch() == "noarch" {
+                pkg.set_location_href(format!("noarch/{}.rpm", pkg.nvra()));
+            }
+            Some(pkg)
+        })?;
+    assert_eq!(report.package_files_transferred, 2);
+    assert_eq!(report.package_files_copied, 0);
+    assert_eq!(
+        std::fs::read_to_string(new_dir.join("noarch/alpha-1.0-1.noarch.rpm"))?,
+        hrefs[0]
+    );
+    assert!(new_dir.join(hrefs[1]).exists());
+    assert!(source_dir.join(hrefs[0]).exists());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = |path: std::path::PathBuf| std::fs::metadata(path).map(|m| m.ino());
+        assert_eq!(
+            inode(new_dir.join(hrefs[1]))?,
+            inode(source_dir.join(hrefs[1]))?
+        );
+    }
+
+    // reflinks fall back to copying on filesystems which don't support them
+    let new_dir = tmp_dir.path().join("reflinked");
+    let report = RepositoryTransformer::new(RepositoryReader::new_from_directory(&source_dir)?)
+        .package_files(TransferMode::Reflink)
+        .transform(&new_dir, Some)?;
+    assert_eq!(report.package_files_transferred, 2);
+    for href in hrefs {
+        assert_eq!(std::fs::read_to_string(new_dir.join(href))?, href);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_transformer_extract_advisories() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_transformer_extract_advisories")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+
+    let transformer = RepositoryTransformer::new(RepositoryReader::new_from_directory(source)?);
+    let report = transformer.extract_advisories(tmp_dir.path(), &["EXAMPLE-2022-0001"])?;
+    assert_eq!(
+        report,
+        TransformReport {
+            packages_read: 3,
+            packages_written: 1,
+            advisories_written: 1,
+            package_files_transferred: 0,
+            package_files_copied: 0,
+        }
+    );
+
+    let repo = Repository::load_from_directory(tmp_dir.path())?;
+    let nevras: Vec<String> = repo.packages().values().map(|p| p.nevra()).collect();
+    assert_eq!(nevras, vec!["hello-0:2.10-1.fc36.x86_64"]);
+    assert!(repo.advisories().contains_key("EXAMPLE-2022-0001"));
+
+    let tmp_dir = TempDir::new("test_repository_transformer_extract_advisories")?;
+    assert!(matches!(
+        transformer.extract_advisories(tmp_dir.path(), &["EXAMPLE-2022-0001", "EXAMPLE-2022-9999"]),
+        Err(MetadataError::Write(WriteError::AdvisoryNotFound(id))) if id == "EXAMPLE-2022-9999"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_transformer_unknown_records() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_transformer_unknown_records")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+
+    // a repository with an extra record which this library doesn't parse
+    let susedata = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<susedata xmlns=\"http://linux.duke.edu/metadata/susedata\" packages=\"0\"/>\n";
+    let susedata_path = tmp_dir.path().join("susedata.xml");
+    std::fs::write(&susedata_path, susedata)?;
+    let source_dir = tmp_dir.path().join("source");
+    let mut repo_writer = RepositoryWriter::new(&source_dir, 3)?;
+    for package in RepositoryReader::new_from_directory(source)?.iter_packages()? {
+        repo_writer.add_package(&package?)?;
+    }
+    repo_writer.add_metadata_file("susedata", &susedata_path, CompressionType::Gzip)?;
+    repo_writer.finish()?;
+
+    let dest_dir = tmp_dir.path().join("dest");
+    RepositoryTransformer::new(RepositoryReader::new_from_directory(&source_dir)?)
+        .unknown_records(UnknownRecordPolicy::Preserve)
+        .transform(&dest_dir, Some)?;
+
+    let source_record = RepositoryReader::new_from_directory(&source_dir)?
+        .repomd()
+        .get_record("susedata")
+        .cloned()
+        .unwrap();
+    let reader = RepositoryReader::new_from_directory(&dest_dir)?;
+    let record = reader.repomd().get_record("susedata").unwrap();
+    // copied as-is, rather than recompressed, with a single checksum prefix
+    assert_eq!(record.checksum, source_record.checksum);
+    assert_eq!(record.location_href, source_record.location_href);
+    let mut contents = String::new();
+    reader
+        .open_record("susedata")?
+        .unwrap()
+        .read_to_string(&mut contents)?;
+    assert_eq!(contents, susedata);
+    assert_eq!(reader.iter_packages()?.count(), 3);
+
+    // not copied by default
+    let dest_dir = tmp_dir.path().join("dest_default");
+    RepositoryTransformer::new(RepositoryReader::new_from_directory(&source_dir)?)
+        .transform(&dest_dir, Some)?;
+    let reader = RepositoryReader::new_from_directory(&dest_dir)?;
+    assert!(reader.repomd().get_record("susedata").is_none());
+
+    // recompressed with the metadata compression type of the new repository
+    let dest_dir = tmp_dir.path().join("dest_recompress");
+    RepositoryTransformer::new(RepositoryReader::new_from_directory(&source_dir)?)
+        .options(RepositoryOptions::default().metadata_compression_type(CompressionType::Xz))
+        .unknown_records(UnknownRecordPolicy::Recompress)
+        .transform(&dest_dir, Some)?;
+    let reader = RepositoryReader::new_from_directory(&dest_dir)?;
+    let record = reader.repomd().get_record("susedata").unwrap();
+    assert!(record
+        .location_href
+        .to_string_lossy()
+        .ends_with("-susedata.xml.xz"));
+    assert_eq!(record.open_checksum, source_record.open_checksum);
+    let mut contents = String::new();
+    reader
+        .open_record("susedata")?
+        .unwrap()
+        .read_to_string(&mut contents)?;
+    assert_eq!(contents, susedata);
+
+    // nothing is written if unknown records are an error
+    let dest_dir = tmp_dir.path().join("dest_error");
+    let result = RepositoryTransformer::new(RepositoryReader::new_from_directory(&source_dir)?)
+        .unknown_records(UnknownRecordPolicy::Error)
+        .transform(&dest_dir, Some);
+    assert!(
+        matches!(result, Err(MetadataError::RepoLayout(RepoLayoutError::UnknownRecord(name))) if name == "susedata")
+    );
+    assert!(!dest_dir.exists());
+
+    Ok(())
+}
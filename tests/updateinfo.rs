@@ -9,7 +9,7 @@ extern crate rpmrepo_metadata;
 use pretty_assertions::assert_eq;
 use rpmrepo_metadata::UpdateRecord;
 use rpmrepo_metadata::*;
-use std::fs::{Metadata, OpenOptions};
+use std::fs::OpenOptions;
 use std::io::{Cursor, Read, Seek, SeekFrom};
 use tempdir::TempDir;
 
@@ -31,6 +31,28 @@ static EMPTY_UPDATEINFO_NO_DECL: &str = r#"
 
 static COMPLEX_UPDATEINFO: &str = r#""#;
 
+static SOURCE_UPDATEINFO: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<updates>
+  <update status="stable" from="updates@example.com" type="bugfix" version="2.0">
+    <id>EXAMPLE-2022-0001</id>
+    <title>complex-package bugfix update</title>
+    <pkglist>
+      <collection short="">
+        <name>Example 8</name>
+        <package name="complex-package" version="2.3.4" release="5.el8" epoch="1" arch="x86_64" src="complex-package-2.3.4-5.el8.src.rpm">
+          <filename>complex-package-2.3.4-5.el8.x86_64.rpm</filename>
+          <sum type="sha256">6e46283a16954c9cecd3799246eb1a426d7d8a8b1bc8d57c55c3da4253e200e5</sum>
+          <reboot_suggested>1</reboot_suggested>
+        </package>
+        <package name="complex-package" version="2.3.4" release="5.el8" epoch="1" arch="src">
+          <filename>complex-package-2.3.4-5.el8.src.rpm</filename>
+        </package>
+      </collection>
+    </pkglist>
+  </update>
+</updates>
+"#;
+
 #[test]
 fn test_updateinfo_xml_writer_empty() -> Result<(), MetadataError> {
     let mut writer = UpdateinfoXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
@@ -104,7 +126,7 @@ fn test_updateinfo_xml_writer_file() -> Result<(), MetadataError> {
 //     assert_eq!(updateinfo_xml.read_header()?, 0);
 //     assert!(matches!(
 //         updateinfo_xml.read_header(),
-//         Err(MetadataError::MissingHeaderError)
+//         Err(MetadataError::Parse(ParseError::MissingHeader))
 //     ));
 
 //     // Test that the header parses correctly when there are no packages and the footer element doesn't exist (EOF)
@@ -113,7 +135,7 @@ fn test_updateinfo_xml_writer_file() -> Result<(), MetadataError> {
 //     assert_eq!(updateinfo_xml.read_header()?, 0);
 //     assert!(matches!(
 //         updateinfo_xml.read_header(),
-//         Err(MetadataError::MissingHeaderError)
+//         Err(MetadataError::Parse(ParseError::MissingHeader))
 //     ));
 
 //     // Test that the header parses correctly when there is no XML declaration at the top
@@ -122,7 +144,7 @@ fn test_updateinfo_xml_writer_file() -> Result<(), MetadataError> {
 //     assert_eq!(updateinfo_xml.read_header()?, 0);
 //     assert!(matches!(
 //         updateinfo_xml.read_header(),
-//         Err(MetadataError::MissingHeaderError)
+//         Err(MetadataError::Parse(ParseError::MissingHeader))
 //     ));
 
 //     // Test that the header parses correctly when there is packages
@@ -131,7 +153,7 @@ fn test_updateinfo_xml_writer_file() -> Result<(), MetadataError> {
 //     assert_eq!(updateinfo_xml.read_header()?, 1);
 //     assert!(matches!(
 //         updateinfo_xml.read_header(),
-//         Err(MetadataError::MissingHeaderError)
+//         Err(MetadataError::Parse(ParseError::MissingHeader))
 //     ));
 
 //     Ok(())
@@ -161,3 +183,245 @@ fn test_updateinfo_xml_read_updaterecord() -> Result<(), MetadataError> {
 
     Ok(())
 }
+
+#[test]
+fn test_updateinfo_xml_source_packages() -> Result<(), MetadataError> {
+    let mut updateinfo_xml =
+        UpdateinfoXml::new_reader(utils::create_xml_reader(SOURCE_UPDATEINFO.as_bytes()));
+    let record = updateinfo_xml.read_update()?.unwrap();
+    assert!(updateinfo_xml.read_update()?.is_none());
+
+    let packages = &record.pkglist[0].packages;
+    assert_eq!(packages.len(), 2);
+
+    assert!(!packages[0].is_source());
+    assert_eq!(packages[0].src, "complex-package-2.3.4-5.el8.src.rpm");
+    assert!(packages[0].reboot_suggested);
+    assert!(packages[0].checksum.is_some());
+
+    // the source package entry has no "src" attribute, it is its own source
+    assert!(packages[1].is_source());
+    assert_eq!(packages[1].src, "complex-package-2.3.4-5.el8.src.rpm");
+    assert_eq!(
+        record.source_rpms(),
+        vec!["complex-package-2.3.4-5.el8.src.rpm"]
+    );
+
+    // round trip
+    let mut writer = UpdateinfoXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+    writer.write_header()?;
+    writer.write_updaterecord(&record)?;
+    writer.finish()?;
+    let buffer = writer.into_inner().into_inner();
+    let mut updateinfo_xml = UpdateinfoXml::new_reader(utils::create_xml_reader(&buffer[..]));
+    assert_eq!(updateinfo_xml.read_update()?.unwrap(), record);
+
+    // correlate with the source packages of a repository
+    let mut repo = Repository::new();
+    repo.load_metadata_str::<UpdateinfoXml>(SOURCE_UPDATEINFO)?;
+    let mut srpm = common::COMPLEX_PACKAGE.clone();
+    srpm.set_arch("src");
+    srpm.set_location_href("Packages/complex-package-2.3.4-5.el8.src.rpm");
+    repo.packages_mut().insert("srpm".to_owned(), srpm.clone());
+    repo.packages_mut().insert(
+        common::COMPLEX_PACKAGE.pkgid().to_owned(),
+        common::COMPLEX_PACKAGE.clone(),
+    );
+
+    let advisory = repo.advisories().get("EXAMPLE-2022-0001").unwrap();
+    assert_eq!(repo.advisory_source_packages(advisory), vec![&srpm]);
+
+    Ok(())
+}
+
+#[test]
+fn test_updateinfo_xml_cdata_fields() -> Result<(), MetadataError> {
+    let record = UpdateRecord {
+        id: "EXAMPLE-2022-0002".to_owned(),
+        summary: "Fixes for <b>important</b> bugs".to_owned(),
+        description: "<p>Fixes a crash & a memory leak</p>".to_owned(),
+        ..Default::default()
+    };
+
+    let mut writer = UpdateinfoXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+    writer.set_text_escaping(UpdateinfoTextField::Description, TextEscaping::CData);
+    writer.write_header()?;
+    writer.write_updaterecord(&record)?;
+    writer.finish()?;
+    let buffer = writer.into_inner().into_inner();
+
+    let actual = std::str::from_utf8(&buffer)?;
+    assert!(actual.contains("<![CDATA[<p>Fixes a crash & a memory leak</p>]]>"));
+    assert!(actual.contains("<summary>Fixes for &lt;b&gt;important&lt;/b&gt; bugs</summary>"));
+
+    // both escaped text and CDATA sections are read
+    let mut updateinfo_xml = UpdateinfoXml::new_reader(utils::create_xml_reader(&buffer[..]));
+    assert_eq!(updateinfo_xml.read_update()?.unwrap(), record);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_update_records() -> Result<(), MetadataError> {
+    let records = read_update_records(SOURCE_UPDATEINFO.as_bytes())?
+        .collect::<Result<Vec<UpdateRecord>, MetadataError>>()?;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].id, "EXAMPLE-2022-0001");
+
+    assert_eq!(read_update_records(EMPTY_UPDATEINFO.as_bytes())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_merge_advisories() -> Result<(), MetadataError> {
+    let advisory = |id: &str, updated_date: Option<&str>, title: &str| UpdateRecord {
+        id: id.to_owned(),
+        issued_date: Some("2022-01-01 00:00:00".to_owned()),
+        updated_date: updated_date.map(str::to_owned),
+        title: title.to_owned(),
+        ..Default::default()
+    };
+
+    let downstream = || {
+        let mut repo = Repository::new();
+        let report = repo.merge_advisories(
+            vec![
+                advisory("EXAMPLE-2022-0001", None, "original"),
+                advisory("EXAMPLE-2022-0002", Some("2022-02-01 00:00:00"), "original"),
+                advisory("EXAMPLE-2022-0003", Some("2022-02-01 00:00:00"), "original"),
+            ],
+            MergePolicy::Newest,
+        );
+        assert_eq!(report.added.len(), 3);
+        repo
+    };
+
+    let upstream = vec![
+        advisory("EXAMPLE-2022-0001", Some("2022-03-01 00:00:00"), "updated"),
+        advisory("EXAMPLE-2022-0002", Some("2022-01-15 00:00:00"), "stale"),
+        advisory("EXAMPLE-2022-0003", Some("2022-02-01 00:00:00"), "original"),
+        advisory("EXAMPLE-2022-0004", None, "new"),
+    ];
+
+    let mut newest = downstream();
+    let report = newest.merge_advisories(upstream.clone(), MergePolicy::Newest);
+    assert_eq!(report.added, vec!["EXAMPLE-2022-0004"]);
+    assert_eq!(report.updated, vec!["EXAMPLE-2022-0001"]);
+    assert_eq!(
+        report.unchanged,
+        vec!["EXAMPLE-2022-0002", "EXAMPLE-2022-0003"]
+    );
+    assert_eq!(newest.advisories().len(), 4);
+    assert_eq!(newest.advisories()["EXAMPLE-2022-0001"].title, "updated");
+    assert_eq!(newest.advisories()["EXAMPLE-2022-0002"].title, "original");
+
+    let mut keep_existing = downstream();
+    let report = keep_existing.merge_advisories(upstream.clone(), MergePolicy::KeepExisting);
+    assert!(report.updated.is_empty());
+    assert_eq!(
+        keep_existing.advisories()["EXAMPLE-2022-0001"].title,
+        "original"
+    );
+
+    let mut replace = downstream();
+    let report = replace.merge_advisories(upstream, MergePolicy::Replace);
+    assert_eq!(
+        report.updated,
+        vec!["EXAMPLE-2022-0001", "EXAMPLE-2022-0002"]
+    );
+    assert_eq!(replace.advisories()["EXAMPLE-2022-0002"].title, "stale");
+
+    Ok(())
+}
+
+#[test]
+fn test_updaterecord_cve_ids() {
+    let record = UpdateRecord {
+        references: vec![
+            UpdateReference {
+                href: "https://www.cve.org/CVERecord?id=CVE-2022-0778".to_owned(),
+                id: "CVE-2022-0778".to_owned(),
+                title: "CVE-2022-0778".to_owned(),
+                reftype: "cve".to_owned(),
+            },
+            UpdateReference {
+                href: "https://bugzilla.redhat.com/show_bug.cgi?id=2062202".to_owned(),
+                id: "2062202".to_owned(),
+                title: "CVE-2021-99999 is mentioned in a bug title".to_owned(),
+                reftype: "bugzilla".to_owned(),
+            },
+            UpdateReference {
+                href: "https://access.redhat.com/security/cve/CVE-2021-4160".to_owned(),
+                id: String::new(),
+                title: String::new(),
+                reftype: "cve".to_owned(),
+            },
+        ],
+        description: "Fixes CVE-2022-0778 and CVE-2022-1292 (CVE-2022-12345678). \
+            Not CVES: XCVE-2022-1111, CVE-22-1111, CVE-2022-111, CVE-2022"
+            .to_owned(),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        record.cve_ids(),
+        vec![
+            "CVE-2022-0778",
+            "CVE-2021-4160",
+            "CVE-2022-1292",
+            "CVE-2022-12345678"
+        ]
+    );
+    assert!(UpdateRecord::default().cve_ids().is_empty());
+}
+
+#[test]
+fn test_updateinfo_xml_update_level_suggestions() -> Result<(), MetadataError> {
+    let suse_updateinfo = r#"<?xml version="1.0" encoding="UTF-8"?>
+<updates>
+  <update from="maint-coord@suse.de" status="stable" type="recommended" version="1">
+    <id>SUSE-2022-1234</id>
+    <title>Recommended update for kernel-firmware</title>
+    <severity>moderate</severity>
+    <release>SUSE Updates SLE-Product-SLES 15-SP4 x86_64</release>
+    <issued date="1655796843"/>
+    <reboot_suggested>true</reboot_suggested>
+    <relogin_suggested> yes </relogin_suggested>
+    <references/>
+    <description>This update for kernel-firmware fixes the following issues.</description>
+    <pkglist>
+      <collection>
+        <package name="kernel-firmware" epoch="0" version="20220509" release="150400.4.5.1" arch="noarch" src="kernel-firmware-20220509-150400.4.5.1.src.rpm">
+          <filename>kernel-firmware-20220509-150400.4.5.1.noarch.rpm</filename>
+        </package>
+      </collection>
+    </pkglist>
+  </update>
+</updates>
+"#;
+
+    let mut updateinfo_xml =
+        UpdateinfoXml::new_reader(utils::create_xml_reader(suse_updateinfo.as_bytes()));
+    let record = updateinfo_xml.read_update()?.unwrap();
+    assert!(record.reboot_suggested);
+    assert!(!record.restart_suggested);
+    assert!(record.relogin_suggested);
+    assert!(!record.pkglist[0].packages[0].reboot_suggested);
+
+    // only the suggestions which are set are written, and they round trip
+    let mut writer = UpdateinfoXml::new_writer(utils::create_xml_writer(Cursor::new(Vec::new())));
+    writer.write_header()?;
+    writer.write_updaterecord(&record)?;
+    writer.finish()?;
+    let buffer = writer.into_inner().into_inner();
+    let written = std::str::from_utf8(&buffer)?;
+    assert!(written.contains("<reboot_suggested>True</reboot_suggested>"));
+    assert!(written.contains("<relogin_suggested>True</relogin_suggested>"));
+    assert!(!written.contains("restart_suggested"));
+
+    let mut updateinfo_xml = UpdateinfoXml::new_reader(utils::create_xml_reader(&buffer[..]));
+    assert_eq!(updateinfo_xml.read_update()?.unwrap(), record);
+
+    Ok(())
+}
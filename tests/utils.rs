@@ -0,0 +1,153 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::Write;
+
+use rpmrepo_metadata::{utils, ChecksumType, CompressionType, MetadataError};
+use tempdir::TempDir;
+
+const CONTENTS: &str = "<metadata packages=\"0\"></metadata>\n";
+
+#[test]
+fn test_estimated_decompressed_size() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("estimated_size")?;
+    let contents = CONTENTS.repeat(100);
+
+    let estimate = |compression| -> Result<Option<u64>, MetadataError> {
+        let (path, mut writer) =
+            utils::writer_to_file(&tmp_dir.path().join("primary.xml"), compression)?;
+        writer.write_all(contents.as_bytes())?;
+        drop(writer);
+        utils::estimated_decompressed_size(&path)
+    };
+
+    let expected = contents.len() as u64;
+    assert_eq!(estimate(CompressionType::None)?, Some(expected));
+    assert_eq!(estimate(CompressionType::Gzip)?, Some(expected));
+    assert_eq!(estimate(CompressionType::Xz)?, None);
+    assert_eq!(estimate(CompressionType::Bz2)?, None);
+    // the content size is optional in zstd frames
+    if let Some(size) = estimate(CompressionType::Zstd)? {
+        assert_eq!(size, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pgp_signature_key_id() {
+    // v4 signature packet (new format header) with the issuer in the unhashed subpackets
+    let signature = [
+        0xc2, 0x1a, // header
+        0x04, 0x00, 0x01,
+        0x08, // version, signature type, public key algorithm, hash algorithm
+        0x00, 0x06, 0x05, 0x02, 0x62, 0x00, 0x00, 0x00, // hashed subpackets: creation time
+        0x00, 0x0a, 0x09, 0x10, 0x19, 0x9e, 0x2f, 0x91, 0xfd, 0x43, 0x1d,
+        0x51, // unhashed: issuer
+        0xab, 0xcd, // hash prefix
+    ];
+    assert_eq!(
+        utils::pgp_signature_key_id(&signature),
+        Some(0x199e2f91fd431d51)
+    );
+
+    // v3 signature packet (old format header)
+    let signature = [
+        0x88, 0x13, // header
+        0x03, 0x05, 0x00, 0x62, 0x00, 0x00,
+        0x00, // version, hashed length, type, creation time
+        0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x00, 0x01, // key ID
+        0x01, 0x08, 0xab, 0xcd, // public key algorithm, hash algorithm, hash prefix
+    ];
+    assert_eq!(
+        utils::pgp_signature_key_id(&signature),
+        Some(0xdeadbeef00000001)
+    );
+
+    // truncated
+    assert_eq!(utils::pgp_signature_key_id(&signature[..8]), None);
+    assert_eq!(utils::pgp_signature_key_id(&[]), None);
+}
+
+#[test]
+fn test_checksum_streaming_and_parallel() -> Result<(), MetadataError> {
+    let repodata = std::path::Path::new("./tests/assets/doctest_repo/repodata");
+    let paths: Vec<_> = ["primary.xml", "filelists.xml", "other.xml", "missing.xml"]
+        .iter()
+        .map(|name| repodata.join(name))
+        .collect();
+
+    let results = utils::checksum_file_parallel(&paths, ChecksumType::Sha256, 2);
+    assert_eq!(results.len(), 4);
+    for (path, result) in paths.iter().zip(&results[..3]) {
+        assert_eq!(
+            result.as_ref().unwrap(),
+            &utils::checksum_file(path, ChecksumType::Sha256)?
+        );
+    }
+    assert!(results[3].is_err());
+
+    // several digests in a single pass
+    let checksums = utils::checksums_reader(
+        std::fs::File::open(&paths[0])?,
+        &[ChecksumType::Sha256, ChecksumType::Sha512],
+    )?;
+    assert_eq!(
+        checksums[0],
+        utils::checksum_file(&paths[0], ChecksumType::Sha256)?
+    );
+    assert_eq!(
+        checksums[1],
+        utils::checksum_file(&paths[0], ChecksumType::Sha512)?
+    );
+
+    // while streaming data through a writer
+    let mut writer = utils::ChecksumWriter::new(Vec::new(), &[ChecksumType::Sha256]);
+    writer.write_all(CONTENTS.as_bytes())?;
+    let (contents, checksums) = writer.finish();
+    assert_eq!(contents, CONTENTS.as_bytes());
+    assert_eq!(
+        checksums,
+        vec![utils::checksum_reader(
+            CONTENTS.as_bytes(),
+            ChecksumType::Sha256
+        )?]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_digest_metadata_file() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("digest_metadata_file")?;
+    let contents = CONTENTS.repeat(10);
+    let open_checksum = utils::checksum_reader(contents.as_bytes(), ChecksumType::Sha256)?;
+
+    for compression in [CompressionType::None, CompressionType::Gzip] {
+        let (path, mut writer) =
+            utils::writer_to_file(&tmp_dir.path().join("comps.xml"), compression)?;
+        writer.write_all(contents.as_bytes())?;
+        drop(writer);
+
+        let digest = utils::digest_metadata_file(&path, ChecksumType::Sha256)?;
+        assert_eq!(
+            digest.checksum,
+            utils::checksum_file(&path, ChecksumType::Sha256)?
+        );
+        assert_eq!(digest.size, std::fs::metadata(&path)?.len());
+
+        if compression == CompressionType::None {
+            assert_eq!(digest.checksum, open_checksum);
+            assert_eq!(digest.open_checksum, None);
+            assert_eq!(digest.open_size, None);
+        } else {
+            assert_eq!(digest.open_checksum, Some(open_checksum.clone()));
+            assert_eq!(digest.open_size, Some(contents.len() as u64));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    utils, validate_schema, Checksum, CompressionType, MetadataError, MetadataSchema, Package,
+    Repository, RepositoryOptions, RepositoryReader, RepositoryWriter, ValidationProblem,
+    WriteError, EVR,
+};
+use tempdir::TempDir;
+
+mod common;
+
+#[test]
+fn test_repository_validate() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_validate")?;
+
+    let options = RepositoryOptions::default()
+        .metadata_compression_type(CompressionType::None)
+        .simple_metadata_filenames(true);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 2, options)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.add_package(&common::RPM_EMPTY)?;
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let report = reader.validate()?;
+    assert!(report.is_valid(), "{}", report);
+
+    // drop a package from other.xml without changing its size, and update the checksum in repomd.xml
+    let other_path = reader.record_path("other").unwrap();
+    let other = std::fs::read_to_string(&other_path)?;
+    let start = other
+        .find(&format!("<package pkgid=\"{}\"", common::RPM_EMPTY.pkgid()))
+        .unwrap();
+    let end = start + other[start..].find("</package>").unwrap() + "</package>".len();
+    let padding = " ".repeat(end - start);
+    let other = format!("{}{}", &other[..start], &other[end..])
+        .replace("</otherdata>", &format!("{}</otherdata>", padding));
+    std::fs::write(&other_path, other)?;
+
+    let old_checksum = reader
+        .repomd()
+        .get_record("other")
+        .unwrap()
+        .checksum
+        .clone();
+    let new_checksum = utils::checksum_file(&other_path, old_checksum.checksum_type())?;
+    let repomd_path = tmp_dir.path().join("repodata/repomd.xml");
+    let repomd = std::fs::read_to_string(&repomd_path)?
+        .replace(old_checksum.to_values()?.1, new_checksum.to_values()?.1);
+    std::fs::write(&repomd_path, repomd)?;
+
+    // corrupt filelists.xml
+    let filelists_path = reader.record_path("filelists").unwrap();
+    let mut filelists = std::fs::read(&filelists_path)?;
+    filelists.extend_from_slice(b"<!-- -->");
+    std::fs::write(&filelists_path, filelists)?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let report = reader.validate()?;
+    assert!(!report.is_valid());
+    assert_eq!(report.problems.len(), 4, "{}", report);
+    assert!(report
+        .problems
+        .contains(&ValidationProblem::PackageCountMismatch {
+            file: "other",
+            declared: 2,
+            actual: 1,
+        }));
+    assert!(report
+        .problems
+        .contains(&ValidationProblem::MissingPackage {
+            file: "other",
+            pkgid: common::RPM_EMPTY.pkgid().to_owned(),
+        }));
+    assert!(report.problems.iter().any(|problem| matches!(
+        problem,
+        ValidationProblem::SizeMismatch { record, .. } if record == "filelists"
+    )));
+    assert!(report.problems.iter().any(|problem| matches!(
+        problem,
+        ValidationProblem::ChecksumMismatch { record, .. } if record == "filelists"
+    )));
+
+    // in-memory checks
+    let mut repo = Repository::new();
+    let mut package = common::RPM_EMPTY.clone();
+    package.set_location_href("../../etc/passwd");
+    repo.packages_mut()
+        .insert(package.pkgid().to_owned(), package.clone());
+    package.set_checksum(Checksum::Sha256("not-a-checksum".to_owned()));
+    package.set_location_href("Packages/r/rpm-empty.rpm");
+    repo.packages_mut().insert("bogus".to_owned(), package);
+
+    let report = repo.validate();
+    assert_eq!(
+        report.problems,
+        vec![
+            ValidationProblem::HrefOutsideRepository {
+                href: "../../etc/passwd".to_owned()
+            },
+            ValidationProblem::InvalidChecksum {
+                pkgid: "not-a-checksum".to_owned(),
+                checksum: Checksum::Sha256("not-a-checksum".to_owned()),
+            },
+            ValidationProblem::PkgidMismatch {
+                key: "bogus".to_owned(),
+                pkgid: "not-a-checksum".to_owned(),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_schema() -> Result<(), MetadataError> {
+    let doctest_repo = std::path::Path::new("./tests/assets/doctest_repo");
+
+    // metadata written by createrepo_c conforms
+    let report = RepositoryReader::new_from_directory(doctest_repo)?.validate_schema()?;
+    assert!(report.is_valid(), "{}", report);
+
+    // so does the metadata written by the writer, which checks it before publishing
+    let tmp_dir = TempDir::new("test_validate_schema")?;
+    let options = RepositoryOptions::default().validate_schema(true);
+    Repository::load_from_directory(doctest_repo)?
+        .write_to_directory_with_options(tmp_dir.path(), options)?;
+    let report = RepositoryReader::new_from_directory(tmp_dir.path())?.validate_schema()?;
+    assert!(report.is_valid(), "{}", report);
+
+    // violations are reported with their position, and streaming continues past them
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata xmlns="http://linux.duke.edu/metadata/common" packages="1">
+  <package type="rpm">
+    <name>foo</name>
+    <arch>noarch</arch>
+    <version epoch="x" ver="1.0" rel="1"/>
+    <bogus><name/></bogus>
+  </package>
+</metadata>"#;
+    let problems = validate_schema(
+        &mut utils::create_xml_reader(xml.as_bytes()),
+        MetadataSchema::Primary,
+        "primary",
+    );
+    let messages: Vec<&str> = problems
+        .iter()
+        .map(|problem| match problem {
+            ValidationProblem::SchemaViolation { file, message, .. } => {
+                assert_eq!(file, "primary");
+                message.as_str()
+            }
+            _ => panic!("unexpected problem {:?}", problem),
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "missing required attribute \"xmlns:rpm\" of <metadata>",
+            "invalid value \"x\" of attribute \"epoch\" of <version>: expected an unsigned integer",
+            "unexpected element <bogus> in <package>",
+            "missing required element <checksum> in <package>",
+            "missing required element <summary> in <package>",
+            "missing required element <description> in <package>",
+            "missing required element <time> in <package>",
+            "missing required element <size> in <package>",
+            "missing required element <location> in <package>",
+            "missing required element <format> in <package>",
+        ]
+    );
+    assert!(matches!(
+        problems[0],
+        ValidationProblem::SchemaViolation { position, .. } if xml[position..].trim_start().starts_with("<metadata")
+    ));
+
+    // the writer refuses to publish metadata which doesn't conform
+    let tmp_dir = TempDir::new("test_validate_schema")?;
+    let mut writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    let mut package = Package::default();
+    package
+        .set_name("")
+        .set_arch("noarch")
+        .set_evr(EVR::new("0", "1.0", "1"))
+        .set_checksum(Checksum::Sha256("a".repeat(64)))
+        .set_location_href("foo-1.0-1.noarch.rpm");
+    writer.add_package(&package)?;
+    assert!(matches!(
+        writer.finish(),
+        Err(MetadataError::Write(WriteError::SchemaViolation(_)))
+    ));
+    assert!(!tmp_dir.path().join("repodata").exists());
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cmp::Ordering;
+
+use rpmrepo_metadata::version::{rpmvercmp, Nevra, EVR};
+use rpmrepo_metadata::{MetadataError, ParseError};
+
+mod common;
+
+#[test]
+fn test_nevra_parse() -> Result<(), MetadataError> {
+    let nevra = Nevra::parse("bash-0:5.1-2.fc35.x86_64")?;
+    assert_eq!(nevra.name, "bash");
+    assert_eq!(nevra.evr, EVR::new("0", "5.1", "2.fc35"));
+    assert_eq!(nevra.arch, "x86_64");
+    assert_eq!(nevra.to_string(), "bash-0:5.1-2.fc35.x86_64");
+
+    let nevra: Nevra = "bash-completion-2.11-6.fc36.noarch".parse()?;
+    assert_eq!(nevra.name, "bash-completion");
+    assert_eq!(nevra.evr.epoch(), "");
+    assert_eq!(nevra.to_string(), "bash-completion-2.11-6.fc36.noarch");
+
+    assert!(matches!(
+        Nevra::parse("bash-5.1"),
+        Err(MetadataError::Parse(ParseError::InvalidNevra(_)))
+    ));
+
+    let nevra = Nevra::from(&*common::COMPLEX_PACKAGE);
+    assert_eq!(nevra.to_string(), common::COMPLEX_PACKAGE.nevra());
+
+    Ok(())
+}
+
+#[test]
+fn test_nevra_ord() -> Result<(), MetadataError> {
+    let mut nevras = [
+        Nevra::parse("bash-5.1-10.fc35.x86_64")?,
+        Nevra::parse("bash-5.1-2.fc35.x86_64")?,
+        Nevra::parse("bash-0:5.1-2.fc35.i686")?,
+        Nevra::parse("acl-2.3.1-3.fc36.x86_64")?,
+    ];
+    nevras.sort();
+    let sorted: Vec<String> = nevras.iter().map(|n| n.to_string()).collect();
+    assert_eq!(
+        sorted,
+        [
+            "acl-2.3.1-3.fc36.x86_64",
+            "bash-0:5.1-2.fc35.i686",
+            "bash-5.1-2.fc35.x86_64",
+            "bash-5.1-10.fc35.x86_64",
+        ]
+    );
+
+    // an empty epoch is the same as epoch 0
+    assert_eq!(
+        Nevra::parse("bash-5.1-2.fc35.x86_64")?,
+        Nevra::parse("bash-0:5.1-2.fc35.x86_64")?
+    );
+
+    // equality is consistent with the ordering
+    let a = Nevra::parse("bash-1.01-1.x86_64")?;
+    let b = Nevra::parse("bash-1.1-1.x86_64")?;
+    assert_eq!(a.cmp(&b), Ordering::Equal);
+    assert_eq!(a, b);
+    assert_ne!(a, Nevra::parse("bash-1.1-1.i686")?);
+
+    assert_eq!(rpmvercmp("1.0~rc1-1", "1.0-1"), Ordering::Less);
+    assert_eq!(rpmvercmp("1:1.0-1", "2.0-1"), Ordering::Greater);
+
+    Ok(())
+}
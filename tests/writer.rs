@@ -0,0 +1,706 @@
+// Copyright (c) 2022 Daniel Alley
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use pretty_assertions::assert_eq;
+use rpmrepo_metadata::{
+    package_layout_href, utils, CompressionType, MetadataError, Package, PrimaryXml,
+    RepoLayoutError, Repository, RepositoryOptions, RepositoryReader, RepositoryWriter,
+    TransferMode, UpdateRecord, UpdateinfoXml, WriteError,
+};
+use tempdir::TempDir;
+
+mod common;
+
+/// Copy the metadata of the doctest repository into `repodata_dir`, so that a test can modify it
+fn copy_doctest_repodata(repodata_dir: &std::path::Path) -> Result<(), MetadataError> {
+    std::fs::create_dir_all(repodata_dir)?;
+    for entry in std::fs::read_dir("./tests/assets/doctest_repo/repodata")? {
+        let entry = entry?;
+        std::fs::copy(entry.path(), repodata_dir.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_not_enough_packages() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer")?;
+
+    let repo_writer = RepositoryWriter::new(tmp_dir.path(), 1)?;
+    assert!(matches!(
+        repo_writer.finish(),
+        Err(MetadataError::Write(WriteError::PackageCountMismatch {
+            declared: 1,
+            written: 0
+        }))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_too_many_packages() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer")?;
+
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 0)?;
+    assert!(matches!(
+        repo_writer.add_package(&common::COMPLEX_PACKAGE),
+        Err(MetadataError::Write(WriteError::PackageCountMismatch {
+            declared: 0,
+            written: 1
+        }))
+    ));
+    // the package was rejected, so the writer is still consistent
+    repo_writer.finish()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_rewrite_package_count() -> Result<(), MetadataError> {
+    let options = RepositoryOptions::default()
+        .metadata_compression_type(CompressionType::None)
+        .rewrite_package_count(true);
+
+    for declared in [0, 1, 100] {
+        let tmp_dir = TempDir::new("test_repository_writer")?;
+
+        let mut repo_writer =
+            RepositoryWriter::new_with_options(tmp_dir.path(), declared, options)?;
+        repo_writer.add_package(&common::RPM_EMPTY)?;
+        repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+        repo_writer.finish()?;
+
+        let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+        let packages = reader.iter_packages()?;
+        assert!(packages.package_counts_match());
+        assert_eq!(packages.total_packages(), 2);
+        assert_eq!(packages.count(), 2);
+    }
+
+    // compressed metadata can't be rewritten in place
+    let tmp_dir = TempDir::new("test_repository_writer")?;
+    let options = options.metadata_compression_type(CompressionType::Zstd);
+    assert!(RepositoryWriter::new_with_options(tmp_dir.path(), 1, options).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_add_package_raw() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_add_package_raw")?;
+
+    // serialize a package, then cut out its <package> element from each metadata file
+    let source_dir = tmp_dir.path().join("source");
+    let mut repo_writer = RepositoryWriter::new(&source_dir, 1)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+    let source = RepositoryReader::new_from_directory(&source_dir)?;
+    let fragment = |name: &str| -> Result<String, MetadataError> {
+        let mut contents = String::new();
+        source
+            .open_record(name)?
+            .unwrap()
+            .read_to_string(&mut contents)?;
+        let start = contents.find("<package").unwrap();
+        let end = contents.rfind("</package>").unwrap() + "</package>".len();
+        Ok(contents[start..end].to_owned())
+    };
+    let (primary, filelists, other) = (
+        fragment("primary")?,
+        fragment("filelists")?,
+        fragment("other")?,
+    );
+
+    let repo_dir = tmp_dir.path().join("repo");
+    let mut repo_writer = RepositoryWriter::new(&repo_dir, 2)?;
+    repo_writer.add_package_raw(&primary, &filelists, &other)?;
+    repo_writer.add_package(&common::RPM_EMPTY)?;
+    assert!(repo_writer
+        .add_package_raw("<name>foo</name>", &filelists, &other)
+        .is_err());
+    repo_writer.finish()?;
+
+    let repo = Repository::load_from_directory(&repo_dir)?;
+    assert_eq!(repo.packages().len(), 2);
+    assert_eq!(
+        repo.packages().get(common::COMPLEX_PACKAGE.pkgid()),
+        Some(&*common::COMPLEX_PACKAGE)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_add_metadata_file() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer")?;
+    let comps_path = tmp_dir.path().join("comps.xml");
+    let comps = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<comps></comps>\n";
+    std::fs::write(&comps_path, comps)?;
+
+    let repo_dir = tmp_dir.path().join("repo");
+    let mut repo_writer = RepositoryWriter::new(&repo_dir, 0)?;
+    repo_writer.add_metadata_file(
+        "group",
+        &comps_path,
+        rpmrepo_metadata::CompressionType::Gzip,
+    )?;
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(&repo_dir)?;
+    let record = reader.repomd().get_record("group").unwrap();
+    let (_, checksum) = record.checksum.to_values()?;
+    assert_eq!(
+        record.location_href,
+        std::path::PathBuf::from(format!("repodata/{}-comps.xml.gz", checksum))
+    );
+    assert!(repo_dir.join(&record.location_href).exists());
+    assert_eq!(record.open_size, Some(comps.len() as u64));
+    assert_eq!(
+        record.checksum,
+        utils::checksum_file(
+            &repo_dir.join(&record.location_href),
+            rpmrepo_metadata::ChecksumType::Sha256
+        )?
+    );
+
+    // files which are already compressed are copied as they are, and the original stays in place
+    let published_path = repo_dir.join(&record.location_href);
+    let mut repo_writer =
+        RepositoryWriter::modify_existing(&repo_dir, RepositoryOptions::default())?;
+    repo_writer.add_metadata_file(
+        "group_gz",
+        &published_path,
+        rpmrepo_metadata::CompressionType::Zstd,
+    )?;
+    repo_writer.finish()?;
+    assert!(published_path.exists());
+
+    let reader = RepositoryReader::new_from_directory(&repo_dir)?;
+    let record = reader.repomd().get_record("group_gz").unwrap();
+    let (_, gz_checksum) = record.checksum.to_values()?;
+    assert_eq!(gz_checksum, checksum);
+    assert_eq!(
+        record.location_href,
+        std::path::PathBuf::from(format!("repodata/{}-comps.xml.gz", checksum))
+    );
+    assert_eq!(record.open_size, Some(comps.len() as u64));
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_hashed_filenames() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer")?;
+
+    let options = RepositoryOptions::default()
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::Gzip);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    for name in ["primary", "filelists", "other"] {
+        let record = reader.repomd().get_record(name).unwrap();
+        let (_, checksum) = record.checksum.to_values()?;
+        assert_eq!(
+            record.location_href,
+            std::path::PathBuf::from(format!("repodata/{}-{}.xml.gz", checksum, name))
+        );
+        assert!(tmp_dir.path().join(&record.location_href).exists());
+        assert!(!tmp_dir
+            .path()
+            .join(format!("repodata/{}.xml.gz", name))
+            .exists());
+    }
+
+    let repo = reader.into_repo()?;
+    assert_eq!(
+        repo.packages().values().collect::<Vec<_>>(),
+        vec![&*common::COMPLEX_PACKAGE]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_core_file_subset() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer")?;
+
+    let options = RepositoryOptions::default()
+        .simple_metadata_filenames(true)
+        .metadata_compression_type(rpmrepo_metadata::CompressionType::None)
+        .write_filelists(false)
+        .write_other(false);
+    let mut repo_writer = RepositoryWriter::new_with_options(tmp_dir.path(), 1, options)?;
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let repodata_dir = tmp_dir.path().join("repodata");
+    assert!(repodata_dir.join("primary.xml").exists());
+    assert!(!repodata_dir.join("filelists.xml").exists());
+    assert!(!repodata_dir.join("other.xml").exists());
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let record_names: Vec<&str> = reader
+        .repomd()
+        .records()
+        .iter()
+        .map(|r| r.metadata_name.as_str())
+        .collect();
+    assert_eq!(record_names, vec!["primary"]);
+
+    let mut repo = Repository::new();
+    repo.load_metadata_file::<PrimaryXml>(&repodata_dir.join("primary.xml"))?;
+    let package = repo.packages().values().next().unwrap();
+    assert_eq!(package.nevra(), common::COMPLEX_PACKAGE.nevra());
+    assert!(package.changelogs().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_location_prefix_and_base() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_location_prefix_and_base")?;
+
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 2)?;
+    repo_writer.set_location_prefix("/Packages/r/");
+    repo_writer.add_package(&common::RPM_EMPTY)?;
+    repo_writer.set_location_prefix("");
+    repo_writer.set_location_base(Some("https://packages.example.com/"));
+    repo_writer.add_package(&common::COMPLEX_PACKAGE)?;
+    repo_writer.finish()?;
+
+    let repo = RepositoryReader::new_from_directory(tmp_dir.path())?.into_repo()?;
+    let empty = &repo.packages()[common::RPM_EMPTY.pkgid()];
+    assert_eq!(
+        empty.location_href(),
+        format!("Packages/r/{}", common::RPM_EMPTY.location_href())
+    );
+    assert_eq!(empty.location_base(), None);
+    let complex = &repo.packages()[common::COMPLEX_PACKAGE.pkgid()];
+    assert_eq!(
+        complex.location_href(),
+        common::COMPLEX_PACKAGE.location_href()
+    );
+    assert_eq!(
+        complex.location_base(),
+        Some("https://packages.example.com/")
+    );
+
+    // the prefixed locations are still checked against the href policy
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 1)?;
+    repo_writer.set_location_prefix("../elsewhere");
+    assert!(matches!(
+        repo_writer.add_package(&common::RPM_EMPTY),
+        Err(MetadataError::RepoLayout(RepoLayoutError::UnsafeHref(_)))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_organize_rpm_file() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_organize_rpm_file")?;
+    let flat = tmp_dir.path().join("flat");
+    std::fs::create_dir_all(&flat)?;
+    for name in ["Bash-5.1.rpm", "zsh-5.8.rpm", "vim-9.0.rpm"] {
+        std::fs::write(flat.join(name), name)?;
+    }
+
+    assert_eq!(
+        package_layout_href("Bash-5.1.rpm"),
+        "Packages/b/Bash-5.1.rpm"
+    );
+
+    let repo_path = tmp_dir.path().join("repo");
+    let repo_writer = RepositoryWriter::new(&repo_path, 3)?;
+    let href = repo_writer.organize_rpm_file(&flat.join("Bash-5.1.rpm"), TransferMode::Copy)?;
+    assert_eq!(href, "Packages/b/Bash-5.1.rpm");
+    assert!(flat.join("Bash-5.1.rpm").exists());
+    assert_eq!(std::fs::read(repo_path.join(&href))?, b"Bash-5.1.rpm");
+
+    let href = repo_writer.organize_rpm_file(&flat.join("zsh-5.8.rpm"), TransferMode::Move)?;
+    assert!(!flat.join("zsh-5.8.rpm").exists());
+    assert!(repo_path.join(href).exists());
+
+    let href = repo_writer.organize_rpm_file(&flat.join("vim-9.0.rpm"), TransferMode::Hardlink)?;
+    assert!(flat.join("vim-9.0.rpm").exists());
+    // organizing a file which is already in place does nothing
+    let again = repo_writer.organize_rpm_file(&repo_path.join(&href), TransferMode::Move)?;
+    assert_eq!(href, again);
+    assert_eq!(std::fs::read(repo_path.join(&href))?, b"vim-9.0.rpm");
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_revision_and_tags() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_revision_and_tags")?;
+
+    let mut repo_writer = RepositoryWriter::new(tmp_dir.path(), 0)?;
+    let repomd = repo_writer.repomd_mut();
+    repomd.set_revision("2022.1");
+    repomd.add_distro_tag(
+        "Fedora 36".to_owned(),
+        Some("cpe:/o:fedoraproject:fedora:36".to_owned()),
+    );
+    repomd.add_content_tag("binary-x86_64".to_owned());
+    repomd.add_repo_tag("Fedora".to_owned());
+    repo_writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let repomd = reader.repomd();
+    assert_eq!(repomd.revision(), Some("2022.1"));
+    assert_eq!(repomd.distro_tags().len(), 1);
+    assert_eq!(repomd.distro_tags()[0].name, "Fedora 36");
+    assert_eq!(
+        repomd.distro_tags()[0].cpeid.as_deref(),
+        Some("cpe:/o:fedoraproject:fedora:36")
+    );
+    assert_eq!(repomd.content_tags(), &vec!["binary-x86_64".to_owned()]);
+    assert_eq!(repomd.repo_tags(), &vec!["Fedora".to_owned()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_compression_level() -> Result<(), MetadataError> {
+    let repo =
+        Repository::load_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+
+    for (compression, level) in [(CompressionType::Zstd, 19), (CompressionType::Gzip, 1)] {
+        let tmp_dir = TempDir::new("test_repository_writer_compression_level")?;
+        let options = RepositoryOptions::default()
+            .metadata_compression_type(compression)
+            .metadata_compression_level(Some(level))
+            .zstd_long_distance_matching(true);
+        repo.write_to_directory_with_options(tmp_dir.path(), options)?;
+
+        let written = Repository::load_from_directory(tmp_dir.path())?;
+        assert_eq!(written.packages(), repo.packages());
+    }
+
+    // levels beyond what the compression type supports are rejected
+    let tmp_dir = TempDir::new("test_repository_writer_compression_level")?;
+    let options = RepositoryOptions::default()
+        .metadata_compression_type(CompressionType::Gzip)
+        .metadata_compression_level(Some(19));
+    assert!(RepositoryWriter::new_with_options(tmp_dir.path(), 0, options).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_compression_threads() -> Result<(), MetadataError> {
+    let repo =
+        Repository::load_from_directory(std::path::Path::new("./tests/assets/doctest_repo"))?;
+
+    for compression in [CompressionType::Zstd, CompressionType::Xz] {
+        let tmp_dir = TempDir::new("test_repository_writer_compression_threads")?;
+        let options = RepositoryOptions::default()
+            .metadata_compression_type(compression)
+            .compression_threads(4);
+        repo.write_to_directory_with_options(tmp_dir.path(), options)?;
+
+        let written = Repository::load_from_directory(tmp_dir.path())?;
+        assert_eq!(written.packages(), repo.packages());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_modify_existing() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_modify_existing")?;
+    let repodata_dir = tmp_dir.path().join("repo").join("repodata");
+    copy_doctest_repodata(&repodata_dir)?;
+    let repo_dir = tmp_dir.path().join("repo");
+    let original = RepositoryReader::new_from_directory(&repo_dir)?;
+
+    // swap in a new updateinfo.xml
+    let mut repo = Repository::new();
+    let advisory = UpdateRecord {
+        id: "EXAMPLE-2022-0002".to_owned(),
+        ..Default::default()
+    };
+    repo.advisories_mut()
+        .insert(advisory.id.clone(), advisory.clone());
+    let updateinfo_path = tmp_dir.path().join("updateinfo.xml");
+    std::fs::write(
+        &updateinfo_path,
+        repo.write_metadata_string::<UpdateinfoXml>()?,
+    )?;
+
+    let mut writer = RepositoryWriter::modify_existing(&repo_dir, RepositoryOptions::default())?;
+    writer.add_metadata_file("updateinfo", &updateinfo_path, CompressionType::Gzip)?;
+    writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(&repo_dir)?;
+    let names: Vec<&str> = reader
+        .repomd()
+        .records()
+        .iter()
+        .map(|r| r.metadata_name.as_str())
+        .collect();
+    assert_eq!(names, vec!["primary", "filelists", "other", "updateinfo"]);
+    for name in ["primary", "filelists", "other"] {
+        assert_eq!(
+            reader.repomd().get_record(name),
+            original.repomd().get_record(name)
+        );
+    }
+    let revision: u64 = reader.repomd().revision().unwrap().parse().unwrap();
+    assert!(revision > 1641000000);
+
+    let advisories: Vec<UpdateRecord> = reader.iter_advisories()?.collect::<Result<_, _>>()?;
+    assert_eq!(advisories, vec![advisory]);
+    assert_eq!(reader.iter_packages()?.count(), 3);
+    // the replaced file is removed
+    assert!(!repodata_dir.join("updateinfo.xml").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_modify_existing_packages() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_modify_existing_packages")?;
+    let repodata_dir = tmp_dir.path().join("repodata");
+    copy_doctest_repodata(&repodata_dir)?;
+    let original = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let packages = original
+        .iter_packages()?
+        .filter(|pkg| pkg.as_ref().map_or(true, |pkg| pkg.name() != "goodbye"))
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+
+    let mut writer = RepositoryWriter::modify_existing_packages(
+        tmp_dir.path(),
+        packages.len(),
+        RepositoryOptions::default(),
+    )?;
+    for pkg in &packages {
+        writer.add_package(pkg)?;
+    }
+    writer.finish()?;
+
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    let written = reader
+        .iter_packages()?
+        .collect::<Result<Vec<Package>, MetadataError>>()?;
+    assert_eq!(written, packages);
+    assert_eq!(
+        reader.repomd().get_record("updateinfo"),
+        original.repomd().get_record("updateinfo")
+    );
+    assert_ne!(
+        reader.repomd().get_record("primary"),
+        original.repomd().get_record("primary")
+    );
+    assert!(reader.repomd().revision() > original.repomd().revision());
+    // the replaced files are removed
+    assert!(!repodata_dir.join("primary.xml").exists());
+    assert!(repodata_dir.join("updateinfo.xml").exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_repository_writer_snapshot_metadata() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_snapshot_metadata")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+    let options = RepositoryOptions::default().snapshot_metadata(true);
+
+    let write_snapshot = |revision: &str, num_pkgs: usize| -> Result<(), MetadataError> {
+        let mut writer = RepositoryWriter::new_with_options(tmp_dir.path(), num_pkgs, options)?;
+        writer.repomd_mut().set_revision(revision);
+        for package in RepositoryReader::new_from_directory(source)?
+            .iter_packages()?
+            .take(num_pkgs)
+        {
+            writer.add_package(&package?)?;
+        }
+        writer.finish()
+    };
+
+    write_snapshot("100", 3)?;
+    let link = tmp_dir.path().join("repodata");
+    assert_eq!(
+        std::fs::read_link(&link)?,
+        std::path::PathBuf::from("repodata-100")
+    );
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    assert_eq!(reader.iter_packages()?.count(), 3);
+
+    // the symlink is switched over to the new snapshot, the old one is kept
+    write_snapshot("101", 2)?;
+    assert_eq!(
+        std::fs::read_link(&link)?,
+        std::path::PathBuf::from("repodata-101")
+    );
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    assert_eq!(reader.repomd().revision(), Some("101"));
+    assert_eq!(reader.iter_packages()?.count(), 2);
+    assert!(tmp_dir.path().join("repodata-100/repomd.xml").exists());
+
+    // nothing besides the snapshots and the symlink is left behind
+    let mut entries: Vec<String> = std::fs::read_dir(tmp_dir.path())?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    assert_eq!(entries, vec!["repodata", "repodata-100", "repodata-101"]);
+
+    // existing snapshots are not overwritten
+    assert!(write_snapshot("101", 2).is_err());
+
+    // revisions which would name another directory are rejected, and nothing is published
+    for revision in ["../102", "102/../..", ""] {
+        assert!(matches!(
+            write_snapshot(revision, 2),
+            Err(MetadataError::Io(rpmrepo_metadata::IoError::Io(e)))
+                if e.kind() == std::io::ErrorKind::InvalidInput
+        ));
+    }
+    assert_eq!(
+        std::fs::read_link(&link)?,
+        std::path::PathBuf::from("repodata-101")
+    );
+    let mut entries: Vec<String> = std::fs::read_dir(tmp_dir.path())?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    assert_eq!(entries, vec!["repodata", "repodata-100", "repodata-101"]);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+#[test]
+fn test_repository_writer_snapshot_metadata_unsupported() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_snapshot_metadata_unsupported")?;
+    let options = RepositoryOptions::default().snapshot_metadata(true);
+    let result = RepositoryWriter::new_with_options(tmp_dir.path(), 0, options);
+    assert!(matches!(
+        result,
+        Err(MetadataError::Io(rpmrepo_metadata::IoError::Io(e)))
+            if e.kind() == std::io::ErrorKind::Unsupported
+    ));
+    assert!(!tmp_dir.path().join("repodata").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_replaces_metadata_atomically() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_replaces_metadata_atomically")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+    let repodata_files = || -> Result<Vec<String>, MetadataError> {
+        let mut files: Vec<String> = std::fs::read_dir(tmp_dir.path().join("repodata"))?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+        Ok(files)
+    };
+    let write_packages = |num_pkgs: usize| -> Result<RepositoryWriter, MetadataError> {
+        let mut writer = RepositoryWriter::new(tmp_dir.path(), num_pkgs)?;
+        for package in RepositoryReader::new_from_directory(source)?
+            .iter_packages()?
+            .take(num_pkgs)
+        {
+            writer.add_package(&package?)?;
+        }
+        Ok(writer)
+    };
+
+    write_packages(3)?.finish()?;
+    let first_files = repodata_files()?;
+    assert_eq!(first_files.len(), 4);
+
+    // nothing is visible until the writer is finished, and an abandoned writer leaves nothing behind
+    let writer = write_packages(2)?;
+    assert_eq!(repodata_files()?, first_files);
+    drop(writer);
+    assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+
+    // the new metadata replaces the old, and the stale hashed files are removed
+    write_packages(2)?.finish()?;
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    assert_eq!(reader.iter_packages()?.count(), 2);
+    let second_files = repodata_files()?;
+    assert_eq!(second_files.len(), 4);
+    assert!(second_files
+        .iter()
+        .all(|f| !first_files.contains(f) || f == "repomd.xml"));
+    assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_repository_writer_simple_filenames_publishing() -> Result<(), MetadataError> {
+    let tmp_dir = TempDir::new("test_repository_writer_simple_filenames_publishing")?;
+    let source = std::path::Path::new("./tests/assets/doctest_repo");
+    let write_packages = |options: RepositoryOptions, revision: &str, num_pkgs: usize| {
+        let mut writer = RepositoryWriter::new_with_options(tmp_dir.path(), num_pkgs, options)?;
+        writer.repomd_mut().set_revision(revision);
+        for package in RepositoryReader::new_from_directory(source)?
+            .iter_packages()?
+            .take(num_pkgs)
+        {
+            writer.add_package(&package?)?;
+        }
+        writer.finish()
+    };
+    let options = RepositoryOptions::default().simple_metadata_filenames(true);
+
+    // in place, the files are replaced under the same names and the result is consistent
+    write_packages(options, "100", 3)?;
+    write_packages(options, "101", 2)?;
+    let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+    assert!(reader.validate()?.is_valid());
+    assert_eq!(reader.iter_packages()?.count(), 2);
+    assert_eq!(
+        std::fs::read_dir(tmp_dir.path().join("repodata"))?.count(),
+        4
+    );
+
+    // as snapshots, the files of the previous metadata are never touched
+    #[cfg(unix)]
+    {
+        let tmp_dir = TempDir::new("test_repository_writer_simple_filenames_snapshots")?;
+        let options = options.snapshot_metadata(true);
+        let write_snapshot = |revision: &str, num_pkgs: usize| -> Result<(), MetadataError> {
+            let mut writer = RepositoryWriter::new_with_options(tmp_dir.path(), num_pkgs, options)?;
+            writer.repomd_mut().set_revision(revision);
+            for package in RepositoryReader::new_from_directory(source)?
+                .iter_packages()?
+                .take(num_pkgs)
+            {
+                writer.add_package(&package?)?;
+            }
+            writer.finish()
+        };
+        let snapshot_files = |name: &str| -> Result<Vec<(String, Vec<u8>)>, MetadataError> {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(tmp_dir.path().join(name))? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                files.push((name, std::fs::read(entry.path())?));
+            }
+            files.sort();
+            Ok(files)
+        };
+
+        write_snapshot("100", 3)?;
+        let first_snapshot = snapshot_files("repodata-100")?;
+        write_snapshot("101", 2)?;
+        assert_eq!(snapshot_files("repodata-100")?, first_snapshot);
+        let reader = RepositoryReader::new_from_directory(tmp_dir.path())?;
+        assert!(reader.validate()?.is_valid());
+        assert_eq!(reader.iter_packages()?.count(), 2);
+    }
+
+    Ok(())
+}